@@ -1,9 +1,47 @@
 use std::io::Result;
 
 fn main() -> Result<()> {
-    prost_build::compile_protos(
-        &["src/proto/chat.proto", "src/proto/llm.proto"],
-        &["src/proto"],
-    )?;
+    prost_build::Config::new()
+        // json representation of the http api, enum fields by name as in the proto json mapping
+        .message_attribute(
+            ".",
+            "#[derive(serde::Serialize, serde::Deserialize)] #[serde(default)]",
+        )
+        .enum_attribute(
+            ".",
+            "#[derive(serde::Serialize, serde::Deserialize)] #[serde(rename_all = \"snake_case\")]",
+        )
+        .field_attribute(
+            "created_at",
+            "#[serde(with = \"crate::models::json::timestamp\")]",
+        )
+        .field_attribute(
+            "updated_at",
+            "#[serde(with = \"crate::models::json::timestamp\")]",
+        )
+        .field_attribute(
+            "deleted_at",
+            "#[serde(with = \"crate::models::json::timestamp\")]",
+        )
+        .field_attribute(
+            ".chat.Message.role",
+            "#[serde(with = \"crate::models::json::role\")]",
+        )
+        .field_attribute(
+            ".llm.OpenAISettings.model",
+            "#[serde(with = \"crate::models::json::open_ai_model\")]",
+        )
+        .field_attribute(
+            ".llm.OpenAISettings.truncation",
+            "#[serde(with = \"crate::models::json::open_ai_truncation\")]",
+        )
+        .compile_protos(
+            &[
+                "src/proto/chat.proto",
+                "src/proto/llm.proto",
+                "src/proto/service.proto",
+            ],
+            &["src/proto"],
+        )?;
     Ok(())
 }
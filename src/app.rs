@@ -10,7 +10,7 @@ use crossterm::{
     execute, terminal,
 };
 use ratatui::{Terminal, prelude::Backend};
-use std::{env, fs, io::Write};
+use std::{env, fs, io::Write, time::Instant};
 use tempfile::NamedTempFile;
 use tokio::{
     select,
@@ -36,8 +36,10 @@ impl App {
 
     /// Runs the application's main loop until the user quits.
     pub async fn run(&mut self, cfg: Config) -> Result<()> {
-        let mut terminal = ratatui::init();
+        let themes = view::theme::load_themes(&Config::dir()?.join("themes"))?;
         let mut model = Model::new(cfg);
+        model.set_themes(themes)?;
+        let mut terminal = ratatui::init();
 
         let mut event_reader = EventStream::new();
         // enable crossterm bracketed paste
@@ -58,6 +60,7 @@ impl App {
 
             // draw first so we see the latest state immediately
             terminal.draw(|f| view::render_ui(&mut model, f))?;
+            let toast_expires_at = model.toast.as_ref().map(|toast| toast.expires_at());
             let mut maybe_msg = select! {
                 // key event from Crossterm
                 maybe_evt = event_reader.next() => {
@@ -65,7 +68,13 @@ impl App {
                 }
                 // service response
                 maybe_resp = self.resp_rx.recv() => {
-                    maybe_resp.map(Message::ServiceResp)
+                    maybe_resp.map(|resp| Message::ServiceResp(Box::new(resp)))
+                }
+                // hide toast once expired
+                _ = tokio::time::sleep_until(
+                    toast_expires_at.unwrap_or_else(Instant::now).into()
+                ), if toast_expires_at.is_some() => {
+                    Some(Message::ToastExpired)
                 }
             };
 
@@ -114,7 +123,11 @@ fn handle_crossterm_event(
         Some(Ok(Event::Mouse(evt)))
             if matches!(
                 evt.kind,
-                MouseEventKind::Down(_) | MouseEventKind::ScrollUp | MouseEventKind::ScrollDown
+                MouseEventKind::Down(_)
+                    | MouseEventKind::Drag(_)
+                    | MouseEventKind::Up(_)
+                    | MouseEventKind::ScrollUp
+                    | MouseEventKind::ScrollDown
             ) =>
         {
             Ok(Some(Message::MouseEvent(evt)))
@@ -134,7 +147,7 @@ pub enum Message {
     CrosstermClose,
     MouseEvent(MouseEvent),
 
-    ServiceResp(ServiceResp),
+    ServiceResp(Box<ServiceResp>),
 
     /* ----- model wide activities ----- */
     /// Sends message.
@@ -143,14 +156,36 @@ pub enum Message {
     Editing,
     /// Opens setting manager or saves setting manager update and closes it.
     Setting,
+    /// Switches to the next theme.
+    CycleTheme,
+    /// Opens help listing key bindings of the open popup and focused widget or closes it.
+    Help,
     /// Starts new empty chat at tui.
     NewSession,
-    /// Deletes currently selected session and navigates to the next session.
-    DeleteSession,
+    /// Moves marked or selected sessions to trash and navigates to the next session if the current
+    /// one is deleted, permanently deletes them if trash is shown.
+    DeleteSessions,
+    /// Restores marked or selected sessions if trash is shown, otherwise restores the sessions just
+    /// deleted while their toast is shown.
+    RestoreSessions,
+    /// Hides toast if it has expired.
+    ToastExpired,
+    /// Saves title edited for selected session in session manager.
+    RenameSession,
+    /// Pins or unpins selected session in session manager.
+    PinSession,
+    /// Archives or unarchives selected session in session manager.
+    ArchiveSession,
+    /// Opens tag editor for selected session in session manager or saves its tags and closes it.
+    Tags,
     /// Selects next session in session manager.
     SelectNextSession,
+    /// Loads next page of sessions if the selection is near the end of loaded ones.
+    LoadMoreSessions,
     /// Selects previews session in session manager.
     SelectPrevSession,
+    /// Cancels background response of current session.
+    CancelResponse,
 
     /* ----- editor activities ----- */
     /// Pastes event from crossterm.
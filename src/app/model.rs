@@ -1,18 +1,38 @@
 pub mod editor;
 pub mod focus;
+pub mod help;
 pub mod messages;
+pub mod search;
 pub mod session;
 pub mod session_manager;
 pub mod setting_manager;
+pub mod tag_editor;
+pub mod toast;
+pub mod vim;
+
+use color_eyre::{Result, eyre::bail};
+use crossterm::event::{KeyCode, KeyEvent};
 
 use crate::{
-    app::model::{
-        focus::{Focusable, Focused},
-        session::Session,
-        session_manager::SessionManager,
-        setting_manager::SettingManager,
+    app::{
+        model::{
+            editor::Editor,
+            focus::{Focusable, Focused},
+            help::Help,
+            session::Session,
+            session_manager::SessionManager,
+            setting_manager::SettingManager,
+            tag_editor::TagEditor,
+            toast::Toast,
+            vim::Vim,
+        },
+        view::theme::{self, Theme},
+    },
+    models::{
+        configs::Config,
+        keymap::{Context, KeyChord, KeyMatch},
+        settings::ProviderStatus,
     },
-    models::configs::Config,
 };
 
 pub struct Model {
@@ -26,12 +46,27 @@ pub struct Model {
     pub selected_session_id: Option<String>,
 
     pub setting_manager_popup: Option<SettingManager>,
+    pub tag_editor_popup: Option<TagEditor>,
+    /// Key bindings shown on top of other popups.
+    pub help_popup: Option<Help>,
+    /// Vim emulation of the input editor if enabled.
+    pub vim: Option<Vim>,
+    /// Themes to cycle through and index of the current one.
+    themes: Vec<Theme>,
+    theme_idx: usize,
+    /// Availability of llm providers.
+    pub providers: Vec<ProviderStatus>,
+
+    /// Notice shown for a few seconds, e.g. to undo deleting sessions.
+    pub toast: Option<Toast>,
 
     /// Irrecoverable failure message.
     pub error_message: Option<String>,
 
     pub show_sidebar: bool,
     pub focused: Focused,
+    /// Keys pressed so far of a key sequence, e.g. `g` of `g g`.
+    pending_keys: Vec<KeyChord>,
     focus_order: Vec<fn(&mut Model) -> &mut dyn Focusable>,
     pub should_quit: bool,
 }
@@ -40,6 +75,7 @@ impl Model {
     pub fn new(configs: Config) -> Self {
         // FIXME: fix config usage
         let default_llm_settings = configs.derive_llm_settings();
+        let vim = configs.editor.vim.then(Vim::default);
 
         let mut this = Self {
             configs,
@@ -47,10 +83,18 @@ impl Model {
             session_manager: SessionManager::default(),
             selected_session_id: None,
             setting_manager_popup: None,
+            tag_editor_popup: None,
+            help_popup: None,
+            vim,
+            themes: Vec::new(),
+            theme_idx: 0,
+            providers: Vec::new(),
+            toast: None,
             error_message: None,
             show_sidebar: false,
             should_quit: false,
             focused: Focused::InputEditor,
+            pending_keys: Vec::new(),
             focus_order: Vec::new(),
         };
 
@@ -81,6 +125,124 @@ impl Model {
         self.show_sidebar = !self.show_sidebar;
     }
 
+    /// Sets themes to cycle through and applies the configured one.
+    pub fn set_themes(&mut self, themes: Vec<Theme>) -> Result<()> {
+        let name = &self.configs.theme.name;
+        let Some(theme_idx) = themes.iter().position(|t| &t.name == name) else {
+            bail!("unknown theme `{name}`");
+        };
+        theme::set_current(themes[theme_idx].clone());
+        self.themes = themes;
+        self.theme_idx = theme_idx;
+        Ok(())
+    }
+
+    /// Applies the next theme and returns its name.
+    pub fn cycle_theme(&mut self) -> Option<&str> {
+        if self.themes.is_empty() {
+            return None;
+        }
+        self.theme_idx = (self.theme_idx + 1) % self.themes.len();
+        let theme = &self.themes[self.theme_idx];
+        theme::set_current(theme.clone());
+        // markdown styles are baked into lines
+        self.session.messages.rebuild_lines();
+        Some(&theme.name)
+    }
+
+    /// Returns key binding context of the open popup or focused widget.
+    pub fn key_context(&self) -> Context {
+        if let Some(help) = &self.help_popup {
+            return if help.is_searching() {
+                Context::Prompt
+            } else {
+                Context::Help
+            };
+        }
+        self.popup_context()
+            .unwrap_or_else(|| self.focused_context())
+    }
+
+    /// Returns key binding contexts listed in help, i.e. of the open popup and focused widget.
+    pub fn help_contexts(&self) -> Vec<Context> {
+        self.popup_context()
+            .into_iter()
+            .chain([self.focused_context()])
+            .collect()
+    }
+
+    fn popup_context(&self) -> Option<Context> {
+        if self.setting_manager_popup.is_some() {
+            return Some(Context::SettingManager);
+        }
+        if self.tag_editor_popup.is_some() {
+            return Some(Context::Prompt);
+        }
+        None
+    }
+
+    fn focused_context(&self) -> Context {
+        match self.focused {
+            Focused::InputEditor if self.session.input_editor.is_editing() => {
+                Context::InputEditorEditing
+            }
+            Focused::InputEditor => Context::InputEditor,
+            Focused::Messages if self.session.messages.viewport.is_search_editing() => {
+                Context::Prompt
+            }
+            Focused::Messages => Context::Messages,
+            Focused::SessionManager
+                if self.session_manager.rename_input().is_some()
+                    || self.session_manager.is_filter_editing() =>
+            {
+                Context::Prompt
+            }
+            Focused::SessionManager => Context::SessionManager,
+        }
+    }
+
+    /// Returns whether the key is typed into a text field rather than looked up in key bindings.
+    fn is_text_input(&self, evt: KeyEvent) -> bool {
+        let is_text_key = KeyChord::from(evt).char().is_some() || evt.code == KeyCode::Backspace;
+        let is_text_field = match (self.key_context(), &self.setting_manager_popup) {
+            (Context::Prompt, _) => true,
+            (Context::SettingManager, Some(setting_manager)) => {
+                setting_manager.selected_field().is_text()
+            }
+            _ => false,
+        };
+        is_text_key && is_text_field
+    }
+
+    /// Looks up the key pressed after pending keys in the current context, starting over from the
+    /// key if they don't form a bound sequence. Keys typed into text fields are unbound.
+    pub fn resolve_key(&mut self, evt: KeyEvent) -> KeyMatch {
+        if self.is_text_input(evt) {
+            self.pending_keys.clear();
+            return KeyMatch::Unbound;
+        }
+        let context = self.key_context();
+        self.pending_keys.push(evt.into());
+        let mut key_match = self.configs.keys.resolve(context, &self.pending_keys);
+        if key_match == KeyMatch::Unbound && self.pending_keys.len() > 1 {
+            self.pending_keys.drain(..self.pending_keys.len() - 1);
+            key_match = self.configs.keys.resolve(context, &self.pending_keys);
+        }
+        if key_match != KeyMatch::Pending {
+            self.pending_keys.clear();
+        }
+        key_match
+    }
+
+    /// Returns vim and the input editor if keys go to vim first, i.e. vim is enabled and the input
+    /// editor is focused without typing, popups or pending key sequences.
+    pub fn vim_mut(&mut self) -> Option<(&mut Vim, &mut Editor)> {
+        if self.key_context() != Context::InputEditor || !self.pending_keys.is_empty() {
+            return None;
+        }
+        Some((self.vim.as_mut()?, &mut self.session.input_editor))
+    }
+
     /// Opens an new empty chat and enables editing.
     pub fn new_draft_chat(&mut self) {
         self.session.reset(self.configs.derive_llm_settings());
@@ -100,6 +262,14 @@ impl Model {
         None
     }
 
+    /// Updates selected session to the session at `row` of the session manager list and returns
+    /// the updated selected session id.
+    pub fn handle_select_session_at(&mut self, row: usize) -> Option<String> {
+        let selected_session_id = self.session_manager.select_at(row)?;
+        self.selected_session_id = Some(selected_session_id.clone());
+        Some(selected_session_id)
+    }
+
     /// Updates selected session to the previous session of current selection in session manager
     /// and returns the updated selected session id.
     pub fn handle_select_prev_session(&mut self) -> Option<String> {
@@ -132,7 +302,9 @@ mod tests {
             provider: Some(crate::llm::llm_settings::Provider::OpenAi(OpenAiSettings {
                 model: OpenAiModel::Gpt4o as i32,
                 web_search: false,
+                ..Default::default()
             })),
+            ..Default::default()
         };
         let session_id = Uuid::new_v4().to_string();
         let title = "Awesome chat".to_string();
@@ -141,28 +313,31 @@ mod tests {
         let payload = chat_event::Payload::Message(Message {
             role: Role::User as i32,
             msg: "history question".to_string(),
+            response_ref: None,
         });
         messages.handle_chat_event_stream(ChatEvent::new(
             session_id.clone(),
-            Some(llm_settings),
+            Some(llm_settings.clone()),
             payload,
         ));
         let payload = chat_event::Payload::Message(Message {
             role: Role::Assistant as i32,
             msg: "history response".to_string(),
+            response_ref: None,
         });
         messages.handle_chat_event_stream(ChatEvent::new(
             session_id.clone(),
-            Some(llm_settings),
+            Some(llm_settings.clone()),
             payload,
         ));
         let payload = chat_event::Payload::Message(Message {
             role: Role::User as i32,
             msg: "pending question".to_string(),
+            response_ref: None,
         });
         messages.handle_chat_event_stream(ChatEvent::new(
             session_id.clone(),
-            Some(llm_settings),
+            Some(llm_settings.clone()),
             payload,
         ));
         messages.scroll_down();
@@ -185,6 +360,7 @@ mod tests {
                 llm_settings: None,
                 updated_at: Some(prost_types::Timestamp::from(SystemTime::now())),
                 created_at: None,
+                ..Default::default()
             }],
             model.selected_session_id.clone(),
         );
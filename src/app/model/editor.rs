@@ -1,11 +1,32 @@
+mod history;
+
+use std::ops::Range;
+
 use crate::{
     app::{
-        model::focus::{Focusable, Focused},
-        view::editor_viewport::EditorViewport,
+        model::{
+            editor::history::{Edit, History},
+            focus::{Focusable, Focused},
+        },
+        view::{editor_viewport::EditorViewport, utils::motion},
     },
     impl_focusable,
 };
 
+/// Maximum number of killed texts kept for pasting.
+const KILL_RING_SIZE: usize = 30;
+
+/// Kill or paste right before, which the next one chains to.
+#[derive(Default, Clone)]
+enum LastKill {
+    #[default]
+    None,
+    /// Consecutive kills accumulate into one killed text.
+    Kill,
+    /// Pasted kill ring entry at index and its char range, replaced on cycling.
+    Paste(usize, Range<usize>),
+}
+
 #[derive(Debug, Default, Clone, Copy)]
 pub enum WrapMode {
     /// Vim-style: fill to the column limit, then break.
@@ -24,6 +45,12 @@ pub struct Editor {
     pub cursor_char_idx: usize,
     focused: bool,
     is_editing: bool,
+    /// Char idx where selection started, the other end is the cursor.
+    selection_anchor: Option<usize>,
+    history: History,
+    /// Killed texts, latest last.
+    kill_ring: Vec<String>,
+    last_kill: LastKill,
 
     pub viewport: EditorViewport,
 }
@@ -64,13 +91,21 @@ impl Editor {
     /// Since each character in a string can be contain multiple bytes, it's necessary to calculate
     /// the byte idx based on the idx of the character.
     fn cursor_byte_idx(&self) -> usize {
+        self.byte_idx(self.cursor_char_idx)
+    }
+
+    fn byte_idx(&self, char_idx: usize) -> usize {
         self.input
             .char_indices()
             .map(|(i, _)| i)
-            .nth(self.cursor_char_idx)
+            .nth(char_idx)
             .unwrap_or(self.input.len())
     }
 
+    fn char_idx(&self, byte_idx: usize) -> usize {
+        self.input[..byte_idx].chars().count()
+    }
+
     pub fn set_viewport_width(&mut self, viewport_width: usize) {
         self.viewport
             .set_viewport_width(viewport_width, &self.input, self.cursor_byte_idx())
@@ -80,50 +115,48 @@ impl Editor {
     // Input change.
     // ----------------------------------------------------------------
 
-    // TODO: don't reflow with full input on editing.
+    /// Replaces selection or inserts at cursor.
     pub fn enter_char(&mut self, new_char: char) {
-        let idx = self.cursor_byte_idx();
-        self.input.insert(idx, new_char);
-        self.viewport.reflow(&self.input);
-
-        self.move_cursor_right();
+        let range = self
+            .selection()
+            .unwrap_or(self.cursor_char_idx..self.cursor_char_idx);
+        self.edit(range, &new_char.to_string(), !new_char.is_whitespace());
     }
 
+    /// Deletes selection or the char before cursor.
     pub fn delete_char(&mut self) {
-        if self.cursor_char_idx != 0 {
-            // not using `remove` since it works on bytes instead of the chars
-            let current_idx = self.cursor_char_idx;
-            let from_left_to_current_idx = current_idx - 1;
-
-            let before_char_to_delete = self.input.chars().take(from_left_to_current_idx);
-            let after_char_to_delete = self.input.chars().skip(current_idx);
-
-            // put all characters together except the selected one
-            self.input = before_char_to_delete.chain(after_char_to_delete).collect();
-            self.viewport.reflow(&self.input);
+        if let Some(selection) = self.selection() {
+            self.edit(selection, "", false);
+        } else if self.cursor_char_idx != 0 {
+            self.edit(self.cursor_char_idx - 1..self.cursor_char_idx, "", false);
+        }
+    }
 
-            self.move_cursor_left();
+    /// Deletes selection or the char under cursor.
+    pub fn delete_char_forward(&mut self) {
+        if let Some(selection) = self.selection() {
+            self.edit(selection, "", false);
+        } else if self.cursor_char_idx < self.input.chars().count() {
+            self.edit(self.cursor_char_idx..self.cursor_char_idx + 1, "", false);
         }
     }
 
     pub fn paste_data(&mut self, data: &str) {
-        let idx = self.cursor_byte_idx();
         // FIXME: ratatui cannot handle \t, replace with 2 spaces for now but we should add guard
         // for other invisible charactors.
         let data = data.replace("\t", "  ");
-        self.input.insert_str(idx, &data);
-        self.viewport.reflow(&self.input);
-
-        self.move_cursor_right_by(data.chars().count());
+        let range = self
+            .selection()
+            .unwrap_or(self.cursor_char_idx..self.cursor_char_idx);
+        self.edit(range, &data, false);
     }
 
-    /// Clears input.
+    /// Clears input and its history.
     pub fn clear(&mut self) {
         self.input = String::new();
         self.viewport.reflow(&self.input);
-
-        self.cursor_char_idx = 0;
-        self.viewport.update_cursor_position(self.cursor_byte_idx());
+        self.history = History::default();
+        self.clamp_and_update_cursor_position(0);
     }
 
     /// Updates input with editor editting result
@@ -133,9 +166,181 @@ impl Editor {
         // remove trailing newline
         data.pop();
 
-        self.input = data;
+        let cursor_char_idx = self.cursor_char_idx;
+        self.edit(0..self.input.chars().count(), &data, false);
+        self.clamp_and_update_cursor_position(cursor_char_idx);
+    }
+
+    /// Replaces text in char `range` with `text` as one undoable edit and moves cursor after it.
+    pub fn replace(&mut self, range: Range<usize>, text: &str) {
+        self.edit(range, text, false);
+    }
+
+    /// Replaces text in char `range` with `text` as an undoable edit and moves cursor after it.
+    /// Typing merges into the previous edit if it is typing too.
+    // TODO: don't reflow with full input on editing.
+    fn edit(&mut self, range: Range<usize>, text: &str, is_typing: bool) {
+        let edit = Edit {
+            char_idx: range.start,
+            deleted: self
+                .input
+                .chars()
+                .skip(range.start)
+                .take(range.len())
+                .collect(),
+            inserted: text.to_string(),
+            cursor_before: self.cursor_char_idx,
+            cursor_after: range.start + text.chars().count(),
+        };
+        self.apply(&edit);
+        self.history.push(edit, is_typing);
+    }
+
+    fn apply(&mut self, edit: &Edit) {
+        let range = edit.deleted_range();
+        let byte_range = self.byte_idx(range.start)..self.byte_idx(range.end);
+        self.input.replace_range(byte_range, &edit.inserted);
         self.viewport.reflow(&self.input);
-        self.clamp_and_update_cursor_position(self.cursor_char_idx);
+        self.clamp_and_update_cursor_position(edit.cursor_after);
+    }
+
+    pub fn can_undo(&self) -> bool {
+        self.history.can_undo()
+    }
+
+    pub fn undo(&mut self) {
+        if let Some(edit) = self.history.undo() {
+            self.apply(&edit);
+        }
+    }
+
+    pub fn redo(&mut self) {
+        if let Some(edit) = self.history.redo() {
+            self.apply(&edit);
+        }
+    }
+
+    // ----------------------------------------------------------------
+    // Kill and paste.
+    // ----------------------------------------------------------------
+
+    /// Kills text in char `range` into the kill ring, appending to the last killed text if
+    /// killing right after another kill, or prepending if `backward`.
+    fn kill(&mut self, range: Range<usize>, backward: bool) {
+        if range.is_empty() {
+            return;
+        }
+        let killed: String = self
+            .input
+            .chars()
+            .skip(range.start)
+            .take(range.len())
+            .collect();
+        match (&self.last_kill, self.kill_ring.last_mut()) {
+            (LastKill::Kill, Some(last)) if backward => last.insert_str(0, &killed),
+            (LastKill::Kill, Some(last)) => last.push_str(&killed),
+            _ => {
+                self.kill_ring.push(killed);
+                if self.kill_ring.len() > KILL_RING_SIZE {
+                    self.kill_ring.remove(0);
+                }
+            }
+        }
+        self.edit(range, "", false);
+        self.last_kill = LastKill::Kill;
+    }
+
+    /// Kills from cursor to the end of line, or the line break if already there.
+    pub fn kill_to_line_end(&mut self) {
+        let end = self.line_end();
+        let end = if end == self.cursor_char_idx && end < self.input.chars().count() {
+            end + 1
+        } else {
+            end
+        };
+        self.kill(self.cursor_char_idx..end, false);
+    }
+
+    /// Kills from the start of line to cursor.
+    pub fn kill_to_line_start(&mut self) {
+        self.kill(self.line_start()..self.cursor_char_idx, true);
+    }
+
+    /// Kills selection, or the word before cursor.
+    pub fn kill_word_backward(&mut self) {
+        if let Some(selection) = self.selection() {
+            self.kill(selection, false);
+            return;
+        }
+        let start = motion::previous_word_bound(&self.input, self.cursor_byte_idx());
+        self.kill(self.char_idx(start)..self.cursor_char_idx, true);
+    }
+
+    /// Kills from cursor to the end of the word.
+    pub fn kill_word_forward(&mut self) {
+        let end = motion::next_word_bound(&self.input, self.cursor_byte_idx());
+        self.kill(self.cursor_char_idx..self.char_idx(end), false);
+    }
+
+    /// Pastes the last killed text, replacing selection.
+    pub fn paste_killed(&mut self) {
+        let Some(killed) = self.kill_ring.last().cloned() else {
+            return;
+        };
+        let range = self
+            .selection()
+            .unwrap_or(self.cursor_char_idx..self.cursor_char_idx);
+        let start = range.start;
+        self.edit(range, &killed, false);
+        self.last_kill = LastKill::Paste(
+            self.kill_ring.len() - 1,
+            start..start + killed.chars().count(),
+        );
+    }
+
+    /// Replaces text pasted right before with the killed text before it in the kill ring.
+    pub fn cycle_killed(&mut self) {
+        let LastKill::Paste(idx, range) = self.last_kill.clone() else {
+            return;
+        };
+        let idx = idx.checked_sub(1).unwrap_or(self.kill_ring.len() - 1);
+        let killed = self.kill_ring[idx].clone();
+        let start = range.start;
+        self.edit(range, &killed, false);
+        self.last_kill = LastKill::Paste(idx, start..start + killed.chars().count());
+    }
+
+    // ----------------------------------------------------------------
+    // Selection.
+    // ----------------------------------------------------------------
+
+    /// Returns selected char range if not empty.
+    pub fn selection(&self) -> Option<Range<usize>> {
+        let anchor = self.selection_anchor?;
+        let range = anchor.min(self.cursor_char_idx)..anchor.max(self.cursor_char_idx);
+        (!range.is_empty()).then_some(range)
+    }
+
+    /// Returns selected byte range if not empty.
+    pub fn selection_byte_range(&self) -> Option<Range<usize>> {
+        self.selection()
+            .map(|range| self.byte_idx(range.start)..self.byte_idx(range.end))
+    }
+
+    /// Returns char idx where selection started, even if nothing is selected yet.
+    pub fn selection_anchor(&self) -> Option<usize> {
+        self.selection_anchor
+    }
+
+    pub fn set_selection_anchor(&mut self, selection_anchor: Option<usize>) {
+        self.selection_anchor = selection_anchor;
+    }
+
+    /// Moves cursor with `movement`, extending selection from where the cursor was.
+    pub fn select_with(&mut self, movement: impl FnOnce(&mut Self)) {
+        let anchor = self.selection_anchor.unwrap_or(self.cursor_char_idx);
+        movement(self);
+        self.selection_anchor = Some(anchor);
     }
 
     // ----------------------------------------------------------------
@@ -160,25 +365,90 @@ impl Editor {
         self.clamp_and_update_cursor_position(target_cursor_char_idx);
     }
 
+    /// Moves cursor to the char at `position` in content, or the nearest one.
+    pub fn move_cursor_to(&mut self, position: (u16 /*x*/, u16 /*y*/)) {
+        let target_cursor_byte_idx = self.viewport.find_cursor_byte_idx(position);
+        let target_cursor_char_idx = self.input[..target_cursor_byte_idx].chars().count();
+        self.clamp_and_update_cursor_position(target_cursor_char_idx);
+    }
+
+    /// Moves cursor to the char at `position` in the visible area.
+    pub fn click(&mut self, (x, y): (u16, u16)) {
+        let offset = self.viewport.scroll_state().vertical_scroll_offset as u16;
+        self.move_cursor_to((x, y + offset));
+    }
+
+    /// Scrolls by `delta` lines, moving cursor along if it would go out of view.
+    pub fn scroll_by(&mut self, delta: isize) {
+        let line_count = self.viewport.line_count();
+        let height = self.viewport.area().height as usize;
+        let scroll_state = self.viewport.scroll_state();
+        scroll_state.scroll_by(delta, line_count);
+        if let Some(position) = scroll_state.cursor_position_in_view(height) {
+            self.move_cursor_to(position);
+        }
+    }
+
+    pub fn move_cursor_to_char_idx(&mut self, char_idx: usize) {
+        self.clamp_and_update_cursor_position(char_idx);
+    }
+
     pub fn move_cursor_left(&mut self) {
         let target_cursor_char_idx = self.cursor_char_idx.saturating_sub(1);
         self.clamp_and_update_cursor_position(target_cursor_char_idx);
     }
 
     pub fn move_cursor_right(&mut self) {
-        self.move_cursor_right_by(1)
+        let target_cursor_char_idx = self.cursor_char_idx.saturating_add(1);
+        self.clamp_and_update_cursor_position(target_cursor_char_idx);
     }
 
-    fn move_cursor_right_by(&mut self, offset: usize) {
-        let target_cursor_char_idx = self.cursor_char_idx.saturating_add(offset);
-        self.cursor_char_idx = target_cursor_char_idx.clamp(0, self.input.chars().count());
-        self.clamp_and_update_cursor_position(target_cursor_char_idx);
+    /// Moves cursor to the end of the word.
+    pub fn move_cursor_to_next_word(&mut self) {
+        let target_cursor_byte_idx = motion::next_word_bound(&self.input, self.cursor_byte_idx());
+        self.clamp_and_update_cursor_position(self.char_idx(target_cursor_byte_idx));
+    }
+
+    /// Moves cursor to the start of the previous word.
+    pub fn move_cursor_to_previous_word(&mut self) {
+        let target_cursor_byte_idx =
+            motion::previous_word_bound(&self.input, self.cursor_byte_idx());
+        self.clamp_and_update_cursor_position(self.char_idx(target_cursor_byte_idx));
+    }
+
+    pub fn move_cursor_to_line_start(&mut self) {
+        self.clamp_and_update_cursor_position(self.line_start());
+    }
+
+    pub fn move_cursor_to_line_end(&mut self) {
+        self.clamp_and_update_cursor_position(self.line_end());
     }
 
-    /// Updates cursor position to clamped target cursor position.
+    /// Returns char idx of the start of the logical line.
+    fn line_start(&self) -> usize {
+        let cursor_byte_idx = self.cursor_byte_idx();
+        let start = self.input[..cursor_byte_idx]
+            .rfind('\n')
+            .map_or(0, |i| i + 1);
+        self.char_idx(start)
+    }
+
+    /// Returns char idx of the end of the logical line, before the line break.
+    fn line_end(&self) -> usize {
+        let cursor_byte_idx = self.cursor_byte_idx();
+        let end = self.input[cursor_byte_idx..]
+            .find('\n')
+            .map_or(self.input.len(), |i| cursor_byte_idx + i);
+        self.char_idx(end)
+    }
+
+    /// Updates cursor position to clamped target cursor position, clearing selection and
+    /// breaking kill chains.
     fn clamp_and_update_cursor_position(&mut self, target_cursor_char_idx: usize) {
         self.cursor_char_idx = target_cursor_char_idx.clamp(0, self.input.chars().count());
         self.viewport.update_cursor_position(self.cursor_byte_idx());
+        self.selection_anchor = None;
+        self.last_kill = LastKill::None;
     }
 }
 
@@ -274,4 +544,176 @@ mod tests {
             );
         }
     }
+
+    fn editor(input: &str, char_idx: usize) -> Editor {
+        let mut editor = Editor::new(input.to_string(), WrapMode::Character);
+        editor.set_viewport_width(20);
+        editor.clamp_and_update_cursor_position(char_idx);
+        editor
+    }
+
+    #[test]
+    fn undo_redo() {
+        let mut editor = editor("", 0);
+        "hello world".chars().for_each(|c| editor.enter_char(c));
+        editor.delete_char();
+        assert_eq!(editor.input(), "hello worl");
+
+        // typing merges into a word at a time
+        let mut inputs = Vec::new();
+        for _ in 0..4 {
+            editor.undo();
+            inputs.push(editor.input().to_string());
+        }
+        assert_eq!(inputs, vec!["hello world", "hello ", "hello", ""]);
+
+        editor.redo();
+        editor.redo();
+        assert_eq!(editor.input(), "hello ");
+        assert_eq!(editor.cursor_char_idx, 6);
+
+        // new edit drops redoable ones
+        editor.enter_char('x');
+        editor.redo();
+        assert_eq!(editor.input(), "hello x");
+    }
+
+    #[test]
+    fn edit_with_keys() {
+        struct Case {
+            description: &'static str,
+            input: &'static str,
+            char_idx: usize,
+            edit: fn(&mut Editor),
+            expected_input: &'static str,
+            expected_char_idx: usize,
+        }
+        let cases = vec![
+            Case {
+                description: "next word",
+                input: "hello, wörld",
+                char_idx: 0,
+                edit: Editor::move_cursor_to_next_word,
+                expected_input: "hello, wörld",
+                expected_char_idx: 5,
+            },
+            Case {
+                description: "previous word",
+                input: "hello, wörld",
+                char_idx: 12,
+                edit: Editor::move_cursor_to_previous_word,
+                expected_input: "hello, wörld",
+                expected_char_idx: 7,
+            },
+            Case {
+                description: "line start",
+                input: "ab\ncd",
+                char_idx: 4,
+                edit: Editor::move_cursor_to_line_start,
+                expected_input: "ab\ncd",
+                expected_char_idx: 3,
+            },
+            Case {
+                description: "line end",
+                input: "ab\ncd",
+                char_idx: 0,
+                edit: Editor::move_cursor_to_line_end,
+                expected_input: "ab\ncd",
+                expected_char_idx: 2,
+            },
+            Case {
+                description: "delete forward",
+                input: "芋泥",
+                char_idx: 0,
+                edit: Editor::delete_char_forward,
+                expected_input: "泥",
+                expected_char_idx: 0,
+            },
+            Case {
+                description: "kill to line end",
+                input: "ab\ncd",
+                char_idx: 1,
+                edit: Editor::kill_to_line_end,
+                expected_input: "a\ncd",
+                expected_char_idx: 1,
+            },
+            Case {
+                description: "kill line break at line end",
+                input: "ab\ncd",
+                char_idx: 2,
+                edit: Editor::kill_to_line_end,
+                expected_input: "abcd",
+                expected_char_idx: 2,
+            },
+            Case {
+                description: "kill to line start",
+                input: "ab\ncd",
+                char_idx: 4,
+                edit: Editor::kill_to_line_start,
+                expected_input: "ab\nd",
+                expected_char_idx: 3,
+            },
+            Case {
+                description: "kill word backward",
+                input: "hello wörld",
+                char_idx: 11,
+                edit: Editor::kill_word_backward,
+                expected_input: "hello ",
+                expected_char_idx: 6,
+            },
+            Case {
+                description: "kill word forward",
+                input: "hello wörld",
+                char_idx: 5,
+                edit: Editor::kill_word_forward,
+                expected_input: "hello",
+                expected_char_idx: 5,
+            },
+            Case {
+                description: "type over selection",
+                input: "hello",
+                char_idx: 0,
+                edit: |editor| {
+                    editor.select_with(Editor::move_cursor_right);
+                    editor.select_with(Editor::move_cursor_right);
+                    editor.enter_char('J');
+                },
+                expected_input: "Jllo",
+                expected_char_idx: 1,
+            },
+        ];
+        for case in cases {
+            let mut editor = editor(case.input, case.char_idx);
+            (case.edit)(&mut editor);
+            assert_eq!(editor.input(), case.expected_input, "{}", case.description);
+            assert_eq!(
+                editor.cursor_char_idx, case.expected_char_idx,
+                "{} cursor char idx",
+                case.description
+            );
+        }
+    }
+
+    #[test]
+    fn kill_ring() {
+        let mut editor = editor("one two three", 13);
+        // consecutive kills accumulate
+        editor.kill_word_backward();
+        editor.kill_word_backward();
+        assert_eq!(editor.input(), "one ");
+
+        editor.move_cursor_to_line_start();
+        editor.kill_to_line_end();
+        assert_eq!(editor.input(), "");
+
+        editor.paste_killed();
+        assert_eq!(editor.input(), "one ");
+        editor.cycle_killed();
+        assert_eq!(editor.input(), "two three");
+        editor.cycle_killed();
+        assert_eq!(editor.input(), "one ");
+
+        editor.undo();
+        assert_eq!(editor.input(), "two three");
+    }
 }
@@ -0,0 +1,86 @@
+use std::ops::Range;
+
+/// Maximum number of undoable edits.
+const MAX_EDITS: usize = 200;
+
+/// Replacement of `deleted` text at a char index with `inserted`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Edit {
+    pub char_idx: usize,
+    pub deleted: String,
+    pub inserted: String,
+    /// Cursor char idx before and after the edit.
+    pub cursor_before: usize,
+    pub cursor_after: usize,
+}
+
+impl Edit {
+    /// Returns char range of text replaced by the edit.
+    pub fn deleted_range(&self) -> Range<usize> {
+        self.char_idx..self.char_idx + self.deleted.chars().count()
+    }
+
+    /// Returns the edit reverting this one.
+    fn inverse(&self) -> Self {
+        Self {
+            char_idx: self.char_idx,
+            deleted: self.inserted.clone(),
+            inserted: self.deleted.clone(),
+            cursor_before: self.cursor_after,
+            cursor_after: self.cursor_before,
+        }
+    }
+}
+
+/// Undo and redo stacks of edits.
+#[derive(Default)]
+pub struct History {
+    undo: Vec<Edit>,
+    redo: Vec<Edit>,
+    /// Whether the last edit is typing that the next typed char can merge into.
+    is_typing: bool,
+}
+
+impl History {
+    /// Records an edit and drops redoable ones. Typing right after typing merges into one edit,
+    /// so that undo reverts a word at a time.
+    pub fn push(&mut self, edit: Edit, is_typing: bool) {
+        self.redo.clear();
+        let was_typing = std::mem::replace(&mut self.is_typing, is_typing);
+        if is_typing
+            && was_typing
+            && let Some(last) = self.undo.last_mut()
+            && edit.deleted.is_empty()
+            && last.char_idx + last.inserted.chars().count() == edit.char_idx
+        {
+            last.inserted.push_str(&edit.inserted);
+            last.cursor_after = edit.cursor_after;
+            return;
+        }
+        self.undo.push(edit);
+        if self.undo.len() > MAX_EDITS {
+            self.undo.remove(0);
+        }
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    /// Returns the edit reverting the last one, which becomes redoable.
+    pub fn undo(&mut self) -> Option<Edit> {
+        let edit = self.undo.pop()?;
+        let inverse = edit.inverse();
+        self.redo.push(edit);
+        self.is_typing = false;
+        Some(inverse)
+    }
+
+    /// Returns the last undone edit, which becomes undoable again.
+    pub fn redo(&mut self) -> Option<Edit> {
+        let edit = self.redo.pop()?;
+        self.undo.push(edit.clone());
+        self.is_typing = false;
+        Some(edit)
+    }
+}
@@ -0,0 +1,171 @@
+use crate::models::keymap::{Action, Context, Keymap};
+
+/// Key bindings of an action, e.g. `down / j` to move down.
+pub struct HelpEntry {
+    pub keys: String,
+    pub action: Action,
+}
+
+pub struct HelpSection {
+    pub title: &'static str,
+    pub entries: Vec<HelpEntry>,
+}
+
+/// Popup listing key bindings of the open popup and focused widget, filtered by a search query.
+pub struct Help {
+    sections: Vec<HelpSection>,
+    query: String,
+    is_searching: bool,
+    /// Index of the first visible line.
+    scroll: usize,
+}
+
+impl Help {
+    /// Lists bindings of `contexts` in order, grouping keys of the same action.
+    pub fn new(keymap: &Keymap, contexts: &[Context]) -> Self {
+        let sections = contexts
+            .iter()
+            .map(|context| {
+                let mut entries: Vec<HelpEntry> = Vec::new();
+                for binding in keymap.bindings(*context) {
+                    let keys = binding.keys.to_string();
+                    match entries.iter_mut().find(|e| e.action == binding.action) {
+                        Some(entry) => entry.keys = format!("{} / {keys}", entry.keys),
+                        None => entries.push(HelpEntry {
+                            keys,
+                            action: binding.action,
+                        }),
+                    }
+                }
+                HelpSection {
+                    title: context.title(),
+                    entries,
+                }
+            })
+            .collect();
+        Self {
+            sections,
+            query: String::new(),
+            is_searching: false,
+            scroll: 0,
+        }
+    }
+
+    /// Returns sections with entries whose keys or description contain the query, ignoring case.
+    pub fn sections(&self) -> Vec<(&'static str, Vec<&HelpEntry>)> {
+        let query = self.query.to_lowercase();
+        self.sections
+            .iter()
+            .map(|section| {
+                let entries = section
+                    .entries
+                    .iter()
+                    .filter(|e| {
+                        e.keys.to_lowercase().contains(&query)
+                            || e.action.description().contains(&query)
+                    })
+                    .collect::<Vec<_>>();
+                (section.title, entries)
+            })
+            .filter(|(_, entries)| !entries.is_empty())
+            .collect()
+    }
+
+    pub fn query(&self) -> &str {
+        &self.query
+    }
+
+    pub fn is_searching(&self) -> bool {
+        self.is_searching
+    }
+
+    pub fn start_search(&mut self) {
+        self.is_searching = true;
+    }
+
+    /// Stops editing the query and keeps it.
+    pub fn stop_search(&mut self) {
+        self.is_searching = false;
+    }
+
+    pub fn clear_search(&mut self) {
+        self.query.clear();
+        self.is_searching = false;
+        self.scroll = 0;
+    }
+
+    pub fn push(&mut self, c: char) {
+        self.query.push(c);
+        self.scroll = 0;
+    }
+
+    pub fn backspace(&mut self) {
+        self.query.pop();
+        self.scroll = 0;
+    }
+
+    pub fn scroll(&self) -> usize {
+        self.scroll
+    }
+
+    /// Limits scroll so that the last line stays at the bottom, given the visible line count.
+    pub fn clamp_scroll(&mut self, line_count: usize, height: usize) {
+        self.scroll = self.scroll.min(line_count.saturating_sub(height));
+    }
+
+    pub fn scroll_down(&mut self) {
+        self.scroll = self.scroll.saturating_add(1);
+    }
+
+    pub fn scroll_up(&mut self) {
+        self.scroll = self.scroll.saturating_sub(1);
+    }
+
+    pub fn scroll_to_top(&mut self) {
+        self.scroll = 0;
+    }
+
+    /// Scrolls to the end, clamped on the next render.
+    pub fn scroll_to_bottom(&mut self) {
+        self.scroll = usize::MAX;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        app::model::help::Help,
+        models::keymap::{Action, Context, Keymap},
+    };
+
+    #[test]
+    fn search() {
+        let mut help = Help::new(
+            &Keymap::default(),
+            &[Context::SettingManager, Context::Messages],
+        );
+        let sections = help.sections();
+        assert_eq!(
+            sections.iter().map(|(title, _)| *title).collect::<Vec<_>>(),
+            vec!["Settings", "Messages"]
+        );
+        let move_down = sections[0]
+            .1
+            .iter()
+            .find(|e| e.action == Action::MoveDown)
+            .unwrap();
+        assert_eq!(move_down.keys, "down / j");
+
+        for c in "TOP".chars() {
+            help.push(c);
+        }
+        let sections = help.sections();
+        assert_eq!(sections.len(), 1);
+        assert_eq!(sections[0].0, "Messages");
+        assert_eq!(sections[0].1.len(), 1);
+        assert_eq!(sections[0].1[0].keys, "g g");
+
+        help.clear_search();
+        assert_eq!(help.sections().len(), 2);
+    }
+}
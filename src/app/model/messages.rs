@@ -13,6 +13,14 @@ pub struct Messages {
     is_pending: bool,
     focused: bool,
     title: Option<String>,
+    /// Latest notice that history is left out of llm requests.
+    context_trim: Option<ContextTrim>,
+    /// Progress of background response in progress.
+    pending_response: Option<PendingResponse>,
+    /// Cursor to load older events before, None if all are loaded.
+    events_cursor: Option<String>,
+    /// Whether older events are requested.
+    loading_events: bool,
     pub viewport: MessagesViewport,
 }
 
@@ -41,6 +49,29 @@ impl Messages {
         self.stream_message.as_ref()
     }
 
+    pub fn context_trim(&self) -> Option<&ContextTrim> {
+        self.context_trim.as_ref()
+    }
+
+    pub fn pending_response(&self) -> Option<&PendingResponse> {
+        self.pending_response.as_ref()
+    }
+
+    pub fn set_events_cursor(&mut self, events_cursor: Option<String>) {
+        self.events_cursor = events_cursor;
+    }
+
+    /// Marks older events requested if there are any not loaded and not already requested, and
+    /// returns the cursor to request them before.
+    pub fn load_older(&mut self) -> Option<String> {
+        if self.loading_events {
+            return None;
+        }
+        let cursor = self.events_cursor.clone()?;
+        self.loading_events = true;
+        Some(cursor)
+    }
+
     // ----------------------------------------------------------------
     // Scroll.
     // ----------------------------------------------------------------
@@ -70,11 +101,24 @@ impl Messages {
     pub fn handle_send(&mut self) {
         self.viewport.scroll_to_top();
         self.is_pending = true;
+        // service notifies again if history is still trimmed
+        self.context_trim = None;
+    }
+
+    /// Stops pending on failed chat, dropping the message streamed so far.
+    pub fn handle_failure(&mut self) {
+        self.is_pending = false;
+        self.stream_message = None;
+        self.viewport
+            .build_lines(self.chat_events.as_slice(), self.stream_message.as_ref());
     }
 
     /// Handles chat events streamed from service.
     pub fn handle_chat_event_stream(&mut self, chat_event: ChatEvent) {
-        if self.is_pending() {
+        // background responses may be resumed without sending user message
+        if let Some(chat_event::Payload::PendingResponse(pending_response)) = &chat_event.payload {
+            self.handle_pending_response(pending_response.clone());
+        } else if self.is_pending() {
             match &chat_event.payload {
                 Some(chat_event::Payload::Message(message)) if message.role() == Role::User => {
                     self.chat_events.push(chat_event);
@@ -87,6 +131,9 @@ impl Messages {
                     self.stream_message = None;
                     self.is_pending = false;
                 }
+                Some(chat_event::Payload::ContextTrim(context_trim)) => {
+                    self.context_trim = Some(context_trim.clone());
+                }
                 Some(chat_event::Payload::MessageDelta(message_delta)) => {
                     if let Some(stream_message) = &mut self.stream_message {
                         stream_message.delta.push_str(&message_delta.delta);
@@ -117,12 +164,70 @@ impl Messages {
             self.stream_message = Some(message_delta);
             self.is_pending = true;
         }
+        // pick up background response in progress.
+        if let Some(pending_response) = chat_events.iter().find_map(|event| match &event.payload {
+            Some(chat_event::Payload::PendingResponse(p)) => Some(p.clone()),
+            _ => None,
+        }) {
+            self.handle_pending_response(pending_response);
+        }
+        // pick up stored summary.
+        self.context_trim = chat_events
+            .iter()
+            .rev()
+            .find_map(|event| match &event.payload {
+                Some(chat_event::Payload::ContextTrim(context_trim)) => Some(context_trim.clone()),
+                _ => None,
+            });
         self.chat_events = chat_events.into_iter().collect();
         self.viewport
             .build_lines(self.chat_events.as_slice(), self.stream_message.as_ref());
     }
 }
 
+impl Messages {
+    /// Prepends `chat_events` older than loaded ones if they are the page requested, keeping the
+    /// cursor on the same message.
+    pub fn handle_older_events(
+        &mut self,
+        before: &str,
+        mut chat_events: Vec<ChatEvent>,
+        next_cursor: Option<String>,
+    ) {
+        if !self.loading_events || self.events_cursor.as_deref() != Some(before) {
+            return;
+        }
+        self.loading_events = false;
+        self.events_cursor = next_cursor;
+        chat_events.append(&mut self.chat_events);
+        self.chat_events = chat_events;
+        self.viewport.build_lines_keeping_position(
+            self.chat_events.as_slice(),
+            self.stream_message.as_ref(),
+        );
+    }
+
+    /// Rebuilds lines keeping the cursor, e.g. to restyle them after switching theme.
+    pub fn rebuild_lines(&mut self) {
+        self.viewport.build_lines_keeping_position(
+            self.chat_events.as_slice(),
+            self.stream_message.as_ref(),
+        );
+    }
+
+    /// Tracks progress of background response and stops pending once it is done without reply.
+    fn handle_pending_response(&mut self, pending_response: PendingResponse) {
+        if pending_response.is_done() {
+            self.pending_response = None;
+            self.stream_message = None;
+            self.is_pending = false;
+        } else {
+            self.pending_response = Some(pending_response);
+            self.is_pending = true;
+        }
+    }
+}
+
 impl Focusable for Messages {
     fn set_focus(&mut self, focused: bool) {
         // clear visual selection if navigating away.
@@ -0,0 +1,215 @@
+use std::ops::Range;
+
+use regex::RegexBuilder;
+
+/// Search of a query over text, case sensitive only if the query has uppercase letters.
+#[derive(Default)]
+pub struct Search {
+    query: String,
+    /// Whether query is a regex rather than plain text.
+    is_regex: bool,
+    is_editing: bool,
+    /// Byte index the search started from.
+    origin: usize,
+    /// Byte ranges of matches in order.
+    matches: Vec<Range<usize>>,
+    /// Why the query is not a valid regex.
+    error: Option<String>,
+}
+
+impl Search {
+    /// Starts editing an empty query from byte index `origin`.
+    pub fn new(origin: usize) -> Self {
+        Self {
+            is_editing: true,
+            origin,
+            ..Default::default()
+        }
+    }
+
+    pub fn query(&self) -> &str {
+        &self.query
+    }
+
+    pub fn is_regex(&self) -> bool {
+        self.is_regex
+    }
+
+    pub fn is_editing(&self) -> bool {
+        self.is_editing
+    }
+
+    pub fn origin(&self) -> usize {
+        self.origin
+    }
+
+    pub fn matches(&self) -> &[Range<usize>] {
+        &self.matches
+    }
+
+    /// Returns index of the match containing `byte_idx`.
+    pub fn current(&self, byte_idx: usize) -> Option<usize> {
+        self.matches.iter().position(|m| m.contains(&byte_idx))
+    }
+
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+
+    pub fn push(&mut self, c: char) {
+        self.query.push(c);
+    }
+
+    pub fn backspace(&mut self) {
+        self.query.pop();
+    }
+
+    pub fn toggle_regex(&mut self) {
+        self.is_regex = !self.is_regex;
+    }
+
+    pub fn stop_editing(&mut self) {
+        self.is_editing = false;
+    }
+
+    /// Finds matches of the query in `text`.
+    pub fn find(&mut self, text: &str) {
+        self.matches.clear();
+        self.error = None;
+        if self.query.is_empty() {
+            return;
+        }
+
+        let pattern = if self.is_regex {
+            self.query.clone()
+        } else {
+            regex::escape(&self.query)
+        };
+        let case_insensitive = !has_uppercase(&self.query, self.is_regex);
+        match RegexBuilder::new(&pattern)
+            .case_insensitive(case_insensitive)
+            .build()
+        {
+            Ok(regex) => {
+                self.matches = regex
+                    .find_iter(text)
+                    .map(|m| m.range())
+                    .filter(|range| !range.is_empty())
+                    .collect();
+            }
+            Err(err) => self.error = Some(err.to_string()),
+        }
+    }
+
+    /// Returns start of the first match at or after `byte_idx`, wrapping around.
+    pub fn first_from(&self, byte_idx: usize) -> Option<usize> {
+        self.matches
+            .iter()
+            .find(|m| m.start >= byte_idx)
+            .or(self.matches.first())
+            .map(|m| m.start)
+    }
+
+    /// Returns start of the first match after `byte_idx`, wrapping around.
+    pub fn next(&self, byte_idx: usize) -> Option<usize> {
+        self.first_from(byte_idx + 1)
+    }
+
+    /// Returns start of the last match before `byte_idx`, wrapping around.
+    pub fn previous(&self, byte_idx: usize) -> Option<usize> {
+        self.matches
+            .iter()
+            .rfind(|m| m.start < byte_idx)
+            .or(self.matches.last())
+            .map(|m| m.start)
+    }
+}
+
+/// Whether `query` has uppercase letters to match. Escapes of regex queries, e.g. `\W` or
+/// `\P{Greek}`, are not letters to match.
+fn has_uppercase(query: &str, is_regex: bool) -> bool {
+    if !is_regex {
+        return query.chars().any(char::is_uppercase);
+    }
+    let mut chars = query.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            if c.is_uppercase() {
+                return true;
+            }
+            continue;
+        }
+        match chars.next() {
+            // class name of `\pL` or `\p{..}`, hex digits of `\x41` or `\x{..}`
+            Some('p' | 'P') if chars.peek() != Some(&'{') => {
+                chars.next();
+            }
+            Some('x' | 'u' | 'U') => while chars.next_if(char::is_ascii_hexdigit).is_some() {},
+            _ => {}
+        }
+        if chars.next_if_eq(&'{').is_some() {
+            while chars.next().is_some_and(|c| c != '}') {}
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::app::model::search::Search;
+
+    fn search(query: &str, is_regex: bool, text: &str) -> Search {
+        let mut search = Search::new(0);
+        query.chars().for_each(|c| search.push(c));
+        if is_regex {
+            search.toggle_regex();
+        }
+        search.find(text);
+        search
+    }
+
+    #[test]
+    fn find() {
+        let text = "Rust is fun, rust is fast. a.b";
+        let cases = [
+            ("rust", false, vec![(0, 4), (13, 17)]),
+            // smart case
+            ("Rust", false, vec![(0, 4)]),
+            ("a.b", false, vec![(27, 30)]),
+            ("f[a-z]+", true, vec![(8, 11), (21, 25)]),
+            // regex escapes are not uppercase letters
+            ("\\Wrust", true, vec![(12, 17)]),
+            ("\\W*rust", true, vec![(0, 4), (11, 17)]),
+            ("\\P{Greek}ust", true, vec![(0, 4), (13, 17)]),
+            ("\\x52ust", true, vec![(0, 4), (13, 17)]),
+            ("\\W*Rust", true, vec![(0, 4)]),
+            ("", false, vec![]),
+        ];
+        for (query, is_regex, expected) in cases {
+            let matches: Vec<(usize, usize)> = search(query, is_regex, text)
+                .matches()
+                .iter()
+                .map(|m| (m.start, m.end))
+                .collect();
+            assert_eq!(matches, expected, "{query}");
+        }
+
+        let invalid = search("(", true, text);
+        assert!(invalid.error().is_some());
+        assert!(invalid.matches().is_empty());
+    }
+
+    #[test]
+    fn navigate() {
+        let search = search("ab", false, "ab ab ab");
+        assert_eq!(search.first_from(1), Some(3));
+        assert_eq!(search.next(3), Some(6));
+        assert_eq!(search.previous(6), Some(3));
+        // wraps around
+        assert_eq!(search.next(6), Some(0));
+        assert_eq!(search.previous(0), Some(6));
+
+        assert_eq!(search.current(4), Some(1));
+        assert_eq!(search.current(2), None);
+    }
+}
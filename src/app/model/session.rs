@@ -36,7 +36,7 @@ impl Session {
     }
 
     pub fn llm_settings(&self) -> LlmSettings {
-        self.llm_settings
+        self.llm_settings.clone()
     }
 
     pub fn set_llm_settings(&mut self, llm_settings: LlmSettings) {
@@ -82,8 +82,9 @@ impl Session {
         let payload = chat_event::Payload::Message(crate::chat::Message {
             role: Role::User as i32,
             msg: msg_,
+            response_ref: None,
         });
-        let user_message = ChatEvent::new(session_id, Some(self.llm_settings), payload);
+        let user_message = ChatEvent::new(session_id, Some(self.llm_settings.clone()), payload);
         self.messages.handle_send();
         self.input_editor.clear();
         Some(user_message)
@@ -99,6 +100,13 @@ impl Session {
         }
     }
 
+    /// Stops waiting for the reply if chat of current session failed.
+    pub fn handle_chat_failed(&mut self, session_id: &str) {
+        if self.session_id.as_deref() == Some(session_id) {
+            self.messages.handle_failure();
+        }
+    }
+
     /// Replaces current content with given session except for editor input.
     pub fn handle_session(&mut self, session: ChatSession) {
         self.session_id = Some(session.id);
@@ -106,9 +114,25 @@ impl Session {
 
         self.messages.reset();
         self.messages.set_title(Some(session.title));
+        self.messages
+            .set_events_cursor(Some(session.events_cursor).filter(|c| !c.is_empty()));
         self.messages.handle_chat_events(session.events);
     }
 
+    /// Prepends older events if they are for current session.
+    pub fn handle_events(
+        &mut self,
+        session_id: &str,
+        before: &str,
+        chat_events: Vec<ChatEvent>,
+        next_cursor: Option<String>,
+    ) {
+        if self.session_id.as_deref() == Some(session_id) {
+            self.messages
+                .handle_older_events(before, chat_events, next_cursor);
+        }
+    }
+
     /// Updates title if `session_summary` is for current session.
     pub fn handle_session_summary(&mut self, session_summary: ChatSession) {
         if let Some(session_id) = self.session_id.clone()
@@ -1,4 +1,5 @@
 use ratatui::widgets::ListState;
+use std::collections::HashSet;
 
 use crate::{
     app::{
@@ -11,11 +12,30 @@ use crate::{
 
 #[derive(Default)]
 pub struct SessionManager {
+    /// Listed sessions, pinned ones first.
     session_summaries: Vec<ChatSession>,
+    /// Loaded pages of sessions matching filter, archived ones only if shown.
+    all_session_summaries: Vec<ChatSession>,
+    /// Cursor of the next page, None if all pages are loaded.
+    next_cursor: Option<String>,
+    /// Cursor of the page requested and not yet received, Some(None) for the first page.
+    requested_cursor: Option<Option<String>>,
+    show_archived: bool,
+    /// Lists sessions in trash instead of the others.
+    show_trash: bool,
+    /// Sessions marked for bulk actions, e.g. delete.
+    marked: HashSet<String>,
+    /// Title being edited for the selected session.
+    rename_input: Option<String>,
+    /// Query of the filter bar, see `SessionQuery`.
+    filter: String,
+    filter_editing: bool,
     list_state: ListState,
     focused: bool,
     /// Area for mouse event handling.
     area: Area,
+    /// Area of the session list for mouse event handling.
+    list_area: Area,
 }
 
 impl_focusable!(SessionManager, Focused::SessionManager);
@@ -29,6 +49,158 @@ impl SessionManager {
         &mut self.list_state
     }
 
+    /// Returns the selected session in the list.
+    pub fn selected(&self) -> Option<&ChatSession> {
+        self.list_state
+            .selected()
+            .and_then(|i| self.session_summaries.get(i))
+    }
+
+    pub fn show_archived(&self) -> bool {
+        self.show_archived
+    }
+
+    /// Shows or hides archived sessions and marks the first page requested, sessions listed so far
+    /// are kept until it is received.
+    pub fn toggle_show_archived(&mut self) {
+        self.show_archived = !self.show_archived;
+        self.reload();
+    }
+
+    pub fn rename_input(&self) -> Option<&str> {
+        self.rename_input.as_deref()
+    }
+
+    /// Starts editing title of the selected session, returns whether there is one.
+    pub fn start_rename(&mut self) -> bool {
+        self.rename_input = self.selected().map(|s| s.title.clone());
+        self.rename_input.is_some()
+    }
+
+    pub fn rename_input_mut(&mut self) -> Option<&mut String> {
+        self.rename_input.as_mut()
+    }
+
+    /// Stops editing title and returns the selected session id with the edited title.
+    pub fn finish_rename(&mut self) -> Option<(String, String)> {
+        let title = self.rename_input.take()?;
+        self.selected()
+            .map(|s| (s.id.clone(), title.trim().to_string()))
+    }
+
+    pub fn cancel_rename(&mut self) {
+        self.rename_input = None;
+    }
+
+    pub fn filter(&self) -> &str {
+        &self.filter
+    }
+
+    pub fn is_filter_editing(&self) -> bool {
+        self.filter_editing
+    }
+
+    pub fn start_filter(&mut self) {
+        self.filter_editing = true;
+    }
+
+    /// Stops editing filter while keeping it.
+    pub fn stop_filter(&mut self) {
+        self.filter_editing = false;
+    }
+
+    /// Returns the query to request sessions for, empty for all sessions.
+    pub fn query(&self) -> &str {
+        self.filter.trim()
+    }
+
+    /// Edits filter with `f` and marks the first page of matching sessions requested.
+    pub fn edit_filter(&mut self, f: impl FnOnce(&mut String)) {
+        f(&mut self.filter);
+        self.reload();
+    }
+
+    /// Clears filter and marks the first page of all sessions requested.
+    pub fn clear_filter(&mut self) {
+        self.filter_editing = false;
+        self.edit_filter(String::clear);
+    }
+
+    /// Marks the first page requested.
+    pub fn reload(&mut self) {
+        self.requested_cursor = Some(None);
+    }
+
+    pub fn show_trash(&self) -> bool {
+        self.show_trash
+    }
+
+    /// Switches between sessions in trash and the others and marks the first page requested.
+    pub fn toggle_trash(&mut self) {
+        self.show_trash = !self.show_trash;
+        self.marked.clear();
+        self.all_session_summaries.clear();
+        self.refresh();
+        self.list_state.select(None);
+        self.reload();
+    }
+
+    pub fn is_marked(&self, session_id: &str) -> bool {
+        self.marked.contains(session_id)
+    }
+
+    /// Marks or unmarks the selected session.
+    pub fn toggle_marked(&mut self) {
+        if let Some(session_id) = self.selected().map(|s| s.id.clone())
+            && !self.marked.remove(&session_id)
+        {
+            self.marked.insert(session_id);
+        }
+    }
+
+    /// Returns ids of marked sessions in list order, or the selected one if none is marked, and
+    /// clears marks.
+    pub fn take_marked_or_selected(&mut self) -> Vec<String> {
+        let session_ids: Vec<String> = if self.marked.is_empty() {
+            self.selected().map(|s| s.id.clone()).into_iter().collect()
+        } else {
+            self.session_summaries
+                .iter()
+                .filter(|s| self.marked.contains(&s.id))
+                .map(|s| s.id.clone())
+                .collect()
+        };
+        self.marked.clear();
+        session_ids
+    }
+
+    /// Marks the next page requested if the selection is near the end of loaded pages and returns
+    /// the cursor to request it for.
+    pub fn load_more(&mut self) -> Option<String> {
+        const PREFETCH: usize = 5;
+        let selected = self.list_state.selected().unwrap_or_default();
+        if self.requested_cursor.is_some()
+            || selected + PREFETCH < self.session_summaries.len()
+            || self.next_cursor.is_none()
+        {
+            return None;
+        }
+        self.requested_cursor = Some(self.next_cursor.clone());
+        self.next_cursor.clone()
+    }
+
+    /// Returns tags of all sessions, sorted.
+    pub fn tags(&self) -> Vec<String> {
+        let mut tags: Vec<String> = self
+            .all_session_summaries
+            .iter()
+            .flat_map(|s| s.tags.iter().cloned())
+            .collect();
+        tags.sort();
+        tags.dedup();
+        tags
+    }
+
     pub fn area(&self) -> &Area {
         &self.area
     }
@@ -37,7 +209,16 @@ impl SessionManager {
         self.area = area;
     }
 
+    pub fn list_area(&self) -> &Area {
+        &self.list_area
+    }
+
+    pub fn set_list_area(&mut self, list_area: Area) {
+        self.list_area = list_area;
+    }
+
     pub fn select_next(&mut self) -> Option<String> {
+        self.rename_input = None;
         match self.list_state.selected() {
             Some(i) if i + 1 < self.session_summaries.len() => {
                 self.list_state.select_next();
@@ -52,7 +233,17 @@ impl SessionManager {
         }
     }
 
+    /// Selects session at `row` of the visible list and returns its id.
+    pub fn select_at(&mut self, row: usize) -> Option<String> {
+        let idx = self.list_state.offset() + row;
+        let session_id = self.session_summaries.get(idx)?.id.clone();
+        self.rename_input = None;
+        self.list_state.select(Some(idx));
+        Some(session_id)
+    }
+
     pub fn select_prev(&mut self) -> Option<String> {
+        self.rename_input = None;
         match self.list_state.selected() {
             Some(i) if i > 0 => {
                 self.list_state.select_previous();
@@ -80,26 +271,310 @@ impl SessionManager {
         session_summaries: Vec<ChatSession>,
         session_id: Option<String>,
     ) {
-        self.session_summaries = session_summaries;
-        self.session_summaries.sort_by(|a, b| {
-            b.updated_at
-                .as_ref()
-                .map(|t| (t.seconds, t.nanos))
-                .cmp(&a.updated_at.as_ref().map(|t| (t.seconds, t.nanos)))
-        });
-
+        self.all_session_summaries = session_summaries;
+        self.refresh();
         self.set_selected(session_id);
     }
 
-    /// Updates title of given session summary.
+    /// Updates title, pin and archive state of given session summary while keeping the selection.
     pub fn handle_session_summary(&mut self, session_summary: ChatSession) {
         tracing::debug!("updating session manager with {session_summary:?}");
         if let Some(current) = self
-            .session_summaries
+            .all_session_summaries
             .iter_mut()
             .find(|s| s.id == session_summary.id)
         {
             current.title = session_summary.title;
+            current.pinned = session_summary.pinned;
+            current.archived = session_summary.archived;
+            current.tags = session_summary.tags;
         }
+        // sessions archived meanwhile leave the list like pages leave them out
+        if !self.show_archived {
+            self.all_session_summaries.retain(|s| !s.archived);
+        }
+        self.refresh_keeping_selection();
+    }
+
+    /// Lists page of `sessions` after `cursor` if it is the page requested for the filter, the first
+    /// page replaces loaded ones.
+    pub fn handle_session_query(
+        &mut self,
+        query: &str,
+        cursor: Option<String>,
+        trash: bool,
+        include_archived: bool,
+        sessions: Vec<ChatSession>,
+        next_cursor: Option<String>,
+    ) {
+        if query != self.query()
+            || trash != self.show_trash
+            || include_archived != self.show_archived
+            || self.requested_cursor.as_ref() != Some(&cursor)
+        {
+            return;
+        }
+        self.requested_cursor = None;
+        self.next_cursor = next_cursor;
+        if cursor.is_none() {
+            self.all_session_summaries.clear();
+            // drop marks of sessions no longer listed, e.g. deleted elsewhere
+            self.marked
+                .retain(|id| sessions.iter().any(|s| &s.id == id));
+        }
+        for session in sessions {
+            // sessions updated meanwhile move between pages
+            self.all_session_summaries.retain(|s| s.id != session.id);
+            self.all_session_summaries.push(session);
+        }
+        self.refresh_keeping_selection();
+    }
+
+    fn refresh_keeping_selection(&mut self) {
+        let selected = self.selected().map(|s| s.id.clone());
+        self.refresh();
+        self.set_selected(selected);
+    }
+
+    /// Lists sessions matching filter, pinned first then most recently updated.
+    fn refresh(&mut self) {
+        self.session_summaries = self.all_session_summaries.clone();
+        self.session_summaries.sort_by(|a, b| {
+            b.pinned.cmp(&a.pinned).then_with(|| {
+                b.updated_at
+                    .as_ref()
+                    .map(|t| (t.seconds, t.nanos))
+                    .cmp(&a.updated_at.as_ref().map(|t| (t.seconds, t.nanos)))
+            })
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{app::model::session_manager::SessionManager, chat::ChatSession};
+
+    fn session(id: &str, updated_at: i64, pinned: bool, archived: bool) -> ChatSession {
+        ChatSession {
+            id: id.to_string(),
+            updated_at: Some(prost_types::Timestamp {
+                seconds: updated_at,
+                nanos: 0,
+            }),
+            pinned,
+            archived,
+            ..Default::default()
+        }
+    }
+
+    fn ids(session_manager: &SessionManager) -> Vec<&str> {
+        session_manager
+            .session_summaries()
+            .iter()
+            .map(|s| s.id.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn pinned_first_and_archived_hidden() {
+        let mut session_manager = SessionManager::default();
+        session_manager.handle_session_summaries(
+            vec![
+                session("old", 1, false, false),
+                session("new", 3, false, false),
+                session("pinned", 0, true, false),
+            ],
+            Some("old".to_string()),
+        );
+        assert_eq!(ids(&session_manager), vec!["pinned", "new", "old"]);
+        assert_eq!(session_manager.selected().unwrap().id, "old");
+
+        // archived sessions are queried again, listed ones are kept meanwhile
+        session_manager.toggle_show_archived();
+        assert_eq!(ids(&session_manager), vec!["pinned", "new", "old"]);
+        session_manager.handle_session_query(
+            "",
+            None,
+            false,
+            false,
+            vec![session("outdated", 4, false, false)],
+            None,
+        );
+        assert_eq!(ids(&session_manager), vec!["pinned", "new", "old"]);
+        session_manager.handle_session_query(
+            "",
+            None,
+            false,
+            true,
+            vec![
+                session("pinned", 0, true, false),
+                session("new", 3, false, false),
+                session("archived", 2, false, true),
+                session("old", 1, false, false),
+            ],
+            None,
+        );
+        assert_eq!(
+            ids(&session_manager),
+            vec!["pinned", "new", "archived", "old"]
+        );
+        assert_eq!(
+            session_manager.selected().unwrap().id,
+            "old",
+            "selection is kept"
+        );
+
+        // pinning and archiving reorder the list, archived sessions leave it unless shown
+        session_manager.toggle_show_archived();
+        session_manager.handle_session_query(
+            "",
+            None,
+            false,
+            false,
+            vec![
+                session("pinned", 0, true, false),
+                session("new", 3, false, false),
+                session("old", 1, false, false),
+            ],
+            None,
+        );
+        session_manager.handle_session_summary(session("old", 1, true, false));
+        session_manager.handle_session_summary(session("new", 3, false, true));
+        assert_eq!(ids(&session_manager), vec!["old", "pinned"]);
+        assert_eq!(session_manager.selected().unwrap().id, "old");
+    }
+
+    #[test]
+    fn filter_and_load_pages() {
+        let mut session_manager = SessionManager::default();
+        session_manager.reload();
+        assert_eq!(session_manager.query(), "");
+        let page: Vec<_> = (0..10)
+            .map(|i| session(&i.to_string(), 20 - i, false, false))
+            .collect();
+        session_manager.handle_session_query(
+            "",
+            None,
+            false,
+            false,
+            page,
+            Some("cursor".to_string()),
+        );
+        assert_eq!(session_manager.session_summaries().len(), 10);
+
+        // next page is loaded once near the end
+        session_manager.set_selected(Some("0".to_string()));
+        assert_eq!(session_manager.load_more(), None);
+        session_manager.set_selected(Some("6".to_string()));
+        assert_eq!(session_manager.load_more().as_deref(), Some("cursor"));
+        assert_eq!(session_manager.load_more(), None, "already requested");
+        session_manager.handle_session_query(
+            "",
+            Some("cursor".to_string()),
+            false,
+            false,
+            vec![
+                session("9", 11, false, false),
+                session("10", 10, false, false),
+            ],
+            None,
+        );
+        assert_eq!(session_manager.session_summaries().len(), 11);
+        assert_eq!(session_manager.selected().unwrap().id, "6");
+
+        session_manager.start_filter();
+        session_manager.edit_filter(|filter| filter.push_str("tag:work "));
+        assert_eq!(session_manager.query(), "tag:work");
+        // results of an outdated query are dropped
+        session_manager.handle_session_query("tag:", None, false, false, vec![], None);
+        assert_eq!(session_manager.session_summaries().len(), 11);
+        session_manager.handle_session_query(
+            "tag:work",
+            None,
+            false,
+            false,
+            vec![session("6", 14, false, false)],
+            None,
+        );
+        assert_eq!(ids(&session_manager), vec!["6"]);
+        assert_eq!(session_manager.selected().unwrap().id, "6");
+
+        session_manager.clear_filter();
+        assert!(!session_manager.is_filter_editing());
+        assert_eq!(session_manager.query(), "");
+    }
+
+    #[test]
+    fn mark_and_trash() {
+        let mut session_manager = SessionManager::default();
+        session_manager.reload();
+        let page: Vec<_> = (0..4)
+            .map(|i| session(&i.to_string(), 10 - i, false, false))
+            .collect();
+        session_manager.handle_session_query("", None, false, false, page, None);
+
+        session_manager.set_selected(Some("2".to_string()));
+        assert_eq!(session_manager.take_marked_or_selected(), vec!["2"]);
+        session_manager.toggle_marked();
+        session_manager.set_selected(Some("0".to_string()));
+        session_manager.toggle_marked();
+        session_manager.set_selected(Some("1".to_string()));
+        session_manager.toggle_marked();
+        session_manager.toggle_marked();
+        assert!(session_manager.is_marked("2"));
+        assert!(!session_manager.is_marked("1"), "unmarked");
+        assert_eq!(
+            session_manager.take_marked_or_selected(),
+            vec!["0", "2"],
+            "marked ones in list order"
+        );
+        assert!(!session_manager.is_marked("2"));
+
+        // trash is listed apart from the other sessions
+        session_manager.toggle_trash();
+        assert!(session_manager.session_summaries().is_empty());
+        session_manager.handle_session_query(
+            "",
+            None,
+            false,
+            false,
+            vec![session("0", 0, false, false)],
+            None,
+        );
+        assert!(session_manager.session_summaries().is_empty(), "not trash");
+        session_manager.handle_session_query(
+            "",
+            None,
+            true,
+            false,
+            vec![session("2", 0, false, false)],
+            None,
+        );
+        assert_eq!(ids(&session_manager), vec!["2"]);
+    }
+
+    #[test]
+    fn rename() {
+        let mut session_manager = SessionManager::default();
+        assert!(!session_manager.start_rename(), "nothing selected");
+
+        session_manager.handle_session_summaries(
+            vec![ChatSession {
+                title: "Old title".to_string(),
+                ..session("session", 0, false, false)
+            }],
+            Some("session".to_string()),
+        );
+        assert!(session_manager.start_rename());
+        assert_eq!(session_manager.rename_input(), Some("Old title"));
+
+        let input = session_manager.rename_input_mut().unwrap();
+        input.clear();
+        input.push_str(" New title ");
+        assert_eq!(
+            session_manager.finish_rename(),
+            Some(("session".to_string(), "New title".to_string()))
+        );
+        assert_eq!(session_manager.rename_input(), None);
     }
 }
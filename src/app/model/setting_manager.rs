@@ -1,75 +1,582 @@
+use color_eyre::{Result, eyre::eyre};
 use ratatui::widgets::ListState;
 
-use crate::{llm::*, models::OPENAI_MODELS};
+use crate::{
+    llm::*,
+    models::settings::{ProviderKind, ProviderStatus},
+};
+
+/// Section of setting manager form, in displayed order.
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub enum Section {
+    Provider,
+    Model,
+    Tools,
+    Parameters,
+    Instructions,
+}
+
+impl Section {
+    pub fn title(&self) -> &'static str {
+        match self {
+            Section::Provider => "Provider",
+            Section::Model => "Model",
+            Section::Tools => "Tools",
+            Section::Parameters => "Parameters",
+            Section::Instructions => "Instructions",
+        }
+    }
+}
+
+/// Model parameters editable in setting manager.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Param {
+    Temperature,
+    TopP,
+    MaxOutputTokens,
+    Truncation,
+}
+
+pub const PARAMS: &[Param] = &[
+    Param::Temperature,
+    Param::TopP,
+    Param::MaxOutputTokens,
+    Param::Truncation,
+];
+
+impl Param {
+    pub fn display_name(&self) -> &'static str {
+        match self {
+            Param::Temperature => "temperature",
+            Param::TopP => "top_p",
+            Param::MaxOutputTokens => "max output tokens",
+            Param::Truncation => "truncation",
+        }
+    }
+}
+
+/// Field of setting manager form.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Field {
+    Provider(ProviderKind),
+    Model(OpenAiModel),
+    WebSearch,
+    Store,
+    Param(Param),
+    Instructions,
+}
+
+impl Field {
+    pub fn section(&self) -> Section {
+        match self {
+            Field::Provider(_) => Section::Provider,
+            Field::Model(_) => Section::Model,
+            Field::WebSearch | Field::Store => Section::Tools,
+            Field::Param(_) => Section::Parameters,
+            Field::Instructions => Section::Instructions,
+        }
+    }
+
+    /// Returns whether the field is edited by typing.
+    pub fn is_text(&self) -> bool {
+        matches!(
+            self,
+            Field::Param(Param::Temperature | Param::TopP | Param::MaxOutputTokens)
+                | Field::Instructions
+        )
+    }
+
+    pub fn display_name(&self) -> &'static str {
+        match self {
+            Field::Provider(provider) => provider.display_name(),
+            Field::Model(model) => model.display_name(),
+            Field::WebSearch => "web search",
+            Field::Store => "store responses",
+            Field::Param(param) => param.display_name(),
+            Field::Instructions => "instructions",
+        }
+    }
+}
 
 pub struct SettingManager {
     llm_settings: LlmSettings,
+    /// Typed numeric parameters, parsed into settings on validation.
+    temperature: String,
+    top_p: String,
+    max_output_tokens: String,
+    /// Availability of providers reported by service, providers not reported are assumed
+    /// available.
+    providers: Vec<ProviderStatus>,
+    /// Index of selected field in `fields`.
+    selected: usize,
     list_state: ListState,
+    /// Validation error of current settings.
+    error: Option<String>,
 }
 
 impl SettingManager {
     pub fn new(llm_settings: LlmSettings) -> Self {
-        let mut list_state = ListState::default();
-        if let Some(idx) = OPENAI_MODELS.iter().position(|m| {
-            if let Some(llm_settings::Provider::OpenAi(settings)) = &llm_settings.provider {
-                settings.model == *m as i32
-            } else {
-                false
-            }
-        }) {
-            list_state.select(Some(idx));
-        } else {
-            tracing::error!("unexpected current model {:?}", llm_settings)
-        }
-
-        Self {
+        let settings = match llm_settings.provider {
+            Some(llm_settings::Provider::OpenAi(settings)) => settings,
+            Some(llm_settings::Provider::Mock(_)) | None => OpenAiSettings::default(),
+        };
+        let to_input = |v: Option<String>| v.unwrap_or_default();
+        let mut setting_manager = Self {
             llm_settings,
-            list_state,
+            temperature: to_input(settings.temperature.map(|v| v.to_string())),
+            top_p: to_input(settings.top_p.map(|v| v.to_string())),
+            max_output_tokens: to_input(settings.max_output_tokens.map(|v| v.to_string())),
+            providers: Vec::new(),
+            selected: 0,
+            list_state: ListState::default(),
+            error: None,
+        };
+
+        // start at current model, or current provider if it has no models
+        let current = match setting_manager.llm_settings.provider_kind() {
+            Some(ProviderKind::OpenAi) => Field::Model(settings.model()),
+            Some(provider) => Field::Provider(provider),
+            None => Field::Provider(ProviderKind::default()),
+        };
+        match setting_manager.fields().iter().position(|f| *f == current) {
+            Some(idx) => setting_manager.selected = idx,
+            None => tracing::error!(
+                "unexpected current model {:?}",
+                setting_manager.llm_settings
+            ),
         }
+        setting_manager
+    }
+
+    pub fn with_providers(mut self, providers: Vec<ProviderStatus>) -> Self {
+        self.providers = providers;
+        self
     }
 
     pub fn llm_settings(&self) -> LlmSettings {
-        self.llm_settings
+        self.llm_settings.clone()
     }
 
     pub fn list_state_mut(&mut self) -> &mut ListState {
         &mut self.list_state
     }
 
-    // TODO: refactor this and next when implementing other providers
-    pub fn select_next(&mut self) {
-        if let Some(i) = self.list_state.selected() {
-            if i + 1 < OPENAI_MODELS.len() {
-                if let Some(llm_settings::Provider::OpenAi(settings)) =
-                    &mut self.llm_settings.provider
-                {
-                    self.list_state.select_next();
-                    settings.model = OPENAI_MODELS[i + 1] as i32;
-                } else {
-                    tracing::error!(
-                        "current provider does not match {:?}",
-                        self.llm_settings.provider_name()
-                    )
-                }
+    pub fn error(&self) -> Option<&String> {
+        self.error.as_ref()
+    }
+
+    /// Returns fields of all sections in order, with providers reported by service, or the chosen
+    /// one before any is reported, and models of the chosen provider.
+    pub fn fields(&self) -> Vec<Field> {
+        let chosen = self.llm_settings.provider_kind();
+        let models = chosen.map_or(&[][..], |p| p.models());
+        let providers: Vec<ProviderKind> = if self.providers.is_empty() {
+            chosen.into_iter().collect()
+        } else {
+            self.providers.iter().map(|s| s.provider).collect()
+        };
+        providers
+            .into_iter()
+            .map(Field::Provider)
+            .chain(models.iter().map(|m| Field::Model(*m)))
+            .chain([Field::WebSearch, Field::Store])
+            .chain(PARAMS.iter().map(|p| Field::Param(*p)))
+            .chain([Field::Instructions])
+            .collect()
+    }
+
+    pub fn selected_field(&self) -> Field {
+        let fields = self.fields();
+        fields[self.selected.min(fields.len() - 1)]
+    }
+
+    /// Returns the reason `provider` is unavailable if it is.
+    pub fn provider_error(&self, provider: ProviderKind) -> Option<&str> {
+        self.providers
+            .iter()
+            .find(|s| s.provider == provider)
+            .and_then(|s| s.error.as_deref())
+    }
+
+    /// Returns whether `field` is the chosen provider or model.
+    pub fn is_chosen(&self, field: Field) -> bool {
+        match field {
+            Field::Provider(provider) => self.llm_settings.provider_kind() == Some(provider),
+            Field::Model(model) => self.open_ai_settings().is_some_and(|s| s.model() == model),
+            _ => false,
+        }
+    }
+
+    /// Returns displayed value of a tool, parameter or instructions field, "n/a" if the provider
+    /// or model does not support it.
+    pub fn field_value(&self, field: Field) -> String {
+        let toggle = |on: bool| if on { "[x]" } else { "[ ]" }.to_string();
+        let value = match (field, self.open_ai_settings()) {
+            (Field::Provider(_) | Field::Model(_), _) => String::new(),
+            (Field::Instructions, _) => self.llm_settings.instructions.clone(),
+            // tools and parameters are provider specific
+            (_, None) => "n/a".to_string(),
+            (Field::WebSearch, Some(settings)) => toggle(settings.web_search),
+            (Field::Store, Some(settings)) => toggle(settings.store),
+            (Field::Param(Param::Temperature | Param::TopP), Some(settings))
+                if !settings.model().supports_sampling() =>
+            {
+                "n/a".to_string()
+            }
+            (Field::Param(Param::Temperature), _) => self.temperature.clone(),
+            (Field::Param(Param::TopP), _) => self.top_p.clone(),
+            (Field::Param(Param::MaxOutputTokens), _) => self.max_output_tokens.clone(),
+            (Field::Param(Param::Truncation), Some(settings)) => {
+                settings.truncation().display_name().to_string()
             }
+        };
+        // show placeholder unless the field is being typed into
+        if value.is_empty() && field.is_text() && field != self.selected_field() {
+            "default".to_string()
+        } else {
+            value
+        }
+    }
+
+    fn open_ai_settings(&self) -> Option<OpenAiSettings> {
+        match self.llm_settings.provider {
+            Some(llm_settings::Provider::OpenAi(settings)) => Some(settings),
+            Some(llm_settings::Provider::Mock(_)) | None => None,
+        }
+    }
+
+    fn open_ai_settings_mut(&mut self) -> Option<&mut OpenAiSettings> {
+        match &mut self.llm_settings.provider {
+            Some(llm_settings::Provider::OpenAi(settings)) => Some(settings),
+            Some(llm_settings::Provider::Mock(_)) | None => None,
+        }
+    }
+
+    // ----------------------------------------------------------------
+    // Event handling.
+    // ----------------------------------------------------------------
+
+    pub fn select_next(&mut self) {
+        if self.selected + 1 < self.fields().len() {
+            self.selected += 1;
         }
     }
 
     pub fn select_previous(&mut self) {
-        if let Some(i) = self.list_state.selected() {
-            if i > 0 {
-                if let Some(llm_settings::Provider::OpenAi(settings)) =
-                    &mut self.llm_settings.provider
-                {
-                    self.list_state.select_previous();
-                    settings.model = OPENAI_MODELS[i - 1] as i32;
-                } else {
-                    tracing::error!(
-                        "current provider does not match {:?}",
-                        self.llm_settings.provider_name()
-                    )
+        self.selected = self.selected.saturating_sub(1);
+    }
+
+    /// Selects the first field of the next section.
+    pub fn select_next_section(&mut self) {
+        let section = self.selected_field().section();
+        if let Some(idx) = self.fields().iter().position(|f| f.section() > section) {
+            self.selected = idx;
+        }
+    }
+
+    /// Selects the first field of current section, or of the previous section if already there.
+    pub fn select_previous_section(&mut self) {
+        let fields = self.fields();
+        let section = self.selected_field().section();
+        let start = |section: Section| fields.iter().position(|f| f.section() == section);
+        self.selected = match start(section) {
+            Some(idx) if idx < self.selected => idx,
+            _ => fields[..self.selected]
+                .last()
+                .and_then(|f| start(f.section()))
+                .unwrap_or(0),
+        };
+    }
+
+    /// Picks selected provider or model, toggles selected tool or cycles selected option.
+    pub fn pick(&mut self) {
+        match self.selected_field() {
+            Field::Provider(provider) => {
+                if let Some(error) = self.provider_error(provider) {
+                    self.error = Some(format!(
+                        "{} is unavailable: {error}",
+                        provider.display_name()
+                    ));
+                    return;
+                }
+                if self.llm_settings.provider_kind() != Some(provider) {
+                    self.llm_settings.provider = Some(provider.default_provider());
+                }
+            }
+            Field::Model(model) => {
+                if let Some(settings) = self.open_ai_settings_mut() {
+                    settings.model = model as i32;
+                    settings.clear_unsupported();
+                }
+                if !model.supports_sampling() {
+                    self.temperature.clear();
+                    self.top_p.clear();
                 }
             }
+            Field::WebSearch => {
+                if let Some(settings) = self.open_ai_settings_mut() {
+                    settings.web_search = !settings.web_search;
+                }
+            }
+            Field::Store => {
+                if let Some(settings) = self.open_ai_settings_mut() {
+                    settings.store = !settings.store;
+                }
+            }
+            Field::Param(Param::Truncation) => {
+                if let Some(settings) = self.open_ai_settings_mut() {
+                    let truncation = match settings.truncation() {
+                        OpenAiTruncation::Unspecified => OpenAiTruncation::Auto,
+                        OpenAiTruncation::Auto => OpenAiTruncation::Disabled,
+                        OpenAiTruncation::Disabled => OpenAiTruncation::Unspecified,
+                    };
+                    settings.truncation = truncation as i32;
+                }
+            }
+            Field::Param(_) | Field::Instructions => {}
+        }
+        self.error = None;
+    }
+
+    /// Types `c` into selected text field. Numeric fields only take digits and dot.
+    pub fn input(&mut self, c: char) {
+        let decimal = c.is_ascii_digit() || c == '.';
+        match self.selected_field() {
+            Field::Param(Param::Temperature) if decimal => self.temperature.push(c),
+            Field::Param(Param::TopP) if decimal => self.top_p.push(c),
+            Field::Param(Param::MaxOutputTokens) if c.is_ascii_digit() => {
+                self.max_output_tokens.push(c)
+            }
+            Field::Instructions => self.llm_settings.instructions.push(c),
+            _ => return,
+        }
+        self.error = None;
+    }
+
+    /// Deletes the last character of selected text field.
+    pub fn backspace(&mut self) {
+        match self.selected_field() {
+            Field::Param(Param::Temperature) => self.temperature.pop(),
+            Field::Param(Param::TopP) => self.top_p.pop(),
+            Field::Param(Param::MaxOutputTokens) => self.max_output_tokens.pop(),
+            Field::Instructions => self.llm_settings.instructions.pop(),
+            _ => return,
+        };
+        self.error = None;
+    }
+
+    /// Parses typed parameters into settings and validates them, keeping the error to display.
+    /// Returns whether settings are valid.
+    pub fn validate(&mut self) -> bool {
+        self.error = self.apply_params().err().map(|e| e.to_string());
+        self.error.is_none()
+    }
+
+    fn apply_params(&mut self) -> Result<()> {
+        let temperature = parse_param(Param::Temperature, &self.temperature)?;
+        let top_p = parse_param(Param::TopP, &self.top_p)?;
+        let max_output_tokens = parse_param(Param::MaxOutputTokens, &self.max_output_tokens)?;
+        if let Some(settings) = self.open_ai_settings_mut() {
+            settings.temperature = temperature;
+            settings.top_p = top_p;
+            settings.max_output_tokens = max_output_tokens;
+        }
+        if let Some(provider) = self.llm_settings.provider_kind()
+            && let Some(error) = self.provider_error(provider)
+        {
+            return Err(eyre!("{} is unavailable: {error}", provider.display_name()));
+        }
+        self.llm_settings.validate()
+    }
+}
+
+/// Parses typed `input` of `param`, empty input leaves it to provider default.
+fn parse_param<T: std::str::FromStr>(param: Param, input: &str) -> Result<Option<T>> {
+    if input.is_empty() {
+        return Ok(None);
+    }
+    input
+        .parse()
+        .map(Some)
+        .map_err(|_| eyre!("{} must be a number, got {input}", param.display_name()))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        app::model::setting_manager::{Field, Param, Section, SettingManager},
+        llm::*,
+        models::settings::{ProviderKind, ProviderStatus},
+    };
+
+    fn settings(model: OpenAiModel) -> LlmSettings {
+        LlmSettings {
+            provider: Some(llm_settings::Provider::OpenAi(OpenAiSettings {
+                model: model as i32,
+                ..Default::default()
+            })),
+            ..Default::default()
+        }
+    }
+
+    fn open_ai(setting_manager: &SettingManager) -> OpenAiSettings {
+        match setting_manager.llm_settings().provider {
+            Some(llm_settings::Provider::OpenAi(settings)) => settings,
+            _ => unreachable!(),
+        }
+    }
+
+    fn select(setting_manager: &mut SettingManager, field: Field) {
+        while setting_manager.selected_field() != field {
+            setting_manager.select_next();
+        }
+    }
+
+    #[test]
+    fn navigate_sections() {
+        let mut setting_manager = SettingManager::new(settings(OpenAiModel::O4Mini));
+        assert_eq!(
+            setting_manager.selected_field(),
+            Field::Model(OpenAiModel::O4Mini)
+        );
+
+        let mut sections = vec![];
+        for _ in 0..4 {
+            setting_manager.select_next_section();
+            sections.push(setting_manager.selected_field().section());
+        }
+        assert_eq!(
+            sections,
+            vec![
+                Section::Tools,
+                Section::Parameters,
+                Section::Instructions,
+                Section::Instructions,
+            ]
+        );
+
+        setting_manager.select_previous_section();
+        setting_manager.select_next();
+        setting_manager.select_previous_section();
+        assert_eq!(
+            setting_manager.selected_field(),
+            Field::Param(Param::Temperature)
+        );
+        for _ in 0..4 {
+            setting_manager.select_previous_section();
+        }
+        assert_eq!(
+            setting_manager.selected_field().section(),
+            Section::Provider
+        );
+    }
+
+    #[test]
+    fn edit_and_validate() {
+        struct Case {
+            description: &'static str,
+            model: OpenAiModel,
+            field: Field,
+            input: &'static str,
+            expected_valid: bool,
+        }
+        let cases = vec![
+            Case {
+                description: "temperature on non reasoning model",
+                model: OpenAiModel::Gpt4o,
+                field: Field::Param(Param::Temperature),
+                input: "0.7",
+                expected_valid: true,
+            },
+            Case {
+                description: "temperature on reasoning model",
+                model: OpenAiModel::O3,
+                field: Field::Param(Param::Temperature),
+                input: "0.7",
+                expected_valid: false,
+            },
+            Case {
+                description: "malformed number",
+                model: OpenAiModel::Gpt4o,
+                field: Field::Param(Param::TopP),
+                input: "0..5",
+                expected_valid: false,
+            },
+            Case {
+                description: "max output tokens ignores non digits",
+                model: OpenAiModel::Gpt4o,
+                field: Field::Param(Param::MaxOutputTokens),
+                input: "2k048",
+                expected_valid: true,
+            },
+            Case {
+                description: "max output tokens below minimum",
+                model: OpenAiModel::Gpt4o,
+                field: Field::Param(Param::MaxOutputTokens),
+                input: "8",
+                expected_valid: false,
+            },
+        ];
+
+        for case in cases {
+            let mut setting_manager = SettingManager::new(settings(case.model));
+            select(&mut setting_manager, case.field);
+            for c in case.input.chars() {
+                setting_manager.input(c);
+            }
+            assert_eq!(
+                setting_manager.validate(),
+                case.expected_valid,
+                "{}",
+                case.description
+            );
         }
     }
+
+    #[test]
+    fn pick() {
+        let mut setting_manager = SettingManager::new(settings(OpenAiModel::Gpt4o));
+        select(&mut setting_manager, Field::WebSearch);
+        setting_manager.pick();
+        select(&mut setting_manager, Field::Param(Param::Temperature));
+        for c in "1.2".chars() {
+            setting_manager.input(c);
+        }
+        select(&mut setting_manager, Field::Instructions);
+        for c in "Be brief.".chars() {
+            setting_manager.input(c);
+        }
+        assert!(setting_manager.validate());
+        let settings = open_ai(&setting_manager);
+        assert!(settings.web_search);
+        assert_eq!(settings.temperature, Some(1.2));
+        assert_eq!(setting_manager.llm_settings().instructions, "Be brief.");
+
+        // picking a reasoning model drops sampling parameters
+        while setting_manager.selected_field() != Field::Model(OpenAiModel::O3) {
+            setting_manager.select_previous();
+        }
+        setting_manager.pick();
+        assert!(setting_manager.validate());
+        assert_eq!(open_ai(&setting_manager).model(), OpenAiModel::O3);
+        assert_eq!(open_ai(&setting_manager).temperature, None);
+    }
+
+    #[test]
+    fn unavailable_provider() {
+        let mut setting_manager =
+            SettingManager::new(settings(OpenAiModel::Gpt4o)).with_providers(vec![
+                ProviderStatus {
+                    provider: ProviderKind::OpenAi,
+                    error: Some("set the OPENAI_API_KEY environment variable".to_string()),
+                },
+            ]);
+        setting_manager.select_previous_section();
+        setting_manager.pick();
+        assert_eq!(
+            setting_manager.error().map(String::as_str),
+            Some("openAI is unavailable: set the OPENAI_API_KEY environment variable")
+        );
+        assert!(!setting_manager.validate());
+    }
 }
@@ -0,0 +1,62 @@
+/// Popup editing tags of a session as words separated by spaces or commas.
+pub struct TagEditor {
+    session_id: String,
+    input: String,
+    /// Tags of all sessions, shown as hints.
+    known_tags: Vec<String>,
+}
+
+impl TagEditor {
+    pub fn new(session_id: String, tags: &[String], known_tags: Vec<String>) -> Self {
+        Self {
+            session_id,
+            input: tags.join(" "),
+            known_tags,
+        }
+    }
+
+    pub fn session_id(&self) -> &str {
+        &self.session_id
+    }
+
+    pub fn input(&self) -> &str {
+        &self.input
+    }
+
+    pub fn known_tags(&self) -> &[String] {
+        &self.known_tags
+    }
+
+    pub fn push(&mut self, c: char) {
+        self.input.push(c);
+    }
+
+    pub fn backspace(&mut self) {
+        self.input.pop();
+    }
+
+    /// Returns tags in input without leading `#`.
+    pub fn tags(&self) -> Vec<String> {
+        self.input
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .map(|tag| tag.trim_start_matches('#'))
+            .filter(|tag| !tag.is_empty())
+            .map(String::from)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::app::model::tag_editor::TagEditor;
+
+    #[test]
+    fn tags() {
+        let mut tag_editor = TagEditor::new("session".to_string(), &["work".to_string()], vec![]);
+        assert_eq!(tag_editor.input(), "work");
+        for c in ", #rust,,async ".chars() {
+            tag_editor.push(c);
+        }
+        assert_eq!(tag_editor.tags(), vec!["work", "rust", "async"]);
+    }
+}
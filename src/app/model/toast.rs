@@ -0,0 +1,38 @@
+use std::time::{Duration, Instant};
+
+/// How long toasts are shown.
+const TOAST_DURATION: Duration = Duration::from_secs(5);
+
+/// Short lived notice, e.g. of sessions moved to trash that can be restored.
+pub struct Toast {
+    message: String,
+    /// Sessions restored on undo.
+    undo: Vec<String>,
+    expires_at: Instant,
+}
+
+impl Toast {
+    pub fn new(message: String, undo: Vec<String>) -> Self {
+        Self {
+            message,
+            undo,
+            expires_at: Instant::now() + TOAST_DURATION,
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    pub fn undo(&self) -> &[String] {
+        &self.undo
+    }
+
+    pub fn expires_at(&self) -> Instant {
+        self.expires_at
+    }
+
+    pub fn is_expired(&self) -> bool {
+        Instant::now() >= self.expires_at
+    }
+}
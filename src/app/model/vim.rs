@@ -0,0 +1,903 @@
+use std::{collections::HashMap, ops::Range};
+
+use crossterm::event::{KeyCode, KeyModifiers};
+
+use crate::{
+    app::{model::editor::Editor, view::utils::motion},
+    models::keymap::KeyChord,
+};
+
+/// Register always written by yanks and deletes.
+const UNNAMED_REGISTER: char = '"';
+/// Upper bound of counts, like vim's.
+const MAX_COUNT: usize = 999_999_999;
+/// Upper bound of chars put at once, puts of more are dropped like vim's.
+const MAX_PUT_CHARS: usize = 1 << 20;
+
+/// Result of feeding a key to vim.
+#[derive(Debug, PartialEq)]
+pub enum Outcome {
+    /// Key is part of a command, with text yanked or deleted into a system clipboard register.
+    Handled(Option<String>),
+    /// Key is not a vim command and is left to key bindings.
+    Unhandled,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Motion {
+    Left,
+    Right,
+    Up,
+    Down,
+    WordForward,
+    WordBackward,
+    WordEnd,
+    LineStart,
+    FirstNonBlank,
+    LineEnd,
+    /// First line, or line of count.
+    Top,
+    /// Last line, or line of count.
+    Bottom,
+}
+
+impl Motion {
+    /// Whether operators act on whole lines between cursor and target.
+    fn is_linewise(self) -> bool {
+        matches!(self, Self::Up | Self::Down | Self::Top | Self::Bottom)
+    }
+
+    /// Whether operators include the char at target.
+    fn is_inclusive(self) -> bool {
+        self == Self::WordEnd
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Operator {
+    Delete,
+    Change,
+    Yank,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Target {
+    Motion(Motion),
+    /// Lines from cursor, by doubling the operator, e.g. `dd`.
+    Line,
+}
+
+/// Where insert mode starts.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Insert {
+    Cursor,
+    After,
+    LineStart,
+    LineEnd,
+    LineBelow,
+    LineAbove,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Kind {
+    Move(Motion),
+    Operate(Operator, Target),
+    /// Operates on visual selection.
+    OperateSelection(Operator),
+    DeleteChar,
+    Put {
+        before: bool,
+    },
+    Insert(Insert),
+    Undo,
+    Repeat,
+    ToggleVisual,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Command {
+    register: Option<char>,
+    count: Option<usize>,
+    kind: Kind,
+}
+
+impl Command {
+    /// Whether `.` repeats the command.
+    fn is_change(&self) -> bool {
+        match self.kind {
+            Kind::Operate(operator, _) => operator != Operator::Yank,
+            Kind::DeleteChar | Kind::Put { .. } | Kind::Insert(_) => true,
+            _ => false,
+        }
+    }
+}
+
+enum Parse<T> {
+    Incomplete,
+    Invalid,
+    Complete(T),
+}
+
+/// Splits leading count off keys, `0` alone being a motion. Counts are capped at `MAX_COUNT`.
+fn split_count(keys: &[char]) -> (Option<usize>, &[char]) {
+    if keys
+        .first()
+        .is_none_or(|c| !c.is_ascii_digit() || *c == '0')
+    {
+        return (None, keys);
+    }
+    let len = keys.iter().take_while(|c| c.is_ascii_digit()).count();
+    let count = keys[..len].iter().fold(0, |count, c| {
+        (count * 10 + c.to_digit(10).unwrap_or_default() as usize).min(MAX_COUNT)
+    });
+    (Some(count), &keys[len..])
+}
+
+fn parse_motion(keys: &[char]) -> Parse<Motion> {
+    let motion = match keys {
+        [] | ['g'] => return Parse::Incomplete,
+        ['h'] => Motion::Left,
+        ['l'] => Motion::Right,
+        ['k'] => Motion::Up,
+        ['j'] => Motion::Down,
+        ['w'] => Motion::WordForward,
+        ['b'] => Motion::WordBackward,
+        ['e'] => Motion::WordEnd,
+        ['0'] => Motion::LineStart,
+        ['^'] => Motion::FirstNonBlank,
+        ['$'] => Motion::LineEnd,
+        ['g', 'g'] => Motion::Top,
+        ['G'] => Motion::Bottom,
+        _ => return Parse::Invalid,
+    };
+    Parse::Complete(motion)
+}
+
+/// Parses `["x][count]command`, where an operator takes `[count]motion` or itself for lines.
+fn parse(keys: &[char], is_visual: bool) -> Parse<Command> {
+    let (register, keys) = match keys {
+        ['"'] => return Parse::Incomplete,
+        ['"', register, keys @ ..] if is_register(*register) => (Some(*register), keys),
+        ['"', ..] => return Parse::Invalid,
+        keys => (None, keys),
+    };
+    let (count, keys) = split_count(keys);
+    let Some((&key, rest)) = keys.split_first() else {
+        return Parse::Incomplete;
+    };
+
+    let operator = match key {
+        'd' => Some(Operator::Delete),
+        'c' => Some(Operator::Change),
+        'y' => Some(Operator::Yank),
+        _ => None,
+    };
+    let kind = match (key, operator) {
+        ('x', _) if is_visual => Some(Kind::OperateSelection(Operator::Delete)),
+        (_, Some(operator)) if is_visual => Some(Kind::OperateSelection(operator)),
+        (_, Some(operator)) => {
+            let (motion_count, rest) = split_count(rest);
+            let count = match (count, motion_count) {
+                (None, None) => None,
+                (count, motion_count) => Some(
+                    count
+                        .unwrap_or(1)
+                        .saturating_mul(motion_count.unwrap_or(1))
+                        .min(MAX_COUNT),
+                ),
+            };
+            let target = if rest == [key] {
+                Target::Line
+            } else {
+                match parse_motion(rest) {
+                    Parse::Complete(motion) => Target::Motion(motion),
+                    Parse::Incomplete => return Parse::Incomplete,
+                    Parse::Invalid => return Parse::Invalid,
+                }
+            };
+            return Parse::Complete(Command {
+                register,
+                count,
+                kind: Kind::Operate(operator, target),
+            });
+        }
+        ('x', _) => Some(Kind::DeleteChar),
+        ('D', _) => Some(Kind::Operate(
+            Operator::Delete,
+            Target::Motion(Motion::LineEnd),
+        )),
+        ('C', _) => Some(Kind::Operate(
+            Operator::Change,
+            Target::Motion(Motion::LineEnd),
+        )),
+        ('Y', _) => Some(Kind::Operate(Operator::Yank, Target::Line)),
+        ('p', _) => Some(Kind::Put { before: false }),
+        ('P', _) => Some(Kind::Put { before: true }),
+        ('i', _) => Some(Kind::Insert(Insert::Cursor)),
+        ('a', _) => Some(Kind::Insert(Insert::After)),
+        ('I', _) => Some(Kind::Insert(Insert::LineStart)),
+        ('A', _) => Some(Kind::Insert(Insert::LineEnd)),
+        ('o', _) => Some(Kind::Insert(Insert::LineBelow)),
+        ('O', _) => Some(Kind::Insert(Insert::LineAbove)),
+        ('u', _) => Some(Kind::Undo),
+        ('.', _) => Some(Kind::Repeat),
+        ('v', _) => Some(Kind::ToggleVisual),
+        _ => match parse_motion(keys) {
+            Parse::Complete(motion) => Some(Kind::Move(motion)),
+            Parse::Incomplete => return Parse::Incomplete,
+            Parse::Invalid => None,
+        },
+    };
+    match kind {
+        Some(kind) => Parse::Complete(Command {
+            register,
+            count,
+            kind,
+        }),
+        None => Parse::Invalid,
+    }
+}
+
+/// Unnamed, named `a` to `z` and system clipboard `+` and `*` registers.
+fn is_register(c: char) -> bool {
+    c == UNNAMED_REGISTER || c.is_ascii_lowercase() || c == '+' || c == '*'
+}
+
+fn is_clipboard_register(c: char) -> bool {
+    c == '+' || c == '*'
+}
+
+/// Returns char idx of the start of the line at `char_idx`.
+fn line_start(chars: &[char], char_idx: usize) -> usize {
+    chars[..char_idx]
+        .iter()
+        .rposition(|c| *c == '\n')
+        .map_or(0, |i| i + 1)
+}
+
+/// Returns char idx of the end of the line at `char_idx`, before the line break.
+fn line_end(chars: &[char], char_idx: usize) -> usize {
+    chars[char_idx..]
+        .iter()
+        .position(|c| *c == '\n')
+        .map_or(chars.len(), |i| char_idx + i)
+}
+
+/// Returns char idx of the first non-whitespace char of the line at `char_idx`.
+fn first_non_blank(chars: &[char], char_idx: usize) -> usize {
+    let start = line_start(chars, char_idx);
+    let end = line_end(chars, char_idx);
+    start
+        + chars[start..end]
+            .iter()
+            .take_while(|c| c.is_whitespace())
+            .count()
+}
+
+/// Returns char idxs of the starts of all lines.
+fn line_starts(chars: &[char]) -> Vec<usize> {
+    std::iter::once(0)
+        .chain(
+            chars
+                .iter()
+                .enumerate()
+                .filter(|(_, c)| **c == '\n')
+                .map(|(i, _)| i + 1),
+        )
+        .collect()
+}
+
+/// Returns char idx `motion` moves cursor to.
+fn motion_target(
+    chars: &[char],
+    text: &str,
+    cursor: usize,
+    motion: Motion,
+    count: Option<usize>,
+) -> usize {
+    let n = count.unwrap_or(1);
+    // stops at either end of text rather than repeating in place
+    let repeat = |next: fn(&str, usize) -> usize| {
+        let mut i = cursor;
+        for _ in 0..n {
+            match next(text, i) {
+                next if next == i => break,
+                next => i = next,
+            }
+        }
+        i
+    };
+    match motion {
+        Motion::Left => cursor.saturating_sub(n).max(line_start(chars, cursor)),
+        Motion::Right => cursor.saturating_add(n).min(line_end(chars, cursor)),
+        Motion::Up | Motion::Down => {
+            let starts = line_starts(chars);
+            let line = starts.iter().rposition(|s| *s <= cursor).unwrap_or(0);
+            let target_line = if motion == Motion::Down {
+                line.saturating_add(n).min(starts.len() - 1)
+            } else {
+                line.saturating_sub(n)
+            };
+            let start = starts[target_line];
+            (start + cursor - starts[line]).min(line_end(chars, start))
+        }
+        Motion::WordForward => repeat(motion::next_word_start),
+        Motion::WordBackward => repeat(motion::previous_word_start),
+        Motion::WordEnd => repeat(motion::word_end),
+        Motion::LineStart => line_start(chars, cursor),
+        Motion::FirstNonBlank => first_non_blank(chars, cursor),
+        Motion::LineEnd => line_end(chars, cursor),
+        Motion::Top | Motion::Bottom => {
+            let starts = line_starts(chars);
+            let line = match (count, motion) {
+                (Some(count), _) => count.saturating_sub(1).min(starts.len() - 1),
+                (None, Motion::Top) => 0,
+                (None, _) => starts.len() - 1,
+            };
+            first_non_blank(chars, starts[line])
+        }
+    }
+}
+
+/// Returns char range from the start of the line at `from` to the end of the line at `to`.
+fn line_range(chars: &[char], from: usize, to: usize) -> Range<usize> {
+    line_start(chars, from.min(to))..line_end(chars, from.max(to))
+}
+
+/// Yanked or deleted text.
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Register {
+    text: String,
+    /// Whether text is whole lines, put below or above the current line.
+    is_linewise: bool,
+}
+
+/// Change that entered insert mode, with cursor char idx and input length when it did.
+struct InsertStart {
+    command: Command,
+    char_idx: usize,
+    len: usize,
+}
+
+/// Vim emulation of the input editor when not typing, typing is its insert mode.
+#[derive(Default)]
+pub struct Vim {
+    /// Keys pressed so far of the pending command, e.g. `2d`.
+    keys: Vec<char>,
+    registers: HashMap<char, Register>,
+    is_visual: bool,
+    /// Last change and text typed in the insert mode it entered, repeated by `.`.
+    last_change: Option<(Command, Option<String>)>,
+    insert_start: Option<InsertStart>,
+}
+
+impl Vim {
+    /// Handles key pressed in normal or visual mode.
+    pub fn handle_key(&mut self, editor: &mut Editor, key: KeyChord) -> Outcome {
+        // selection is cleared by mouse and key bindings too
+        self.is_visual &= editor.selection_anchor().is_some();
+
+        if key == KeyChord::new(KeyCode::Esc, KeyModifiers::NONE)
+            && (!self.keys.is_empty() || self.is_visual)
+        {
+            self.keys.clear();
+            self.exit_visual(editor);
+            return Outcome::Handled(None);
+        }
+        let Some(c) = key.char() else {
+            if self.keys.is_empty() {
+                return Outcome::Unhandled;
+            }
+            self.keys.clear();
+            return Outcome::Handled(None);
+        };
+
+        self.keys.push(c);
+        match parse(&self.keys, self.is_visual) {
+            Parse::Incomplete => Outcome::Handled(None),
+            Parse::Invalid => {
+                let is_first_key = self.keys.len() == 1;
+                self.keys.clear();
+                if is_first_key {
+                    Outcome::Unhandled
+                } else {
+                    Outcome::Handled(None)
+                }
+            }
+            Parse::Complete(command) => {
+                self.keys.clear();
+                Outcome::Handled(self.execute(editor, command))
+            }
+        }
+    }
+
+    /// Leaves insert mode, recording text typed since the change that entered it for `.`.
+    pub fn finish_insert(&mut self, editor: &mut Editor) {
+        if let Some(InsertStart {
+            command,
+            char_idx,
+            len,
+        }) = self.insert_start.take()
+        {
+            let cursor = editor.cursor_char_idx;
+            let inserted = editor
+                .input()
+                .chars()
+                .skip(char_idx)
+                .take(cursor.saturating_sub(char_idx))
+                .collect::<String>();
+            // typed text is only repeatable if nothing else changed
+            let is_typed = cursor >= char_idx
+                && editor.input().chars().count() == len + inserted.chars().count();
+            self.last_change = Some((command, is_typed.then_some(inserted)));
+        }
+        editor.set_is_editing(false);
+        let chars: Vec<char> = editor.input().chars().collect();
+        if editor.cursor_char_idx > line_start(&chars, editor.cursor_char_idx) {
+            editor.move_cursor_left();
+        }
+    }
+
+    /// Executes command, returns text to copy to the system clipboard.
+    fn execute(&mut self, editor: &mut Editor, command: Command) -> Option<String> {
+        let chars: Vec<char> = editor.input().chars().collect();
+        let cursor = editor.cursor_char_idx.min(chars.len());
+        let count = command.count.unwrap_or(1);
+        let mut copied = None;
+        if !matches!(
+            command.kind,
+            Kind::Move(_) | Kind::OperateSelection(_) | Kind::ToggleVisual
+        ) {
+            self.exit_visual(editor);
+        }
+
+        match command.kind {
+            Kind::Move(motion) => {
+                let target = motion_target(&chars, editor.input(), cursor, motion, command.count);
+                if self.is_visual {
+                    editor.select_with(|e| e.move_cursor_to_char_idx(target));
+                } else {
+                    editor.move_cursor_to_char_idx(target);
+                }
+            }
+            Kind::Operate(operator, target) => {
+                let (range, is_linewise) = Self::operator_range(
+                    &chars,
+                    editor.input(),
+                    cursor,
+                    operator,
+                    target,
+                    command.count,
+                );
+                copied = self.operate(
+                    editor,
+                    &chars,
+                    operator,
+                    command.register,
+                    range,
+                    is_linewise,
+                );
+            }
+            Kind::OperateSelection(operator) => {
+                let anchor = editor.selection_anchor().unwrap_or(cursor);
+                let range = anchor.min(cursor)..(anchor.max(cursor) + 1).min(chars.len());
+                self.exit_visual(editor);
+                copied = self.operate(editor, &chars, operator, command.register, range, false);
+            }
+            Kind::DeleteChar => {
+                let range = cursor..cursor.saturating_add(count).min(line_end(&chars, cursor));
+                if !range.is_empty() {
+                    copied = self.operate(
+                        editor,
+                        &chars,
+                        Operator::Delete,
+                        command.register,
+                        range,
+                        false,
+                    );
+                }
+            }
+            Kind::Put { before } => {
+                self.put(editor, &chars, cursor, command.register, count, before)
+            }
+            Kind::Insert(insert) => Self::insert(editor, &chars, cursor, insert),
+            Kind::Undo => {
+                for _ in 0..count {
+                    if !editor.can_undo() {
+                        break;
+                    }
+                    editor.undo();
+                }
+            }
+            Kind::Repeat => {
+                if let Some((mut change, inserted)) = self.last_change.clone() {
+                    change.count = command.count.or(change.count);
+                    copied = self.execute(editor, change);
+                    if editor.is_editing() {
+                        if let Some(inserted) = inserted {
+                            let cursor = editor.cursor_char_idx;
+                            editor.replace(cursor..cursor, &inserted);
+                        }
+                        self.finish_insert(editor);
+                    }
+                }
+                return copied;
+            }
+            Kind::ToggleVisual => {
+                if self.is_visual {
+                    self.exit_visual(editor);
+                } else {
+                    self.is_visual = true;
+                    editor.set_selection_anchor(Some(cursor));
+                }
+            }
+        }
+
+        if editor.is_editing() {
+            self.insert_start = Some(InsertStart {
+                command,
+                char_idx: editor.cursor_char_idx,
+                len: editor.input().chars().count(),
+            });
+        } else {
+            if command.is_change() {
+                self.last_change = Some((command, None));
+            }
+            self.clamp_cursor(editor);
+        }
+        copied
+    }
+
+    /// Returns char range an operator acts on and whether it is whole lines.
+    fn operator_range(
+        chars: &[char],
+        text: &str,
+        cursor: usize,
+        operator: Operator,
+        target: Target,
+        count: Option<usize>,
+    ) -> (Range<usize>, bool) {
+        let motion = match target {
+            Target::Line => {
+                let to = motion_target(
+                    chars,
+                    text,
+                    cursor,
+                    Motion::Down,
+                    Some(count.unwrap_or(1) - 1),
+                );
+                return (line_range(chars, cursor, to), true);
+            }
+            Target::Motion(motion) => motion,
+        };
+        // `cw` changes to the end of word like `ce`
+        let motion = if operator == Operator::Change
+            && motion == Motion::WordForward
+            && chars.get(cursor).is_some_and(|c| !c.is_whitespace())
+        {
+            Motion::WordEnd
+        } else {
+            motion
+        };
+        let to = motion_target(chars, text, cursor, motion, count);
+        if motion.is_linewise() {
+            return (line_range(chars, cursor, to), true);
+        }
+        let mut range = cursor.min(to)..cursor.max(to);
+        if motion.is_inclusive() {
+            range.end = (range.end + 1).min(chars.len());
+        }
+        // `dw` on the last word of a line stops at the line break
+        if motion == Motion::WordForward && line_end(chars, cursor) > cursor {
+            range.end = range.end.min(line_end(chars, cursor));
+        }
+        (range, false)
+    }
+
+    /// Yanks text in char `range` into registers, and deletes or changes it.
+    fn operate(
+        &mut self,
+        editor: &mut Editor,
+        chars: &[char],
+        operator: Operator,
+        register: Option<char>,
+        range: Range<usize>,
+        is_linewise: bool,
+    ) -> Option<String> {
+        let copied = self.store(
+            register,
+            Register {
+                text: chars[range.clone()].iter().collect(),
+                is_linewise,
+            },
+        );
+        match operator {
+            Operator::Yank if !is_linewise => editor.move_cursor_to_char_idx(range.start),
+            Operator::Yank => {}
+            Operator::Delete if is_linewise => {
+                // delete the line break after, or before the last line
+                let range = if range.end < chars.len() {
+                    range.start..range.end + 1
+                } else {
+                    range.start.saturating_sub(1)..range.end
+                };
+                editor.replace(range.clone(), "");
+                let chars: Vec<char> = editor.input().chars().collect();
+                let cursor = line_start(&chars, range.start.min(chars.len()));
+                editor.move_cursor_to_char_idx(first_non_blank(&chars, cursor));
+            }
+            Operator::Delete => editor.replace(range, ""),
+            Operator::Change => {
+                editor.replace(range, "");
+                editor.set_is_editing(true);
+            }
+        }
+        copied
+    }
+
+    /// Puts text of `register` after or before cursor, or below or above the line if linewise.
+    fn put(
+        &mut self,
+        editor: &mut Editor,
+        chars: &[char],
+        cursor: usize,
+        register: Option<char>,
+        count: usize,
+        before: bool,
+    ) {
+        let Some(register) = self
+            .registers
+            .get(&register.unwrap_or(UNNAMED_REGISTER))
+            .filter(|r| !r.text.is_empty())
+        else {
+            return;
+        };
+        if count.saturating_mul(register.text.chars().count() + 1) > MAX_PUT_CHARS {
+            return;
+        }
+        if register.is_linewise {
+            let text = vec![register.text.as_str(); count].join("\n");
+            if before {
+                let start = line_start(chars, cursor);
+                editor.replace(start..start, &format!("{text}\n"));
+                editor.move_cursor_to_char_idx(start);
+            } else {
+                let end = line_end(chars, cursor);
+                editor.replace(end..end, &format!("\n{text}"));
+                editor.move_cursor_to_char_idx(end + 1);
+            }
+        } else {
+            let text = register.text.repeat(count);
+            let idx = if before {
+                cursor
+            } else {
+                (cursor + 1).min(line_end(chars, cursor))
+            };
+            editor.replace(idx..idx, &text);
+            editor.move_cursor_left();
+        }
+    }
+
+    /// Enters insert mode, opening a line first for `o` and `O`.
+    fn insert(editor: &mut Editor, chars: &[char], cursor: usize, insert: Insert) {
+        let char_idx = match insert {
+            Insert::Cursor => cursor,
+            Insert::After => (cursor + 1).min(line_end(chars, cursor)),
+            Insert::LineStart => first_non_blank(chars, cursor),
+            Insert::LineEnd => line_end(chars, cursor),
+            Insert::LineBelow => {
+                let end = line_end(chars, cursor);
+                editor.replace(end..end, "\n");
+                end + 1
+            }
+            Insert::LineAbove => {
+                let start = line_start(chars, cursor);
+                editor.replace(start..start, "\n");
+                start
+            }
+        };
+        editor.move_cursor_to_char_idx(char_idx);
+        editor.set_is_editing(true);
+    }
+
+    /// Stores yanked or deleted text into the unnamed register and the given one, returns the
+    /// text if the given one is a system clipboard register.
+    fn store(&mut self, register: Option<char>, content: Register) -> Option<String> {
+        let copied = register
+            .filter(|r| is_clipboard_register(*r))
+            .map(|_| content.text.clone());
+        if let Some(register) = register {
+            self.registers.insert(register, content.clone());
+        }
+        self.registers.insert(UNNAMED_REGISTER, content);
+        copied
+    }
+
+    fn exit_visual(&mut self, editor: &mut Editor) {
+        if self.is_visual {
+            self.is_visual = false;
+            editor.set_selection_anchor(None);
+        }
+    }
+
+    /// Keeps cursor on a char in normal mode rather than after the end of line.
+    fn clamp_cursor(&self, editor: &mut Editor) {
+        let chars: Vec<char> = editor.input().chars().collect();
+        let cursor = editor.cursor_char_idx.min(chars.len());
+        if cursor == line_end(&chars, cursor) && cursor > line_start(&chars, cursor) {
+            if self.is_visual {
+                editor.select_with(Editor::move_cursor_left);
+            } else {
+                editor.move_cursor_left();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crossterm::event::{KeyCode, KeyModifiers};
+
+    use crate::{
+        app::model::{
+            editor::{Editor, WrapMode},
+            vim::{Outcome, Vim},
+        },
+        models::keymap::KeyChord,
+    };
+
+    /// Presses keys in normal mode, or types them in insert mode, `<` being esc.
+    fn press(vim: &mut Vim, editor: &mut Editor, keys: &str) -> Vec<String> {
+        let mut copied = Vec::new();
+        for c in keys.chars() {
+            match (editor.is_editing(), c) {
+                (true, '<') => vim.finish_insert(editor),
+                (true, c) => editor.enter_char(c),
+                (false, c) => {
+                    let key = match c {
+                        '<' => KeyChord::new(KeyCode::Esc, KeyModifiers::NONE),
+                        c => KeyChord::new(KeyCode::Char(c), KeyModifiers::NONE),
+                    };
+                    if let Outcome::Handled(Some(text)) = vim.handle_key(editor, key) {
+                        copied.push(text);
+                    }
+                }
+            }
+        }
+        copied
+    }
+
+    fn editor(input: &str, char_idx: usize) -> Editor {
+        let mut editor = Editor::new(input.to_string(), WrapMode::default());
+        editor.move_cursor_to_char_idx(char_idx);
+        editor
+    }
+
+    #[test]
+    fn edit_with_commands() {
+        let cases = [
+            // (input, cursor, keys, expected input, expected cursor)
+            ("foo bar baz", 0, "dw", "bar baz", 0),
+            ("foo bar baz", 0, "2dw", "baz", 0),
+            ("foo bar baz", 0, "d2w", "baz", 0),
+            ("foo bar baz", 4, "de", "foo  baz", 4),
+            ("foo bar baz", 4, "d$", "foo ", 3),
+            ("foo bar baz", 4, "D", "foo ", 3),
+            ("foo bar baz", 4, "d0", "bar baz", 0),
+            ("foo bar\nbaz", 4, "dw", "foo \nbaz", 3),
+            ("foo bar baz", 4, "cwqux<", "foo qux baz", 6),
+            ("foo bar baz", 0, "3x", " bar baz", 0),
+            ("foo bar baz", 10, "x", "foo bar ba", 9),
+            ("one\ntwo\nthree", 5, "dd", "one\nthree", 4),
+            ("one\ntwo\nthree", 5, "2dd", "one", 0),
+            ("one\ntwo\nthree", 5, "dj", "one", 0),
+            ("one\ntwo\nthree", 5, "dk", "three", 0),
+            ("one\ntwo\nthree", 5, "dgg", "three", 0),
+            ("one\ntwo\nthree", 5, "dG", "one", 0),
+            ("one\n  two\nthree", 6, "ccx<", "one\nx\nthree", 4),
+            ("one\ntwo", 0, "yyp", "one\none\ntwo", 4),
+            ("one\ntwo", 4, "yyP", "one\ntwo\ntwo", 4),
+            ("one\ntwo", 0, "yy2p", "one\none\none\ntwo", 4),
+            ("foo bar", 0, "ywP", "foo foo bar", 3),
+            ("foo bar", 0, "xp", "ofo bar", 1),
+            ("foo bar", 0, "\"ayw$\"ap", "foo barfoo ", 10),
+            ("one\ntwo", 0, "onew<", "one\nnew\ntwo", 6),
+            ("one\ntwo", 4, "Onew<", "one\nnew\ntwo", 6),
+            ("  foo", 4, "I-<", "  -foo", 2),
+            ("foo", 0, "A!<", "foo!", 3),
+            ("foo", 0, "a!<", "f!oo", 1),
+            ("foo bar baz", 0, "vey", "foo bar baz", 0),
+            ("foo bar baz", 0, "wvex", "foo  baz", 4),
+            ("foo bar baz", 4, "vecqux<", "foo qux baz", 6),
+            // repeat
+            ("a b c d", 0, "x..", " c d", 0),
+            ("foo bar baz qux", 0, "dw.", "baz qux", 0),
+            ("foo bar baz qux", 0, "dw2.", "qux", 0),
+            ("foo bar baz", 0, "cwx<w.", "x x baz", 2),
+            ("a\nb", 0, "A;<j.", "a;\nb;", 4),
+            // undo
+            ("foo bar baz", 0, "dwdwu", "bar baz", 0),
+            ("foo bar baz", 0, "dwdw2u", "foo bar baz", 0),
+            // motions
+            ("foo bar baz", 0, "wwx", "foo bar az", 8),
+            ("foo bar baz", 10, "bx", "foo bar az", 8),
+            ("foo bar baz", 0, "$x", "foo bar ba", 9),
+            ("foo bar baz", 0, "3lx", "foobar baz", 3),
+            ("one\ntwo\nthree", 1, "jx", "one\nto\nthree", 5),
+            ("one\ntwo\nthree", 1, "Gx", "one\ntwo\nhree", 8),
+            ("one\ntwo\nthree", 10, "ggx", "ne\ntwo\nthree", 0),
+            ("one\ntwo\nthree", 0, "2Gx", "one\nwo\nthree", 4),
+            // invalid commands are dropped
+            ("foo", 0, "dzx", "oo", 0),
+            // huge counts are capped and stop at either end
+            ("foo bar baz", 0, "99999999999999999999wx", "foo bar ba", 9),
+            ("foo bar baz", 10, "999999999bx", "oo bar baz", 0),
+            ("foo bar", 0, "99999999999lx", "foo ba", 5),
+            ("one\ntwo", 0, "99999999999jx", "one\nwo", 4),
+            ("foo bar baz", 0, "999999999x", "", 0),
+            ("foo bar baz", 0, "99999999999d99999999999w", "", 0),
+            ("foo bar baz", 0, "dwdw999999999u", "foo bar baz", 0),
+            ("foo", 0, "yl99999999999p", "foo", 0),
+        ];
+        for (input, cursor, keys, expected_input, expected_cursor) in cases {
+            let mut vim = Vim::default();
+            let mut editor = editor(input, cursor);
+            press(&mut vim, &mut editor, keys);
+            assert_eq!(
+                (editor.input(), editor.cursor_char_idx),
+                (expected_input, expected_cursor),
+                "{keys:?} on {input:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn registers() {
+        let mut vim = Vim::default();
+        let mut editor = editor("one\ntwo", 0);
+
+        // clipboard registers are copied to system clipboard too
+        assert_eq!(press(&mut vim, &mut editor, "\"+yy"), vec!["one"]);
+        assert_eq!(press(&mut vim, &mut editor, "j\"*dd"), vec!["two"]);
+        assert_eq!(press(&mut vim, &mut editor, "yw"), Vec::<String>::new());
+        assert_eq!(editor.input(), "one");
+
+        press(&mut vim, &mut editor, "\"+p");
+        assert_eq!(editor.input(), "one\none");
+        press(&mut vim, &mut editor, "\"bdd\"bP");
+        assert_eq!(editor.input(), "one\none");
+        // named register is kept across other yanks
+        press(&mut vim, &mut editor, "\"byyggx\"bp");
+        assert_eq!(editor.input(), "ne\none\none");
+    }
+
+    #[test]
+    fn fall_back_to_key_bindings() {
+        let mut vim = Vim::default();
+        let mut editor = editor("foo", 0);
+        let key = |c| KeyChord::new(KeyCode::Char(c), KeyModifiers::NONE);
+
+        assert_eq!(vim.handle_key(&mut editor, key('q')), Outcome::Unhandled);
+        assert_eq!(
+            vim.handle_key(
+                &mut editor,
+                KeyChord::new(KeyCode::Enter, KeyModifiers::NONE)
+            ),
+            Outcome::Unhandled
+        );
+        assert_eq!(
+            vim.handle_key(&mut editor, KeyChord::new(KeyCode::Esc, KeyModifiers::NONE)),
+            Outcome::Unhandled
+        );
+        // keys of a pending command are swallowed
+        assert_eq!(
+            vim.handle_key(&mut editor, key('d')),
+            Outcome::Handled(None)
+        );
+        assert_eq!(
+            vim.handle_key(&mut editor, key('q')),
+            Outcome::Handled(None)
+        );
+        assert_eq!(editor.input(), "foo");
+    }
+}
@@ -1,18 +1,31 @@
+mod help;
 mod input_editor;
 mod messages;
+mod session_manager;
+mod setting_manager;
+mod tag_editor;
 
-use crossterm::event::{KeyCode, KeyEvent, KeyModifiers, MouseEvent};
+use crossterm::event::{KeyEvent, MouseEvent, MouseEventKind};
 
 use crate::{
     app::{
         Command, Message,
-        model::{Model, focus::Focused, setting_manager::SettingManager},
+        model::{
+            Model, focus::Focused, help::Help, session_manager::SessionManager,
+            setting_manager::SettingManager, tag_editor::TagEditor, toast::Toast,
+        },
+    },
+    models::{
+        ServiceReq, ServiceResp,
+        keymap::{Action, KeyMatch},
     },
-    models::{ServiceReq, ServiceResp},
 };
 
 pub type Update = (Option<Message>, Option<Command>);
 
+/// Lines scrolled per mouse wheel step.
+const SCROLL_LINES: isize = 3;
+
 /// Updates model with message and optionally creates next message for chained update and command
 /// for side effect.
 pub fn update(model: &mut Model, msg: Message) -> Update {
@@ -23,7 +36,7 @@ pub fn update(model: &mut Model, msg: Message) -> Update {
         }
         Message::MouseEvent(evt) => return handle_mouse_event(model, evt),
         Message::ServiceResp(resp) => {
-            return handle_service_resp(model, resp);
+            return handle_service_resp(model, *resp);
         }
 
         /* ----- model wide activities ----- */
@@ -45,24 +58,152 @@ pub fn update(model: &mut Model, msg: Message) -> Update {
         }
         Message::Setting => match &mut model.setting_manager_popup {
             None => {
-                model.setting_manager_popup =
-                    Some(SettingManager::new(model.session.llm_settings()))
+                model.setting_manager_popup = Some(
+                    SettingManager::new(model.session.llm_settings())
+                        .with_providers(model.providers.clone()),
+                )
             }
             Some(setting_manager) => {
-                model
-                    .session
-                    .set_llm_settings(setting_manager.llm_settings());
-                model.setting_manager_popup = None;
+                // keep popup open to show validation error
+                if setting_manager.validate() {
+                    model
+                        .session
+                        .set_llm_settings(setting_manager.llm_settings());
+                    model.setting_manager_popup = None;
+                }
             }
         },
         Message::NewSession => {
             model.new_draft_chat();
         }
-        Message::DeleteSession => {
-            if let Some(session_id) = &model.selected_session_id {
+        Message::DeleteSessions => {
+            let session_ids = model.session_manager.take_marked_or_selected();
+            if session_ids.is_empty() {
+                return (None, None);
+            }
+            let count = sessions_count(session_ids.len());
+            let deletes_current = model
+                .selected_session_id
+                .as_ref()
+                .is_some_and(|id| session_ids.contains(id));
+            if model.session_manager.show_trash() {
+                model.toast = Some(Toast::new(format!("Deleted {count} forever"), Vec::new()));
+                return (
+                    deletes_current.then_some(Message::NewSession),
+                    Some(Command::ServiceReq(ServiceReq::PurgeSessions(session_ids))),
+                );
+            }
+            model.toast = Some(Toast::new(
+                format!("Moved {count} to trash, u to undo"),
+                session_ids.clone(),
+            ));
+            // navigate away from current session if it is deleted
+            let next_msg = deletes_current.then_some(if session_ids.len() == 1 {
+                Message::SelectNextSession
+            } else {
+                Message::NewSession
+            });
+            return (
+                next_msg,
+                Some(Command::ServiceReq(ServiceReq::DeleteSessions(session_ids))),
+            );
+        }
+        Message::RestoreSessions => {
+            let session_ids = if model.session_manager.show_trash() {
+                let session_ids = model.session_manager.take_marked_or_selected();
+                if !session_ids.is_empty() {
+                    let count = sessions_count(session_ids.len());
+                    model.toast = Some(Toast::new(format!("Restored {count}"), Vec::new()));
+                }
+                session_ids
+            } else {
+                match model.toast.take_if(|toast| !toast.undo().is_empty()) {
+                    Some(toast) => toast.undo().to_vec(),
+                    None => Vec::new(),
+                }
+            };
+            if !session_ids.is_empty() {
                 return (
-                    Some(Message::SelectNextSession),
-                    Some(Command::ServiceReq(ServiceReq::DeleteSession(
+                    None,
+                    Some(Command::ServiceReq(ServiceReq::RestoreSessions(
+                        session_ids,
+                    ))),
+                );
+            }
+        }
+        Message::ToastExpired => {
+            model.toast.take_if(|toast| toast.is_expired());
+        }
+        Message::RenameSession => {
+            if let Some((session_id, title)) = model.session_manager.finish_rename() {
+                return (
+                    None,
+                    Some(Command::ServiceReq(ServiceReq::RenameSession {
+                        session_id,
+                        title,
+                    })),
+                );
+            }
+        }
+        Message::PinSession => {
+            if let Some(session) = model.session_manager.selected() {
+                return (
+                    None,
+                    Some(Command::ServiceReq(ServiceReq::PinSession {
+                        session_id: session.id.clone(),
+                        pinned: !session.pinned,
+                    })),
+                );
+            }
+        }
+        Message::ArchiveSession => {
+            if let Some(session) = model.session_manager.selected() {
+                return (
+                    None,
+                    Some(Command::ServiceReq(ServiceReq::ArchiveSession {
+                        session_id: session.id.clone(),
+                        archived: !session.archived,
+                    })),
+                );
+            }
+        }
+        Message::CycleTheme => {
+            if let Some(name) = model.cycle_theme() {
+                model.toast = Some(Toast::new(format!("Theme: {name}"), Vec::new()));
+            }
+        }
+        Message::Help => {
+            model.help_popup = match model.help_popup {
+                None => Some(Help::new(&model.configs.keys, &model.help_contexts())),
+                Some(_) => None,
+            }
+        }
+        Message::Tags => match &model.tag_editor_popup {
+            None => {
+                if let Some(session) = model.session_manager.selected() {
+                    model.tag_editor_popup = Some(TagEditor::new(
+                        session.id.clone(),
+                        &session.tags,
+                        model.session_manager.tags(),
+                    ));
+                }
+            }
+            Some(tag_editor) => {
+                let cmd = Command::ServiceReq(ServiceReq::SetSessionTags {
+                    session_id: tag_editor.session_id().to_string(),
+                    tags: tag_editor.tags(),
+                });
+                model.tag_editor_popup = None;
+                return (None, Some(cmd));
+            }
+        },
+        Message::CancelResponse => {
+            if model.session.messages.pending_response().is_some()
+                && let Some(session_id) = model.session.session_id()
+            {
+                return (
+                    None,
+                    Some(Command::ServiceReq(ServiceReq::CancelResponse(
                         session_id.to_string(),
                     ))),
                 );
@@ -72,7 +213,15 @@ pub fn update(model: &mut Model, msg: Message) -> Update {
             let maybe_cmd = model
                 .handle_select_next_session()
                 .map(|id| Command::ServiceReq(ServiceReq::GetSession(id)));
-            return (None, maybe_cmd);
+            return (Some(Message::LoadMoreSessions), maybe_cmd);
+        }
+        Message::LoadMoreSessions => {
+            if let Some(cursor) = model.session_manager.load_more() {
+                return (
+                    None,
+                    Some(query_sessions(&model.session_manager, Some(cursor))),
+                );
+            }
         }
         Message::SelectPrevSession => {
             let maybe_cmd = model
@@ -94,12 +243,61 @@ pub fn update(model: &mut Model, msg: Message) -> Update {
     (None, None)
 }
 
+/// Requests page of sessions listed in session manager after `cursor`.
+fn query_sessions(session_manager: &SessionManager, cursor: Option<String>) -> Command {
+    Command::ServiceReq(ServiceReq::QuerySessions {
+        query: session_manager.query().to_string(),
+        cursor,
+        trash: session_manager.show_trash(),
+        include_archived: session_manager.show_archived(),
+    })
+}
+
+fn sessions_count(count: usize) -> String {
+    if count == 1 {
+        "1 session".to_string()
+    } else {
+        format!("{count} sessions")
+    }
+}
+
 fn handle_service_resp(model: &mut Model, resp: ServiceResp) -> Update {
     match resp {
         ServiceResp::ChatEvent(chat_event) => model.session.handle_chat_event(chat_event),
-        ServiceResp::Sessions(session_summaries) => model
-            .session_manager
-            .handle_session_summaries(session_summaries, model.selected_session_id.clone()),
+        // sessions are listed page by page, reload on sessions created or deleted
+        ServiceResp::SessionsChanged => {
+            model.session_manager.reload();
+            return (None, Some(query_sessions(&model.session_manager, None)));
+        }
+        ServiceResp::SessionQuery {
+            query,
+            cursor,
+            trash,
+            include_archived,
+            sessions,
+            next_cursor,
+        } => {
+            model.session_manager.handle_session_query(
+                &query,
+                cursor,
+                trash,
+                include_archived,
+                sessions,
+                next_cursor,
+            );
+            // restore selection of session loaded in an earlier page
+            model
+                .session_manager
+                .set_selected(model.selected_session_id.clone());
+        }
+        ServiceResp::Events {
+            session_id,
+            before,
+            events,
+            next_cursor,
+        } => model
+            .session
+            .handle_events(&session_id, &before, events, next_cursor),
         ServiceResp::Session(session) => {
             if model.selected_session_id.as_ref() == Some(&session.id) {
                 model.session.handle_session(session);
@@ -114,8 +312,25 @@ fn handle_service_resp(model: &mut Model, resp: ServiceResp) -> Update {
             model
                 .session_manager
                 .handle_session_summary(session_summary);
+            // the session may no longer match filter
+            if !model.session_manager.query().is_empty() {
+                model.session_manager.reload();
+                return (None, Some(query_sessions(&model.session_manager, None)));
+            }
         }
+        ServiceResp::Providers(providers) => model.providers = providers,
+        // only the server proxies conversations
+        ServiceResp::Conversation { .. } => {}
         ServiceResp::Error(msg) => model.error_message = Some(msg),
+        ServiceResp::Notice(msg) => model.toast = Some(Toast::new(msg, Vec::new())),
+        ServiceResp::ChatFailed { session_id, error } => {
+            model.session.handle_chat_failed(&session_id);
+            // details such as response bodies are logged
+            let error = error.lines().next().unwrap_or_default();
+            model.toast = Some(Toast::new(format!("Chat failed: {error}"), Vec::new()));
+        }
+        // the daemon unwraps replies, an in process service sends them as is
+        ServiceResp::ToClient { resp, .. } => return handle_service_resp(model, *resp),
     }
     (None, None)
 }
@@ -126,82 +341,127 @@ fn handle_key_event(model: &mut Model, evt: KeyEvent) -> Update {
         model.quit()
     }
 
-    if let Some(setting_manager) = &mut model.setting_manager_popup {
-        match evt.code {
-            KeyCode::Down | KeyCode::Char('j') => {
-                setting_manager.select_next();
-                return (None, None);
-            }
-            KeyCode::Up | KeyCode::Char('k') => {
-                setting_manager.select_previous();
-                return (None, None);
-            }
-            KeyCode::Esc => model.setting_manager_popup = None,
-            KeyCode::Enter => return (Some(Message::Setting), None),
-            _ => {}
+    if let Some(update) = input_editor::handle_vim_key_event(model, evt) {
+        return update;
+    }
+    let action = match model.resolve_key(evt) {
+        KeyMatch::Action(action) => Some(action),
+        KeyMatch::Pending => return (None, None),
+        KeyMatch::Unbound => None,
+    };
+    match action {
+        Some(Action::Quit) => {
+            model.quit();
+            return (None, None);
         }
-        return (None, None);
+        Some(Action::ToggleSidebar) => {
+            model.toggle_sidebar();
+            return (None, None);
+        }
+        Some(Action::ShiftFocus) => {
+            model.shift_focus();
+            return (None, None);
+        }
+        Some(Action::NewSession) => return (Some(Message::NewSession), None),
+        Some(Action::Edit) => return (Some(Message::Editing), None),
+        Some(Action::Settings) => return (Some(Message::Setting), None),
+        Some(Action::Help) => return (Some(Message::Help), None),
+        Some(Action::CycleTheme) => return (Some(Message::CycleTheme), None),
+        _ => {}
+    }
+
+    if model.help_popup.is_some() {
+        return help::handle_key_event(model, evt, action);
+    }
+    if model.setting_manager_popup.is_some() {
+        return setting_manager::handle_key_event(model, evt, action);
+    }
+    if model.tag_editor_popup.is_some() {
+        return tag_editor::handle_key_event(model, evt, action);
     }
 
     match model.focused {
-        Focused::InputEditor => {
-            return input_editor::handle_key_event(model, evt);
-        }
-        Focused::Messages => {
-            return messages::handle_key_event(model, evt);
-        }
-        Focused::SessionManager => match (evt.code, evt.modifiers) {
-            (KeyCode::Char('q'), _) => model.quit(),
-            (KeyCode::Char('e'), KeyModifiers::CONTROL) => model.toggle_sidebar(),
-            (KeyCode::Char('n'), _) => return (Some(Message::NewSession), None),
-            (KeyCode::Char('d'), _) => return (Some(Message::DeleteSession), None),
-            (KeyCode::Char('i'), _) => return (Some(Message::Editing), None),
-            (KeyCode::Char('s'), _) => return (Some(Message::Setting), None),
-            (KeyCode::Down | KeyCode::Char('j'), _) => {
-                return (Some(Message::SelectNextSession), None);
-            }
-            (KeyCode::Up | KeyCode::Char('k'), _) => {
-                return (Some(Message::SelectPrevSession), None);
-            }
-            (KeyCode::Tab, _) => model.shift_focus(),
-            _ => {}
-        },
+        Focused::InputEditor => input_editor::handle_key_event(model, evt, action),
+        Focused::Messages => messages::handle_key_event(model, evt, action),
+        Focused::SessionManager => session_manager::handle_key_event(model, evt, action),
     }
-    (None, None)
 }
 
 fn handle_mouse_event(model: &mut Model, evt: MouseEvent) -> Update {
     tracing::debug!(?evt);
-    // TODO: handle popups
+    // popups capture the mouse
+    if model.error_message.is_some() || model.tag_editor_popup.is_some() {
+        return (None, None);
+    }
+    if let Some(help) = &mut model.help_popup {
+        match evt.kind {
+            MouseEventKind::ScrollDown => help.scroll_down(),
+            MouseEventKind::ScrollUp => help.scroll_up(),
+            _ => {}
+        }
+        return (None, None);
+    }
+    if let Some(setting_manager) = &mut model.setting_manager_popup {
+        match evt.kind {
+            MouseEventKind::ScrollDown => setting_manager.select_next(),
+            MouseEventKind::ScrollUp => setting_manager.select_previous(),
+            _ => {}
+        }
+        return (None, None);
+    }
+
+    // keep selecting messages while dragging out of them
+    if model.focused == Focused::Messages
+        && matches!(evt.kind, MouseEventKind::Drag(_) | MouseEventKind::Up(_))
+    {
+        let evt = model
+            .session
+            .messages
+            .viewport
+            .area()
+            .clamped_mouse_event(evt);
+        return messages::handle_mouse_event(model, evt);
+    }
+
     if model.show_sidebar
-        && let Some(_) = model.session_manager.area().maybe_mouse_event(evt)
+        && model
+            .session_manager
+            .area()
+            .maybe_mouse_event(evt)
+            .is_some()
     {
         model.shift_focus_to(Focused::SessionManager);
-    } else if model
+        return session_manager::handle_mouse_event(model, evt);
+    }
+    if let Some(evt) = model
         .session
         .messages
         .viewport
         .area()
         .maybe_mouse_event(evt)
-        .is_some()
     {
         model.shift_focus_to(Focused::Messages);
-    } else if model
+        return messages::handle_mouse_event(model, evt);
+    }
+    if let Some(evt) = model
         .session
         .input_editor
         .viewport
         .area()
         .maybe_mouse_event(evt)
-        .is_some()
     {
         model.shift_focus_to(Focused::InputEditor);
+        return input_editor::handle_mouse_event(model, evt);
     }
     (None, None)
 }
 
 #[cfg(test)]
 mod tests {
-    use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+    use crossterm::event::{
+        KeyCode, KeyEvent, KeyModifiers, MouseButton, MouseEvent, MouseEventKind,
+    };
+    use ratatui::text::Line;
     use rstest::{fixture, rstest};
     use tracing::{
         level_filters::LevelFilter,
@@ -211,10 +471,13 @@ mod tests {
 
     use crate::{
         app::{
+            Command,
             model::{Model, focus::Focused},
-            update::{self, handle_key_event},
+            update::{self, handle_key_event, handle_mouse_event, handle_service_resp},
+            view::utils::area::Area,
         },
-        models::configs::Config,
+        chat::*,
+        models::{ServiceResp, configs::Config},
     };
 
     #[fixture]
@@ -353,4 +616,261 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn notice_keeps_app_running() {
+        let mut model = Model::new(Config::default());
+        handle_service_resp(
+            &mut model,
+            ServiceResp::Notice("session s1 not found".to_string()),
+        );
+        assert_eq!(model.error_message, None);
+        assert_eq!(
+            model.toast.as_ref().map(|t| t.message()),
+            Some("session s1 not found")
+        );
+
+        // e.g. of importing a session that exists, keys are handled rather than quitting
+        handle_service_resp(
+            &mut model,
+            ServiceResp::Notice("session s1 already exists".to_string()),
+        );
+        handle_key_event(
+            &mut model,
+            KeyEvent::new(KeyCode::Char('j'), KeyModifiers::NONE),
+        );
+        assert!(!model.should_quit);
+    }
+
+    #[test]
+    fn mouse_select_and_copy() {
+        let mut model = Model::new(Config::default());
+        let events = vec![ChatEvent::new(
+            "session".to_string(),
+            None,
+            chat_event::Payload::Message(Message {
+                role: Role::Assistant as i32,
+                msg: "hello world".to_string(),
+                response_ref: None,
+            }),
+        )];
+        let viewport = &mut model.session.messages.viewport;
+        viewport.build_lines(&events, None);
+        viewport.set_viewport_width(40);
+        viewport.set_area(Area {
+            column: 0,
+            row: 1,
+            height: 10,
+            width: 40,
+        });
+
+        let mouse = |kind, column, row| MouseEvent {
+            kind,
+            column,
+            row,
+            modifiers: KeyModifiers::NONE,
+        };
+        // click focuses messages and places cursor
+        handle_mouse_event(
+            &mut model,
+            mouse(MouseEventKind::Down(MouseButton::Left), 6, 1),
+        );
+        assert_eq!(model.focused, Focused::Messages);
+        assert_eq!(model.session.messages.viewport.cursor_position(), (6, 0));
+
+        // drag selects from where it started and copies on release
+        handle_mouse_event(
+            &mut model,
+            mouse(MouseEventKind::Drag(MouseButton::Left), 10, 1),
+        );
+        let (_, cmd) = handle_mouse_event(
+            &mut model,
+            mouse(MouseEventKind::Up(MouseButton::Left), 10, 1),
+        );
+        let Some(Command::CopyToClipboard(selected)) = cmd else {
+            panic!("expected copy");
+        };
+        assert_eq!(selected, "world");
+        assert!(!model.session.messages.viewport.is_visual_selecting());
+    }
+
+    #[test]
+    fn search_messages() {
+        let mut model = Model::new(Config::default());
+        model.shift_focus_to(Focused::Messages);
+        let events = vec![ChatEvent::new(
+            "session".to_string(),
+            None,
+            chat_event::Payload::Message(Message {
+                role: Role::Assistant as i32,
+                msg: "Rust is fun, rust is fast".to_string(),
+                response_ref: None,
+            }),
+        )];
+        let viewport = &mut model.session.messages.viewport;
+        viewport.build_lines(&events, None);
+        viewport.set_viewport_width(40);
+        viewport.move_cursor_to_end();
+
+        let key = |code| KeyEvent::new(code, KeyModifiers::NONE);
+        let highlighted = |model: &Model| -> Vec<String> {
+            model
+                .session
+                .messages
+                .viewport
+                .lines()
+                .iter()
+                .flat_map(|l| Line::from(l).spans)
+                .filter(|s| s.style.bg.is_some())
+                .map(|s| s.content.to_string())
+                .collect()
+        };
+
+        // moves cursor to the first match while typing, wrapping around
+        for c in "/rust".chars() {
+            handle_key_event(&mut model, key(KeyCode::Char(c)));
+        }
+        handle_key_event(&mut model, key(KeyCode::Enter));
+        let viewport = &model.session.messages.viewport;
+        assert_eq!(viewport.cursor_position(), (0, 0));
+        assert_eq!(viewport.current_match(), Some(0));
+        assert_eq!(highlighted(&model), vec!["Rust", "rust"]);
+
+        handle_key_event(&mut model, key(KeyCode::Char('n')));
+        let viewport = &mut model.session.messages.viewport;
+        assert_eq!(viewport.cursor_position(), (13, 0));
+        assert_eq!(viewport.current_match(), Some(1));
+
+        // highlights follow wrapped lines
+        viewport.set_viewport_width(8);
+        assert_eq!(viewport.lines()[2].content(), "rust is ");
+        assert_eq!(highlighted(&model), vec!["Rust", "rust"]);
+
+        handle_key_event(&mut model, key(KeyCode::Char('N')));
+        assert_eq!(model.session.messages.viewport.current_match(), Some(0));
+
+        handle_key_event(&mut model, key(KeyCode::Esc));
+        assert!(highlighted(&model).is_empty());
+    }
+
+    #[test]
+    fn messages_motions() {
+        let mut model = Model::new(Config::default());
+        model.shift_focus_to(Focused::Messages);
+        let message = |role: Role, msg: &str| {
+            ChatEvent::new(
+                "session".to_string(),
+                None,
+                chat_event::Payload::Message(Message {
+                    role: role as i32,
+                    msg: msg.to_string(),
+                    response_ref: None,
+                }),
+            )
+            .with_created_at(prost_types::Timestamp::default())
+        };
+        let events = vec![
+            message(Role::User, "hello world"),
+            message(
+                Role::Assistant,
+                "Intro text\n\n```rust\nfn main() {}\n```\n\nDone.",
+            ),
+        ];
+        let viewport = &mut model.session.messages.viewport;
+        viewport.build_lines(&events, None);
+        viewport.set_viewport_width(40);
+        viewport.set_area(Area {
+            column: 0,
+            row: 1,
+            height: 4,
+            width: 40,
+        });
+        viewport.move_cursor_to_start();
+
+        let mut press = |keys: &str| {
+            let mut cmd = None;
+            for c in keys.chars() {
+                (_, cmd) = handle_key_event(
+                    &mut model,
+                    KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE),
+                );
+            }
+            cmd.map(|cmd| {
+                let Command::CopyToClipboard(yanked) = cmd else {
+                    panic!("expected copy");
+                };
+                yanked
+            })
+        };
+
+        // jumps to the assistant message and moves by words
+        press("]]");
+        assert_eq!(press("w"), None);
+        assert_eq!(press("e"), None);
+        assert_eq!(press("v0y").as_deref(), Some("Intro text"));
+        press("$");
+        assert_eq!(press("vy").as_deref(), Some("t"));
+
+        // yanks text objects under cursor
+        press("jjj");
+        assert_eq!(press("acy").as_deref(), Some("fn main() {}"));
+        assert_eq!(
+            press("amy").as_deref(),
+            Some("Intro text\n\n```rust\nfn main() {}\n```\n\nDone.")
+        );
+        press("[[[[");
+        assert_eq!(press("amy").as_deref(), Some("hello world"));
+        assert_eq!(press("Vy").as_deref(), Some("└─> hello world"));
+
+        // scrolls half a page moving cursor along
+        press("gg");
+        handle_key_event(
+            &mut model,
+            KeyEvent::new(KeyCode::Char('d'), KeyModifiers::CONTROL),
+        );
+        let viewport = &mut model.session.messages.viewport;
+        assert_eq!(viewport.cursor_position(), (0, 2));
+        assert_eq!(viewport.scroll_state().vertical_scroll_offset, 2);
+    }
+
+    #[test]
+    fn vim_input_editor() {
+        let mut configs = Config::default();
+        configs.editor.vim = true;
+        let mut model = Model::new(configs);
+        fn press(model: &mut Model, keys: &str) -> Option<Command> {
+            let mut cmd = None;
+            for c in keys.chars() {
+                let evt = match c {
+                    '<' => KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE),
+                    c => KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE),
+                };
+                (_, cmd) = handle_key_event(model, evt);
+            }
+            cmd
+        }
+
+        // typing is insert mode
+        press(&mut model, "hello world<");
+        press(&mut model, "0dw");
+        assert!(matches!(
+            press(&mut model, "\"+yy"),
+            Some(Command::CopyToClipboard(yanked)) if yanked == "world"
+        ));
+        press(&mut model, "A, bye<");
+        assert_eq!(model.session.input_editor.input(), "world, bye");
+        assert!(!model.session.input_editor.is_editing());
+
+        // keys vim leaves alone go to key bindings
+        let mut cmd = None;
+        for c in ['x', 'e'] {
+            (_, cmd) = handle_key_event(
+                &mut model,
+                KeyEvent::new(KeyCode::Char(c), KeyModifiers::CONTROL),
+            );
+        }
+        assert!(matches!(cmd, Some(Command::ExternalEditing(input)) if input == "world, bye"));
+        press(&mut model, "q");
+        assert!(model.should_quit);
+    }
 }
@@ -0,0 +1,30 @@
+use crossterm::event::{KeyCode, KeyEvent};
+
+use crate::app::{model::Model, update::Update};
+use crate::models::keymap::{Action, KeyChord};
+
+pub fn handle_key_event(model: &mut Model, evt: KeyEvent, action: Option<Action>) -> Update {
+    let Some(help) = &mut model.help_popup else {
+        return (None, None);
+    };
+    if help.is_searching() {
+        match (action, KeyChord::from(evt).char()) {
+            (None, Some(c)) => help.push(c),
+            (None, None) if evt.code == KeyCode::Backspace => help.backspace(),
+            (Some(Action::Cancel), _) => help.clear_search(),
+            (Some(Action::Save), _) => help.stop_search(),
+            _ => {}
+        }
+        return (None, None);
+    }
+    match action {
+        Some(Action::Cancel) => model.help_popup = None,
+        Some(Action::Filter) => help.start_search(),
+        Some(Action::MoveDown) => help.scroll_down(),
+        Some(Action::MoveUp) => help.scroll_up(),
+        Some(Action::MoveTop) => help.scroll_to_top(),
+        Some(Action::MoveBottom) => help.scroll_to_bottom(),
+        _ => {}
+    }
+    (None, None)
+}
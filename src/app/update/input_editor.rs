@@ -1,57 +1,83 @@
-use crossterm::event::{KeyCode, KeyEvent, KeyModifiers, MouseEvent};
+use crossterm::event::{KeyEvent, MouseButton, MouseEvent, MouseEventKind};
 
-use crate::app::model::focus::Focused;
 use crate::app::Command;
-use crate::app::model::Model;
-use crate::app::{Message, update::Update};
+use crate::app::model::{Model, editor::Editor, vim::Outcome};
+use crate::app::{
+    Message,
+    update::{SCROLL_LINES, Update},
+};
+use crate::models::keymap::{Action, KeyChord};
 
-pub fn handle_key_event(
-    model: &mut Model,
-    KeyEvent {
-        code,
-        modifiers,
-        kind: _,
-        state: _,
-    }: KeyEvent,
-) -> Update {
-    let session = &mut model.session;
-    let editor = &mut session.input_editor;
-    if editor.is_editing() {
-        match (code, modifiers) {
-            (KeyCode::Char('e'), KeyModifiers::CONTROL) => model.toggle_sidebar(),
-            (KeyCode::Char(c), _) => editor.enter_char(c),
-            (KeyCode::Backspace, _) => editor.delete_char(),
-            (KeyCode::Left, _) => editor.move_cursor_left(),
-            (KeyCode::Right, _) => editor.move_cursor_right(),
-            (KeyCode::Esc, _) => editor.set_is_editing(false),
-            (KeyCode::Tab, _) => model.shift_focus(),
-            (KeyCode::Enter, KeyModifiers::SHIFT) => editor.enter_char('\n'),
-            (KeyCode::Enter, _) => return (Some(Message::Send), None),
-            (KeyCode::Down, _) => editor.move_cursor_down(),
-            (KeyCode::Up, _) => editor.move_cursor_up(),
-            _ => {}
+pub fn handle_key_event(model: &mut Model, evt: KeyEvent, action: Option<Action>) -> Update {
+    let editor = &mut model.session.input_editor;
+    let Some(action) = action else {
+        // type unbound characters while editing
+        if editor.is_editing()
+            && let Some(c) = KeyChord::from(evt).char()
+        {
+            editor.enter_char(c);
         }
-    } else {
-        match (code, modifiers) {
-            (KeyCode::Char('q'), _) => model.quit(),
-            (KeyCode::Char('e'), KeyModifiers::CONTROL) => model.toggle_sidebar(),
-            (KeyCode::Char('n'), _) => return (Some(Message::NewSession), None),
-            (KeyCode::Tab, _) => model.shift_focus(),
-            (KeyCode::Char('i'), _) => return (Some(Message::Editing), None),
-            (KeyCode::Char('s'), _) => return (Some(Message::Setting), None),
-            (KeyCode::Char('e'), _) => {
-                return (
-                    None,
-                    Some(Command::ExternalEditing(editor.input().to_string())),
-                );
-            }
-            (KeyCode::Enter, _) => return (Some(Message::Send), None),
-            (KeyCode::Left | KeyCode::Char('h'), _) => editor.move_cursor_left(),
-            (KeyCode::Right | KeyCode::Char('l'), _) => editor.move_cursor_right(),
-            (KeyCode::Down | KeyCode::Char('j'), _) => editor.move_cursor_down(),
-            (KeyCode::Up | KeyCode::Char('k'), _) => editor.move_cursor_up(),
-            _ => {}
+        return (None, None);
+    };
+    match action {
+        Action::Send => return (Some(Message::Send), None),
+        Action::StopEditing => match &mut model.vim {
+            Some(vim) => vim.finish_insert(editor),
+            None => editor.set_is_editing(false),
+        },
+        Action::NewLine => editor.enter_char('\n'),
+        Action::DeleteChar => editor.delete_char(),
+        Action::DeleteCharForward => editor.delete_char_forward(),
+        Action::KillLineEnd => editor.kill_to_line_end(),
+        Action::KillLineStart => editor.kill_to_line_start(),
+        Action::KillWordBackward => editor.kill_word_backward(),
+        Action::KillWordForward => editor.kill_word_forward(),
+        Action::PasteKilled => editor.paste_killed(),
+        Action::CycleKilled => editor.cycle_killed(),
+        Action::Undo => editor.undo(),
+        Action::Redo => editor.redo(),
+        Action::ExternalEditor => {
+            return (
+                None,
+                Some(Command::ExternalEditing(editor.input().to_string())),
+            );
         }
+        Action::MoveLeft => editor.move_cursor_left(),
+        Action::MoveRight => editor.move_cursor_right(),
+        Action::MoveDown => editor.move_cursor_down(),
+        Action::MoveUp => editor.move_cursor_up(),
+        Action::MoveWordForward => editor.move_cursor_to_next_word(),
+        Action::MoveWordBackward => editor.move_cursor_to_previous_word(),
+        Action::MoveLineStart => editor.move_cursor_to_line_start(),
+        Action::MoveLineEnd => editor.move_cursor_to_line_end(),
+        Action::SelectLeft => editor.select_with(Editor::move_cursor_left),
+        Action::SelectRight => editor.select_with(Editor::move_cursor_right),
+        Action::SelectUp => editor.select_with(Editor::move_cursor_up),
+        Action::SelectDown => editor.select_with(Editor::move_cursor_down),
+        Action::SelectLineStart => editor.select_with(Editor::move_cursor_to_line_start),
+        Action::SelectLineEnd => editor.select_with(Editor::move_cursor_to_line_end),
+        _ => {}
+    }
+    (None, None)
+}
+
+/// Feeds key to vim, returns `None` if vim leaves it to key bindings.
+pub fn handle_vim_key_event(model: &mut Model, evt: KeyEvent) -> Option<Update> {
+    let (vim, editor) = model.vim_mut()?;
+    match vim.handle_key(editor, evt.into()) {
+        Outcome::Handled(copied) => Some((None, copied.map(Command::CopyToClipboard))),
+        Outcome::Unhandled => None,
+    }
+}
+
+/// Handles mouse event relative to input editor area.
+pub fn handle_mouse_event(model: &mut Model, evt: MouseEvent) -> Update {
+    let editor = &mut model.session.input_editor;
+    match evt.kind {
+        MouseEventKind::Down(MouseButton::Left) => editor.click((evt.column, evt.row)),
+        MouseEventKind::ScrollDown => editor.scroll_by(SCROLL_LINES),
+        MouseEventKind::ScrollUp => editor.scroll_by(-SCROLL_LINES),
+        _ => {}
     }
     (None, None)
 }
@@ -1,28 +1,34 @@
-use crossterm::event::{KeyCode, KeyEvent, KeyModifiers, MouseEvent};
+use crossterm::event::{KeyCode, KeyEvent, MouseButton, MouseEvent, MouseEventKind};
 
-use crate::app::model::focus::Focused;
 use crate::app::Command;
 use crate::app::model::Model;
-use crate::app::{Message, update::Update};
+use crate::app::{
+    Message,
+    update::{SCROLL_LINES, Update},
+};
+use crate::models::ServiceReq;
+use crate::models::keymap::{Action, KeyChord};
 
-pub fn handle_key_event(
-    model: &mut Model,
-    KeyEvent {
-        code,
-        modifiers,
-        kind: _,
-        state: _,
-    }: KeyEvent,
-) -> Update {
+pub fn handle_key_event(model: &mut Model, evt: KeyEvent, action: Option<Action>) -> Update {
     let messages = &mut model.session.messages;
-    match (code, modifiers) {
-        (KeyCode::Char('q'), _) => model.quit(),
-        (KeyCode::Char('e'), KeyModifiers::CONTROL) => model.toggle_sidebar(),
-        (KeyCode::Char('n'), _) => return (Some(Message::NewSession), None),
-        (KeyCode::Tab, _) => model.shift_focus(),
-        (KeyCode::Char('i'), _) => return (Some(Message::Editing), None),
-        (KeyCode::Char('s'), _) => return (Some(Message::Setting), None),
-        (KeyCode::Char('e'), _) => {
+    if messages.viewport.is_search_editing() {
+        let viewport = &mut messages.viewport;
+        match (action, KeyChord::from(evt).char()) {
+            (None, Some(c)) => viewport.edit_search(|search| search.push(c)),
+            (None, None) if evt.code == KeyCode::Backspace => {
+                viewport.edit_search(|search| search.backspace())
+            }
+            (Some(Action::ToggleRegex), _) => viewport.edit_search(|search| search.toggle_regex()),
+            (Some(Action::Cancel), _) => viewport.clear_search(),
+            (Some(Action::Save), _) => viewport.stop_search_editing(),
+            _ => {}
+        }
+        return (None, None);
+    }
+
+    match action {
+        Some(Action::CancelResponse) => return (Some(Message::CancelResponse), None),
+        Some(Action::ExternalEditor) => {
             return (
                 None,
                 Some(Command::ExternalEditingReadOnly(
@@ -30,21 +36,93 @@ pub fn handle_key_event(
                 )),
             );
         }
-        (KeyCode::Char('v'), _) => messages.viewport.toggle_visual_selection(),
-        (KeyCode::Char('y'), _) => {
+        Some(Action::ToggleVisual) => messages.viewport.toggle_visual_selection(),
+        Some(Action::ToggleVisualLine) => messages.viewport.toggle_visual_line_selection(),
+        Some(Action::SelectMessage) => messages.viewport.select_message(),
+        Some(Action::SelectCodeBlock) => messages.viewport.select_code_block(),
+        Some(Action::Yank) => {
             if let Some(selected) = messages.viewport.yank_visual_selection() {
                 return (None, Some(Command::CopyToClipboard(selected)));
             }
         }
-        (KeyCode::Esc, _) => messages.viewport.clear_visual_selection(),
-        // KeyCode::Down => messages.scroll_down(),
-        // KeyCode::Up => messages.scroll_up(),
-        (KeyCode::Left | KeyCode::Char('h'), _) => messages.viewport.move_cursor_left(),
-        (KeyCode::Right | KeyCode::Char('l'), _) => messages.viewport.move_cursor_right(),
-        (KeyCode::Down | KeyCode::Char('j'), _) => messages.viewport.move_cursor_down(),
-        (KeyCode::Up | KeyCode::Char('k'), _) => messages.viewport.move_cursor_up(),
+        Some(Action::Clear) => {
+            messages.viewport.clear_visual_selection();
+            messages.viewport.clear_search();
+        }
+        Some(Action::Filter) => messages.viewport.start_search(),
+        Some(Action::NextMatch) => messages.viewport.move_cursor_to_next_match(),
+        Some(Action::PreviousMatch) => messages.viewport.move_cursor_to_previous_match(),
+        Some(Action::MoveLeft) => messages.viewport.move_cursor_left(),
+        Some(Action::MoveRight) => messages.viewport.move_cursor_right(),
+        Some(Action::MoveDown) => messages.viewport.move_cursor_down(),
+        Some(Action::MoveTop) => messages.viewport.move_cursor_to_start(),
+        Some(Action::MoveBottom) => messages.viewport.move_cursor_to_end(),
+        Some(Action::MoveWordForward) => messages.viewport.move_cursor_to_next_word(),
+        Some(Action::MoveWordBackward) => messages.viewport.move_cursor_to_previous_word(),
+        Some(Action::MoveWordEnd) => messages.viewport.move_cursor_to_word_end(),
+        Some(Action::MoveLineStart) => messages.viewport.move_cursor_to_line_start(),
+        Some(Action::MoveLineEnd) => messages.viewport.move_cursor_to_line_end(),
+        Some(Action::HalfPageDown) => messages.viewport.scroll_half_page(true),
+        Some(Action::HalfPageUp) => messages.viewport.scroll_half_page(false),
+        Some(Action::NextMessage) => messages.viewport.move_cursor_to_next_message(),
+        Some(Action::PreviousMessage) => messages.viewport.move_cursor_to_previous_message(),
+        Some(Action::MoveUp) => {
+            // load older messages on moving past the first line
+            if messages.viewport.cursor_position().1 == 0
+                && let Some(cmd) = load_older(model)
+            {
+                return (None, Some(cmd));
+            }
+            model.session.messages.viewport.move_cursor_up()
+        }
         _ => {}
     }
     (None, None)
 }
 
+/// Handles mouse event relative to messages area.
+pub fn handle_mouse_event(model: &mut Model, evt: MouseEvent) -> Update {
+    let viewport = &mut model.session.messages.viewport;
+    let position = (evt.column, evt.row);
+    match evt.kind {
+        MouseEventKind::Down(MouseButton::Left) => {
+            viewport.clear_visual_selection();
+            viewport.click(position);
+        }
+        MouseEventKind::Drag(MouseButton::Left) => {
+            // select from where the drag started
+            if !viewport.is_visual_selecting() {
+                viewport.toggle_visual_selection();
+            }
+            viewport.click(position);
+        }
+        MouseEventKind::Up(MouseButton::Left) => {
+            if let Some(selected) = viewport.yank_visual_selection() {
+                return (None, Some(Command::CopyToClipboard(selected)));
+            }
+        }
+        MouseEventKind::ScrollDown => viewport.scroll_by(SCROLL_LINES),
+        MouseEventKind::ScrollUp => {
+            // load older messages on scrolling past the top
+            if viewport.scroll_state().vertical_scroll_offset == 0
+                && let Some(cmd) = load_older(model)
+            {
+                return (None, Some(cmd));
+            }
+            model.session.messages.viewport.scroll_by(-SCROLL_LINES);
+        }
+        _ => {}
+    }
+    (None, None)
+}
+
+/// Returns request for the page of events before loaded ones if there are more.
+fn load_older(model: &mut Model) -> Option<Command> {
+    let session = &mut model.session;
+    let session_id = session.session_id.clone()?;
+    let before = session.messages.load_older()?;
+    Some(Command::ServiceReq(ServiceReq::GetEvents {
+        session_id,
+        before,
+    }))
+}
@@ -0,0 +1,93 @@
+use crossterm::event::{KeyCode, KeyEvent, MouseButton, MouseEvent, MouseEventKind};
+
+use crate::{
+    app::{
+        Command, Message,
+        model::Model,
+        update::{Update, query_sessions},
+    },
+    models::{
+        ServiceReq,
+        keymap::{Action, KeyChord},
+    },
+};
+
+pub fn handle_key_event(model: &mut Model, evt: KeyEvent, action: Option<Action>) -> Update {
+    let c = KeyChord::from(evt).char();
+    let is_backspace = evt.code == KeyCode::Backspace;
+
+    if let Some(input) = model.session_manager.rename_input_mut() {
+        match (action, c) {
+            (None, Some(c)) => input.push(c),
+            (None, None) if is_backspace => {
+                input.pop();
+            }
+            (Some(Action::Cancel), _) => model.session_manager.cancel_rename(),
+            (Some(Action::Save), _) => return (Some(Message::RenameSession), None),
+            _ => {}
+        }
+        return (None, None);
+    }
+
+    if model.session_manager.is_filter_editing() {
+        match (action, c) {
+            (None, Some(c)) => model.session_manager.edit_filter(|filter| filter.push(c)),
+            (None, None) if is_backspace => model.session_manager.edit_filter(|filter| {
+                filter.pop();
+            }),
+            (Some(Action::Cancel), _) => model.session_manager.clear_filter(),
+            (Some(Action::Save), _) => {
+                model.session_manager.stop_filter();
+                return (None, None);
+            }
+            _ => return (None, None),
+        };
+        return (None, Some(query_sessions(&model.session_manager, None)));
+    }
+
+    match action {
+        Some(Action::Delete) => return (Some(Message::DeleteSessions), None),
+        Some(Action::Restore) => return (Some(Message::RestoreSessions), None),
+        Some(Action::Mark) => model.session_manager.toggle_marked(),
+        Some(Action::ToggleTrash) => {
+            model.session_manager.toggle_trash();
+            return (None, Some(query_sessions(&model.session_manager, None)));
+        }
+        Some(Action::Rename) => {
+            model.session_manager.start_rename();
+        }
+        Some(Action::Pin) => return (Some(Message::PinSession), None),
+        Some(Action::Archive) => return (Some(Message::ArchiveSession), None),
+        Some(Action::ToggleArchived) => {
+            model.session_manager.toggle_show_archived();
+            return (None, Some(query_sessions(&model.session_manager, None)));
+        }
+        Some(Action::Tags) => return (Some(Message::Tags), None),
+        Some(Action::Filter) => model.session_manager.start_filter(),
+        Some(Action::Clear) if !model.session_manager.filter().is_empty() => {
+            model.session_manager.clear_filter();
+            return (None, Some(query_sessions(&model.session_manager, None)));
+        }
+        Some(Action::MoveDown) => return (Some(Message::SelectNextSession), None),
+        Some(Action::MoveUp) => return (Some(Message::SelectPrevSession), None),
+        _ => {}
+    }
+    (None, None)
+}
+
+pub fn handle_mouse_event(model: &mut Model, evt: MouseEvent) -> Update {
+    match evt.kind {
+        MouseEventKind::Down(MouseButton::Left) => {
+            let Some(evt) = model.session_manager.list_area().maybe_mouse_event(evt) else {
+                return (None, None);
+            };
+            let maybe_cmd = model
+                .handle_select_session_at(evt.row as usize)
+                .map(|id| Command::ServiceReq(ServiceReq::GetSession(id)));
+            (Some(Message::LoadMoreSessions), maybe_cmd)
+        }
+        MouseEventKind::ScrollDown => (Some(Message::SelectNextSession), None),
+        MouseEventKind::ScrollUp => (Some(Message::SelectPrevSession), None),
+        _ => (None, None),
+    }
+}
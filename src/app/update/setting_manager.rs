@@ -0,0 +1,31 @@
+use crossterm::event::{KeyCode, KeyEvent};
+
+use crate::app::{Message, model::Model, update::Update};
+use crate::models::keymap::{Action, KeyChord};
+
+pub fn handle_key_event(model: &mut Model, evt: KeyEvent, action: Option<Action>) -> Update {
+    let Some(setting_manager) = &mut model.setting_manager_popup else {
+        return (None, None);
+    };
+    let Some(action) = action else {
+        if setting_manager.selected_field().is_text() {
+            match KeyChord::from(evt).char() {
+                Some(c) => setting_manager.input(c),
+                None if evt.code == KeyCode::Backspace => setting_manager.backspace(),
+                None => {}
+            }
+        }
+        return (None, None);
+    };
+    match action {
+        Action::MoveDown => setting_manager.select_next(),
+        Action::MoveUp => setting_manager.select_previous(),
+        Action::NextSection => setting_manager.select_next_section(),
+        Action::PreviousSection => setting_manager.select_previous_section(),
+        Action::Pick => setting_manager.pick(),
+        Action::Cancel => model.setting_manager_popup = None,
+        Action::Save => return (Some(Message::Setting), None),
+        _ => {}
+    }
+    (None, None)
+}
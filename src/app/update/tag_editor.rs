@@ -0,0 +1,21 @@
+use crossterm::event::{KeyCode, KeyEvent};
+
+use crate::app::{Message, model::Model, update::Update};
+use crate::models::keymap::{Action, KeyChord};
+
+pub fn handle_key_event(model: &mut Model, evt: KeyEvent, action: Option<Action>) -> Update {
+    let Some(tag_editor) = &mut model.tag_editor_popup else {
+        return (None, None);
+    };
+    match action {
+        Some(Action::Cancel) => model.tag_editor_popup = None,
+        Some(Action::Save) => return (Some(Message::Tags), None),
+        Some(_) => {}
+        None => match KeyChord::from(evt).char() {
+            Some(c) => tag_editor.push(c),
+            None if evt.code == KeyCode::Backspace => tag_editor.backspace(),
+            None => {}
+        },
+    }
+    (None, None)
+}
@@ -1,17 +1,21 @@
 mod constants;
 pub mod editor_viewport;
 mod error_popup;
+mod help;
 mod messages;
 pub mod messages_viewport;
 mod session;
 mod session_manager;
 mod setting_manager;
+mod tag_editor;
+pub mod theme;
+mod toast;
 pub mod utils;
 pub mod widgets;
 
 use crate::app::{
     model::Model,
-    view::{error_popup::ErrorPopup, utils::area::Area},
+    view::{error_popup::ErrorPopup, help::HintBar, utils::area::Area},
 };
 use ratatui::{
     Frame,
@@ -20,11 +24,12 @@ use ratatui::{
 
 pub fn render_ui(model: &mut Model, frame: &mut Frame) {
     let session_state = &mut session::SessionState::default();
+    let [main_area, hint_area] =
+        Layout::vertical([Constraint::Min(0), Constraint::Length(1)]).areas(frame.area());
 
     if model.show_sidebar {
         let [side_bar_area, session_area] =
-            Layout::horizontal([Constraint::Min(30), Constraint::Percentage(90)])
-                .areas(frame.area());
+            Layout::horizontal([Constraint::Min(30), Constraint::Percentage(90)]).areas(main_area);
 
         let session_manager_area = &mut Area::default();
         frame.render_stateful_widget(
@@ -45,7 +50,7 @@ pub fn render_ui(model: &mut Model, frame: &mut Frame) {
             frame.set_cursor_position(Position::new(x, y));
         }
     } else {
-        frame.render_stateful_widget(&mut model.session, frame.area(), session_state);
+        frame.render_stateful_widget(&mut model.session, main_area, session_state);
 
         if let Some((x, y)) = session_state.cursor_position {
             frame.set_cursor_position(Position::new(x, y));
@@ -67,6 +72,25 @@ pub fn render_ui(model: &mut Model, frame: &mut Frame) {
         frame.render_widget(setting_manager, setting_area);
     }
 
+    if let Some(tag_editor) = &model.tag_editor_popup {
+        let tag_editor_area = utils::centered_rect(frame.area(), 40, 30);
+        frame.render_widget(tag_editor, tag_editor_area);
+    }
+
+    if let Some(help) = &mut model.help_popup {
+        let help_area = utils::centered_rect(frame.area(), 50, 70);
+        frame.render_widget(help, help_area);
+    }
+
+    frame.render_widget(
+        HintBar::new(&model.configs.keys, model.key_context()),
+        hint_area,
+    );
+
+    if let Some(toast) = &model.toast {
+        frame.render_widget(toast, toast::toast_rect(frame.area(), toast));
+    }
+
     if let Some(error_message) = &model.error_message {
         let error_popup = ErrorPopup::new(error_message);
         let area = utils::centered_rect(frame.area(), 60, 30);
@@ -1,4 +1,8 @@
-use std::borrow::Cow;
+use ratatui::{
+    style::Style,
+    text::{Line, Span},
+};
+use std::{borrow::Cow, ops::Range};
 use textwrap::{Options, WordSeparator, wrap};
 use unicode_segmentation::UnicodeSegmentation;
 use unicode_width::UnicodeWidthStr;
@@ -61,6 +65,10 @@ impl EditorViewport {
         self.area = area;
     }
 
+    pub fn line_count(&self) -> usize {
+        self.paragraphs.iter().map(|p| p.lines().len()).sum()
+    }
+
     pub fn set_viewport_width(
         &mut self,
         viewport_width: usize,
@@ -82,6 +90,41 @@ impl EditorViewport {
             .collect()
     }
 
+    /// Returns visual lines with `selection` byte range patched with `style`.
+    pub fn highlighted_lines(
+        &self,
+        selection: Option<Range<usize>>,
+        style: Style,
+    ) -> Vec<Line<'static>> {
+        let Some(selection) = selection else {
+            return self
+                .paragraphs
+                .iter()
+                .flat_map(|p| p.lines().iter().cloned().map(Line::from))
+                .collect();
+        };
+        self.paragraphs
+            .iter()
+            .flat_map(|p| {
+                let mut line_offset = p.byte_offset();
+                p.lines()
+                    .iter()
+                    .map(|line| {
+                        let start = selection.start.clamp(line_offset, line_offset + line.len());
+                        let end = selection.end.clamp(line_offset, line_offset + line.len());
+                        let (start, end) = (start - line_offset, end - line_offset);
+                        line_offset += line.len();
+                        Line::from(vec![
+                            Span::raw(line[..start].to_string()),
+                            Span::styled(line[start..end].to_string(), style),
+                            Span::raw(line[end..].to_string()),
+                        ])
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
     /// Wraps `input` into visual lines.
     fn wrap(&mut self, input: &str) -> Vec<String> {
         match self.wrap_mode {
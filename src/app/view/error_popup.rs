@@ -1,11 +1,13 @@
 use ratatui::{
     buffer::Buffer,
     layout::{Constraint, Layout, Rect},
-    style::{Stylize, palette::tailwind},
+    style::Stylize,
     text::Line,
     widgets::{Block, Clear, Paragraph, Widget},
 };
 
+use crate::app::view::theme;
+
 pub struct ErrorPopup<'a> {
     msg: &'a str,
 }
@@ -21,7 +23,10 @@ impl Widget for ErrorPopup<'_> {
         // clears out the background
         Clear.render(area, buf);
 
-        let block = Block::bordered().title(Line::from("Error Message").centered());
+        let theme = theme::current();
+        let block = Block::bordered()
+            .border_style(theme.error)
+            .title(Line::from("Error Message").centered());
         let inner_area = block.inner(area);
         block.render(area, buf);
 
@@ -33,7 +38,7 @@ impl Widget for ErrorPopup<'_> {
         message.render(message_area, buf);
 
         let footnote = Line::from("Press any key to exit")
-            .fg(tailwind::GRAY.c800)
+            .fg(theme.muted)
             .centered();
         footnote.render(footnote_area, buf);
     }
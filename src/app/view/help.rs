@@ -0,0 +1,112 @@
+use ratatui::{
+    buffer::Buffer,
+    layout::{Constraint, Layout, Rect},
+    style::Stylize,
+    text::{Line, Span},
+    widgets::{Block, Clear, Paragraph, Widget},
+};
+
+use crate::{
+    app::{model::help::Help, view::theme},
+    models::keymap::{Action, Context, Keymap},
+};
+
+impl Widget for &mut Help {
+    /// Renders key bindings grouped under context titles, scrolled to keep the last line at the
+    /// bottom.
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        // clears out the background
+        Clear.render(area, buf);
+
+        let theme = theme::current();
+        let block = Block::bordered()
+            .border_style(theme.border)
+            .title(Line::from("Keys").centered());
+        let inner_area = block.inner(area);
+        block.render(area, buf);
+
+        let [search_area, list_area, footnote_area] = Layout::vertical([
+            Constraint::Length(1),
+            Constraint::Min(0),
+            Constraint::Length(1),
+        ])
+        .areas(inner_area);
+
+        let mut search = Line::from(format!("/{}", self.query()));
+        if self.is_searching() {
+            search.push_span(" ".reversed());
+        }
+        search.fg(theme.muted).render(search_area, buf);
+
+        let mut lines: Vec<Line> = Vec::new();
+        for (title, entries) in self.sections() {
+            if !lines.is_empty() {
+                lines.push(Line::default());
+            }
+            lines.push(Line::from(title.fg(theme.section).bold()));
+            for entry in entries {
+                lines.push(Line::from(vec![
+                    format!("  {:<20}", entry.keys).fg(theme.tag),
+                    entry.action.description().into(),
+                ]));
+            }
+        }
+        if lines.is_empty() {
+            lines.push(Line::from("No matching keys".fg(theme.muted)));
+        }
+
+        self.clamp_scroll(lines.len(), list_area.height as usize);
+        Paragraph::new(lines)
+            .scroll((self.scroll() as u16, 0))
+            .render(list_area, buf);
+
+        Line::from("/ to search, Esc to close")
+            .fg(theme.muted)
+            .centered()
+            .render(footnote_area, buf);
+    }
+}
+
+/// Actions shown in the hint bar if bound in its context, most common first.
+const HINT_ACTIONS: &[Action] = &[
+    Action::Help,
+    Action::Send,
+    Action::StopEditing,
+    Action::Edit,
+    Action::Save,
+    Action::Cancel,
+    Action::Filter,
+    Action::ShiftFocus,
+    Action::ToggleSidebar,
+    Action::NewSession,
+    Action::Settings,
+    Action::Delete,
+    Action::Quit,
+];
+
+/// One line of the most common keys in a context.
+pub struct HintBar<'a> {
+    keymap: &'a Keymap,
+    context: Context,
+}
+
+impl<'a> HintBar<'a> {
+    pub fn new(keymap: &'a Keymap, context: Context) -> Self {
+        Self { keymap, context }
+    }
+}
+
+impl Widget for HintBar<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let theme = theme::current();
+        let bindings = self.keymap.bindings(self.context);
+        let mut spans: Vec<Span> = Vec::new();
+        for action in HINT_ACTIONS {
+            if let Some(binding) = bindings.iter().find(|b| b.action == *action) {
+                spans.push(format!(" {} ", binding.keys).fg(theme.tag));
+                spans.push(format!("{} ", action.description()).fg(theme.muted));
+            }
+        }
+        Line::from(spans).render(area, buf);
+    }
+}
@@ -1,15 +1,15 @@
 use ratatui::{
     buffer::Buffer,
     layout::Rect,
-    style::{Stylize as _, palette::tailwind},
-    text::{Line, Text},
+    style::Stylize as _,
+    text::{Line, Span, Text},
     widgets::{Block, StatefulWidget, Widget},
 };
 
 use crate::{
     app::{
         model::{focus::Focusable, messages::Messages},
-        view::widgets::scroll::AutoScroll,
+        view::{theme, widgets::scroll::AutoScroll},
     },
     models::constants::NEW_SESSION_TITLE,
 };
@@ -23,13 +23,44 @@ impl Widget for &mut Messages {
             .cloned()
             .filter(|t| !t.is_empty())
             .unwrap_or_else(|| NEW_SESSION_TITLE.to_string());
+        // indicate history left out of llm requests
+        let title = match self.context_trim() {
+            Some(context_trim) if !context_trim.summary.is_empty() => {
+                format!("{title} [{} summarized]", context_trim.trimmed_events)
+            }
+            Some(context_trim) => format!("{title} [{} trimmed]", context_trim.trimmed_events),
+            None => title,
+        };
+        // indicate background response in progress
+        let title = match self.pending_response() {
+            Some(pending_response) => format!("{title} [{}]", pending_response.status),
+            None => title,
+        };
 
+        let theme = theme::current();
         let styled_title = if self.is_focused() {
-            title.fg(tailwind::AMBER.c400).bold()
+            title.fg(theme.focused).bold()
         } else {
-            title.fg(tailwind::AMBER.c300)
+            title.fg(theme.unfocused)
         };
-        let block = Block::new().title(Line::from(styled_title).centered());
+        let mut title_spans = vec![styled_title];
+        // search query and match count
+        if let Some(search) = self.viewport.search() {
+            let regex = if search.is_regex() { " (regex)" } else { "" };
+            let count = match (search.error(), self.viewport.current_match()) {
+                (Some(_), _) => "invalid regex".to_string(),
+                _ if search.query().is_empty() => String::new(),
+                (None, Some(i)) => format!("{}/{}", i + 1, search.matches().len()),
+                (None, None) => format!("{} matches", search.matches().len()),
+            };
+            title_spans.push(Span::raw(" [/").fg(theme.accent));
+            title_spans.push(Span::raw(search.query().to_string()).fg(theme.accent));
+            if search.is_editing() {
+                title_spans.push(Span::raw(" ").reversed());
+            }
+            title_spans.push(Span::raw(format!("{regex} {count}]")).fg(theme.accent));
+        }
+        let block = Block::new().title(Line::from(title_spans).centered());
 
         self.set_viewport_width(area.width as usize);
         let styled_lines = self.viewport.lines();
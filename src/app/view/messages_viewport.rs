@@ -1,32 +1,53 @@
-use std::time::SystemTime;
+use std::{ops::Range, time::SystemTime};
 
 use itertools::Itertools;
 use ratatui::{
-    style::{Color, Modifier, Style, palette::tailwind},
+    style::{Modifier, Style},
     text::Span,
 };
 use textwrap::{Options, WordSeparator, wrap};
 
 use crate::{
-    app::view::{
-        utils::{
-            area::Area,
-            markdown,
-            paragraph::{Paragraph, Slicable},
-            styled_line::StyledLine,
+    app::{
+        model::search::Search,
+        view::{
+            theme,
+            utils::{
+                area::Area,
+                markdown, motion,
+                paragraph::{Paragraph, Slicable},
+                styled_line::StyledLine,
+            },
+            widgets::scroll::ScrollState,
         },
-        widgets::scroll::ScrollState,
     },
     chat::*,
     llm::*,
 };
 
+/// Where a chat message starts in paragraphs.
+struct MessageStart {
+    /// Index of the first paragraph, the prompt line of user messages.
+    paragraph_idx: usize,
+    /// Index of the paragraph where content starts and byte offset of content in it, i.e. after
+    /// the prompt of user messages.
+    content_start: (usize, usize),
+}
+
+#[derive(Default, Clone, Copy, PartialEq)]
+enum VisualMode {
+    #[default]
+    Char,
+    Line,
+}
+
 #[derive(Default)]
 pub struct MessagesViewport {
     /// Aggregate paragraphs content.
     input: String,
     /// Logical paragraphs.
     paragraphs: Vec<Paragraph<StyledLine>>,
+    message_starts: Vec<MessageStart>,
     /// Available visual width.
     viewport_width: usize,
     scroll_state: ScrollState,
@@ -34,35 +55,45 @@ pub struct MessagesViewport {
     cursor_char_idx: usize,
     /// Selection start char index.
     selection_start_char_idx: Option<usize>,
+    visual_mode: VisualMode,
+    search: Option<Search>,
     /// Area for mouse event handling.
     area: Area,
 }
 
-const HIGHLIGHT_STYLE: Style = Style::new().fg(tailwind::ZINC.c800).bg(tailwind::ZINC.c200);
-
 impl MessagesViewport {
     pub fn lines(&self) -> Vec<StyledLine> {
-        if let Some((start_offset, end_offset)) = self.visual_selection_byte_range() {
+        let highlights = self.highlights();
+        if !highlights.is_empty() {
             return self
                 .paragraphs
                 .iter()
                 .flat_map(|p| {
                     let lines = p.lines();
                     let mut new_lines: Vec<StyledLine> = Vec::with_capacity(lines.len());
+                    let paragraph_end = p.byte_offset() + p.len();
+                    let highlights = highlights
+                        .iter()
+                        .filter(|(range, _)| {
+                            range.end > p.byte_offset() && range.start <= paragraph_end
+                        })
+                        .collect::<Vec<_>>();
 
                     let mut line_offset = p.byte_offset();
                     for line in lines.iter() {
                         let line_len = line.content().len();
-                        if end_offset <= line_offset || start_offset > line_offset + line_len {
-                            new_lines.push(line.clone());
-                        } else {
-                            let new_line = line.clone();
-                            let start_offset = start_offset.saturating_sub(line_offset);
-                            let end_offset = end_offset.min(line_offset + line_len) - line_offset;
-                            let new_line = new_line
-                                .patch_style(HIGHLIGHT_STYLE, Some((start_offset, end_offset)));
-                            new_lines.push(new_line);
+                        let mut new_line = line.clone();
+                        for (range, style) in &highlights {
+                            // skip ranges starting at the end of line, they continue on the next one
+                            if range.end <= line_offset || range.start >= line_offset + line_len {
+                                continue;
+                            }
+                            let start_offset = range.start.saturating_sub(line_offset);
+                            let end_offset = range.end.min(line_offset + line_len) - line_offset;
+                            new_line =
+                                new_line.patch_style(*style, Some((start_offset, end_offset)));
                         }
+                        new_lines.push(new_line);
                         line_offset += line_len;
                     }
                     new_lines
@@ -105,6 +136,7 @@ impl MessagesViewport {
     fn make_prompt_line(settings: &LlmSettings, elapsed_sec: Option<i64>) -> StyledLine {
         let provider = settings.provider_name();
         let model = settings.model_name();
+        let theme = theme::current();
         let mut line = StyledLine::default();
         let elapsed = elapsed_sec.map_or_else(|| "-".to_string(), |s| format!("{s}s"));
 
@@ -112,13 +144,13 @@ impl MessagesViewport {
         line.append(
             provider,
             Style::default()
-                .fg(Color::LightGreen)
+                .fg(theme.provider)
                 .add_modifier(Modifier::BOLD),
         );
         line.append(" on ", Style::default());
-        line.append(model, Style::default().fg(Color::LightBlue));
+        line.append(model, Style::default().fg(theme.model));
         line.append(" [", Style::default());
-        line.append(elapsed, Style::default().fg(Color::LightMagenta));
+        line.append(elapsed, Style::default().fg(theme.elapsed));
         line.append("]", Style::default());
         line
     }
@@ -139,6 +171,7 @@ impl MessagesViewport {
         stream_message: Option<&MessageDelta>,
     ) {
         let mut lines: Vec<StyledLine> = vec![];
+        let mut message_starts: Vec<MessageStart> = vec![];
 
         // history messages
         let mut iter = chat_events
@@ -147,7 +180,7 @@ impl MessagesViewport {
             .peekable();
 
         while let Some(chat_event) = iter.next() {
-            let chat_event::Payload::Message(Message { role, msg }) =
+            let chat_event::Payload::Message(Message { role, msg, .. }) =
                 chat_event.payload.clone().unwrap()
             else {
                 unreachable!()
@@ -167,9 +200,14 @@ impl MessagesViewport {
                             .unwrap_or(0)
                     });
                     let prefix_line = Self::make_prompt_line(
-                        &chat_event.llm_settings.unwrap_or_default(),
+                        &chat_event.llm_settings.clone().unwrap_or_default(),
                         elapsed_secs,
                     );
+                    let prefix = "└─> ";
+                    message_starts.push(MessageStart {
+                        paragraph_idx: lines.len(),
+                        content_start: (lines.len() + 1, prefix.len()),
+                    });
                     lines.push(prefix_line);
 
                     let mut chat_message_lines: Vec<StyledLine> = msg
@@ -177,11 +215,15 @@ impl MessagesViewport {
                         .map(|l| StyledLine::from(l.to_string()))
                         .collect();
                     if let Some(styled_line) = chat_message_lines.get_mut(0) {
-                        styled_line.insert_prefix(Span::raw("└─> "));
+                        styled_line.insert_prefix(Span::raw(prefix));
                     }
                     lines.extend(chat_message_lines);
                 }
                 Role::Assistant => {
+                    message_starts.push(MessageStart {
+                        paragraph_idx: lines.len(),
+                        content_start: (lines.len(), 0),
+                    });
                     let styled_lines = markdown::from_str(&msg);
                     lines.extend(styled_lines);
                 }
@@ -191,6 +233,10 @@ impl MessagesViewport {
 
         // stream in progress
         if let Some(stream_message) = stream_message {
+            message_starts.push(MessageStart {
+                paragraph_idx: lines.len(),
+                content_start: (lines.len(), 0),
+            });
             let styled_lines = markdown::from_str(&stream_message.delta);
             lines.extend(styled_lines);
         }
@@ -205,7 +251,33 @@ impl MessagesViewport {
             .map(|p| p.content().replace("\t", "  "))
             .join("\n");
         self.paragraphs = lines.into_iter().map(Paragraph::build).collect();
+        self.message_starts = message_starts;
         self.reflow();
+        if let Some(search) = &mut self.search {
+            search.find(&self.input);
+        }
+    }
+
+    /// Builds lines from chat messages with earlier messages prepended, keeping cursor and scroll on
+    /// the same content.
+    pub fn build_lines_keeping_position(
+        &mut self,
+        chat_events: &[ChatEvent],
+        stream_message: Option<&MessageDelta>,
+    ) {
+        let char_count = self.input.chars().count();
+        let line_count = self.line_count();
+        self.build_lines(chat_events, stream_message);
+
+        let added_chars = self.input.chars().count().saturating_sub(char_count);
+        let added_lines = self.line_count().saturating_sub(line_count);
+        self.clamp_and_update_cursor_position(self.cursor_char_idx + added_chars);
+        self.scroll_state
+            .set_vertical_scroll_offset(self.scroll_state.vertical_scroll_offset + added_lines);
+    }
+
+    fn line_count(&self) -> usize {
+        self.paragraphs.iter().map(|p| p.lines().len()).sum()
     }
 
     /// Recalculates paragraph lines.
@@ -322,6 +394,136 @@ impl MessagesViewport {
         self.clamp_and_update_cursor_position(target_cursor_char_idx);
     }
 
+    /// Moves cursor to the char at `position` in content, or the nearest one.
+    pub fn move_cursor_to(&mut self, position: (u16 /*x*/, u16 /*y*/)) {
+        let target_cursor_byte_idx = self.find_cursor_byte_idx(position);
+        let target_cursor_char_idx = self.input[..target_cursor_byte_idx].chars().count();
+        self.clamp_and_update_cursor_position(target_cursor_char_idx);
+    }
+
+    /// Moves cursor to the char at `position` in the visible area.
+    pub fn click(&mut self, (x, y): (u16, u16)) {
+        let offset = self.scroll_state.vertical_scroll_offset as u16;
+        self.move_cursor_to((x, y + offset));
+    }
+
+    /// Scrolls by `delta` lines, moving cursor along if it would go out of view.
+    pub fn scroll_by(&mut self, delta: isize) {
+        self.scroll_state.scroll_by(delta, self.line_count());
+        if let Some(position) = self
+            .scroll_state
+            .cursor_position_in_view(self.area.height as usize)
+        {
+            self.move_cursor_to(position);
+        }
+    }
+
+    pub fn move_cursor_to_start(&mut self) {
+        self.clamp_and_update_cursor_position(0);
+    }
+
+    pub fn move_cursor_to_end(&mut self) {
+        self.clamp_and_update_cursor_position(self.input.chars().count());
+    }
+
+    /// Moves cursor to the start of the next word.
+    pub fn move_cursor_to_next_word(&mut self) {
+        let target_cursor_char_idx = motion::next_word_start(&self.input, self.cursor_char_idx);
+        self.clamp_and_update_cursor_position(target_cursor_char_idx);
+    }
+
+    /// Moves cursor to the start of the previous word.
+    pub fn move_cursor_to_previous_word(&mut self) {
+        let target_cursor_char_idx = motion::previous_word_start(&self.input, self.cursor_char_idx);
+        self.clamp_and_update_cursor_position(target_cursor_char_idx);
+    }
+
+    /// Moves cursor to the end of the word.
+    pub fn move_cursor_to_word_end(&mut self) {
+        let target_cursor_char_idx = motion::word_end(&self.input, self.cursor_char_idx);
+        self.clamp_and_update_cursor_position(target_cursor_char_idx);
+    }
+
+    /// Moves cursor to the start of the logical line.
+    pub fn move_cursor_to_line_start(&mut self) {
+        let start = self
+            .paragraph_at(self.cursor_byte_idx(self.cursor_char_idx))
+            .map(|p| p.byte_offset());
+        if let Some(start) = start {
+            self.move_cursor_to_byte_idx(start);
+        }
+    }
+
+    /// Moves cursor to the last char of the logical line.
+    pub fn move_cursor_to_line_end(&mut self) {
+        let end = self
+            .paragraph_at(self.cursor_byte_idx(self.cursor_char_idx))
+            .map(|p| {
+                let last_char_offset = p.content().content().char_indices().last();
+                p.byte_offset() + last_char_offset.map_or(0, |(i, _)| i)
+            });
+        if let Some(end) = end {
+            self.move_cursor_to_byte_idx(end);
+        }
+    }
+
+    /// Scrolls half a page down or up, moving cursor by as many lines.
+    pub fn scroll_half_page(&mut self, down: bool) {
+        let half_page = (self.area.height as isize / 2).max(1);
+        let delta = if down { half_page } else { -half_page };
+        let line_count = self.line_count();
+        let (x, y) = self.cursor_position();
+        let y = (y as isize + delta).clamp(0, line_count.saturating_sub(1) as isize);
+        self.scroll_state.scroll_by(delta, line_count);
+        self.move_cursor_to((x, y as u16));
+    }
+
+    /// Moves cursor to the start of the next message.
+    pub fn move_cursor_to_next_message(&mut self) {
+        let cursor_byte_idx = self.cursor_byte_idx(self.cursor_char_idx);
+        let start = self
+            .message_start_byte_idxs()
+            .find(|start| *start > cursor_byte_idx);
+        if let Some(start) = start {
+            self.move_cursor_to_byte_idx(start);
+        }
+    }
+
+    /// Moves cursor to the start of the message, or the previous one if already there.
+    pub fn move_cursor_to_previous_message(&mut self) {
+        let cursor_byte_idx = self.cursor_byte_idx(self.cursor_char_idx);
+        let start = self
+            .message_start_byte_idxs()
+            .rfind(|start| *start < cursor_byte_idx);
+        if let Some(start) = start {
+            self.move_cursor_to_byte_idx(start);
+        }
+    }
+
+    fn message_start_byte_idxs(
+        &self,
+    ) -> impl DoubleEndedIterator<Item = usize> + ExactSizeIterator {
+        self.message_starts
+            .iter()
+            .map(|m| self.paragraphs[m.paragraph_idx].byte_offset())
+    }
+
+    /// Returns the paragraph containing `byte_idx`, or the last one if out of bound.
+    fn paragraph_at(&self, byte_idx: usize) -> Option<&Paragraph<StyledLine>> {
+        self.paragraphs.get(self.paragraph_idx_at(byte_idx))
+    }
+
+    fn paragraph_idx_at(&self, byte_idx: usize) -> usize {
+        self.paragraphs
+            .partition_point(|p| p.byte_offset() + p.len() < byte_idx)
+            .min(self.paragraphs.len().saturating_sub(1))
+    }
+
+    fn move_cursor_to_byte_idx(&mut self, byte_idx: usize) {
+        let target_cursor_char_idx = self.input[..byte_idx].chars().count();
+        self.clamp_and_update_cursor_position(target_cursor_char_idx);
+    }
+
     /// Updates cursor position to clamped target cursor position.
     fn clamp_and_update_cursor_position(&mut self, target_cursor_char_idx: usize) {
         self.cursor_char_idx = target_cursor_char_idx.clamp(0, self.input.chars().count());
@@ -331,22 +533,40 @@ impl MessagesViewport {
     // ----------------------------------------------------------------
     // Visual selection.
     // ----------------------------------------------------------------
-    /// Sets selection start `selection_start_char_idx` to current cursor char index if it's not
-    /// already set; otherwise clears selection by sets `selection_start_char_idx` None.
+    /// Starts character-wise visual selection from cursor, switches to it if selecting lines or
+    /// clears selection.
     pub fn toggle_visual_selection(&mut self) {
-        if self.selection_start_char_idx.is_some() {
-            self.selection_start_char_idx = None;
-        } else {
-            self.selection_start_char_idx = Some(self.cursor_char_idx);
+        self.toggle_visual_mode(VisualMode::Char);
+    }
+
+    /// Starts line-wise visual selection from cursor, switches to it if selecting characters or
+    /// clears selection.
+    pub fn toggle_visual_line_selection(&mut self) {
+        self.toggle_visual_mode(VisualMode::Line);
+    }
+
+    fn toggle_visual_mode(&mut self, mode: VisualMode) {
+        match self.selection_start_char_idx {
+            Some(_) if self.visual_mode != mode => self.visual_mode = mode,
+            Some(_) => self.selection_start_char_idx = None,
+            None => {
+                self.selection_start_char_idx = Some(self.cursor_char_idx);
+                self.visual_mode = mode;
+            }
         }
     }
 
+    pub fn is_visual_selecting(&self) -> bool {
+        self.selection_start_char_idx.is_some()
+    }
+
     /// Clears visual selection.
     pub fn clear_visual_selection(&mut self) {
         self.selection_start_char_idx = None;
     }
 
-    /// Returns current visual selection range [start, end) in byte offset.
+    /// Returns current visual selection range [start, end) in byte offset, covering whole
+    /// logical lines in line-wise mode.
     fn visual_selection_byte_range(&self) -> Option<(usize /*start*/, usize /*end*/)> {
         if let Some(mut start_char_idx) = self.selection_start_char_idx {
             let mut end_char_idx = self.cursor_char_idx;
@@ -354,6 +574,11 @@ impl MessagesViewport {
                 (start_char_idx, end_char_idx) = (end_char_idx, start_char_idx);
             }
 
+            if self.visual_mode == VisualMode::Line {
+                let first = self.paragraph_at(self.cursor_byte_idx(start_char_idx))?;
+                let last = self.paragraph_at(self.cursor_byte_idx(end_char_idx))?;
+                return Some((first.byte_offset(), last.byte_offset() + last.len()));
+            }
             return Some((
                 self.cursor_byte_idx(start_char_idx),
                 self.cursor_byte_idx(end_char_idx + 1),
@@ -367,11 +592,182 @@ impl MessagesViewport {
         if let Some((start, end)) = self.visual_selection_byte_range() {
             if let Some(selected) = self.input.get(start..end) {
                 let selected = selected.to_string();
-                self.toggle_visual_selection();
+                self.clear_visual_selection();
                 return Some(selected);
             }
             tracing::warn!("invalid visual selection range {:?}", (start, end));
         }
         None
     }
+
+    /// Selects content of the message under cursor.
+    pub fn select_message(&mut self) {
+        if let Some(range) = self.message_byte_range() {
+            self.select(range);
+        }
+    }
+
+    /// Selects code of the block under cursor, without fences.
+    pub fn select_code_block(&mut self) {
+        if let Some(range) = self.code_block_byte_range() {
+            self.select(range);
+        }
+    }
+
+    fn select(&mut self, range: Range<usize>) {
+        let start_char_idx = self.input[..range.start].chars().count();
+        self.selection_start_char_idx = Some(start_char_idx);
+        self.visual_mode = VisualMode::Char;
+        let end_char_idx = self.input[..range.end].chars().count();
+        self.clamp_and_update_cursor_position(end_char_idx.saturating_sub(1).max(start_char_idx));
+    }
+
+    /// Returns byte range of content of the message under cursor.
+    fn message_byte_range(&self) -> Option<Range<usize>> {
+        let cursor_byte_idx = self.cursor_byte_idx(self.cursor_char_idx);
+        let i = self
+            .message_start_byte_idxs()
+            .rposition(|start| start <= cursor_byte_idx)?;
+        let (content_idx, content_offset) = self.message_starts[i].content_start;
+        // the last paragraph is the extra empty line
+        let end_idx = self
+            .message_starts
+            .get(i + 1)
+            .map_or(self.paragraphs.len() - 1, |m| m.paragraph_idx);
+        if content_idx >= end_idx {
+            return None;
+        }
+        let last = &self.paragraphs[end_idx - 1];
+        Some(
+            self.paragraphs[content_idx].byte_offset() + content_offset
+                ..last.byte_offset() + last.len(),
+        )
+    }
+
+    /// Returns byte range of lines between fences of the code block under cursor.
+    fn code_block_byte_range(&self) -> Option<Range<usize>> {
+        let cursor_idx = self.paragraph_idx_at(self.cursor_byte_idx(self.cursor_char_idx));
+        let is_fence = |p: &Paragraph<StyledLine>| {
+            // skip prefixes of blockquotes and lists
+            p.content()
+                .content()
+                .trim_start_matches(|c: char| !c.is_alphanumeric() && c != '`')
+                .starts_with("```")
+        };
+        let mut open_idx = None;
+        for (i, paragraph) in self.paragraphs.iter().enumerate() {
+            if !is_fence(paragraph) {
+                continue;
+            }
+            let Some(start_idx) = open_idx.take() else {
+                open_idx = Some(i);
+                continue;
+            };
+            if i < cursor_idx {
+                continue;
+            }
+            if start_idx > cursor_idx || i == start_idx + 1 {
+                return None;
+            }
+            let first = &self.paragraphs[start_idx + 1];
+            let last = &self.paragraphs[i - 1];
+            return Some(first.byte_offset()..last.byte_offset() + last.len());
+        }
+        None
+    }
+
+    // ----------------------------------------------------------------
+    // Search.
+    // ----------------------------------------------------------------
+    pub fn search(&self) -> Option<&Search> {
+        self.search.as_ref()
+    }
+
+    /// Starts editing a new search query from cursor.
+    pub fn start_search(&mut self) {
+        self.search = Some(Search::new(self.cursor_byte_idx(self.cursor_char_idx)));
+    }
+
+    /// Edits search query and moves cursor to the first match from where the search started, or
+    /// back there if none.
+    pub fn edit_search(&mut self, edit: impl FnOnce(&mut Search)) {
+        let Some(search) = &mut self.search else {
+            return;
+        };
+        edit(search);
+        search.find(&self.input);
+        let target = search
+            .first_from(search.origin())
+            .unwrap_or(search.origin());
+        self.move_cursor_to_byte_idx(target);
+    }
+
+    /// Stops editing search query and keeps highlighting its matches, or clears an empty one.
+    pub fn stop_search_editing(&mut self) {
+        match &mut self.search {
+            Some(search) if search.query().is_empty() => self.search = None,
+            Some(search) => search.stop_editing(),
+            None => {}
+        }
+    }
+
+    /// Clears search, moving cursor back to where it started if the query is being edited.
+    pub fn clear_search(&mut self) {
+        if let Some(search) = self.search.take()
+            && search.is_editing()
+        {
+            self.move_cursor_to_byte_idx(search.origin());
+        }
+    }
+
+    pub fn is_search_editing(&self) -> bool {
+        self.search.as_ref().is_some_and(Search::is_editing)
+    }
+
+    /// Returns index of the match under cursor.
+    pub fn current_match(&self) -> Option<usize> {
+        let cursor_byte_idx = self.cursor_byte_idx(self.cursor_char_idx);
+        self.search.as_ref()?.current(cursor_byte_idx)
+    }
+
+    pub fn move_cursor_to_next_match(&mut self) {
+        let cursor_byte_idx = self.cursor_byte_idx(self.cursor_char_idx);
+        if let Some(start) = self.search.as_ref().and_then(|s| s.next(cursor_byte_idx)) {
+            self.move_cursor_to_byte_idx(start);
+        }
+    }
+
+    pub fn move_cursor_to_previous_match(&mut self) {
+        let cursor_byte_idx = self.cursor_byte_idx(self.cursor_char_idx);
+        if let Some(start) = self
+            .search
+            .as_ref()
+            .and_then(|s| s.previous(cursor_byte_idx))
+        {
+            self.move_cursor_to_byte_idx(start);
+        }
+    }
+
+    /// Returns byte ranges to highlight with their styles, later ones patched over earlier ones.
+    fn highlights(&self) -> Vec<(Range<usize>, Style)> {
+        let theme = theme::current();
+        let mut highlights = vec![];
+        if let Some(search) = &self.search {
+            let style = Style::new().fg(theme.search_fg).bg(theme.search_bg);
+            let current = self.current_match();
+            highlights.extend(search.matches().iter().enumerate().map(|(i, range)| {
+                let style = if Some(i) == current {
+                    style.bg(theme.search_current_bg)
+                } else {
+                    style
+                };
+                (range.clone(), style)
+            }));
+        }
+        if let Some((start, end)) = self.visual_selection_byte_range() {
+            let style = Style::new().fg(theme.selection_fg).bg(theme.selection_bg);
+            highlights.push((start..end, style));
+        }
+        highlights
+    }
 }
@@ -1,7 +1,7 @@
 use ratatui::{
     buffer::Buffer,
     layout::{Constraint, Layout, Rect},
-    style::{Color, Modifier, Style, Stylize, palette::tailwind},
+    style::{Modifier, Style},
     text::{Line, Span, Text},
     widgets::{Block, Borders, Padding, StatefulWidget, Widget},
 };
@@ -10,6 +10,7 @@ use crate::app::{
     model::{focus::Focusable, session::Session},
     view::{
         constants::{MAX_INPUT_RATIO, MIN_INPUT_HEIGHT},
+        theme,
         utils::area::Area,
         widgets::scroll::AutoScroll,
     },
@@ -55,14 +56,16 @@ impl StatefulWidget for &mut Session {
         // ----------------------------------------------------------------
 
         self.messages.render(messages_area, buf);
-        state.messages_area.height += messages_height;
+        // content below title
+        state.messages_area.row += 1;
+        state.messages_area.height = messages_height.saturating_sub(1);
         state.messages_area.width = area.width;
 
         // ----------------------------------------------------------------
         // Input
         // ----------------------------------------------------------------
 
-        // TODO: centralize style here and prompt style
+        let theme = theme::current();
         let provider = self.llm_settings().provider_name();
         let model = self.llm_settings().model_name();
         let title: Line = Line::from(vec![
@@ -70,19 +73,24 @@ impl StatefulWidget for &mut Session {
             Span::styled(
                 provider,
                 Style::default()
-                    .fg(Color::LightGreen)
+                    .fg(theme.provider)
                     .add_modifier(Modifier::BOLD),
             ),
             Span::raw(" on "),
-            Span::styled(model, Style::default().fg(Color::LightBlue)),
+            Span::styled(model, Style::default().fg(theme.model)),
             Span::raw(" "),
         ]);
 
-        let input_lines: Vec<Line> = lines.into_iter().map(Line::from).collect();
+        let selection_style = Style::new().fg(theme.selection_fg).bg(theme.selection_bg);
+        let input_lines = self
+            .input_editor
+            .viewport
+            .highlighted_lines(self.input_editor.selection_byte_range(), selection_style);
         let text = Text::from(input_lines);
         let scrollable = AutoScroll::from(text).block(
             Block::new()
                 .borders(Borders::TOP)
+                .border_style(theme.border)
                 .padding(Padding::horizontal(1))
                 .title(title.left_aligned()),
         );
@@ -92,9 +100,11 @@ impl StatefulWidget for &mut Session {
             self.input_editor.viewport.scroll_state(),
         );
 
+        // content inside top border and padding
         state.input_editor_area.row += messages_height + 1;
-        state.input_editor_area.height = messages_height + 1;
-        state.input_editor_area.width = messages_height + 1;
+        state.input_editor_area.column += 1;
+        state.input_editor_area.height = input_height.saturating_sub(1);
+        state.input_editor_area.width = area.width.saturating_sub(2);
 
         // ----------------------------------------------------------------
         // Cursor position
@@ -146,7 +156,9 @@ mod tests {
             provider: Some(crate::llm::llm_settings::Provider::OpenAi(OpenAiSettings {
                 model: OpenAiModel::Gpt4o as i32,
                 web_search: false,
+                ..Default::default()
             })),
+            ..Default::default()
         };
         let session_id = Uuid::new_v4().to_string();
 
@@ -154,26 +166,28 @@ mod tests {
         let chat_messages: Vec<ChatEvent> = vec![
             ChatEvent::new(
                 session_id.clone(),
-                Some(llm_settings),
+                Some(llm_settings.clone()),
                 chat_event::Payload::Message(Message {
                     role: Role::User as i32,
                     msg: "history question".to_string(),
+                    response_ref: None,
                 }),
             )
             .with_created_at(user_message_created_at),
             ChatEvent::new(
                 session_id.clone(),
-                Some(llm_settings),
+                Some(llm_settings.clone()),
                 chat_event::Payload::Message(Message {
                     role: Role::Assistant as i32,
                     msg: "history answer".to_string(),
+                    response_ref: None,
                 }),
             )
             .with_created_at(assistant_message_created_at),
         ];
         messages.viewport.build_lines(&chat_messages, None);
         messages.set_title(Some("Awesome chat".to_string()));
-        let mut session = super::Session::new(llm_settings);
+        let mut session = super::Session::new(llm_settings.clone());
         session.set_messages(messages);
         session.input_editor.set_input("repeat this".repeat(3));
         let session_state = &mut SessionState::default();
@@ -1,62 +1,132 @@
 use ratatui::{
     buffer::Buffer,
-    layout::Rect,
-    style::{Modifier, Style, Stylize, palette::tailwind},
+    layout::{Constraint, Layout, Rect},
+    style::{Modifier, Style, Stylize},
     text::Line,
-    widgets::{Block, Borders, HighlightSpacing, List, ListItem, StatefulWidget},
+    widgets::{Block, Borders, HighlightSpacing, List, ListItem, StatefulWidget, Widget},
 };
 
 use crate::app::model::focus::Focusable;
 use crate::{
-    app::{model::session_manager::SessionManager, view::utils::area::Area},
+    app::{
+        model::session_manager::SessionManager,
+        view::{theme, utils::area::Area},
+    },
     chat::ChatSession,
     models::constants::NEW_SESSION_TITLE,
 };
 
 impl From<&ChatSession> for ListItem<'_> {
     fn from(value: &ChatSession) -> Self {
-        let title = if value.title.is_empty() {
-            NEW_SESSION_TITLE.to_string()
-        } else {
-            value.title.clone()
-        };
+        ListItem::new(session_line(value))
+    }
+}
 
-        let line = Line::from(title);
+/// Returns list line of session with its pin, source and tags.
+fn session_line(value: &ChatSession) -> Line<'static> {
+    let title = if value.title.is_empty() {
+        NEW_SESSION_TITLE.to_string()
+    } else {
+        value.title.clone()
+    };
 
-        ListItem::new(line)
+    let theme = theme::current();
+    let mut line = Line::default();
+    if value.pinned {
+        line.push_span("* ".fg(theme.accent));
+    }
+    line.push_span(title);
+    // sessions recorded from other clients are tagged with their source
+    if !value.source.is_empty() {
+        line.push_span(format!(" [{}]", value.source).dim());
+    }
+    for tag in &value.tags {
+        line.push_span(format!(" #{tag}").fg(theme.tag));
     }
+    if value.archived {
+        line = line.italic().dim();
+    }
+    line
 }
-const SELECTED_STYLE: Style = Style::new()
-    .bg(tailwind::ZINC.c200)
-    .add_modifier(Modifier::BOLD);
 
 impl StatefulWidget for &mut SessionManager {
     type State = Area;
 
     fn render(self, area: Rect, buf: &mut Buffer, state_area: &mut Area) {
+        let title = if self.show_trash() {
+            "Trash"
+        } else if self.show_archived() {
+            "Sessions + archived"
+        } else {
+            "Sessions"
+        };
+        let theme = theme::current();
         let styled_title = if self.is_focused() {
-            "Sessions".fg(tailwind::AMBER.c400).bold()
+            title.fg(theme.focused).bold()
         } else {
-            "Sessions".fg(tailwind::AMBER.c300)
+            title.fg(theme.unfocused)
         };
         let block = Block::new()
             .borders(Borders::RIGHT)
+            .border_style(theme.border)
             .title(Line::from(styled_title).centered());
+        let [filter_area, list_area] =
+            Layout::vertical([Constraint::Length(1), Constraint::Min(0)]).areas(block.inner(area));
+        block.render(area, buf);
+
+        let filter = if self.is_filter_editing() {
+            Line::from(vec![
+                "/".fg(theme.accent),
+                self.filter().to_string().into(),
+                " ".reversed(),
+            ])
+        } else if self.filter().is_empty() && self.show_trash() {
+            Line::from("u to restore, d to delete forever").dim()
+        } else if self.filter().is_empty() {
+            Line::from("/ to filter, e.g. rust tag:work model:o3").dim()
+        } else {
+            Line::from(vec!["/".dim(), self.filter().to_string().into()])
+        };
+        filter.render(filter_area, buf);
 
         // Iterate through all elements in the `items` and stylize them.
+        let selected = self.list_state_mut().selected();
         let items: Vec<ListItem> = self
             .session_summaries()
             .iter()
-            .map(ListItem::from)
+            .enumerate()
+            .map(|(i, session)| match self.rename_input() {
+                // edit title inline with a block cursor
+                Some(input) if Some(i) == selected => {
+                    ListItem::new(Line::from(vec![input.to_string().into(), " ".reversed()]))
+                }
+                // marked for bulk actions
+                _ if self.is_marked(&session.id) => {
+                    let mut line = session_line(session);
+                    line.spans.insert(0, "+ ".fg(theme.marked));
+                    ListItem::new(line)
+                }
+                _ => ListItem::from(session),
+            })
             .collect();
 
         // Create a List from all list items and highlight the currently selected one
         let list = List::new(items)
-            .block(block)
-            .highlight_style(SELECTED_STYLE)
+            .highlight_style(
+                Style::new()
+                    .fg(theme.selection_fg)
+                    .bg(theme.selection_bg)
+                    .add_modifier(Modifier::BOLD),
+            )
             .highlight_spacing(HighlightSpacing::Always);
 
-        StatefulWidget::render(list, area, buf, self.list_state_mut());
+        StatefulWidget::render(list, list_area, buf, self.list_state_mut());
+        self.set_list_area(Area {
+            column: list_area.x,
+            row: list_area.y,
+            height: list_area.height,
+            width: list_area.width,
+        });
         state_area.height = area.height;
         state_area.width = area.width - 1;
     }
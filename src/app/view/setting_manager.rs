@@ -1,30 +1,66 @@
-use crate::{app::model::setting_manager::SettingManager, models::OPENAI_MODELS};
+use crate::app::{
+    model::setting_manager::{Field, SettingManager},
+    view::theme,
+};
 use ratatui::{
     buffer::Buffer,
     layout::Rect,
-    style::{Modifier, Style, palette::tailwind},
+    style::{Modifier, Style, Stylize as _},
     text::Line,
     widgets::{Block, Clear, List, ListItem, StatefulWidget, Widget},
 };
 
-const SELECTED_STYLE: Style = Style::new()
-    .fg(tailwind::ZINC.c800)
-    .bg(tailwind::ZINC.c200)
-    .add_modifier(Modifier::BOLD);
-
 impl Widget for &mut SettingManager {
+    /// Renders fields grouped under section headers, highlighting selected field.
     fn render(self, area: Rect, buf: &mut Buffer) {
         // clears out the background
         Clear.render(area, buf);
-        let block = Block::bordered().title(Line::from("Model").centered());
+        let theme = theme::current();
+        let mut block = Block::bordered()
+            .border_style(theme.border)
+            .title(Line::from("Settings").centered());
+        if let Some(error) = self.error().cloned() {
+            block = block.title_bottom(Line::from(error.fg(theme.error)));
+        }
+
+        let selected_field = self.selected_field();
+        let mut items: Vec<ListItem> = Vec::new();
+        let mut selected = None;
+        let mut section = None;
+        for field in self.fields() {
+            if section != Some(field.section()) {
+                section = Some(field.section());
+                items.push(ListItem::from(
+                    field.section().title().fg(theme.section).bold(),
+                ));
+            }
+            if field == selected_field {
+                selected = Some(items.len());
+            }
+            let item = match field {
+                Field::Provider(provider) => {
+                    let marker = if self.is_chosen(field) { "●" } else { " " };
+                    match self.provider_error(provider) {
+                        Some(_) => format!("{marker} {:<18}unavailable", field.display_name()),
+                        None => format!("{marker} {}", field.display_name()),
+                    }
+                }
+                Field::Model(_) => {
+                    let marker = if self.is_chosen(field) { "●" } else { " " };
+                    format!("{marker} {}", field.display_name())
+                }
+                _ => format!("  {:<18}{}", field.display_name(), self.field_value(field)),
+            };
+            items.push(ListItem::from(item));
+        }
+        self.list_state_mut().select(selected);
 
-        let items: Vec<ListItem> = OPENAI_MODELS
-            .iter()
-            .map(|m| ListItem::from(m.display_name()))
-            .collect();
-        let list = List::new(items)
-            .block(block)
-            .highlight_style(SELECTED_STYLE);
+        let list = List::new(items).block(block).highlight_style(
+            Style::new()
+                .fg(theme.selection_fg)
+                .bg(theme.selection_bg)
+                .add_modifier(Modifier::BOLD),
+        );
 
         StatefulWidget::render(list, area, buf, self.list_state_mut());
     }
@@ -0,0 +1,47 @@
+use ratatui::{
+    buffer::Buffer,
+    layout::{Constraint, Layout, Rect},
+    style::Stylize,
+    text::Line,
+    widgets::{Block, Clear, Paragraph, Widget, Wrap},
+};
+
+use crate::app::{model::tag_editor::TagEditor, view::theme};
+
+impl Widget for &TagEditor {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        // clears out the background
+        Clear.render(area, buf);
+
+        let theme = theme::current();
+        let block = Block::bordered()
+            .border_style(theme.border)
+            .title(Line::from("Tags").centered());
+        let inner_area = block.inner(area);
+        block.render(area, buf);
+
+        let [input_area, known_area, footnote_area] = Layout::vertical([
+            Constraint::Length(2),
+            Constraint::Min(0),
+            Constraint::Length(1),
+        ])
+        .areas(inner_area);
+
+        Line::from(vec![self.input().to_string().into(), " ".reversed()]).render(input_area, buf);
+
+        let known_tags = self
+            .known_tags()
+            .iter()
+            .map(|tag| format!("#{tag}"))
+            .collect::<Vec<_>>()
+            .join(" ");
+        Paragraph::new(known_tags.fg(theme.tag))
+            .wrap(Wrap { trim: true })
+            .render(known_area, buf);
+
+        Line::from("Separate with spaces, Enter to save, Esc to cancel")
+            .fg(theme.muted)
+            .centered()
+            .render(footnote_area, buf);
+    }
+}
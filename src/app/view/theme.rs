@@ -0,0 +1,302 @@
+use color_eyre::{
+    Result,
+    eyre::{Context, bail},
+};
+use ratatui::style::{Color, palette::tailwind};
+use serde::{Deserialize, Serialize};
+use std::{
+    path::Path,
+    sync::{Arc, LazyLock, RwLock},
+};
+
+/// Palette of the tui. User themes in `themes/<name>.toml` of the config directory override
+/// colors of the built-in theme they `extends`, `dark` by default.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Theme {
+    #[serde(skip)]
+    pub name: String,
+    /// Borders of panes and popups.
+    #[serde(with = "color")]
+    pub border: Color,
+    /// Title of the focused pane.
+    #[serde(with = "color")]
+    pub focused: Color,
+    /// Titles of other panes.
+    #[serde(with = "color")]
+    pub unfocused: Color,
+    /// Pins, filter prompt and toasts.
+    #[serde(with = "color")]
+    pub accent: Color,
+    /// Tags and keys.
+    #[serde(with = "color")]
+    pub tag: Color,
+    /// Sessions marked for bulk actions.
+    #[serde(with = "color")]
+    pub marked: Color,
+    /// Section titles in popups.
+    #[serde(with = "color")]
+    pub section: Color,
+    /// Hints and footnotes.
+    #[serde(with = "color")]
+    pub muted: Color,
+    #[serde(with = "color")]
+    pub selection_fg: Color,
+    #[serde(with = "color")]
+    pub selection_bg: Color,
+    /// Search matches in messages, the one under the cursor uses `search_current_bg`.
+    #[serde(with = "color")]
+    pub search_fg: Color,
+    #[serde(with = "color")]
+    pub search_bg: Color,
+    #[serde(with = "color")]
+    pub search_current_bg: Color,
+    #[serde(with = "color")]
+    pub error: Color,
+    /// Provider, model and response time in prompt lines.
+    #[serde(with = "color")]
+    pub provider: Color,
+    #[serde(with = "color")]
+    pub model: Color,
+    #[serde(with = "color")]
+    pub elapsed: Color,
+    /// Markdown headings of level 1 to 3.
+    #[serde(with = "color")]
+    pub heading: Color,
+    /// Markdown headings of level 4 to 6.
+    #[serde(with = "color")]
+    pub subheading: Color,
+    #[serde(with = "color")]
+    pub blockquote: Color,
+    #[serde(with = "color")]
+    pub code_fg: Color,
+    #[serde(with = "color")]
+    pub code_bg: Color,
+    #[serde(with = "color")]
+    pub link: Color,
+    /// Syntect theme highlighting code blocks, e.g. `base16-ocean.dark` or `InspiredGitHub`.
+    pub syntax: String,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self::dark()
+    }
+}
+
+impl Theme {
+    pub fn dark() -> Self {
+        Self {
+            name: "dark".to_string(),
+            border: Color::Reset,
+            focused: tailwind::AMBER.c400,
+            unfocused: tailwind::AMBER.c300,
+            accent: tailwind::AMBER.c400,
+            tag: tailwind::SKY.c400,
+            marked: tailwind::EMERALD.c400,
+            section: tailwind::ZINC.c400,
+            muted: tailwind::GRAY.c500,
+            selection_fg: tailwind::ZINC.c800,
+            selection_bg: tailwind::ZINC.c200,
+            search_fg: tailwind::ZINC.c900,
+            search_bg: tailwind::AMBER.c200,
+            search_current_bg: tailwind::AMBER.c500,
+            error: tailwind::RED.c400,
+            provider: Color::LightGreen,
+            model: Color::LightBlue,
+            elapsed: Color::LightMagenta,
+            heading: Color::Cyan,
+            subheading: Color::LightCyan,
+            blockquote: Color::Green,
+            code_fg: Color::White,
+            code_bg: Color::Black,
+            link: Color::Blue,
+            syntax: "base16-ocean.dark".to_string(),
+        }
+    }
+
+    pub fn light() -> Self {
+        Self {
+            name: "light".to_string(),
+            border: tailwind::ZINC.c400,
+            focused: tailwind::AMBER.c700,
+            unfocused: tailwind::AMBER.c500,
+            accent: tailwind::AMBER.c600,
+            tag: tailwind::SKY.c700,
+            marked: tailwind::EMERALD.c700,
+            section: tailwind::ZINC.c600,
+            muted: tailwind::GRAY.c500,
+            selection_fg: tailwind::ZINC.c100,
+            selection_bg: tailwind::ZINC.c700,
+            search_fg: tailwind::ZINC.c900,
+            search_bg: tailwind::AMBER.c200,
+            search_current_bg: tailwind::AMBER.c400,
+            error: tailwind::RED.c600,
+            provider: tailwind::GREEN.c700,
+            model: tailwind::BLUE.c700,
+            elapsed: tailwind::FUCHSIA.c700,
+            heading: tailwind::CYAN.c800,
+            subheading: tailwind::CYAN.c700,
+            blockquote: tailwind::GREEN.c700,
+            code_fg: tailwind::ZINC.c900,
+            code_bg: tailwind::ZINC.c200,
+            link: tailwind::BLUE.c700,
+            syntax: "InspiredGitHub".to_string(),
+        }
+    }
+
+    pub fn high_contrast() -> Self {
+        Self {
+            name: "high-contrast".to_string(),
+            border: Color::White,
+            focused: Color::Yellow,
+            unfocused: Color::White,
+            accent: Color::Yellow,
+            tag: Color::LightCyan,
+            marked: Color::LightGreen,
+            section: Color::White,
+            muted: Color::Gray,
+            selection_fg: Color::Black,
+            selection_bg: Color::Yellow,
+            search_fg: Color::Black,
+            search_bg: Color::LightCyan,
+            search_current_bg: Color::LightMagenta,
+            error: Color::LightRed,
+            provider: Color::LightGreen,
+            model: Color::LightCyan,
+            elapsed: Color::LightMagenta,
+            heading: Color::Yellow,
+            subheading: Color::LightYellow,
+            blockquote: Color::LightGreen,
+            code_fg: Color::White,
+            code_bg: Color::Black,
+            link: Color::LightCyan,
+            syntax: "base16-eighties.dark".to_string(),
+        }
+    }
+
+    /// Parses user theme overriding colors of the built-in theme it extends.
+    fn parse(name: &str, content: &str, built_in: &[Theme]) -> Result<Self> {
+        let mut overrides: toml::Table = toml::from_str(content)?;
+        let base_name = match overrides.remove("extends") {
+            Some(toml::Value::String(base_name)) => base_name,
+            Some(_) => bail!("extends must be the name of a built-in theme"),
+            None => "dark".to_string(),
+        };
+        let Some(base) = built_in.iter().find(|t| t.name == base_name) else {
+            bail!("unknown built-in theme `{base_name}`");
+        };
+        let mut table = toml::Table::try_from(base)?;
+        table.extend(overrides);
+        let mut theme: Theme = table.try_into()?;
+        theme.name = name.to_string();
+        Ok(theme)
+    }
+}
+
+/// Returns built-in themes followed by user themes in `dir` sorted by name, a user theme named
+/// after a built-in one replaces it.
+pub fn load_themes(dir: &Path) -> Result<Vec<Theme>> {
+    let mut themes = vec![Theme::dark(), Theme::light(), Theme::high_contrast()];
+    if !dir.exists() {
+        return Ok(themes);
+    }
+
+    let mut paths = std::fs::read_dir(dir)
+        .wrap_err_with(|| format!("failed to read themes in {}", dir.display()))?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    paths.retain(|path| path.extension().is_some_and(|ext| ext == "toml"));
+    paths.sort();
+
+    let built_in = themes.clone();
+    for path in paths {
+        let Some(name) = path.file_stem().and_then(|s| s.to_str()) else {
+            continue;
+        };
+        let content = std::fs::read_to_string(&path)
+            .wrap_err_with(|| format!("failed to read theme {}", path.display()))?;
+        let theme = Theme::parse(name, &content, &built_in)
+            .wrap_err_with(|| format!("invalid theme {}", path.display()))?;
+        match themes.iter_mut().find(|t| t.name == name) {
+            Some(existing) => *existing = theme,
+            None => themes.push(theme),
+        }
+    }
+    Ok(themes)
+}
+
+static CURRENT: LazyLock<RwLock<Arc<Theme>>> = LazyLock::new(Default::default);
+
+/// Returns the theme views are rendered with.
+pub fn current() -> Arc<Theme> {
+    CURRENT.read().expect("theme lock poisoned").clone()
+}
+
+pub fn set_current(theme: Theme) {
+    *CURRENT.write().expect("theme lock poisoned") = Arc::new(theme);
+}
+
+/// (De)serializes colors as names, e.g. `light-blue`, indexes or hex like `#ffb000`.
+mod color {
+    use ratatui::style::Color;
+    use serde::{Deserialize, Deserializer, Serializer, de::Error};
+    use std::str::FromStr;
+
+    pub fn serialize<S: Serializer>(color: &Color, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&color.to_string())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Color, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        Color::from_str(&s).map_err(|_| D::Error::custom(format!("invalid color `{s}`")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ratatui::style::Color;
+
+    use crate::app::view::theme::{Theme, load_themes};
+
+    #[test]
+    fn load_user_themes() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("solar.toml"),
+            "extends = \"light\"\naccent = \"#ffb000\"\nsyntax = \"Solarized (light)\"\n",
+        )
+        .unwrap();
+        std::fs::write(dir.path().join("dark.toml"), "focused = \"light-red\"\n").unwrap();
+        std::fs::write(dir.path().join("notes.txt"), "not a theme").unwrap();
+
+        let themes = load_themes(dir.path()).unwrap();
+        let names: Vec<&str> = themes.iter().map(|t| t.name.as_str()).collect();
+        assert_eq!(names, vec!["dark", "light", "high-contrast", "solar"]);
+
+        // overrides colors of the theme it extends
+        assert_eq!(
+            themes[3],
+            Theme {
+                name: "solar".to_string(),
+                accent: Color::Rgb(0xff, 0xb0, 0x00),
+                syntax: "Solarized (light)".to_string(),
+                ..Theme::light()
+            }
+        );
+        // replaces built-in theme of the same name
+        assert_eq!(
+            themes[0],
+            Theme {
+                focused: Color::LightRed,
+                ..Theme::dark()
+            }
+        );
+
+        std::fs::write(dir.path().join("bad.toml"), "accent = \"not a color\"\n").unwrap();
+        let err = load_themes(dir.path()).unwrap_err();
+        assert!(
+            format!("{err:?}").contains("invalid color `not a color`"),
+            "{err:?}"
+        );
+    }
+}
@@ -0,0 +1,32 @@
+use ratatui::{
+    buffer::Buffer,
+    layout::Rect,
+    text::Line,
+    widgets::{Block, Clear, Paragraph, Widget},
+};
+
+use crate::app::{model::toast::Toast, view::theme};
+
+impl Widget for &Toast {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        // clears out the background
+        Clear.render(area, buf);
+
+        let block = Block::bordered().border_style(theme::current().accent);
+        Paragraph::new(Line::from(self.message()))
+            .block(block)
+            .render(area, buf);
+    }
+}
+
+/// Returns area at the top right of `area` fitting `toast`.
+pub fn toast_rect(area: Rect, toast: &Toast) -> Rect {
+    // message with borders
+    let width = (toast.message().chars().count() as u16 + 2).min(area.width);
+    Rect {
+        x: area.right() - width,
+        y: area.top(),
+        width,
+        height: 3.min(area.height),
+    }
+}
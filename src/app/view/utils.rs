@@ -1,5 +1,6 @@
 pub mod area;
 pub mod markdown;
+pub mod motion;
 pub mod paragraph;
 pub mod styled_line;
 
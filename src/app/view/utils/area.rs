@@ -29,4 +29,19 @@ impl Area {
         evt.column -= self.column;
         Some(evt)
     }
+
+    /// Returns a mouse event shifted w.r.t. the area and clamped to it, e.g. to keep dragging
+    /// past its edges.
+    pub fn clamped_mouse_event(&self, evt: MouseEvent) -> MouseEvent {
+        let mut evt = evt;
+        evt.row = evt
+            .row
+            .saturating_sub(self.row)
+            .min(self.height.saturating_sub(1));
+        evt.column = evt
+            .column
+            .saturating_sub(self.column)
+            .min(self.width.saturating_sub(1));
+        evt
+    }
 }
@@ -22,7 +22,7 @@ use syntect::{
 };
 use tracing::{debug, instrument, warn};
 
-use crate::app::view::utils::styled_line::StyledLine;
+use crate::app::view::{theme, utils::styled_line::StyledLine};
 
 pub fn from_str(input: &str) -> Vec<StyledLine> {
     let mut options = Options::empty();
@@ -183,12 +183,12 @@ where
             self.push_line(StyledLine::default());
         }
         let style = match level {
-            HeadingLevel::H1 => styles::H1,
-            HeadingLevel::H2 => styles::H2,
-            HeadingLevel::H3 => styles::H3,
-            HeadingLevel::H4 => styles::H4,
-            HeadingLevel::H5 => styles::H5,
-            HeadingLevel::H6 => styles::H6,
+            HeadingLevel::H1 => styles::h1(),
+            HeadingLevel::H2 => styles::h2(),
+            HeadingLevel::H3 => styles::h3(),
+            HeadingLevel::H4 => styles::h4(),
+            HeadingLevel::H5 => styles::h5(),
+            HeadingLevel::H6 => styles::h6(),
         };
         let content = format!("{} ", "#".repeat(level as usize));
         self.push_line(StyledLine::from(content).with_style(style));
@@ -205,7 +205,7 @@ where
             self.needs_newline = false;
         }
         self.line_prefixes.push(Span::from(">"));
-        self.line_styles.push(styles::BLOCKQUOTE);
+        self.line_styles.push(styles::blockquote());
     }
 
     fn end_blockquote(&mut self) {
@@ -250,7 +250,7 @@ where
     }
 
     fn code(&mut self, code: CowStr<'a>) {
-        self.append(code, styles::CODE);
+        self.append(code, styles::code());
     }
 
     fn hard_break(&mut self) {
@@ -316,7 +316,11 @@ where
     fn set_code_highlighter(&mut self, lang: &str) {
         if let Some(syntax) = SYNTAX_SET.find_syntax_by_token(lang) {
             debug!("Starting code block with syntax: {:?}", lang);
-            let theme = &THEME_SET.themes["base16-ocean.dark"];
+            let name = &theme::current().syntax;
+            let theme = THEME_SET.themes.get(name).unwrap_or_else(|| {
+                warn!("Could not find syntax theme: {:?}", name);
+                &THEME_SET.themes["base16-ocean.dark"]
+            });
             let highlighter = HighlightLines::new(syntax, theme);
             self.code_highlighter = Some(highlighter);
         } else {
@@ -352,7 +356,7 @@ where
     fn pop_link(&mut self) {
         if let Some(link) = self.link.take() {
             self.append(" (", Style::default());
-            self.append(link, styles::LINK);
+            self.append(link, styles::link());
             self.append(")", Style::default());
         }
     }
@@ -386,31 +390,58 @@ where
 }
 
 mod styles {
-    use ratatui::style::{Color, Modifier, Style};
-
-    pub const H1: Style = Style::new()
-        .bg(Color::Cyan)
-        .add_modifier(Modifier::BOLD)
-        .add_modifier(Modifier::UNDERLINED);
-    pub const H2: Style = Style::new().fg(Color::Cyan).add_modifier(Modifier::BOLD);
-    pub const H3: Style = Style::new()
-        .fg(Color::Cyan)
-        .add_modifier(Modifier::BOLD)
-        .add_modifier(Modifier::ITALIC);
-    pub const H4: Style = Style::new()
-        .fg(Color::LightCyan)
-        .add_modifier(Modifier::ITALIC);
-    pub const H5: Style = Style::new()
-        .fg(Color::LightCyan)
-        .add_modifier(Modifier::ITALIC);
-    pub const H6: Style = Style::new()
-        .fg(Color::LightCyan)
-        .add_modifier(Modifier::ITALIC);
-    pub const BLOCKQUOTE: Style = Style::new().fg(Color::Green);
-    pub const CODE: Style = Style::new().fg(Color::White).bg(Color::Black);
-    pub const LINK: Style = Style::new()
-        .fg(Color::Blue)
-        .add_modifier(Modifier::UNDERLINED);
+    use ratatui::style::{Modifier, Style};
+
+    use crate::app::view::theme;
+
+    pub fn h1() -> Style {
+        Style::new()
+            .bg(theme::current().heading)
+            .add_modifier(Modifier::BOLD)
+            .add_modifier(Modifier::UNDERLINED)
+    }
+
+    pub fn h2() -> Style {
+        Style::new()
+            .fg(theme::current().heading)
+            .add_modifier(Modifier::BOLD)
+    }
+
+    pub fn h3() -> Style {
+        Style::new()
+            .fg(theme::current().heading)
+            .add_modifier(Modifier::BOLD)
+            .add_modifier(Modifier::ITALIC)
+    }
+
+    pub fn h4() -> Style {
+        Style::new()
+            .fg(theme::current().subheading)
+            .add_modifier(Modifier::ITALIC)
+    }
+
+    pub fn h5() -> Style {
+        h4()
+    }
+
+    pub fn h6() -> Style {
+        h4()
+    }
+
+    pub fn blockquote() -> Style {
+        Style::new().fg(theme::current().blockquote)
+    }
+
+    pub fn code() -> Style {
+        let theme = theme::current();
+        Style::new().fg(theme.code_fg).bg(theme.code_bg)
+    }
+
+    pub fn link() -> Style {
+        Style::new()
+            .fg(theme::current().link)
+            .add_modifier(Modifier::UNDERLINED)
+    }
 }
 
 #[cfg(test)]
@@ -496,17 +527,17 @@ mod tests {
         assert_eq!(
             Text::from(lines),
             Text::from_iter([
-                Line::from_iter(["# ", "Heading 1"]).style(styles::H1),
+                Line::from_iter(["# ", "Heading 1"]).style(styles::h1()),
                 Line::default(),
-                Line::from_iter(["## ", "Heading 2"]).style(styles::H2),
+                Line::from_iter(["## ", "Heading 2"]).style(styles::h2()),
                 Line::default(),
-                Line::from_iter(["### ", "Heading 3"]).style(styles::H3),
+                Line::from_iter(["### ", "Heading 3"]).style(styles::h3()),
                 Line::default(),
-                Line::from_iter(["#### ", "Heading 4"]).style(styles::H4),
+                Line::from_iter(["#### ", "Heading 4"]).style(styles::h4()),
                 Line::default(),
-                Line::from_iter(["##### ", "Heading 5"]).style(styles::H5),
+                Line::from_iter(["##### ", "Heading 5"]).style(styles::h5()),
                 Line::default(),
-                Line::from_iter(["###### ", "Heading 6"]).style(styles::H6),
+                Line::from_iter(["###### ", "Heading 6"]).style(styles::h6()),
             ])
         );
     }
@@ -528,7 +559,7 @@ mod tests {
             Text::from_iter([
                 Line::from("Hello, world!"),
                 Line::default(),
-                Line::from_iter([">", " ", "Blockquote"]).style(styles::BLOCKQUOTE),
+                Line::from_iter([">", " ", "Blockquote"]).style(styles::blockquote()),
             ])
         );
     }
@@ -540,7 +571,7 @@ mod tests {
             .collect();
         assert_eq!(
             Text::from(lines),
-            Text::from(Line::from_iter([">", " ", "Blockquote"]).style(styles::BLOCKQUOTE))
+            Text::from(Line::from_iter([">", " ", "Blockquote"]).style(styles::blockquote()))
         );
     }
 
@@ -557,8 +588,8 @@ mod tests {
         assert_eq!(
             Text::from(lines),
             Text::from_iter([
-                Line::from_iter([">", " ", "Blockquote 1"]).style(styles::BLOCKQUOTE),
-                Line::from_iter([">", " ", "Blockquote 2"]).style(styles::BLOCKQUOTE),
+                Line::from_iter([">", " ", "Blockquote 1"]).style(styles::blockquote()),
+                Line::from_iter([">", " ", "Blockquote 2"]).style(styles::blockquote()),
             ])
         );
     }
@@ -577,9 +608,9 @@ mod tests {
         assert_eq!(
             Text::from(lines),
             Text::from_iter([
-                Line::from_iter([">", " ", "Blockquote 1"]).style(styles::BLOCKQUOTE),
-                Line::from_iter([">", " "]).style(styles::BLOCKQUOTE),
-                Line::from_iter([">", " ", "Blockquote 2"]).style(styles::BLOCKQUOTE),
+                Line::from_iter([">", " ", "Blockquote 1"]).style(styles::blockquote()),
+                Line::from_iter([">", " "]).style(styles::blockquote()),
+                Line::from_iter([">", " ", "Blockquote 2"]).style(styles::blockquote()),
             ])
         );
     }
@@ -598,9 +629,9 @@ mod tests {
         assert_eq!(
             Text::from(lines),
             Text::from_iter([
-                Line::from_iter([">", " ", "Blockquote 1"]).style(styles::BLOCKQUOTE),
+                Line::from_iter([">", " ", "Blockquote 1"]).style(styles::blockquote()),
                 Line::default(),
-                Line::from_iter([">", " ", "Blockquote 2"]).style(styles::BLOCKQUOTE),
+                Line::from_iter([">", " ", "Blockquote 2"]).style(styles::blockquote()),
             ])
         );
     }
@@ -618,9 +649,9 @@ mod tests {
         assert_eq!(
             Text::from(lines),
             Text::from_iter([
-                Line::from_iter([">", " ", "Blockquote 1"]).style(styles::BLOCKQUOTE),
-                Line::from_iter([">", " "]).style(styles::BLOCKQUOTE),
-                Line::from_iter([">", ">", " ", "Nested Blockquote"]).style(styles::BLOCKQUOTE),
+                Line::from_iter([">", " ", "Blockquote 1"]).style(styles::blockquote()),
+                Line::from_iter([">", " "]).style(styles::blockquote()),
+                Line::from_iter([">", ">", " ", "Nested Blockquote"]).style(styles::blockquote()),
             ])
         );
     }
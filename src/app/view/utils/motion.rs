@@ -0,0 +1,145 @@
+//! Word motions over a text, vim-style over char indices or by unicode word boundaries over byte
+//! indices.
+
+use unicode_segmentation::UnicodeSegmentation;
+
+#[derive(PartialEq, Clone, Copy)]
+enum CharClass {
+    Whitespace,
+    Punctuation,
+    Word,
+}
+
+impl From<char> for CharClass {
+    fn from(c: char) -> Self {
+        if c.is_whitespace() {
+            CharClass::Whitespace
+        } else if c.is_alphanumeric() || c == '_' {
+            CharClass::Word
+        } else {
+            CharClass::Punctuation
+        }
+    }
+}
+
+fn classes(text: &str) -> Vec<CharClass> {
+    text.chars().map(CharClass::from).collect()
+}
+
+/// Returns char index of the start of the next word after `char_idx`, or the end of text.
+pub fn next_word_start(text: &str, char_idx: usize) -> usize {
+    let classes = classes(text);
+    let mut i = char_idx;
+    if let Some(&class) = classes.get(i)
+        && class != CharClass::Whitespace
+    {
+        while classes.get(i) == Some(&class) {
+            i += 1;
+        }
+    }
+    while classes.get(i) == Some(&CharClass::Whitespace) {
+        i += 1;
+    }
+    i.min(classes.len())
+}
+
+/// Returns char index of the start of the word before `char_idx`, or the start of text.
+pub fn previous_word_start(text: &str, char_idx: usize) -> usize {
+    let classes = classes(text);
+    let mut i = char_idx.min(classes.len());
+    while i > 0 && classes[i - 1] == CharClass::Whitespace {
+        i -= 1;
+    }
+    if i == 0 {
+        return 0;
+    }
+    let class = classes[i - 1];
+    while i > 0 && classes[i - 1] == class {
+        i -= 1;
+    }
+    i
+}
+
+/// Returns char index of the end of the word after `char_idx`, or the last char of text.
+pub fn word_end(text: &str, char_idx: usize) -> usize {
+    let classes = classes(text);
+    let mut i = char_idx + 1;
+    while classes.get(i) == Some(&CharClass::Whitespace) {
+        i += 1;
+    }
+    let Some(&class) = classes.get(i) else {
+        return classes.len().saturating_sub(1);
+    };
+    while classes.get(i + 1) == Some(&class) {
+        i += 1;
+    }
+    i
+}
+
+/// Returns byte ranges of words by unicode word boundaries, skipping whitespace and punctuation.
+fn word_bounds(text: &str) -> impl Iterator<Item = (usize, usize)> {
+    text.split_word_bound_indices()
+        .filter(|(_, word)| word.chars().any(char::is_alphanumeric))
+        .map(|(i, word)| (i, i + word.len()))
+}
+
+/// Returns byte index of the end of the word at or after `byte_idx`, or the end of text.
+pub fn next_word_bound(text: &str, byte_idx: usize) -> usize {
+    word_bounds(text)
+        .find(|(_, end)| *end > byte_idx)
+        .map_or(text.len(), |(_, end)| end)
+}
+
+/// Returns byte index of the start of the word before `byte_idx`, or the start of text.
+pub fn previous_word_bound(text: &str, byte_idx: usize) -> usize {
+    word_bounds(text)
+        .filter(|(start, _)| *start < byte_idx)
+        .last()
+        .map_or(0, |(start, _)| start)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::app::view::utils::motion::{
+        next_word_bound, next_word_start, previous_word_bound, previous_word_start, word_end,
+    };
+
+    #[test]
+    fn word_motions() {
+        let text = "let x = foo(bar);\n  baz";
+        // l e t _ x _ = _ f o  o  (  b  a  r  )  ;  \n _  _  b  a  z
+        // 0 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15 16 17 18 19 20 21 22
+        let mut starts = vec![0];
+        while let Some(&i) = starts.last().filter(|i| **i < text.len()) {
+            starts.push(next_word_start(text, i));
+        }
+        assert_eq!(starts, vec![0, 4, 6, 8, 11, 12, 15, 20, 23]);
+
+        let mut ends = vec![0];
+        while let Some(&i) = ends.last().filter(|i| **i < 22) {
+            ends.push(word_end(text, i));
+        }
+        assert_eq!(ends, vec![0, 2, 4, 6, 10, 11, 14, 16, 22]);
+        assert_eq!(word_end(text, 22), 22);
+
+        assert_eq!(previous_word_start(text, 23), 20);
+        assert_eq!(previous_word_start(text, 20), 15);
+        assert_eq!(previous_word_start(text, 13), 12);
+        assert_eq!(previous_word_start(text, 5), 4);
+        assert_eq!(previous_word_start(text, 0), 0);
+    }
+
+    #[test]
+    fn word_bounds() {
+        let text = "hello, wörld  芋泥!";
+        assert_eq!(next_word_bound(text, 0), 5);
+        assert_eq!(next_word_bound(text, 5), 13);
+        assert_eq!(next_word_bound(text, 13), 18);
+        assert_eq!(next_word_bound(text, 21), text.len());
+
+        assert_eq!(previous_word_bound(text, text.len()), 18);
+        assert_eq!(previous_word_bound(text, 18), 15);
+        assert_eq!(previous_word_bound(text, 13), 7);
+        assert_eq!(previous_word_bound(text, 3), 0);
+    }
+}
@@ -55,6 +55,27 @@ impl ScrollState {
             .position(self.vertical_scroll_offset);
     }
 
+    /// Scrolls by `delta` lines, keeping at least the last of `line_count` lines visible.
+    pub fn scroll_by(&mut self, delta: isize, line_count: usize) {
+        self.vertical_scroll_offset = self
+            .vertical_scroll_offset
+            .saturating_add_signed(delta)
+            .min(line_count.saturating_sub(1));
+        self.vertical_scroll_bar_state = self
+            .vertical_scroll_bar_state
+            .position(self.vertical_scroll_offset);
+    }
+
+    /// Returns cursor position moved to the nearest of `height` visible lines if it's scrolled out
+    /// of view.
+    pub fn cursor_position_in_view(&self, height: usize) -> Option<(u16, u16)> {
+        let (x, y) = self.cursor_position?;
+        let top = self.vertical_scroll_offset;
+        let bottom = top + height.saturating_sub(1);
+        let line = (y as usize).clamp(top, bottom) as u16;
+        (line != y).then_some((x, line))
+    }
+
     /// Scrolls just enough so that cursor is visible.
     pub fn ensure_cursor_visible(&mut self, height: usize) {
         if let Some((_, y)) = self.cursor_position {
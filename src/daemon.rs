@@ -0,0 +1,533 @@
+use color_eyre::eyre::{Context as _, Result, bail, eyre};
+use std::{
+    io::ErrorKind,
+    os::unix::{fs::FileTypeExt as _, process::CommandExt as _},
+    path::Path,
+    process::{Command, Stdio},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt as _, AsyncWrite, AsyncWriteExt as _},
+    net::{UnixListener, UnixStream},
+    sync::{
+        broadcast,
+        mpsc::{UnboundedReceiver, UnboundedSender, unbounded_channel},
+    },
+};
+
+use crate::{
+    models::{
+        ServiceReq, ServiceResp,
+        configs::Config,
+        settings::{ProviderKind, ProviderStatus},
+    },
+    proto::{self, service_request::Req, service_response::Resp},
+    server::Hub,
+    service::{ServiceBuilder, database::check_db_lock, shutdown},
+};
+
+/// Upper bound of a frame, sessions with long histories are the largest.
+const MAX_FRAME_BYTES: usize = 64 << 20;
+
+/// Writes `msg` as a frame of its big endian u32 length followed by its prost encoding.
+pub async fn write_frame<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    msg: &impl prost::Message,
+) -> Result<()> {
+    let buf = msg.encode_to_vec();
+    writer.write_u32(buf.len() as u32).await?;
+    writer.write_all(&buf).await?;
+    writer.flush().await?;
+    Ok(())
+}
+
+/// Reads a frame written by `write_frame`, None if the peer closed the connection.
+pub async fn read_frame<R: AsyncRead + Unpin, M: prost::Message + Default>(
+    reader: &mut R,
+) -> Result<Option<M>> {
+    let len = match reader.read_u32().await {
+        Ok(len) => len as usize,
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    if len > MAX_FRAME_BYTES {
+        bail!("frame of {len} bytes is too large");
+    }
+    let mut buf = vec![0; len];
+    reader.read_exact(&mut buf).await?;
+    Ok(Some(M::decode(buf.as_slice())?))
+}
+
+/// Runs service headless, serving frontends attached over the daemon socket until SIGINT, SIGTERM
+/// or SIGHUP. Streams in progress keep running after frontends detach.
+pub async fn run(config: Config) -> Result<()> {
+    let socket = config.daemon.socket_path()?;
+    if UnixStream::connect(&socket).await.is_ok() {
+        bail!("daemon is already running on {}", socket.display());
+    }
+    if let Some(dir) = socket.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    // remove socket left by a previous run
+    if std::fs::symlink_metadata(&socket).is_ok_and(|m| m.file_type().is_socket()) {
+        std::fs::remove_file(&socket)?;
+    }
+
+    // build service first, so that no socket is bound if another service owns the database
+    let (req_tx, req_rx) = unbounded_channel::<ServiceReq>();
+    let (resp_tx, mut resp_rx) = unbounded_channel::<ServiceResp>();
+    let Some(service) = ServiceBuilder::new(req_rx, resp_tx, config).build().await else {
+        match resp_rx.recv().await {
+            Some(ServiceResp::Error(e)) => bail!("failed to start service: {e}"),
+            _ => bail!("failed to start service"),
+        }
+    };
+    let listener = UnixListener::bind(&socket)
+        .wrap_err_with(|| format!("failed to bind {}", socket.display()))?;
+    let hub = Hub::spawn(resp_rx);
+
+    let accept = async {
+        loop {
+            let (conn, _) = listener.accept().await?;
+            let (req_tx, hub) = (req_tx.clone(), hub.clone());
+            tokio::spawn(async move {
+                if let Err(e) = serve_frontend(conn, req_tx, hub).await {
+                    tracing::warn!("frontend detached: {e:?}");
+                }
+            });
+        }
+    };
+    // service shuts down on a signal or if accepting fails, saving streams in progress
+    let mut accept_res = Ok(());
+    let shutdown = async {
+        tokio::select! {
+            _ = shutdown::signal() => {}
+            res = accept => accept_res = res,
+        }
+    };
+    let res = service.run_until(shutdown).await;
+    let _ = std::fs::remove_file(&socket);
+    res.and(accept_res)
+}
+
+/// Forwards requests of an attached frontend to the service and service responses back to it,
+/// leaving out replies to other frontends.
+async fn serve_frontend(
+    conn: UnixStream,
+    req_tx: UnboundedSender<ServiceReq>,
+    hub: Arc<Hub>,
+) -> Result<()> {
+    let (mut reader, mut writer) = conn.into_split();
+    let mut resp_rx = hub.subscribe();
+    let client_id = hub.client_id();
+    let send_req = |req: ServiceReq| -> Result<()> {
+        req_tx
+            .send(ServiceReq::FromClient {
+                client_id,
+                req: Box::new(req),
+            })
+            .map_err(|_| eyre!("service stopped"))
+    };
+    // session the frontend loaded last, reloaded if its events are skipped
+    let open_session = Mutex::new(None);
+
+    // initialize frontend with state the service pushed before it attached
+    write_frame(
+        &mut writer,
+        &proto::ServiceResponse::from(ServiceResp::SessionsChanged),
+    )
+    .await?;
+    let providers = ServiceResp::Providers(hub.providers());
+    write_frame(&mut writer, &proto::ServiceResponse::from(providers)).await?;
+
+    // both directions are polled until either ends, reading frames is not cancel safe
+    let forward_reqs = async {
+        while let Some(req) = read_frame::<_, proto::ServiceRequest>(&mut reader).await? {
+            let req = ServiceReq::try_from(req)?;
+            if let ServiceReq::GetSession(session_id) = &req {
+                *open_session.lock().unwrap() = Some(session_id.clone());
+            }
+            send_req(req)?;
+        }
+        Ok(())
+    };
+    let forward_resps = async {
+        loop {
+            let resp = match resp_rx.recv().await {
+                Ok(ServiceResp::ToClient {
+                    client_id: id,
+                    resp,
+                }) if id == client_id => *resp,
+                Ok(ServiceResp::ToClient { .. }) => continue,
+                Ok(resp) => resp,
+                Err(broadcast::error::RecvError::Lagged(n)) => {
+                    // resync state the skipped responses may have changed
+                    tracing::warn!("frontend skipped {n} service responses, resyncing");
+                    let session_id = open_session.lock().unwrap().clone();
+                    if let Some(session_id) = session_id {
+                        send_req(ServiceReq::GetSession(session_id))?;
+                    }
+                    ServiceResp::SessionsChanged
+                }
+                Err(broadcast::error::RecvError::Closed) => return Ok(()),
+            };
+            write_frame(&mut writer, &proto::ServiceResponse::from(resp)).await?;
+        }
+    };
+    tokio::select! {
+        res = forward_reqs => res,
+        res = forward_resps => res,
+    }
+}
+
+/// Connects to the daemon on `socket`, spawning it if it is not running.
+pub async fn connect_or_spawn(socket: &Path) -> Result<UnixStream> {
+    if let Ok(conn) = UnixStream::connect(socket).await {
+        return Ok(conn);
+    }
+    // the daemon would fail to start with its output discarded
+    check_db_lock()?;
+    let exe = std::env::current_exe()?;
+    Command::new(exe)
+        .arg("daemon")
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        // own process group so that closing the terminal does not stop it
+        .process_group(0)
+        .spawn()
+        .wrap_err("failed to spawn daemon")?;
+
+    for _ in 0..100 {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        if let Ok(conn) = UnixStream::connect(socket).await {
+            return Ok(conn);
+        }
+    }
+    Err(eyre!("daemon did not start on {}", socket.display()))
+}
+
+/// Bridges frontend channels over `conn` to the daemon. Returns when the frontend drops its
+/// request sender, fails if the daemon goes away.
+pub async fn attach(
+    conn: UnixStream,
+    mut req_rx: UnboundedReceiver<ServiceReq>,
+    resp_tx: UnboundedSender<ServiceResp>,
+) -> Result<()> {
+    let (mut reader, mut writer) = conn.into_split();
+    let forward_reqs = async {
+        while let Some(req) = req_rx.recv().await {
+            write_frame(&mut writer, &proto::ServiceRequest::from(req)).await?;
+        }
+        Ok(())
+    };
+    let forward_resps = async {
+        while let Some(resp) = read_frame::<_, proto::ServiceResponse>(&mut reader).await? {
+            resp_tx.send(resp.try_into()?)?;
+        }
+        bail!("daemon disconnected")
+    };
+    tokio::select! {
+        res = forward_reqs => res,
+        res = forward_resps => res,
+    }
+}
+
+impl From<ServiceReq> for proto::ServiceRequest {
+    fn from(value: ServiceReq) -> Self {
+        let req = match value {
+            ServiceReq::ChatMessage(chat_event) => Req::ChatMessage(chat_event),
+            ServiceReq::GetSession(session_id) => Req::GetSession(session_id),
+            ServiceReq::DeleteSessions(session_ids) => {
+                Req::DeleteSessions(proto::SessionIds { session_ids })
+            }
+            ServiceReq::RestoreSessions(session_ids) => {
+                Req::RestoreSessions(proto::SessionIds { session_ids })
+            }
+            ServiceReq::PurgeSessions(session_ids) => {
+                Req::PurgeSessions(proto::SessionIds { session_ids })
+            }
+            ServiceReq::CancelResponse(session_id) => Req::CancelResponse(session_id),
+            ServiceReq::ImportSession(session) => Req::ImportSession(*session),
+            ServiceReq::RenameSession { session_id, title } => {
+                Req::RenameSession(proto::RenameSession { session_id, title })
+            }
+            ServiceReq::PinSession { session_id, pinned } => {
+                Req::PinSession(proto::PinSession { session_id, pinned })
+            }
+            ServiceReq::ArchiveSession {
+                session_id,
+                archived,
+            } => Req::ArchiveSession(proto::ArchiveSession {
+                session_id,
+                archived,
+            }),
+            ServiceReq::SetSessionTags { session_id, tags } => {
+                Req::SetSessionTags(proto::SetSessionTags { session_id, tags })
+            }
+            ServiceReq::QuerySessions {
+                query,
+                cursor,
+                trash,
+                include_archived,
+            } => Req::QuerySessions(proto::QuerySessions {
+                query,
+                cursor,
+                trash,
+                include_archived,
+            }),
+            ServiceReq::GetEvents { session_id, before } => {
+                Req::GetEvents(proto::GetEvents { session_id, before })
+            }
+            ServiceReq::FindConversation(key) => Req::FindConversation(key),
+            ServiceReq::RecordConversation { keys, session_id } => {
+                Req::RecordConversation(proto::RecordConversation { keys, session_id })
+            }
+            // client ids are assigned by the daemon, not sent over the wire
+            ServiceReq::FromClient { req, .. } => return Self::from(*req),
+        };
+        Self { req: Some(req) }
+    }
+}
+
+impl TryFrom<proto::ServiceRequest> for ServiceReq {
+    type Error = color_eyre::Report;
+
+    fn try_from(value: proto::ServiceRequest) -> Result<Self> {
+        Ok(
+            match value.req.ok_or_else(|| eyre!("empty service request"))? {
+                Req::ChatMessage(chat_event) => ServiceReq::ChatMessage(chat_event),
+                Req::GetSession(session_id) => ServiceReq::GetSession(session_id),
+                Req::DeleteSessions(ids) => ServiceReq::DeleteSessions(ids.session_ids),
+                Req::RestoreSessions(ids) => ServiceReq::RestoreSessions(ids.session_ids),
+                Req::PurgeSessions(ids) => ServiceReq::PurgeSessions(ids.session_ids),
+                Req::CancelResponse(session_id) => ServiceReq::CancelResponse(session_id),
+                Req::ImportSession(session) => ServiceReq::ImportSession(Box::new(session)),
+                Req::RenameSession(proto::RenameSession { session_id, title }) => {
+                    ServiceReq::RenameSession { session_id, title }
+                }
+                Req::PinSession(proto::PinSession { session_id, pinned }) => {
+                    ServiceReq::PinSession { session_id, pinned }
+                }
+                Req::ArchiveSession(proto::ArchiveSession {
+                    session_id,
+                    archived,
+                }) => ServiceReq::ArchiveSession {
+                    session_id,
+                    archived,
+                },
+                Req::SetSessionTags(proto::SetSessionTags { session_id, tags }) => {
+                    ServiceReq::SetSessionTags { session_id, tags }
+                }
+                Req::QuerySessions(proto::QuerySessions {
+                    query,
+                    cursor,
+                    trash,
+                    include_archived,
+                }) => ServiceReq::QuerySessions {
+                    query,
+                    cursor,
+                    trash,
+                    include_archived,
+                },
+                Req::GetEvents(proto::GetEvents { session_id, before }) => {
+                    ServiceReq::GetEvents { session_id, before }
+                }
+                Req::FindConversation(key) => ServiceReq::FindConversation(key),
+                Req::RecordConversation(proto::RecordConversation { keys, session_id }) => {
+                    ServiceReq::RecordConversation { keys, session_id }
+                }
+            },
+        )
+    }
+}
+
+impl From<ServiceResp> for proto::ServiceResponse {
+    fn from(value: ServiceResp) -> Self {
+        let resp = match value {
+            ServiceResp::ChatEvent(chat_event) => Resp::ChatEvent(chat_event),
+            ServiceResp::SessionsChanged => Resp::SessionsChanged(proto::SessionsChanged {}),
+            ServiceResp::SessionSummary(session) => Resp::SessionSummary(session),
+            ServiceResp::Session(session) => Resp::Session(session),
+            ServiceResp::Providers(providers) => Resp::Providers(proto::Providers {
+                providers: providers
+                    .into_iter()
+                    .map(|status| proto::ProviderStatus {
+                        provider: proto::Provider::from(status.provider) as i32,
+                        error: status.error,
+                    })
+                    .collect(),
+            }),
+            ServiceResp::Error(e) => Resp::Error(e),
+            ServiceResp::Notice(notice) => Resp::Notice(notice),
+            ServiceResp::ChatFailed { session_id, error } => {
+                Resp::ChatFailed(proto::ChatFailed { session_id, error })
+            }
+            ServiceResp::SessionQuery {
+                query,
+                cursor,
+                trash,
+                include_archived,
+                sessions,
+                next_cursor,
+            } => Resp::SessionQuery(proto::SessionQuery {
+                query,
+                cursor,
+                sessions,
+                next_cursor,
+                trash,
+                include_archived,
+            }),
+            ServiceResp::Events {
+                session_id,
+                before,
+                events,
+                next_cursor,
+            } => Resp::Events(proto::Events {
+                session_id,
+                before,
+                events,
+                next_cursor,
+            }),
+            ServiceResp::Conversation { key, session_id } => {
+                Resp::Conversation(proto::Conversation { key, session_id })
+            }
+            ServiceResp::ToClient { resp, .. } => return Self::from(*resp),
+        };
+        Self { resp: Some(resp) }
+    }
+}
+
+impl TryFrom<proto::ServiceResponse> for ServiceResp {
+    type Error = color_eyre::Report;
+
+    fn try_from(value: proto::ServiceResponse) -> Result<Self> {
+        Ok(
+            match value.resp.ok_or_else(|| eyre!("empty service response"))? {
+                Resp::ChatEvent(chat_event) => ServiceResp::ChatEvent(chat_event),
+                Resp::SessionsChanged(proto::SessionsChanged {}) => ServiceResp::SessionsChanged,
+                Resp::SessionSummary(session) => ServiceResp::SessionSummary(session),
+                Resp::Session(session) => ServiceResp::Session(session),
+                Resp::Providers(providers) => ServiceResp::Providers(
+                    providers
+                        .providers
+                        .into_iter()
+                        .map(|status| {
+                            Ok(ProviderStatus {
+                                provider: status.provider().try_into()?,
+                                error: status.error,
+                            })
+                        })
+                        .collect::<Result<_>>()?,
+                ),
+                Resp::Error(e) => ServiceResp::Error(e),
+                Resp::Notice(notice) => ServiceResp::Notice(notice),
+                Resp::ChatFailed(proto::ChatFailed { session_id, error }) => {
+                    ServiceResp::ChatFailed { session_id, error }
+                }
+                Resp::SessionQuery(proto::SessionQuery {
+                    query,
+                    cursor,
+                    sessions,
+                    next_cursor,
+                    trash,
+                    include_archived,
+                }) => ServiceResp::SessionQuery {
+                    query,
+                    cursor,
+                    trash,
+                    include_archived,
+                    sessions,
+                    next_cursor,
+                },
+                Resp::Events(proto::Events {
+                    session_id,
+                    before,
+                    events,
+                    next_cursor,
+                }) => ServiceResp::Events {
+                    session_id,
+                    before,
+                    events,
+                    next_cursor,
+                },
+                Resp::Conversation(proto::Conversation { key, session_id }) => {
+                    ServiceResp::Conversation { key, session_id }
+                }
+            },
+        )
+    }
+}
+
+impl From<ProviderKind> for proto::Provider {
+    fn from(value: ProviderKind) -> Self {
+        match value {
+            ProviderKind::OpenAi => proto::Provider::OpenAi,
+            ProviderKind::Mock => proto::Provider::Mock,
+        }
+    }
+}
+
+impl TryFrom<proto::Provider> for ProviderKind {
+    type Error = color_eyre::Report;
+
+    fn try_from(value: proto::Provider) -> Result<Self> {
+        match value {
+            proto::Provider::Unspecified => Err(eyre!("unspecified provider")),
+            proto::Provider::OpenAi => Ok(ProviderKind::OpenAi),
+            proto::Provider::Mock => Ok(ProviderKind::Mock),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        chat::*,
+        daemon::{read_frame, write_frame},
+        models::{
+            ServiceResp,
+            settings::{ProviderKind, ProviderStatus},
+        },
+        proto,
+    };
+
+    #[tokio::test]
+    async fn frames() {
+        let resps = vec![
+            ServiceResp::Providers(vec![ProviderStatus {
+                provider: ProviderKind::OpenAi,
+                error: Some("set the OPENAI_API_KEY environment variable".to_string()),
+            }]),
+            ServiceResp::SessionsChanged,
+            ServiceResp::ChatEvent(ChatEvent::new(
+                "s1".to_string(),
+                None,
+                chat_event::Payload::MessageDelta(MessageDelta {
+                    delta: "Hi".to_string(),
+                }),
+            )),
+            ServiceResp::ChatFailed {
+                session_id: "s1".to_string(),
+                error: "rate limited".to_string(),
+            },
+        ];
+
+        let mut buf = Vec::new();
+        for resp in resps.clone() {
+            write_frame(&mut buf, &proto::ServiceResponse::from(resp))
+                .await
+                .unwrap();
+        }
+        let mut reader = buf.as_slice();
+        let mut decoded = Vec::new();
+        while let Some(resp) = read_frame::<_, proto::ServiceResponse>(&mut reader)
+            .await
+            .unwrap()
+        {
+            decoded.push(ServiceResp::try_from(resp).unwrap());
+        }
+        assert_eq!(decoded, resps);
+    }
+}
@@ -1,14 +1,20 @@
 mod app;
+mod daemon;
 mod models;
+mod server;
 mod service;
 
-use color_eyre::{Result, eyre::Context};
+use color_eyre::{
+    Result,
+    eyre::{Context, bail},
+};
 use tokio::sync::mpsc;
 
 use crate::{
     app::App,
     models::{ServiceReq, ServiceResp, configs::Config},
-    service::ServiceBuilder,
+    server::{Listener, Server},
+    service::{ServiceBuilder, shutdown},
 };
 
 pub mod llm {
@@ -17,20 +23,44 @@ pub mod llm {
 pub mod chat {
     include!(concat!(env!("OUT_DIR"), "/chat.rs"));
 }
+pub mod proto {
+    include!(concat!(env!("OUT_DIR"), "/service.rs"));
+}
 
 #[tokio::main]
 async fn main() -> Result<()> {
     color_eyre::install()?;
     let _guard = init_logging();
 
+    // TODO: handle error better
+    let config = Config::load().wrap_err_with(|| "load config")?;
+
+    match std::env::args().nth(1).as_deref() {
+        None => run_tui(config).await,
+        Some("daemon") => daemon::run(config).await,
+        Some("serve") => serve(config).await,
+        Some(command) => bail!("unknown command `{command}`, expected `daemon`, `serve` or none"),
+    }
+}
+
+async fn run_tui(config: Config) -> Result<()> {
     // frontend <> backend channels
     let (req_tx, req_rx) = mpsc::unbounded_channel::<ServiceReq>();
     let (resp_tx, resp_rx) = mpsc::unbounded_channel::<ServiceResp>();
 
-    // spawn backend service and tui app, both *should* only return on irrecoverable error
+    // attach to backend daemon or spawn backend service in process and run tui app, both *should*
+    // only return on irrecoverable error
+    let service_config = config.clone();
     let svc_fut = async move {
-        // TODO: use configs to build router in service builder
-        if let Some(service) = ServiceBuilder::new(req_rx, resp_tx).build() {
+        if service_config.daemon.enabled {
+            // streams in progress outlive the tui in the daemon
+            let socket = service_config.daemon.socket_path()?;
+            let conn = daemon::connect_or_spawn(&socket).await?;
+            daemon::attach(conn, req_rx, resp_tx).await
+        } else if let Some(service) = ServiceBuilder::new(req_rx, resp_tx, service_config)
+            .build()
+            .await
+        {
             service.run().await
         } else {
             // service failed to build, just exit
@@ -38,11 +68,12 @@ async fn main() -> Result<()> {
         }
     };
 
-    // TODO: handle error better
-    let config = Config::load().wrap_err_with(|| "load config")?;
     let app_fut = async move {
         let mut app = App::new(req_tx, resp_rx)?;
-        app.run(config).await
+        tokio::select! {
+            res = app.run(config) => res,
+            _ = shutdown::signal() => Ok(()),
+        }
         // req_tx is dropped here and will shutdown backend service
     };
 
@@ -54,6 +85,34 @@ async fn main() -> Result<()> {
     res.map(|(_svc_ok, _tui_ok)| ())
 }
 
+/// Runs service behind the http api until either fails or on SIGINT, SIGTERM or SIGHUP.
+async fn serve(config: Config) -> Result<()> {
+    let (req_tx, req_rx) = mpsc::unbounded_channel::<ServiceReq>();
+    let (resp_tx, mut resp_rx) = mpsc::unbounded_channel::<ServiceResp>();
+
+    let Some(service) = ServiceBuilder::new(req_rx, resp_tx, config.clone())
+        .build()
+        .await
+    else {
+        match resp_rx.recv().await {
+            Some(ServiceResp::Error(e)) => bail!("failed to start service: {e}"),
+            _ => bail!("failed to start service"),
+        }
+    };
+    let listener = Listener::bind(&config.server).await?;
+    let server = Server::new(req_tx, resp_rx, &config).await?;
+    eprintln!("cookie serving on {}", listener.display());
+
+    let mut server_res = Ok(());
+    let shutdown = async {
+        tokio::select! {
+            _ = shutdown::signal() => {}
+            res = server.run(listener) => server_res = res,
+        }
+    };
+    service.run_until(shutdown).await.and(server_res)
+}
+
 fn init_logging() -> tracing_appender::non_blocking::WorkerGuard {
     // creates logs/YYYY-MM-DD/service.log rotating daily
     let file_appender = tracing_appender::rolling::daily("logs", "service.log");
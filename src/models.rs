@@ -1,8 +1,11 @@
 pub mod configs;
 pub mod constants;
+pub mod json;
+pub mod keymap;
+pub mod session_query;
 pub mod settings;
 
-use crate::{chat::*, llm::*};
+use crate::{chat::*, llm::*, models::settings::ProviderStatus};
 
 #[derive(Debug)]
 pub enum ServiceReq {
@@ -10,18 +13,102 @@ pub enum ServiceReq {
     ChatMessage(ChatEvent),
     /// Fetches session by session_id.
     GetSession(String),
-    /// Deletes session by session_id.
-    DeleteSession(String),
+    /// Moves sessions to trash by session_id, they are purged after the retention period.
+    DeleteSessions(Vec<String>),
+    /// Restores sessions from trash by session_id.
+    RestoreSessions(Vec<String>),
+    /// Permanently deletes sessions in trash by session_id.
+    PurgeSessions(Vec<String>),
+    /// Cancels background response of session by session_id.
+    CancelResponse(String),
+    /// Creates session with its recorded events without requesting llm, e.g. history of a
+    /// proxied conversation.
+    ImportSession(Box<ChatSession>),
+    /// Sets title of session.
+    RenameSession { session_id: String, title: String },
+    /// Pins or unpins session to the top of the session list.
+    PinSession { session_id: String, pinned: bool },
+    /// Archives or unarchives session.
+    ArchiveSession { session_id: String, archived: bool },
+    /// Replaces tags of session.
+    SetSessionTags {
+        session_id: String,
+        tags: Vec<String>,
+    },
+    /// Lists a page of sessions matching query of the sidebar filter bar after `cursor`, see
+    /// `SessionQuery`, from trash if `trash`, with archived ones if `include_archived`.
+    QuerySessions {
+        query: String,
+        cursor: Option<String>,
+        trash: bool,
+        include_archived: bool,
+    },
+    /// Loads a page of events of session older than event of id `before`.
+    GetEvents { session_id: String, before: String },
+    /// Looks up session of a proxied conversation by its key.
+    FindConversation(String),
+    /// Records keys of a proxied conversation of session.
+    RecordConversation {
+        keys: Vec<String>,
+        session_id: String,
+    },
+    /// Request of client `client_id` of a shared service, e.g. a daemon frontend, replies to it
+    /// such as errors and pages are sent as `ToClient`.
+    FromClient {
+        client_id: ClientId,
+        req: Box<ServiceReq>,
+    },
 }
 
+/// Id of a client sharing the service, assigned by the daemon or server it connects through.
+pub type ClientId = u64;
+
+#[derive(Clone, Debug, PartialEq)]
 pub enum ServiceResp {
     ChatEvent(ChatEvent),
-    Sessions(Vec<ChatSession>),
+    /// Sessions were created, deleted or restored, listed sessions are queried again.
+    SessionsChanged,
     /// Summary for one session to update title async.
     SessionSummary(ChatSession),
-    /// Fetch full session data when navigating to new session.
+    /// Fetch session data when navigating to new session, with its latest page of events.
     Session(ChatSession),
+    /// Availability of llm providers.
+    Providers(Vec<ProviderStatus>),
+    /// Failure the service can not recover from, e.g. its db failing to open.
     Error(String),
+    /// Request that could not be served, e.g. of a session not found. The service keeps serving.
+    Notice(String),
+    /// Chat of session failed, e.g. the llm request errored. The session keeps serving.
+    ChatFailed {
+        session_id: String,
+        error: String,
+    },
+    /// Session of proxied conversation `key`, None if not recorded.
+    Conversation {
+        key: String,
+        session_id: Option<String>,
+    },
+    /// Reply to `FromClient` request of client `client_id`, not meant for other clients.
+    ToClient {
+        client_id: ClientId,
+        resp: Box<ServiceResp>,
+    },
+    /// Page of sessions matching `query` after `cursor` of `QuerySessions`.
+    SessionQuery {
+        query: String,
+        cursor: Option<String>,
+        trash: bool,
+        include_archived: bool,
+        sessions: Vec<ChatSession>,
+        next_cursor: Option<String>,
+    },
+    /// Page of events of `GetEvents`, oldest first.
+    Events {
+        session_id: String,
+        before: String,
+        events: Vec<ChatEvent>,
+        next_cursor: Option<String>,
+    },
 }
 
 impl OpenAiModel {
@@ -35,6 +122,33 @@ impl OpenAiModel {
             OpenAiModel::O3Mini => "o3-mini",
         }
     }
+
+    /// Returns the context window size in tokens.
+    pub fn context_window(&self) -> usize {
+        match self {
+            OpenAiModel::Unspecified | OpenAiModel::Gpt4o | OpenAiModel::Gpt4oMini => 128_000,
+            OpenAiModel::O4Mini | OpenAiModel::O3 | OpenAiModel::O3Mini => 200_000,
+        }
+    }
+
+    /// Returns whether the model supports sampling parameters, i.e. temperature and top_p.
+    /// Reasoning models do not.
+    pub fn supports_sampling(&self) -> bool {
+        match self {
+            OpenAiModel::Unspecified | OpenAiModel::Gpt4o | OpenAiModel::Gpt4oMini => true,
+            OpenAiModel::O4Mini | OpenAiModel::O3 | OpenAiModel::O3Mini => false,
+        }
+    }
+}
+
+impl OpenAiTruncation {
+    pub fn display_name(&self) -> &'static str {
+        match self {
+            OpenAiTruncation::Unspecified => "default",
+            OpenAiTruncation::Disabled => "disabled",
+            OpenAiTruncation::Auto => "auto",
+        }
+    }
 }
 
 pub const OPENAI_MODELS: &[OpenAiModel] = &[
@@ -76,3 +190,13 @@ impl ChatSession {
         }
     }
 }
+
+impl PendingResponse {
+    /// Returns whether the response is done and no longer runs at the provider.
+    pub fn is_done(&self) -> bool {
+        matches!(
+            self.status.as_str(),
+            "completed" | "failed" | "cancelled" | "incomplete"
+        )
+    }
+}
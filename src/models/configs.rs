@@ -3,44 +3,335 @@ use color_eyre::{
     eyre::{Context, eyre},
 };
 use serde::Deserialize;
+use std::{net::SocketAddr, path::PathBuf, time::Duration};
 
-use crate::{llm::*, models::LlmSettings, service::llms::open_ai::api::Model};
+use crate::{
+    llm::*,
+    models::{LlmSettings, keymap::Keymap, settings::ProviderKind},
+    service::llms::open_ai::api::{Model, Truncation},
+};
+
+/// How long `key_command` may run, e.g. waiting on a password manager prompt.
+const KEY_COMMAND_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Where to read an api key from. Tried in the order of `key_command`, `file` and `env`.
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct ApiKeyConfig {
+    /// Environment variable holding the key, provider specific variable if unset.
+    pub env: Option<String>,
+    /// File holding the key.
+    pub file: Option<PathBuf>,
+    /// Shell command printing the key, e.g. `pass show openai`.
+    pub key_command: Option<String>,
+}
+
+impl ApiKeyConfig {
+    /// Reads the api key, falling back to `default_env` if no source is configured.
+    pub async fn resolve(&self, default_env: &str) -> Result<String> {
+        let key = if let Some(key_command) = &self.key_command {
+            let output = tokio::time::timeout(
+                KEY_COMMAND_TIMEOUT,
+                tokio::process::Command::new("sh")
+                    .arg("-c")
+                    .arg(key_command)
+                    .kill_on_drop(true)
+                    .output(),
+            )
+            .await
+            .map_err(|_| {
+                eyre!(
+                    "key command `{key_command}` timed out after {}s",
+                    KEY_COMMAND_TIMEOUT.as_secs()
+                )
+            })?
+            .wrap_err_with(|| format!("failed to run key command `{key_command}`"))?;
+            if !output.status.success() {
+                return Err(eyre!(
+                    "key command `{key_command}` failed: {}",
+                    String::from_utf8_lossy(&output.stderr).trim()
+                ));
+            }
+            String::from_utf8(output.stdout)
+                .wrap_err_with(|| format!("key command `{key_command}` printed invalid utf-8"))?
+        } else if let Some(file) = &self.file {
+            std::fs::read_to_string(file)
+                .wrap_err_with(|| format!("failed to read key file {}", file.display()))?
+        } else {
+            let env = self.env.as_deref().unwrap_or(default_env);
+            std::env::var(env).wrap_err_with(|| format!("set the {env} environment variable"))?
+        };
 
-#[derive(Deserialize)]
+        let key = key.trim();
+        if key.is_empty() {
+            return Err(eyre!("api key is empty"));
+        }
+        Ok(key.to_string())
+    }
+}
+
+#[derive(Deserialize, Clone)]
 pub struct OpenAIConfig {
     pub model: Model,
     pub web_search: bool,
+    /// Whether open ai stores responses so that follow up requests only send new input. Off by
+    /// default, unlike the api which stores responses unless told not to, so that conversations
+    /// are only kept by open ai when opted in. Background responses are always stored.
+    #[serde(default)]
+    pub store: bool,
+    #[serde(default)]
+    pub temperature: Option<f32>,
+    #[serde(default)]
+    pub top_p: Option<f32>,
+    #[serde(default)]
+    pub max_output_tokens: Option<u32>,
+    #[serde(default)]
+    pub truncation: Option<Truncation>,
+    #[serde(default)]
+    pub api_key: ApiKeyConfig,
+}
+
+/// Mock provider playing scripted responses, for offline development and tests.
+#[derive(Deserialize, Clone, Debug)]
+pub struct MockConfig {
+    /// Json fixture of scripted responses.
+    pub fixture: PathBuf,
+}
+
+/// How to shrink chat history that does not fit in the model's context window.
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ContextStrategy {
+    /// Drops the oldest turns.
+    #[default]
+    DropOldest,
+    /// Keeps the first `keep_first` and the last `keep_last` turns and drops turns in between.
+    KeepEnds,
+    /// Summarizes turns before the last `keep_last` into a stored summary.
+    Summarize,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct ContextConfig {
+    pub strategy: ContextStrategy,
+    pub keep_first: usize,
+    pub keep_last: usize,
+    /// Tokens reserved for model output.
+    pub reserve_tokens: usize,
+    /// Overrides model context window if set.
+    pub max_context_tokens: Option<usize>,
+}
+
+impl Default for ContextConfig {
+    fn default() -> Self {
+        Self {
+            strategy: ContextStrategy::default(),
+            keep_first: 1,
+            keep_last: 4,
+            reserve_tokens: 16_000,
+            max_context_tokens: None,
+        }
+    }
+}
+
+/// Lifecycle of per session chat workers, stopped workers reload their session from the db on
+/// next use.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct WorkerConfig {
+    /// Stops workers idle for longer than this.
+    pub idle_timeout_secs: u64,
+    /// Stops the least recently used idle worker when starting one beyond this.
+    pub max_workers: usize,
+    /// How long workers have to save streamed responses on shutdown.
+    pub shutdown_timeout_secs: u64,
+}
+
+impl Default for WorkerConfig {
+    fn default() -> Self {
+        Self {
+            idle_timeout_secs: 600,
+            max_workers: 32,
+            shutdown_timeout_secs: 5,
+        }
+    }
+}
+
+/// Deleted sessions are kept in trash to be restored until purged.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct TrashConfig {
+    /// Purges sessions in trash for longer than this.
+    pub retention_days: u64,
+}
+
+impl Default for TrashConfig {
+    fn default() -> Self {
+        Self { retention_days: 30 }
+    }
+}
+
+/// Colors of the tui.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct ThemeConfig {
+    /// Built-in theme, i.e. `dark`, `light` or `high-contrast`, or user theme in `themes/` of the
+    /// config directory.
+    pub name: String,
+}
+
+impl Default for ThemeConfig {
+    fn default() -> Self {
+        Self {
+            name: "dark".to_string(),
+        }
+    }
+}
+
+/// Input editor.
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct EditorConfig {
+    /// Vim emulation when not typing, typing being its insert mode.
+    pub vim: bool,
+}
+
+/// Http api of `cookie serve`.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct ServerConfig {
+    /// Loopback address to listen on.
+    pub address: SocketAddr,
+    /// Unix socket to listen on instead of `address` if set.
+    pub socket: Option<PathBuf>,
+    /// Requires `Authorization: Bearer <token>` on every request if set.
+    pub token: Option<ApiKeyConfig>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            address: SocketAddr::from(([127, 0, 0, 1], 8787)),
+            socket: None,
+            token: None,
+        }
+    }
+}
+
+/// Background daemon owning the service that the tui attaches to.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct DaemonConfig {
+    /// Runs service in the tui process instead if disabled.
+    pub enabled: bool,
+    /// Socket to attach on, `$XDG_RUNTIME_DIR/cookie.sock` or next to the db if unset.
+    pub socket: Option<PathBuf>,
+}
+
+impl Default for DaemonConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            socket: None,
+        }
+    }
+}
+
+impl DaemonConfig {
+    pub fn socket_path(&self) -> Result<PathBuf> {
+        if let Some(socket) = &self.socket {
+            return Ok(socket.clone());
+        }
+        if let Some(runtime_dir) = dirs::runtime_dir() {
+            return Ok(runtime_dir.join("cookie.sock"));
+        }
+        let data_dir = std::env::var("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .or_else(|_| {
+                dirs::data_local_dir().ok_or_else(|| eyre!("failed to get local data dir"))
+            })?;
+        Ok(data_dir.join("cookie/daemon.sock"))
+    }
 }
 
 /// Boot time static configs.
-#[derive(Deserialize)]
+#[derive(Deserialize, Clone)]
 pub struct Config {
+    /// Provider of new sessions.
+    #[serde(default)]
+    pub provider: ProviderKind,
     pub open_ai: OpenAIConfig,
+    /// Enables mock provider if set.
+    #[serde(default)]
+    pub mock: Option<MockConfig>,
+    #[serde(default)]
+    pub context: ContextConfig,
+    #[serde(default)]
+    pub workers: WorkerConfig,
+    #[serde(default)]
+    pub trash: TrashConfig,
+    /// Default session instructions inserted into model's context.
+    #[serde(default)]
+    pub instructions: String,
+    #[serde(default)]
+    pub server: ServerConfig,
+    #[serde(default)]
+    pub daemon: DaemonConfig,
+    /// Key bindings of the tui.
+    #[serde(default)]
+    pub keys: Keymap,
+    #[serde(default)]
+    pub theme: ThemeConfig,
+    #[serde(default)]
+    pub editor: EditorConfig,
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
+            provider: ProviderKind::default(),
             open_ai: OpenAIConfig {
                 model: Model::default(),
                 web_search: true,
+                store: false,
+                temperature: None,
+                top_p: None,
+                max_output_tokens: None,
+                truncation: None,
+                api_key: ApiKeyConfig::default(),
             },
+            mock: None,
+            context: ContextConfig::default(),
+            workers: WorkerConfig::default(),
+            trash: TrashConfig::default(),
+            instructions: String::new(),
+            server: ServerConfig::default(),
+            daemon: DaemonConfig::default(),
+            keys: Keymap::default(),
+            theme: ThemeConfig::default(),
+            editor: EditorConfig::default(),
         }
     }
 }
 
 impl Config {
-    /// Loads the configuration from the default location (using $XDG_CONFIG_HOME if exists or the
-    /// platform’s standard config directory). If the config file doesn’t exist, returns the
-    /// built-in default configuration.
-    pub fn load() -> Result<Self> {
+    /// Returns directory of `config.toml` and user themes, `cookie` in $XDG_CONFIG_HOME if exists or
+    /// the platform’s standard config directory.
+    pub fn dir() -> Result<PathBuf> {
         const XDG_CONFIG_HOME: &str = "XDG_CONFIG_HOME";
-        const COOKIE_CONFIG_PATH: &str = "cookie/config.toml";
 
         let config_dir = std::env::var(XDG_CONFIG_HOME)
-            .map(std::path::PathBuf::from)
+            .map(PathBuf::from)
             .or_else(|_| dirs::config_dir().ok_or_else(|| eyre!("failed to get config dir")))?;
-        let config_path = config_dir.join(COOKIE_CONFIG_PATH);
+        Ok(config_dir.join("cookie"))
+    }
+
+    /// Loads the configuration from the default location (using $XDG_CONFIG_HOME if exists or the
+    /// platform’s standard config directory). If the config file doesn’t exist, returns the
+    /// built-in default configuration.
+    pub fn load() -> Result<Self> {
+        let config_path = Self::dir()?.join("config.toml");
 
         if !config_path.exists() {
             tracing::info!(
@@ -52,17 +343,39 @@ impl Config {
 
         let cfg_str = std::fs::read_to_string(config_path.clone())
             .wrap_err_with(|| format!("failed to read file: {}", config_path.display()))?;
-        let cfg = toml::from_str(&cfg_str).wrap_err_with(|| "failed to parth config")?;
+        let cfg: Self = toml::from_str(&cfg_str).wrap_err_with(|| "failed to parth config")?;
+        cfg.derive_llm_settings()
+            .validate()
+            .wrap_err("invalid open_ai config")?;
+        if cfg.provider == ProviderKind::Mock && cfg.mock.is_none() {
+            return Err(eyre!("provider mock requires a [mock] fixture"));
+        }
         Ok(cfg)
     }
 
     pub fn derive_llm_settings(&self) -> LlmSettings {
-        let model: OpenAiModel = (&self.open_ai.model).into();
+        let provider = match self.provider {
+            ProviderKind::OpenAi => {
+                let model: OpenAiModel = (&self.open_ai.model).into();
+                llm_settings::Provider::OpenAi(OpenAiSettings {
+                    model: model as i32,
+                    web_search: self.open_ai.web_search,
+                    store: self.open_ai.store,
+                    temperature: self.open_ai.temperature,
+                    top_p: self.open_ai.top_p,
+                    max_output_tokens: self.open_ai.max_output_tokens,
+                    truncation: self
+                        .open_ai
+                        .truncation
+                        .map_or(OpenAiTruncation::Unspecified, Into::into)
+                        as i32,
+                })
+            }
+            ProviderKind::Mock => llm_settings::Provider::Mock(MockSettings {}),
+        };
         LlmSettings {
-            provider: Some(llm_settings::Provider::OpenAi(OpenAiSettings {
-                model: model as i32,
-                web_search: self.open_ai.web_search,
-            })),
+            provider: Some(provider),
+            instructions: self.instructions.clone(),
         }
     }
 }
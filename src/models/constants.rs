@@ -1 +1,7 @@
 pub const NEW_SESSION_TITLE: &str = "New chat";
+/// Sessions listed in the sidebar per request.
+pub const SESSION_PAGE_SIZE: usize = 50;
+/// Events of a session loaded per request.
+pub const EVENT_PAGE_SIZE: usize = 100;
+/// Minimum of max output tokens accepted by providers.
+pub const MIN_MAX_OUTPUT_TOKENS: u32 = 16;
@@ -0,0 +1,59 @@
+//! Serde helpers for the json representation of generated proto types.
+
+/// Serializes timestamps as RFC 3339 strings.
+pub mod timestamp {
+    use chrono::{DateTime, Utc};
+    use serde::{Deserialize, Deserializer, Serializer, de::Error as _};
+
+    pub fn serialize<S: Serializer>(
+        value: &Option<prost_types::Timestamp>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match value.and_then(|t| DateTime::<Utc>::from_timestamp(t.seconds, t.nanos as u32)) {
+            Some(dt) => serializer.serialize_some(&dt.to_rfc3339()),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<prost_types::Timestamp>, D::Error> {
+        let Some(s) = Option::<String>::deserialize(deserializer)? else {
+            return Ok(None);
+        };
+        let dt = DateTime::parse_from_rfc3339(&s).map_err(D::Error::custom)?;
+        Ok(Some(prost_types::Timestamp {
+            seconds: dt.timestamp(),
+            nanos: dt.timestamp_subsec_nanos() as i32,
+        }))
+    }
+}
+
+/// Implements a serde module for an `i32` proto enum field, serialized by its proto name.
+macro_rules! proto_enum {
+    ($name:ident, $ty:ty) => {
+        pub mod $name {
+            use serde::{Deserialize, Deserializer, Serializer, de::Error as _};
+
+            pub fn serialize<S: Serializer>(value: &i32, serializer: S) -> Result<S::Ok, S::Error> {
+                let value = <$ty>::try_from(*value).unwrap_or_default();
+                serializer.serialize_str(value.as_str_name())
+            }
+
+            pub fn deserialize<'de, D: Deserializer<'de>>(
+                deserializer: D,
+            ) -> Result<i32, D::Error> {
+                let name = String::deserialize(deserializer)?;
+                <$ty>::from_str_name(&name)
+                    .map(|value| value as i32)
+                    .ok_or_else(|| {
+                        D::Error::custom(format!("unknown {} {name}", stringify!($name)))
+                    })
+            }
+        }
+    };
+}
+
+proto_enum!(role, crate::chat::Role);
+proto_enum!(open_ai_model, crate::llm::OpenAiModel);
+proto_enum!(open_ai_truncation, crate::llm::OpenAiTruncation);
@@ -0,0 +1,779 @@
+use color_eyre::{
+    Result,
+    eyre::{bail, eyre},
+};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use serde::Deserialize;
+use std::{collections::HashMap, fmt};
+
+/// What a key binding does, handled according to the context it is bound in, e.g. `move_down`
+/// moves the cursor in the input editor and selects the next session in the session manager.
+#[derive(Deserialize, Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    Quit,
+    ToggleSidebar,
+    ShiftFocus,
+    NewSession,
+    Edit,
+    Settings,
+    ExternalEditor,
+    Send,
+    StopEditing,
+    NewLine,
+    DeleteChar,
+    DeleteCharForward,
+    KillLineEnd,
+    KillLineStart,
+    KillWordBackward,
+    KillWordForward,
+    PasteKilled,
+    CycleKilled,
+    Undo,
+    Redo,
+    SelectLeft,
+    SelectRight,
+    SelectUp,
+    SelectDown,
+    SelectLineStart,
+    SelectLineEnd,
+    MoveLeft,
+    MoveRight,
+    MoveUp,
+    MoveDown,
+    MoveTop,
+    MoveBottom,
+    MoveWordForward,
+    MoveWordBackward,
+    MoveWordEnd,
+    MoveLineStart,
+    MoveLineEnd,
+    HalfPageDown,
+    HalfPageUp,
+    NextMessage,
+    PreviousMessage,
+    ToggleVisual,
+    ToggleVisualLine,
+    SelectMessage,
+    SelectCodeBlock,
+    Yank,
+    Clear,
+    CancelResponse,
+    Delete,
+    Restore,
+    Mark,
+    ToggleTrash,
+    Rename,
+    Pin,
+    Archive,
+    ToggleArchived,
+    Tags,
+    Filter,
+    NextMatch,
+    PreviousMatch,
+    ToggleRegex,
+    NextSection,
+    PreviousSection,
+    Pick,
+    Cancel,
+    Save,
+    Help,
+    CycleTheme,
+}
+
+impl Action {
+    /// Returns what the action does, shown in help.
+    pub fn description(&self) -> &'static str {
+        match self {
+            Action::Quit => "quit",
+            Action::ToggleSidebar => "toggle sidebar",
+            Action::ShiftFocus => "shift focus",
+            Action::NewSession => "new session",
+            Action::Edit => "edit input",
+            Action::Settings => "settings",
+            Action::ExternalEditor => "open in external editor",
+            Action::Send => "send",
+            Action::StopEditing => "stop editing",
+            Action::NewLine => "new line",
+            Action::DeleteChar => "delete character",
+            Action::DeleteCharForward => "delete character under cursor",
+            Action::KillLineEnd => "cut to end of line",
+            Action::KillLineStart => "cut to start of line",
+            Action::KillWordBackward => "cut previous word or selection",
+            Action::KillWordForward => "cut next word",
+            Action::PasteKilled => "paste cut text",
+            Action::CycleKilled => "replace paste with earlier cut text",
+            Action::Undo => "undo",
+            Action::Redo => "redo",
+            Action::SelectLeft => "select left",
+            Action::SelectRight => "select right",
+            Action::SelectUp => "select up",
+            Action::SelectDown => "select down",
+            Action::SelectLineStart => "select to start of line",
+            Action::SelectLineEnd => "select to end of line",
+            Action::MoveLeft => "move left",
+            Action::MoveRight => "move right",
+            Action::MoveUp => "move up",
+            Action::MoveDown => "move down",
+            Action::MoveTop => "move to top",
+            Action::MoveBottom => "move to bottom",
+            Action::MoveWordForward => "move to next word",
+            Action::MoveWordBackward => "move to previous word",
+            Action::MoveWordEnd => "move to end of word",
+            Action::MoveLineStart => "move to start of line",
+            Action::MoveLineEnd => "move to end of line",
+            Action::HalfPageDown => "scroll half page down",
+            Action::HalfPageUp => "scroll half page up",
+            Action::NextMessage => "next message",
+            Action::PreviousMessage => "previous message",
+            Action::ToggleVisual => "toggle visual selection",
+            Action::ToggleVisualLine => "toggle line-wise visual selection",
+            Action::SelectMessage => "select message",
+            Action::SelectCodeBlock => "select code block",
+            Action::Yank => "copy selection",
+            Action::Clear => "clear",
+            Action::CancelResponse => "cancel background response",
+            Action::Delete => "delete",
+            Action::Restore => "restore",
+            Action::Mark => "mark",
+            Action::ToggleTrash => "show or hide trash",
+            Action::Rename => "rename",
+            Action::Pin => "pin or unpin",
+            Action::Archive => "archive or unarchive",
+            Action::ToggleArchived => "show or hide archived",
+            Action::Tags => "edit tags",
+            Action::Filter => "search",
+            Action::NextMatch => "next match",
+            Action::PreviousMatch => "previous match",
+            Action::ToggleRegex => "toggle regex search",
+            Action::NextSection => "next section",
+            Action::PreviousSection => "previous section",
+            Action::Pick => "pick",
+            Action::Cancel => "cancel",
+            Action::Save => "save",
+            Action::Help => "help",
+            Action::CycleTheme => "next theme",
+        }
+    }
+}
+
+/// Where key bindings apply. Bindings of a context fall back to those of its parent.
+#[derive(Deserialize, Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum Context {
+    /// Every widget, including the input editor while editing.
+    Global,
+    /// Every widget while not editing.
+    Normal,
+    InputEditor,
+    InputEditorEditing,
+    Messages,
+    SessionManager,
+    SettingManager,
+    /// Single line inputs, i.e. the tag editor and renaming and filtering sessions.
+    Prompt,
+    Help,
+}
+
+impl Context {
+    /// Returns the context bindings fall back to, None for popups which take all keys.
+    pub fn parent(&self) -> Option<Context> {
+        match self {
+            Context::Global | Context::SettingManager | Context::Prompt | Context::Help => None,
+            Context::Normal | Context::InputEditorEditing => Some(Context::Global),
+            Context::InputEditor | Context::Messages | Context::SessionManager => {
+                Some(Context::Normal)
+            }
+        }
+    }
+
+    pub fn title(&self) -> &'static str {
+        match self {
+            Context::Global => "Global",
+            Context::Normal => "Normal",
+            Context::InputEditor => "Input",
+            Context::InputEditorEditing => "Input (editing)",
+            Context::Messages => "Messages",
+            Context::SessionManager => "Sessions",
+            Context::SettingManager => "Settings",
+            Context::Prompt => "Prompt",
+            Context::Help => "Help",
+        }
+    }
+}
+
+/// Key with modifiers, shift is part of the character for character keys, e.g. `G`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct KeyChord {
+    code: KeyCode,
+    modifiers: KeyModifiers,
+}
+
+impl KeyChord {
+    pub fn new(code: KeyCode, modifiers: KeyModifiers) -> Self {
+        let mut kept = modifiers & (KeyModifiers::CONTROL | KeyModifiers::ALT);
+        // terminals report shift of characters and back tab inconsistently
+        if !matches!(code, KeyCode::Char(_) | KeyCode::BackTab)
+            && modifiers.contains(KeyModifiers::SHIFT)
+        {
+            kept |= KeyModifiers::SHIFT;
+        }
+        Self {
+            code,
+            modifiers: kept,
+        }
+    }
+
+    /// Parses key like `ctrl+e`, `shift+enter`, `G` or `space`.
+    pub fn parse(key: &str) -> Result<Self> {
+        let mut parts: Vec<&str> = key.split('+').collect();
+        // `+` itself, possibly with modifiers
+        if key.ends_with('+') {
+            parts.pop();
+            parts.pop();
+            parts.push("+");
+        }
+        let (name, modifier_names) = parts.split_last().ok_or_else(|| eyre!("empty key"))?;
+        let mut modifiers = KeyModifiers::NONE;
+        for modifier in modifier_names {
+            modifiers |= match modifier.to_lowercase().as_str() {
+                "ctrl" => KeyModifiers::CONTROL,
+                "alt" => KeyModifiers::ALT,
+                "shift" => KeyModifiers::SHIFT,
+                _ => bail!("unknown modifier `{modifier}` in key `{key}`"),
+            };
+        }
+        let mut chars = name.chars();
+        let code = match (chars.next(), chars.next()) {
+            (Some(c), None) if modifiers.contains(KeyModifiers::SHIFT) => {
+                KeyCode::Char(c.to_ascii_uppercase())
+            }
+            (Some(c), None) => KeyCode::Char(c),
+            _ => match name.to_lowercase().as_str() {
+                "space" => KeyCode::Char(' '),
+                "enter" => KeyCode::Enter,
+                "esc" => KeyCode::Esc,
+                "tab" => KeyCode::Tab,
+                "backtab" => KeyCode::BackTab,
+                "backspace" => KeyCode::Backspace,
+                "delete" => KeyCode::Delete,
+                "up" => KeyCode::Up,
+                "down" => KeyCode::Down,
+                "left" => KeyCode::Left,
+                "right" => KeyCode::Right,
+                "home" => KeyCode::Home,
+                "end" => KeyCode::End,
+                "pageup" => KeyCode::PageUp,
+                "pagedown" => KeyCode::PageDown,
+                lower => match lower.strip_prefix('f').map(str::parse) {
+                    Some(Ok(n)) => KeyCode::F(n),
+                    _ => bail!("unknown key `{key}`"),
+                },
+            },
+        };
+        Ok(Self::new(code, modifiers))
+    }
+
+    /// Returns the character typed if no modifier but shift is held.
+    pub fn char(&self) -> Option<char> {
+        match self.code {
+            KeyCode::Char(c) if self.modifiers.is_empty() => Some(c),
+            _ => None,
+        }
+    }
+}
+
+impl From<KeyEvent> for KeyChord {
+    fn from(evt: KeyEvent) -> Self {
+        Self::new(evt.code, evt.modifiers)
+    }
+}
+
+impl fmt::Display for KeyChord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (modifier, name) in [
+            (KeyModifiers::CONTROL, "ctrl+"),
+            (KeyModifiers::ALT, "alt+"),
+            (KeyModifiers::SHIFT, "shift+"),
+        ] {
+            if self.modifiers.contains(modifier) {
+                f.write_str(name)?;
+            }
+        }
+        match self.code {
+            KeyCode::Char(' ') => f.write_str("space"),
+            KeyCode::Char(c) => write!(f, "{c}"),
+            KeyCode::F(n) => write!(f, "f{n}"),
+            code => f.write_str(&format!("{code:?}").to_lowercase()),
+        }
+    }
+}
+
+/// Keys pressed in order to trigger an action, e.g. `g g`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct KeySequence(Vec<KeyChord>);
+
+impl KeySequence {
+    /// Parses space separated keys, e.g. `g g`.
+    pub fn parse(keys: &str) -> Result<Self> {
+        let chords = keys
+            .split_whitespace()
+            .map(KeyChord::parse)
+            .collect::<Result<Vec<_>>>()?;
+        if chords.is_empty() {
+            bail!("empty key sequence");
+        }
+        Ok(Self(chords))
+    }
+
+    fn starts_with(&self, keys: &[KeyChord]) -> bool {
+        self.0.starts_with(keys)
+    }
+}
+
+impl fmt::Display for KeySequence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let keys: Vec<String> = self.0.iter().map(ToString::to_string).collect();
+        f.write_str(&keys.join(" "))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Binding {
+    pub keys: KeySequence,
+    pub action: Action,
+}
+
+/// Result of looking up keys pressed so far.
+#[derive(Debug, PartialEq)]
+pub enum KeyMatch {
+    Action(Action),
+    /// Keys are the start of a longer sequence.
+    Pending,
+    Unbound,
+}
+
+/// Default bindings as `(context, action, keys)`.
+const DEFAULT_BINDINGS: &[(Context, Action, &[&str])] = &[
+    (Context::Global, Action::ToggleSidebar, &["ctrl+e"]),
+    (Context::Global, Action::ShiftFocus, &["tab"]),
+    (Context::Global, Action::Help, &["f1"]),
+    (Context::Global, Action::CycleTheme, &["ctrl+t"]),
+    (Context::Normal, Action::Help, &["?"]),
+    (Context::Normal, Action::Quit, &["q"]),
+    (Context::Normal, Action::NewSession, &["n"]),
+    (Context::Normal, Action::Edit, &["i"]),
+    (Context::Normal, Action::Settings, &["s"]),
+    (
+        Context::InputEditor,
+        Action::ExternalEditor,
+        &["e", "ctrl+x ctrl+e"],
+    ),
+    (Context::InputEditor, Action::Send, &["enter"]),
+    (Context::InputEditor, Action::MoveLeft, &["left", "h"]),
+    (Context::InputEditor, Action::MoveRight, &["right", "l"]),
+    (Context::InputEditor, Action::MoveDown, &["down", "j"]),
+    (Context::InputEditor, Action::MoveUp, &["up", "k"]),
+    (Context::InputEditor, Action::Undo, &["u"]),
+    (Context::InputEditor, Action::Redo, &["ctrl+r"]),
+    (Context::InputEditorEditing, Action::StopEditing, &["esc"]),
+    (Context::InputEditorEditing, Action::Send, &["enter"]),
+    (
+        Context::InputEditorEditing,
+        Action::NewLine,
+        &["shift+enter"],
+    ),
+    (
+        Context::InputEditorEditing,
+        Action::DeleteChar,
+        &["backspace"],
+    ),
+    (Context::InputEditorEditing, Action::MoveLeft, &["left"]),
+    (Context::InputEditorEditing, Action::MoveRight, &["right"]),
+    (Context::InputEditorEditing, Action::MoveDown, &["down"]),
+    (Context::InputEditorEditing, Action::MoveUp, &["up"]),
+    (
+        Context::InputEditorEditing,
+        Action::DeleteCharForward,
+        &["delete", "ctrl+d"],
+    ),
+    (
+        Context::InputEditorEditing,
+        Action::MoveWordForward,
+        &["ctrl+right", "alt+f"],
+    ),
+    (
+        Context::InputEditorEditing,
+        Action::MoveWordBackward,
+        &["ctrl+left", "alt+b"],
+    ),
+    (
+        Context::InputEditorEditing,
+        Action::MoveLineStart,
+        &["home", "ctrl+a"],
+    ),
+    (
+        Context::InputEditorEditing,
+        Action::MoveLineEnd,
+        &["end", "ctrl+e"],
+    ),
+    (
+        Context::InputEditorEditing,
+        Action::KillLineEnd,
+        &["ctrl+k"],
+    ),
+    (
+        Context::InputEditorEditing,
+        Action::KillLineStart,
+        &["ctrl+u"],
+    ),
+    (
+        Context::InputEditorEditing,
+        Action::KillWordBackward,
+        &["ctrl+w", "alt+backspace"],
+    ),
+    (
+        Context::InputEditorEditing,
+        Action::KillWordForward,
+        &["alt+d"],
+    ),
+    (
+        Context::InputEditorEditing,
+        Action::PasteKilled,
+        &["ctrl+y"],
+    ),
+    (Context::InputEditorEditing, Action::CycleKilled, &["alt+y"]),
+    (Context::InputEditorEditing, Action::Undo, &["ctrl+z"]),
+    (Context::InputEditorEditing, Action::Redo, &["ctrl+r"]),
+    (
+        Context::InputEditorEditing,
+        Action::SelectLeft,
+        &["shift+left"],
+    ),
+    (
+        Context::InputEditorEditing,
+        Action::SelectRight,
+        &["shift+right"],
+    ),
+    (Context::InputEditorEditing, Action::SelectUp, &["shift+up"]),
+    (
+        Context::InputEditorEditing,
+        Action::SelectDown,
+        &["shift+down"],
+    ),
+    (
+        Context::InputEditorEditing,
+        Action::SelectLineStart,
+        &["shift+home"],
+    ),
+    (
+        Context::InputEditorEditing,
+        Action::SelectLineEnd,
+        &["shift+end"],
+    ),
+    (Context::Messages, Action::ExternalEditor, &["o"]),
+    (Context::Messages, Action::ToggleVisual, &["v"]),
+    (Context::Messages, Action::ToggleVisualLine, &["V"]),
+    (Context::Messages, Action::SelectMessage, &["a m"]),
+    (Context::Messages, Action::SelectCodeBlock, &["a c"]),
+    (Context::Messages, Action::Yank, &["y"]),
+    (Context::Messages, Action::Clear, &["esc"]),
+    (Context::Messages, Action::CancelResponse, &["X X"]),
+    (Context::Messages, Action::MoveLeft, &["left", "h"]),
+    (Context::Messages, Action::MoveRight, &["right", "l"]),
+    (Context::Messages, Action::MoveDown, &["down", "j"]),
+    (Context::Messages, Action::MoveUp, &["up", "k"]),
+    (Context::Messages, Action::MoveTop, &["g g"]),
+    (Context::Messages, Action::MoveBottom, &["G"]),
+    (Context::Messages, Action::MoveWordForward, &["w"]),
+    (Context::Messages, Action::MoveWordBackward, &["b"]),
+    (Context::Messages, Action::MoveWordEnd, &["e"]),
+    (Context::Messages, Action::MoveLineStart, &["0"]),
+    (Context::Messages, Action::MoveLineEnd, &["$"]),
+    (Context::Messages, Action::HalfPageDown, &["ctrl+d"]),
+    (Context::Messages, Action::HalfPageUp, &["ctrl+u"]),
+    (Context::Messages, Action::NextMessage, &["] ]"]),
+    (Context::Messages, Action::PreviousMessage, &["[ ["]),
+    (Context::Messages, Action::Filter, &["/"]),
+    (Context::Messages, Action::NextMatch, &["n"]),
+    (Context::Messages, Action::PreviousMatch, &["N"]),
+    (Context::SessionManager, Action::MoveDown, &["down", "j"]),
+    (Context::SessionManager, Action::MoveUp, &["up", "k"]),
+    (Context::SessionManager, Action::Delete, &["d"]),
+    (Context::SessionManager, Action::Restore, &["u"]),
+    (Context::SessionManager, Action::Mark, &["space"]),
+    (Context::SessionManager, Action::ToggleTrash, &["T"]),
+    (Context::SessionManager, Action::Rename, &["r"]),
+    (Context::SessionManager, Action::Pin, &["p"]),
+    (Context::SessionManager, Action::Archive, &["a"]),
+    (Context::SessionManager, Action::ToggleArchived, &["A"]),
+    (Context::SessionManager, Action::Tags, &["t"]),
+    (Context::SessionManager, Action::Filter, &["/"]),
+    (Context::SessionManager, Action::Clear, &["esc"]),
+    (Context::SettingManager, Action::MoveDown, &["down", "j"]),
+    (Context::SettingManager, Action::MoveUp, &["up", "k"]),
+    (Context::SettingManager, Action::NextSection, &["tab"]),
+    (
+        Context::SettingManager,
+        Action::PreviousSection,
+        &["backtab"],
+    ),
+    (Context::SettingManager, Action::Pick, &["space"]),
+    (Context::SettingManager, Action::Cancel, &["esc"]),
+    (Context::SettingManager, Action::Save, &["enter"]),
+    (Context::SettingManager, Action::Help, &["?", "f1"]),
+    (Context::Prompt, Action::Cancel, &["esc"]),
+    (Context::Prompt, Action::Save, &["enter"]),
+    (Context::Prompt, Action::Help, &["f1"]),
+    (Context::Prompt, Action::ToggleRegex, &["ctrl+r"]),
+    (Context::Help, Action::Cancel, &["esc", "q", "?", "f1"]),
+    (Context::Help, Action::Filter, &["/"]),
+    (Context::Help, Action::MoveDown, &["down", "j"]),
+    (Context::Help, Action::MoveUp, &["up", "k"]),
+    (Context::Help, Action::MoveTop, &["g g"]),
+    (Context::Help, Action::MoveBottom, &["G"]),
+];
+
+/// Keys of an action in `config.toml`, one or a list of them.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum KeysConfig {
+    One(String),
+    Many(Vec<String>),
+}
+
+/// Key bindings per context, defaults overridden by the `[keys.<context>]` tables of
+/// `config.toml`, e.g. `move_top = ["g g", "home"]`. Binding an action replaces its default keys
+/// in that context, an empty list unbinds it.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(try_from = "HashMap<Context, HashMap<Action, KeysConfig>>")]
+pub struct Keymap {
+    bindings: HashMap<Context, Vec<Binding>>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        let mut bindings: HashMap<Context, Vec<Binding>> = HashMap::new();
+        for (context, action, keys) in DEFAULT_BINDINGS {
+            for keys in *keys {
+                bindings.entry(*context).or_default().push(Binding {
+                    keys: KeySequence::parse(keys).expect("invalid default key"),
+                    action: *action,
+                });
+            }
+        }
+        Self { bindings }
+    }
+}
+
+impl TryFrom<HashMap<Context, HashMap<Action, KeysConfig>>> for Keymap {
+    type Error = color_eyre::Report;
+
+    fn try_from(config: HashMap<Context, HashMap<Action, KeysConfig>>) -> Result<Self> {
+        let mut this = Self::default();
+        for (context, actions) in config {
+            let bindings = this.bindings.entry(context).or_default();
+            for (action, keys) in actions {
+                bindings.retain(|b| b.action != action);
+                let keys = match keys {
+                    KeysConfig::One(keys) => vec![keys],
+                    KeysConfig::Many(keys) => keys,
+                };
+                for keys in keys {
+                    bindings.push(Binding {
+                        keys: KeySequence::parse(&keys)?,
+                        action,
+                    });
+                }
+            }
+        }
+        this.validate()?;
+        Ok(this)
+    }
+}
+
+impl Keymap {
+    /// Fails on keys bound to different actions in the same context, or keys that start a longer
+    /// sequence in the same context and would never trigger.
+    fn validate(&self) -> Result<()> {
+        for (context, bindings) in &self.bindings {
+            for (i, a) in bindings.iter().enumerate() {
+                for b in &bindings[i + 1..] {
+                    if a.keys == b.keys && a.action != b.action {
+                        bail!(
+                            "key `{}` is bound to both {:?} and {:?} in {context:?}",
+                            a.keys,
+                            a.action,
+                            b.action
+                        );
+                    }
+                    let (short, long) = if a.keys.0.len() < b.keys.0.len() {
+                        (a, b)
+                    } else {
+                        (b, a)
+                    };
+                    if short.keys != long.keys && long.keys.starts_with(&short.keys.0) {
+                        bail!(
+                            "key `{}` of {:?} starts `{}` of {:?} in {context:?}",
+                            short.keys,
+                            short.action,
+                            long.keys,
+                            long.action
+                        );
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Looks up `keys` pressed so far in `context` and then its parents.
+    pub fn resolve(&self, context: Context, keys: &[KeyChord]) -> KeyMatch {
+        let mut context = Some(context);
+        while let Some(current) = context {
+            let bindings = self.bindings.get(&current).map_or(&[][..], Vec::as_slice);
+            if let Some(binding) = bindings.iter().find(|b| b.keys.0 == keys) {
+                return KeyMatch::Action(binding.action);
+            }
+            if bindings.iter().any(|b| b.keys.starts_with(keys)) {
+                return KeyMatch::Pending;
+            }
+            context = current.parent();
+        }
+        KeyMatch::Unbound
+    }
+
+    /// Returns bindings of `context` followed by those of its parents not shadowed by it.
+    pub fn bindings(&self, context: Context) -> Vec<&Binding> {
+        let mut bindings: Vec<&Binding> = Vec::new();
+        let mut context = Some(context);
+        while let Some(current) = context {
+            for binding in self.bindings.get(&current).into_iter().flatten() {
+                if !bindings.iter().any(|b| b.keys == binding.keys) {
+                    bindings.push(binding);
+                }
+            }
+            context = current.parent();
+        }
+        bindings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crossterm::event::{KeyCode, KeyModifiers};
+
+    use crate::models::keymap::{Action, Context, KeyChord, KeyMatch, Keymap};
+
+    fn chords(keys: &[&str]) -> Vec<KeyChord> {
+        keys.iter().map(|k| KeyChord::parse(k).unwrap()).collect()
+    }
+
+    #[test]
+    fn parse_keys() {
+        let cases = [
+            ("ctrl+e", KeyCode::Char('e'), KeyModifiers::CONTROL),
+            ("shift+enter", KeyCode::Enter, KeyModifiers::SHIFT),
+            ("shift+g", KeyCode::Char('G'), KeyModifiers::NONE),
+            ("G", KeyCode::Char('G'), KeyModifiers::NONE),
+            ("space", KeyCode::Char(' '), KeyModifiers::NONE),
+            ("alt++", KeyCode::Char('+'), KeyModifiers::ALT),
+            ("f5", KeyCode::F(5), KeyModifiers::NONE),
+        ];
+        for (key, code, modifiers) in cases {
+            let chord = KeyChord::parse(key).unwrap();
+            assert_eq!(chord, KeyChord::new(code, modifiers), "{key}");
+            assert_eq!(KeyChord::parse(&chord.to_string()).unwrap(), chord, "{key}");
+        }
+        // shift is part of the character typed
+        assert_eq!(
+            KeyChord::new(KeyCode::Char('G'), KeyModifiers::SHIFT),
+            KeyChord::parse("G").unwrap()
+        );
+        assert!(KeyChord::parse("hyper+x").is_err());
+        assert!(KeyChord::parse("enterr").is_err());
+    }
+
+    #[test]
+    fn resolve() {
+        let keymap = Keymap::default();
+        let cases = [
+            (Context::Messages, vec!["g"], KeyMatch::Pending),
+            (
+                Context::Messages,
+                vec!["g", "g"],
+                KeyMatch::Action(Action::MoveTop),
+            ),
+            (Context::Messages, vec!["g", "j"], KeyMatch::Unbound),
+            // falls back to parent contexts
+            (Context::Messages, vec!["q"], KeyMatch::Action(Action::Quit)),
+            (
+                Context::InputEditorEditing,
+                vec!["ctrl+t"],
+                KeyMatch::Action(Action::CycleTheme),
+            ),
+            // overrides parent bindings
+            (
+                Context::InputEditorEditing,
+                vec!["ctrl+e"],
+                KeyMatch::Action(Action::MoveLineEnd),
+            ),
+            (Context::InputEditorEditing, vec!["q"], KeyMatch::Unbound),
+            (Context::Prompt, vec!["tab"], KeyMatch::Unbound),
+        ];
+        for (context, keys, expected) in cases {
+            assert_eq!(
+                keymap.resolve(context, &chords(&keys)),
+                expected,
+                "{context:?} {keys:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn load_from_config() {
+        let keymap: Keymap = toml::from_str(
+            r#"
+            [messages]
+            move_top = ["home", "g t"]
+            move_bottom = []
+
+            [session_manager]
+            delete = "x"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            keymap.resolve(Context::Messages, &chords(&["g", "t"])),
+            KeyMatch::Action(Action::MoveTop)
+        );
+        assert_eq!(
+            keymap.resolve(Context::Messages, &chords(&["G"])),
+            KeyMatch::Unbound
+        );
+        assert_eq!(
+            keymap.resolve(Context::SessionManager, &chords(&["d"])),
+            KeyMatch::Unbound
+        );
+        assert_eq!(
+            keymap.resolve(Context::SessionManager, &chords(&["x"])),
+            KeyMatch::Action(Action::Delete)
+        );
+
+        let conflicts = [
+            (
+                "[session_manager]\ndelete = \"p\"",
+                "key `p` is bound to both",
+            ),
+            (
+                "[messages]\nyank = \"g\"",
+                "key `g` of Yank starts `g g` of MoveTop",
+            ),
+            ("[messages]\njump = \"g\"", "unknown variant `jump`"),
+        ];
+        for (config, expected) in conflicts {
+            let err = toml::from_str::<Keymap>(config).unwrap_err();
+            assert!(err.to_string().contains(expected), "{err}");
+        }
+    }
+}
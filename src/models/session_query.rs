@@ -0,0 +1,65 @@
+/// Session list filter parsed from the sidebar filter bar, e.g. `rust async tag:work model:o3`.
+/// Qualified terms must all match and the rest fuzzily match the title.
+#[derive(Debug, Default, PartialEq)]
+pub struct SessionQuery {
+    /// Characters the title contains in order, ignoring case.
+    pub title: String,
+    pub tags: Vec<String>,
+    pub models: Vec<String>,
+    /// Lists sessions in trash instead of the others, not parsed from the filter bar.
+    pub trash: bool,
+    /// Lists archived sessions too, not parsed from the filter bar.
+    pub include_archived: bool,
+}
+
+impl SessionQuery {
+    pub fn parse(query: &str) -> Self {
+        let mut this = Self::default();
+        let mut words = Vec::new();
+        for term in query.split_whitespace() {
+            match term.split_once(':') {
+                Some(("tag", tag)) if !tag.is_empty() => this.tags.push(tag.to_string()),
+                Some(("model", model)) if !model.is_empty() => this.models.push(model.to_string()),
+                _ => words.push(term),
+            }
+        }
+        this.title = words.join(" ");
+        this
+    }
+
+    /// Returns the `LIKE` pattern matching titles that contain the title characters in order,
+    /// escaped with `\`.
+    pub fn title_pattern(&self) -> String {
+        let mut pattern = String::from("%");
+        for c in self.title.chars().filter(|c| !c.is_whitespace()) {
+            if matches!(c, '%' | '_' | '\\') {
+                pattern.push('\\');
+            }
+            pattern.push(c);
+            pattern.push('%');
+        }
+        pattern
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::models::session_query::SessionQuery;
+
+    #[test]
+    fn parse() {
+        let query = SessionQuery::parse(" rust  tag:work model:o3 tag: 100%_ ");
+        assert_eq!(
+            query,
+            SessionQuery {
+                title: "rust tag: 100%_".to_string(),
+                tags: vec!["work".to_string()],
+                models: vec!["o3".to_string()],
+                trash: false,
+                include_archived: false,
+            }
+        );
+        assert_eq!(SessionQuery::parse("r a").title_pattern(), "%r%a%");
+        assert_eq!(SessionQuery::parse("1%").title_pattern(), "%1%\\%%");
+    }
+}
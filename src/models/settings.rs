@@ -1,19 +1,205 @@
-use crate::llm::*;
+use color_eyre::{Result, eyre::bail};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    llm::*,
+    models::{OPENAI_MODELS, constants::MIN_MAX_OUTPUT_TOKENS},
+};
+
+/// Llm providers selectable in settings.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum ProviderKind {
+    #[default]
+    OpenAi,
+    /// Plays scripted responses, for offline development and tests.
+    Mock,
+}
+
+pub const PROVIDERS: &[ProviderKind] = &[ProviderKind::OpenAi, ProviderKind::Mock];
+
+impl ProviderKind {
+    pub fn display_name(&self) -> &'static str {
+        match self {
+            ProviderKind::OpenAi => "openAI",
+            ProviderKind::Mock => "mock",
+        }
+    }
+
+    /// Returns models of the provider.
+    pub fn models(&self) -> &'static [OpenAiModel] {
+        match self {
+            ProviderKind::OpenAi => OPENAI_MODELS,
+            ProviderKind::Mock => &[],
+        }
+    }
+
+    /// Returns default settings of the provider.
+    pub fn default_provider(&self) -> llm_settings::Provider {
+        match self {
+            ProviderKind::OpenAi => llm_settings::Provider::OpenAi(OpenAiSettings {
+                model: OpenAiModel::Gpt4o as i32,
+                ..Default::default()
+            }),
+            ProviderKind::Mock => llm_settings::Provider::Mock(MockSettings {}),
+        }
+    }
+}
+
+/// Availability of a provider, unavailable providers carry the reason.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ProviderStatus {
+    pub provider: ProviderKind,
+    pub error: Option<String>,
+}
 
 impl LlmSettings {
-    /// Returns provider display name.
-    pub fn provider_name(&self) -> &'static str {
+    pub fn provider_kind(&self) -> Option<ProviderKind> {
         match self.provider {
-            Some(llm_settings::Provider::OpenAi(_)) => "openAI",
-            None => "Unspecified",
+            Some(llm_settings::Provider::OpenAi(_)) => Some(ProviderKind::OpenAi),
+            Some(llm_settings::Provider::Mock(_)) => Some(ProviderKind::Mock),
+            None => None,
         }
     }
 
+    /// Returns provider display name.
+    pub fn provider_name(&self) -> &'static str {
+        self.provider_kind()
+            .map_or("Unspecified", |p| p.display_name())
+    }
+
     /// Returns the model display name.
     pub fn model_name(&self) -> &'static str {
         match self.provider {
             Some(llm_settings::Provider::OpenAi(settings)) => settings.model().display_name(),
+            Some(llm_settings::Provider::Mock(_)) => "scripted",
             None => "Unspecified",
         }
     }
+
+    /// Returns the context window size in tokens of the model.
+    pub fn context_window(&self) -> usize {
+        match self.provider {
+            Some(llm_settings::Provider::OpenAi(settings)) => settings.model().context_window(),
+            Some(llm_settings::Provider::Mock(_)) | None => {
+                OpenAiModel::Unspecified.context_window()
+            }
+        }
+    }
+}
+
+impl LlmSettings {
+    /// Validates parameters against their ranges and what the model supports.
+    pub fn validate(&self) -> Result<()> {
+        match &self.provider {
+            Some(llm_settings::Provider::OpenAi(settings)) => settings.validate(),
+            Some(llm_settings::Provider::Mock(_)) | None => Ok(()),
+        }
+    }
+}
+
+impl OpenAiSettings {
+    /// Validates parameters against their ranges and what the model supports.
+    pub fn validate(&self) -> Result<()> {
+        let model = self.model();
+        if !model.supports_sampling() && (self.temperature.is_some() || self.top_p.is_some()) {
+            bail!(
+                "{} does not support temperature or top_p",
+                model.display_name()
+            );
+        }
+        if let Some(temperature) = self.temperature
+            && !(0.0..=2.0).contains(&temperature)
+        {
+            bail!("temperature must be between 0 and 2, got {temperature}");
+        }
+        if let Some(top_p) = self.top_p
+            && !(0.0..=1.0).contains(&top_p)
+        {
+            bail!("top_p must be between 0 and 1, got {top_p}");
+        }
+        if let Some(max_output_tokens) = self.max_output_tokens
+            && max_output_tokens < MIN_MAX_OUTPUT_TOKENS
+        {
+            bail!(
+                "max output tokens must be at least {MIN_MAX_OUTPUT_TOKENS}, got {max_output_tokens}"
+            );
+        }
+        Ok(())
+    }
+
+    /// Clears parameters the model does not support.
+    pub fn clear_unsupported(&mut self) {
+        if !self.model().supports_sampling() {
+            self.temperature = None;
+            self.top_p = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::llm::*;
+
+    #[test]
+    fn validate() {
+        struct Case {
+            description: &'static str,
+            settings: OpenAiSettings,
+            expected_valid: bool,
+        }
+        let cases = vec![
+            Case {
+                description: "defaults",
+                settings: OpenAiSettings::default(),
+                expected_valid: true,
+            },
+            Case {
+                description: "sampling parameters on non reasoning model",
+                settings: OpenAiSettings {
+                    model: OpenAiModel::Gpt4o as i32,
+                    temperature: Some(0.7),
+                    top_p: Some(0.9),
+                    ..Default::default()
+                },
+                expected_valid: true,
+            },
+            Case {
+                description: "temperature on reasoning model",
+                settings: OpenAiSettings {
+                    model: OpenAiModel::O4Mini as i32,
+                    temperature: Some(0.7),
+                    ..Default::default()
+                },
+                expected_valid: false,
+            },
+            Case {
+                description: "temperature out of range",
+                settings: OpenAiSettings {
+                    model: OpenAiModel::Gpt4o as i32,
+                    temperature: Some(2.5),
+                    ..Default::default()
+                },
+                expected_valid: false,
+            },
+            Case {
+                description: "max output tokens too small",
+                settings: OpenAiSettings {
+                    model: OpenAiModel::O3 as i32,
+                    max_output_tokens: Some(8),
+                    ..Default::default()
+                },
+                expected_valid: false,
+            },
+        ];
+
+        for case in cases {
+            assert_eq!(
+                case.settings.validate().is_ok(),
+                case.expected_valid,
+                "{}",
+                case.description
+            );
+        }
+    }
 }
@@ -0,0 +1,921 @@
+pub mod http;
+pub mod proxy;
+
+use color_eyre::eyre::{Context as _, Result, bail};
+use serde::Deserialize;
+use std::{
+    net::IpAddr,
+    os::unix::fs::FileTypeExt as _,
+    path::PathBuf,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncRead, AsyncWrite, BufReader},
+    net::{TcpListener, UnixListener, UnixStream},
+    sync::{
+        broadcast,
+        mpsc::{UnboundedReceiver, UnboundedSender},
+    },
+};
+
+use crate::{
+    chat::*,
+    llm::*,
+    models::{
+        ClientId, ServiceReq, ServiceResp,
+        configs::{Config, ServerConfig},
+        settings::ProviderStatus,
+    },
+    server::http::{
+        Request, RequestError, Response, read_request, write_event, write_event_stream_head,
+    },
+};
+
+/// How long to wait for the service to answer a request.
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long a reply may go without chat events before its stream is ended, e.g. if they were
+/// skipped.
+const REPLY_IDLE_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Service responses fanned out to connections, with providers cached from them since the service
+/// only pushes those.
+pub struct Hub {
+    resp_tx: broadcast::Sender<ServiceResp>,
+    next_client_id: AtomicU64,
+    providers: Mutex<Vec<ProviderStatus>>,
+}
+
+impl Hub {
+    /// Spawns task forwarding service responses from `resp_rx` to subscribers.
+    pub fn spawn(mut resp_rx: UnboundedReceiver<ServiceResp>) -> Arc<Self> {
+        let (resp_tx, _) = broadcast::channel(1024);
+        let hub = Arc::new(Self {
+            resp_tx,
+            next_client_id: AtomicU64::default(),
+            providers: Mutex::default(),
+        });
+        tokio::spawn({
+            let hub = hub.clone();
+            async move {
+                while let Some(resp) = resp_rx.recv().await {
+                    hub.update(&resp);
+                    // no subscribers is fine
+                    let _ = hub.resp_tx.send(resp);
+                }
+            }
+        });
+        hub
+    }
+
+    fn update(&self, resp: &ServiceResp) {
+        if let ServiceResp::Providers(providers) = resp {
+            *self.providers.lock().unwrap() = providers.clone()
+        }
+    }
+
+    /// Assigns an id to a new client of the service.
+    pub fn client_id(&self) -> ClientId {
+        self.next_client_id.fetch_add(1, Ordering::Relaxed)
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<ServiceResp> {
+        self.resp_tx.subscribe()
+    }
+
+    pub fn providers(&self) -> Vec<ProviderStatus> {
+        self.providers.lock().unwrap().clone()
+    }
+}
+
+/// Receives the first response matching `f`, None if the service stops or does not answer in
+/// time.
+pub async fn recv_matching(
+    resp_rx: &mut broadcast::Receiver<ServiceResp>,
+    f: impl Fn(&ServiceResp) -> bool,
+) -> Option<ServiceResp> {
+    tokio::time::timeout(RESPONSE_TIMEOUT, async {
+        loop {
+            match resp_rx.recv().await {
+                Ok(resp) if f(&resp) => return Some(resp),
+                Ok(_) => {}
+                Err(broadcast::error::RecvError::Lagged(n)) => {
+                    tracing::warn!("skipped {n} service responses")
+                }
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    })
+    .await
+    .ok()
+    .flatten()
+}
+
+/// Receives reply to client `client_id`, None if the service stops or does not answer in time.
+pub async fn recv_reply(
+    resp_rx: &mut broadcast::Receiver<ServiceResp>,
+    client_id: ClientId,
+) -> Option<ServiceResp> {
+    match recv_matching(resp_rx, |resp| is_reply(resp, client_id)).await? {
+        ServiceResp::ToClient { resp, .. } => Some(*resp),
+        _ => None,
+    }
+}
+
+fn is_reply(resp: &ServiceResp, client_id: ClientId) -> bool {
+    matches!(resp, ServiceResp::ToClient { client_id: id, .. } if *id == client_id)
+}
+
+/// Returns whether `host` header names a loopback address, with or without port.
+fn is_loopback_host(host: &str) -> bool {
+    let name = match host.strip_prefix('[') {
+        Some(rest) => rest.split_once(']').map_or(rest, |(ip, _)| ip),
+        None => host.split_once(':').map_or(host, |(name, _)| name),
+    };
+    name.eq_ignore_ascii_case("localhost")
+        || name.parse::<IpAddr>().is_ok_and(|ip| ip.is_loopback())
+}
+
+/// Returns whether `content_type` is json, ignoring parameters such as charset.
+fn is_json(content_type: &str) -> bool {
+    content_type
+        .split(';')
+        .next()
+        .is_some_and(|mime| mime.trim().eq_ignore_ascii_case("application/json"))
+}
+
+/// Compares `a` and `b` in time independent of where they differ, e.g. for secrets.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+pub enum Listener {
+    Tcp(TcpListener),
+    Unix(UnixListener, PathBuf),
+}
+
+impl Listener {
+    /// Binds unix socket if configured, otherwise the loopback address.
+    pub async fn bind(config: &ServerConfig) -> Result<Self> {
+        if let Some(socket) = &config.socket {
+            // remove socket left by a previous run
+            if let Ok(metadata) = std::fs::symlink_metadata(socket) {
+                if !metadata.file_type().is_socket() {
+                    bail!("{} exists and is not a socket", socket.display());
+                }
+                if UnixStream::connect(socket).await.is_ok() {
+                    bail!("{} is in use", socket.display());
+                }
+                std::fs::remove_file(socket)
+                    .wrap_err_with(|| format!("failed to remove {}", socket.display()))?;
+            }
+            let listener = UnixListener::bind(socket)
+                .wrap_err_with(|| format!("failed to bind {}", socket.display()))?;
+            return Ok(Listener::Unix(listener, socket.clone()));
+        }
+        if !config.address.ip().is_loopback() {
+            bail!(
+                "server address {} is not a loopback address",
+                config.address
+            );
+        }
+        let listener = TcpListener::bind(config.address)
+            .await
+            .wrap_err_with(|| format!("failed to bind {}", config.address))?;
+        Ok(Listener::Tcp(listener))
+    }
+
+    pub fn display(&self) -> String {
+        match self {
+            Listener::Tcp(listener) => listener
+                .local_addr()
+                .map_or_else(|e| e.to_string(), |addr| format!("http://{addr}")),
+            Listener::Unix(_, path) => path.display().to_string(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct PostMessage {
+    msg: String,
+    /// Settings of the message, configured defaults if unset.
+    #[serde(default)]
+    llm_settings: Option<LlmSettings>,
+}
+
+/// Http api over `Service` for other tools, e.g. editor plugins, scripts or a web view. Routes:
+///
+/// - `GET /v1/sessions?query=...&cursor=...&archived=true` lists a page of sessions without events
+///   matching sidebar filter `query`, archived ones if `archived`, `next_cursor` is set if there
+///   are more.
+/// - `GET /v1/sessions/{id}` gets session with its latest events, `events_cursor` is set if there
+///   are older ones.
+/// - `DELETE /v1/sessions/{id}` moves session to trash.
+/// - `POST /v1/sessions/{id}/messages` sends `{"msg": ..., "llm_settings": ...}`, creating the
+///   session if new, and streams chat events of the reply as server sent events.
+/// - `GET /v1/sessions/{id}/events` streams chat events of session as server sent events.
+/// - `GET /v1/providers` lists providers and their availability.
+/// - `POST /v1/chat/completions` and `POST /v1/responses` proxy open ai sdk requests through
+///   cookie's providers, recording conversations as sessions.
+#[derive(Clone)]
+pub struct Server {
+    req_tx: UnboundedSender<ServiceReq>,
+    hub: Arc<Hub>,
+    token: Option<String>,
+    default_llm_settings: LlmSettings,
+}
+
+impl Server {
+    pub async fn new(
+        req_tx: UnboundedSender<ServiceReq>,
+        resp_rx: UnboundedReceiver<ServiceResp>,
+        config: &Config,
+    ) -> Result<Self> {
+        let token = match &config.server.token {
+            Some(token) => Some(
+                token
+                    .resolve("COOKIE_SERVER_TOKEN")
+                    .await
+                    .wrap_err("failed to read server token")?,
+            ),
+            None => None,
+        };
+        Ok(Self {
+            req_tx,
+            hub: Hub::spawn(resp_rx),
+            token,
+            default_llm_settings: config.derive_llm_settings(),
+        })
+    }
+
+    /// Serves connections of `listener` until it fails.
+    pub async fn run(self, listener: Listener) -> Result<()> {
+        loop {
+            match &listener {
+                Listener::Tcp(listener) => {
+                    let (conn, _) = listener.accept().await?;
+                    tokio::spawn(self.clone().serve(conn));
+                }
+                Listener::Unix(listener, _) => {
+                    let (conn, _) = listener.accept().await?;
+                    tokio::spawn(self.clone().serve(conn));
+                }
+            }
+        }
+    }
+
+    async fn serve<S: AsyncRead + AsyncWrite + Unpin>(self, conn: S) {
+        let mut conn = BufReader::new(conn);
+        let result = match read_request(&mut conn).await {
+            Ok(req) => self.handle(req, conn.get_mut()).await,
+            Err(e) => {
+                let status = e.downcast_ref::<RequestError>().map_or(400, |e| e.status);
+                Response::error(status, &format!("{e:#}"))
+                    .write(conn.get_mut())
+                    .await
+            }
+        };
+        if let Err(e) = result {
+            tracing::debug!("connection closed: {e:?}");
+        }
+    }
+
+    fn authorized(&self, req: &Request) -> bool {
+        let Some(token) = &self.token else {
+            return true;
+        };
+        req.header("authorization")
+            .and_then(|v| v.strip_prefix("Bearer "))
+            .is_some_and(|v| constant_time_eq(v.as_bytes(), token.as_bytes()))
+    }
+
+    /// Sends `req` as a new client of the service, returns its id and a receiver of responses
+    /// from then on. None if the service stopped.
+    fn send(&self, req: ServiceReq) -> Option<(ClientId, broadcast::Receiver<ServiceResp>)> {
+        let resp_rx = self.hub.subscribe();
+        let client_id = self.hub.client_id();
+        self.req_tx
+            .send(ServiceReq::FromClient {
+                client_id,
+                req: Box::new(req),
+            })
+            .ok()?;
+        Some((client_id, resp_rx))
+    }
+
+    async fn handle<W: AsyncWrite + Unpin>(&self, req: Request, conn: &mut W) -> Result<()> {
+        // web pages may send requests to loopback addresses too, through dns rebinding or as
+        // cross origin requests that skip preflight
+        if !req.header("host").is_some_and(is_loopback_host) {
+            return Response::error(403, "host must be a loopback address")
+                .write(conn)
+                .await;
+        }
+        if req.header("origin").is_some() {
+            return Response::error(403, "requests from web pages are not allowed")
+                .write(conn)
+                .await;
+        }
+        if req.method == "POST" && !req.header("content-type").is_some_and(is_json) {
+            return Response::error(415, "content-type must be application/json")
+                .write(conn)
+                .await;
+        }
+        if !self.authorized(&req) {
+            return Response::error(401, "missing or invalid bearer token")
+                .write(conn)
+                .await;
+        }
+        let segments: Vec<&str> = req.path().trim_matches('/').split('/').collect();
+        let resp = match (req.method.as_str(), segments.as_slice()) {
+            ("GET", ["v1", "sessions"]) => {
+                self.list_sessions(
+                    req.query("query").unwrap_or_default(),
+                    req.query("cursor"),
+                    req.query("archived").is_some_and(|v| v == "true"),
+                )
+                .await
+            }
+            ("GET", ["v1", "sessions", id]) => self.get_session(id).await,
+            ("DELETE", ["v1", "sessions", id]) => self.delete_session(id).await,
+            ("POST", ["v1", "sessions", id, "messages"]) => {
+                return match req.json::<PostMessage>() {
+                    Ok(body) => self.post_message(id, body, conn).await,
+                    Err(e) => Response::error(400, &format!("{e:#}")).write(conn).await,
+                };
+            }
+            ("GET", ["v1", "sessions", id, "events"]) => {
+                return self.stream_events(id, conn).await;
+            }
+            ("GET", ["v1", "providers"]) => Response::json(200, &self.hub.providers()),
+            ("POST", ["v1", "chat", "completions"]) => {
+                return self.chat_completions(&req, conn).await;
+            }
+            ("POST", ["v1", "responses"]) => return self.responses(&req, conn).await,
+            _ => Response::error(404, &format!("no route for {} {}", req.method, req.path())),
+        };
+        resp.write(conn).await
+    }
+
+    /// Looks up session of `session_id` not in trash, Err with the response otherwise.
+    async fn lookup_session(&self, session_id: &str) -> std::result::Result<ChatSession, Response> {
+        let Some((client_id, mut resp_rx)) =
+            self.send(ServiceReq::GetSession(session_id.to_string()))
+        else {
+            return Err(Response::error(503, "service stopped"));
+        };
+        match recv_reply(&mut resp_rx, client_id).await {
+            Some(ServiceResp::Session(session)) if session.deleted_at.is_none() => Ok(session),
+            Some(ServiceResp::Session(_)) => Err(Response::error(
+                404,
+                &format!("session {session_id} not found"),
+            )),
+            Some(ServiceResp::Notice(notice)) => Err(Response::error(404, &notice)),
+            Some(ServiceResp::Error(e)) => Err(Response::error(500, &e)),
+            _ => Err(Response::error(504, "service did not respond")),
+        }
+    }
+
+    async fn list_sessions(
+        &self,
+        query: String,
+        cursor: Option<String>,
+        include_archived: bool,
+    ) -> Response {
+        let Some((client_id, mut resp_rx)) = self.send(ServiceReq::QuerySessions {
+            query,
+            cursor,
+            trash: false,
+            include_archived,
+        }) else {
+            return Response::error(503, "service stopped");
+        };
+        match recv_reply(&mut resp_rx, client_id).await {
+            Some(ServiceResp::SessionQuery {
+                sessions,
+                next_cursor,
+                ..
+            }) => Response::json(
+                200,
+                &serde_json::json!({"sessions": sessions, "next_cursor": next_cursor}),
+            ),
+            Some(ServiceResp::Notice(notice)) => Response::error(400, &notice),
+            Some(ServiceResp::Error(e)) => Response::error(500, &e),
+            _ => Response::error(504, "service did not respond"),
+        }
+    }
+
+    async fn get_session(&self, session_id: &str) -> Response {
+        match self.lookup_session(session_id).await {
+            Ok(session) => Response::json(200, &session),
+            Err(resp) => resp,
+        }
+    }
+
+    async fn delete_session(&self, session_id: &str) -> Response {
+        if let Err(resp) = self.lookup_session(session_id).await {
+            return resp;
+        }
+        let Some((client_id, mut resp_rx)) =
+            self.send(ServiceReq::DeleteSessions(vec![session_id.to_string()]))
+        else {
+            return Response::error(503, "service stopped");
+        };
+        match recv_matching(&mut resp_rx, |resp| {
+            is_reply(resp, client_id) || matches!(resp, ServiceResp::SessionsChanged)
+        })
+        .await
+        {
+            Some(ServiceResp::ToClient { resp, .. }) => match *resp {
+                ServiceResp::Error(e) => Response::error(500, &e),
+                _ => Response::error(500, "unexpected service response"),
+            },
+            Some(_) => Response::empty(204),
+            None => Response::error(504, "service did not respond"),
+        }
+    }
+
+    /// Sends user message and streams chat events of session until the assistant message.
+    async fn post_message<W: AsyncWrite + Unpin>(
+        &self,
+        session_id: &str,
+        body: PostMessage,
+        conn: &mut W,
+    ) -> Result<()> {
+        let llm_settings = body
+            .llm_settings
+            .unwrap_or_else(|| self.default_llm_settings.clone());
+        if let Err(e) = llm_settings.validate() {
+            return Response::error(400, &format!("{e:#}")).write(conn).await;
+        }
+        let user_message = ChatEvent::new(
+            session_id.to_string(),
+            Some(llm_settings),
+            chat_event::Payload::Message(Message {
+                role: Role::User as i32,
+                msg: body.msg,
+                response_ref: None,
+            }),
+        );
+
+        let Some((client_id, mut resp_rx)) = self.send(ServiceReq::ChatMessage(user_message))
+        else {
+            return Response::error(503, "service stopped").write(conn).await;
+        };
+        write_event_stream_head(conn).await?;
+        self.forward_events(session_id, &mut resp_rx, conn, Some(client_id))
+            .await
+    }
+
+    async fn stream_events<W: AsyncWrite + Unpin>(
+        &self,
+        session_id: &str,
+        conn: &mut W,
+    ) -> Result<()> {
+        let mut resp_rx = self.hub.subscribe();
+        write_event_stream_head(conn).await?;
+        self.forward_events(session_id, &mut resp_rx, conn, None)
+            .await
+    }
+
+    /// Writes chat events of `session_id` as json until the service stops or the connection
+    /// closes. If replying to the message of client `reply_to`, until the assistant message
+    /// arrives, an error is replied to the client or no events arrive for a while.
+    async fn forward_events<W: AsyncWrite + Unpin>(
+        &self,
+        session_id: &str,
+        resp_rx: &mut broadcast::Receiver<ServiceResp>,
+        conn: &mut W,
+        reply_to: Option<ClientId>,
+    ) -> Result<()> {
+        loop {
+            let recv = match reply_to {
+                Some(_) => match tokio::time::timeout(REPLY_IDLE_TIMEOUT, resp_rx.recv()).await {
+                    Ok(recv) => recv,
+                    Err(_) => {
+                        let data =
+                            serde_json::json!({"error": {"message": "service did not respond"}});
+                        return write_event(conn, Some("error"), &data.to_string()).await;
+                    }
+                },
+                None => resp_rx.recv().await,
+            };
+            let chat_event = match recv {
+                Ok(ServiceResp::ChatEvent(chat_event)) if chat_event.session_id == session_id => {
+                    chat_event
+                }
+                Ok(ServiceResp::ToClient { client_id, resp }) if Some(client_id) == reply_to => {
+                    let (ServiceResp::Error(e) | ServiceResp::Notice(e)) = *resp else {
+                        continue;
+                    };
+                    let data = serde_json::json!({"error": {"message": e}}).to_string();
+                    return write_event(conn, Some("error"), &data).await;
+                }
+                Ok(ServiceResp::ChatFailed {
+                    session_id: id,
+                    error,
+                }) if id == session_id => {
+                    let data = serde_json::json!({"error": {"message": error}}).to_string();
+                    return write_event(conn, Some("error"), &data).await;
+                }
+                Ok(_) => continue,
+                Err(broadcast::error::RecvError::Lagged(n)) => {
+                    tracing::warn!("skipped {n} service responses");
+                    continue;
+                }
+                Err(broadcast::error::RecvError::Closed) => return Ok(()),
+            };
+            write_event(conn, None, &serde_json::to_string(&chat_event)?).await?;
+            let is_reply = matches!(
+                &chat_event.payload,
+                Some(chat_event::Payload::Message(m)) if m.role() == Role::Assistant
+            );
+            if reply_to.is_some() && is_reply {
+                return Ok(());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::StreamExt;
+    use reqwest_eventsource::{Event, EventSource};
+    use rstest::rstest;
+    use rusqlite::Connection;
+    use std::sync::Arc;
+    use tokio::{
+        io::{AsyncReadExt as _, AsyncWriteExt as _, BufReader},
+        net::{TcpListener, TcpStream},
+        sync::mpsc::unbounded_channel,
+    };
+
+    use crate::{
+        chat::*,
+        models::{ServiceReq, ServiceResp, configs::Config, settings::ProviderKind},
+        server::{
+            Listener, Server,
+            http::{RequestError, read_request},
+            is_loopback_host,
+        },
+        service::{
+            ServiceBuilder,
+            database::init_db_conn,
+            llms::{
+                LlmClientRouter,
+                mock::{MockLlmClient, MockScript},
+            },
+        },
+    };
+
+    #[rstest]
+    #[case("localhost:8787", true)]
+    #[case("127.0.0.1", true)]
+    #[case("[::1]:8787", true)]
+    #[case("127.0.0.1.attacker.example", false)]
+    #[case("attacker.example:8787", false)]
+    #[case("[::ffff:10.0.0.1]", false)]
+    fn loopback_host(#[case] host: &str, #[case] expected: bool) {
+        assert_eq!(is_loopback_host(host), expected);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn slow_headers_time_out() {
+        let (mut client, server) = tokio::io::duplex(64);
+        client
+            .write_all(b"GET /v1/sessions HTTP/1.1\r\n")
+            .await
+            .unwrap();
+        let err = read_request(&mut BufReader::new(server))
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(err.downcast_ref::<RequestError>().unwrap().status, 408);
+    }
+
+    #[tokio::test]
+    async fn api() {
+        let (req_tx, req_rx) = unbounded_channel::<ServiceReq>();
+        let (resp_tx, resp_rx) = unbounded_channel::<ServiceResp>();
+        let script: MockScript =
+            serde_json::from_str(r#"{"responses": [{"events": [{"delta": "Hello!"}]}]}"#).unwrap();
+        let mut llm_router = LlmClientRouter::default();
+        llm_router.register(ProviderKind::Mock, Arc::new(MockLlmClient::new(script)));
+        let mut config = Config {
+            provider: ProviderKind::Mock,
+            ..Config::default()
+        };
+        config.server.token = Some(crate::models::configs::ApiKeyConfig {
+            key_command: Some("echo secret".to_string()),
+            ..Default::default()
+        });
+        let service = ServiceBuilder::new(req_rx, resp_tx, config.clone())
+            .with_db_conn(init_db_conn(Connection::open_in_memory().unwrap()).unwrap())
+            .with_llm_router(llm_router)
+            .build()
+            .await
+            .unwrap();
+        let service = tokio::spawn(service.run());
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let server = Server::new(req_tx, resp_rx, &config).await.unwrap();
+        let server = tokio::spawn(server.run(Listener::Tcp(listener)));
+
+        let client = reqwest::Client::new();
+        let resp = client
+            .get(format!("{url}/v1/sessions"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), 401);
+        let resp = client
+            .get(format!("{url}/v1/sessions/missing"))
+            .bearer_auth("secret")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), 404);
+
+        // requests of web pages are rejected
+        let resp = client
+            .get(format!("{url}/v1/providers"))
+            .header("host", "attacker.example:8787")
+            .bearer_auth("secret")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), 403);
+        let resp = client
+            .get(format!("{url}/v1/providers"))
+            .header("origin", "https://attacker.example")
+            .bearer_auth("secret")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), 403);
+        let resp = client
+            .post(format!("{url}/v1/sessions/s1/messages"))
+            .header("content-type", "text/plain")
+            .body(r#"{"msg": "hi"}"#)
+            .bearer_auth("secret")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), 415);
+
+        // chunked bodies are rejected rather than misread
+        let mut conn = TcpStream::connect(url.trim_start_matches("http://"))
+            .await
+            .unwrap();
+        conn.write_all(b"POST /v1/sessions/s1/messages HTTP/1.1\r\nauthorization: Bearer secret\r\ntransfer-encoding: chunked\r\n\r\n0\r\n\r\n")
+            .await
+            .unwrap();
+        let mut head = String::new();
+        conn.read_to_string(&mut head).await.unwrap();
+        assert!(head.starts_with("HTTP/1.1 411"), "{head}");
+
+        // headers are read up to a limit
+        let mut conn = TcpStream::connect(url.trim_start_matches("http://"))
+            .await
+            .unwrap();
+        let mut req = b"GET /v1/sessions HTTP/1.1\r\nx-padding: ".to_vec();
+        req.resize(32 << 10, b'a');
+        conn.write_all(&req).await.unwrap();
+        let mut head = String::new();
+        conn.read_to_string(&mut head).await.unwrap();
+        assert!(head.starts_with("HTTP/1.1 431"), "{head}");
+
+        let req = client
+            .post(format!("{url}/v1/sessions/s1/messages"))
+            .bearer_auth("secret")
+            .json(&serde_json::json!({"msg": "hi"}));
+        let mut events = EventSource::new(req).unwrap();
+        let mut payloads = Vec::new();
+        while let Some(event) = events.next().await {
+            match event {
+                Ok(Event::Open) => {}
+                Ok(Event::Message(msg)) => {
+                    let chat_event: ChatEvent = serde_json::from_str(&msg.data).unwrap();
+                    payloads.push(chat_event.payload.unwrap());
+                }
+                Err(_) => break,
+            }
+        }
+        assert!(matches!(
+            payloads.last(),
+            Some(chat_event::Payload::Message(m)) if m.role() == Role::Assistant && m.msg == "Hello!"
+        ));
+        assert_eq!(payloads.len(), 3);
+
+        let session: ChatSession = client
+            .get(format!("{url}/v1/sessions/s1"))
+            .bearer_auth("secret")
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(session.events.len(), 2);
+
+        let resp = client
+            .delete(format!("{url}/v1/sessions/s1"))
+            .bearer_auth("secret")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), 204);
+        let resp = client
+            .get(format!("{url}/v1/sessions/s1"))
+            .bearer_auth("secret")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), 404);
+
+        // stop service by dropping request sender held by the server
+        server.abort();
+        service.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn proxy() {
+        let (req_tx, req_rx) = unbounded_channel::<ServiceReq>();
+        let (resp_tx, resp_rx) = unbounded_channel::<ServiceResp>();
+        let script: MockScript = serde_json::from_str(
+            r#"{"responses": [{"events": [{"delta": "Hel"}, {"delta": "lo!"}]}]}"#,
+        )
+        .unwrap();
+        let mut llm_router = LlmClientRouter::default();
+        llm_router.register(ProviderKind::Mock, Arc::new(MockLlmClient::new(script)));
+        let config = Config {
+            provider: ProviderKind::Mock,
+            ..Config::default()
+        };
+        let service = ServiceBuilder::new(req_rx, resp_tx, config.clone())
+            .with_db_conn(init_db_conn(Connection::open_in_memory().unwrap()).unwrap())
+            .with_llm_router(llm_router)
+            .build()
+            .await
+            .unwrap();
+        let service = tokio::spawn(service.run());
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let server = Server::new(req_tx, resp_rx, &config).await.unwrap();
+        let server = tokio::spawn(server.run(Listener::Tcp(listener)));
+        let client = reqwest::Client::new();
+
+        // history of a new conversation is imported
+        let mut messages = serde_json::json!([
+            {"role": "system", "content": "Be brief."},
+            {"role": "user", "content": "hi"},
+            {"role": "assistant", "content": "hey"},
+            {"role": "user", "content": [{"type": "text", "text": "how are you"}]},
+        ]);
+        let completion: serde_json::Value = client
+            .post(format!("{url}/v1/chat/completions"))
+            .header("x-cookie-source", "script")
+            .json(&serde_json::json!({"model": "mock", "messages": messages}))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(completion["object"], "chat.completion");
+        assert_eq!(completion["choices"][0]["message"]["content"], "Hello!");
+
+        // resent history continues the conversation
+        let messages = messages.as_array_mut().unwrap();
+        messages.push(serde_json::json!({"role": "assistant", "content": "Hello!"}));
+        messages.push(serde_json::json!({"role": "user", "content": "bye"}));
+        let req = client
+            .post(format!("{url}/v1/chat/completions"))
+            .header("x-cookie-source", "script")
+            .json(&serde_json::json!({"model": "mock", "messages": messages, "stream": true}));
+        let mut events = EventSource::new(req).unwrap();
+        let mut data = Vec::new();
+        while let Some(event) = events.next().await {
+            match event {
+                Ok(Event::Open) => {}
+                Ok(Event::Message(msg)) => data.push(msg.data),
+                Err(_) => break,
+            }
+        }
+        assert_eq!(data.last().map(String::as_str), Some("[DONE]"));
+        let content: String = data[..data.len() - 1]
+            .iter()
+            .map(|d| serde_json::from_str::<serde_json::Value>(d).unwrap())
+            .filter_map(|chunk| {
+                chunk["choices"][0]["delta"]["content"]
+                    .as_str()
+                    .map(str::to_string)
+            })
+            .collect();
+        assert_eq!(content, "Hello!");
+
+        let page: serde_json::Value = client
+            .get(format!("{url}/v1/sessions"))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert!(page["next_cursor"].is_null());
+        let sessions: Vec<ChatSession> = serde_json::from_value(page["sessions"].clone()).unwrap();
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[0].source, "script");
+        let page: serde_json::Value = client
+            .get(format!("{url}/v1/sessions?query=tag%3Amissing"))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(page["sessions"], serde_json::json!([]));
+        let session: ChatSession = client
+            .get(format!("{url}/v1/sessions/{}", sessions[0].id))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(session.events.len(), 6);
+        assert_eq!(
+            session
+                .llm_settings
+                .as_ref()
+                .map(|s| s.instructions.as_str()),
+            Some("Be brief.")
+        );
+
+        // responses continue from previous response id
+        let response: serde_json::Value = client
+            .post(format!("{url}/v1/responses"))
+            .json(&serde_json::json!({"model": "mock", "input": "hi"}))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(response["output"][0]["content"][0]["text"], "Hello!");
+        let req = client
+            .post(format!("{url}/v1/responses"))
+            .json(&serde_json::json!({
+                "model": "mock",
+                "input": [{"role": "user", "content": [{"type": "input_text", "text": "bye"}]}],
+                "previous_response_id": response["id"],
+                "stream": true,
+            }));
+        let mut events = EventSource::new(req).unwrap();
+        let mut kinds = Vec::new();
+        while let Some(event) = events.next().await {
+            match event {
+                Ok(Event::Open) => {}
+                Ok(Event::Message(msg)) => kinds.push(msg.event),
+                Err(_) => break,
+            }
+        }
+        assert_eq!(
+            kinds,
+            [
+                "response.created",
+                "response.output_text.delta",
+                "response.output_text.delta",
+                "response.output_text.done",
+                "response.completed"
+            ]
+        );
+        let page: serde_json::Value = client
+            .get(format!("{url}/v1/sessions"))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        let sessions: Vec<ChatSession> = serde_json::from_value(page["sessions"].clone()).unwrap();
+        assert_eq!(sessions.len(), 2);
+        assert!(sessions.iter().any(|s| s.source == "proxy"));
+
+        let resp = client
+            .post(format!("{url}/v1/chat/completions"))
+            .json(&serde_json::json!({"model": "gpt-5", "messages": [{"role": "user", "content": "hi"}]}))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), 400);
+
+        server.abort();
+        service.await.unwrap().unwrap();
+    }
+}
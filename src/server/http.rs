@@ -0,0 +1,239 @@
+//! Minimal http/1.1 over any byte stream, serving one request per connection.
+
+use color_eyre::eyre::{Context as _, Result, bail, eyre};
+use serde::{Serialize, de::DeserializeOwned};
+use std::time::Duration;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt as _, AsyncReadExt as _, AsyncWrite, AsyncWriteExt};
+
+/// Upper bound of request bodies read into memory.
+const MAX_BODY_BYTES: usize = 16 << 20;
+/// Upper bound of request line and headers read into memory.
+const MAX_HEAD_BYTES: u64 = 32 << 10;
+/// How long a client may take to send request line and headers.
+const HEAD_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Request that is well formed but not served, answered with `status`.
+#[derive(Debug)]
+pub struct RequestError {
+    pub status: u16,
+    pub message: String,
+}
+
+impl std::fmt::Display for RequestError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for RequestError {}
+
+pub struct Request {
+    pub method: String,
+    /// Request target, path with query.
+    pub target: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    /// Returns path of the request target without query.
+    pub fn path(&self) -> &str {
+        self.target
+            .split_once('?')
+            .map_or(self.target.as_str(), |(path, _)| path)
+    }
+
+    /// Returns value of header `name`, case insensitive.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Returns decoded value of query parameter `name`.
+    pub fn query(&self, name: &str) -> Option<String> {
+        let url = reqwest::Url::parse(&format!("http://localhost{}", self.target)).ok()?;
+        url.query_pairs()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.into_owned())
+    }
+
+    pub fn json<T: DeserializeOwned>(&self) -> Result<T> {
+        serde_json::from_slice(&self.body).wrap_err("failed to parse request body")
+    }
+}
+
+/// Reads request line, headers and body of a request.
+pub async fn read_request<R: AsyncBufRead + Unpin>(reader: &mut R) -> Result<Request> {
+    let mut head = reader.take(MAX_HEAD_BYTES);
+    let mut request = match tokio::time::timeout(HEAD_TIMEOUT, read_head(&mut head)).await {
+        Ok(request) => request?,
+        Err(_) => {
+            return Err(RequestError {
+                status: 408,
+                message: "timed out reading request headers".to_string(),
+            }
+            .into());
+        }
+    };
+
+    // bodies are delimited by content-length only
+    if request.header("transfer-encoding").is_some() {
+        return Err(RequestError {
+            status: 411,
+            message: "transfer-encoding is not supported, set content-length".to_string(),
+        }
+        .into());
+    }
+    let content_length: usize = match request.header("content-length") {
+        Some(len) => len.parse().wrap_err("invalid content-length")?,
+        None => 0,
+    };
+    if content_length > MAX_BODY_BYTES {
+        return Err(RequestError {
+            status: 413,
+            message: format!("request body of {content_length} bytes is too large"),
+        }
+        .into());
+    }
+    request.body = vec![0; content_length];
+    let reader = head.into_inner();
+    reader.read_exact(&mut request.body).await?;
+    Ok(request)
+}
+
+/// Reads request line and headers, with a body to be read.
+async fn read_head<R: AsyncBufRead + Unpin>(reader: &mut tokio::io::Take<R>) -> Result<Request> {
+    let mut line = String::new();
+    read_head_line(reader, &mut line).await?;
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        bail!("malformed request line {line:?}");
+    };
+    let (method, target) = (method.to_string(), target.to_string());
+
+    let mut headers = Vec::new();
+    loop {
+        line.clear();
+        read_head_line(reader, &mut line).await?;
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        let (name, value) = header
+            .split_once(':')
+            .ok_or_else(|| eyre!("malformed header {header:?}"))?;
+        headers.push((name.to_string(), value.trim().to_string()));
+    }
+    Ok(Request {
+        method,
+        target,
+        headers,
+        body: Vec::new(),
+    })
+}
+
+/// Reads a line of request head into `line`, failing if the head exceeds its limit.
+async fn read_head_line<R: AsyncBufRead + Unpin>(
+    reader: &mut tokio::io::Take<R>,
+    line: &mut String,
+) -> Result<()> {
+    if reader.read_line(line).await? == 0 || !line.ends_with('\n') {
+        if reader.limit() == 0 {
+            return Err(RequestError {
+                status: 431,
+                message: format!("request headers exceed {MAX_HEAD_BYTES} bytes"),
+            }
+            .into());
+        }
+        bail!("connection closed before end of headers");
+    }
+    Ok(())
+}
+
+pub struct Response {
+    pub status: u16,
+    pub content_type: String,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    pub fn json<T: Serialize>(status: u16, value: &T) -> Self {
+        match serde_json::to_vec(value) {
+            Ok(body) => Self {
+                status,
+                content_type: "application/json".to_string(),
+                body,
+            },
+            Err(e) => Self::error(500, &e.to_string()),
+        }
+    }
+
+    /// Returns error response with body in the shape of open ai errors.
+    pub fn error(status: u16, message: &str) -> Self {
+        Self::json(
+            status,
+            &serde_json::json!({"error": {"message": message, "type": reason(status)}}),
+        )
+    }
+
+    pub fn empty(status: u16) -> Self {
+        Self {
+            status,
+            content_type: "text/plain".to_string(),
+            body: Vec::new(),
+        }
+    }
+
+    pub async fn write<W: AsyncWrite + Unpin>(&self, writer: &mut W) -> Result<()> {
+        let head = format!(
+            "HTTP/1.1 {} {}\r\ncontent-type: {}\r\ncontent-length: {}\r\nconnection: close\r\n\r\n",
+            self.status,
+            reason(self.status),
+            self.content_type,
+            self.body.len()
+        );
+        writer.write_all(head.as_bytes()).await?;
+        writer.write_all(&self.body).await?;
+        writer.flush().await?;
+        Ok(())
+    }
+}
+
+/// Writes head of an event stream response, delimited by closing the connection.
+pub async fn write_event_stream_head<W: AsyncWrite + Unpin>(writer: &mut W) -> Result<()> {
+    writer
+        .write_all(
+            b"HTTP/1.1 200 OK\r\ncontent-type: text/event-stream\r\ncache-control: no-cache\r\nconnection: close\r\n\r\n",
+        )
+        .await?;
+    writer.flush().await?;
+    Ok(())
+}
+
+/// Writes a server sent event with optional `event` name.
+pub async fn write_event<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    event: Option<&str>,
+    data: &str,
+) -> Result<()> {
+    let mut frame = String::new();
+    if let Some(event) = event {
+        frame.push_str(&format!("event: {event}\n"));
+    }
+    for line in data.lines() {
+        frame.push_str(&format!("data: {line}\n"));
+    }
+    frame.push('\n');
+    writer.write_all(frame.as_bytes()).await?;
+    writer.flush().await?;
+    Ok(())
+}
+
+fn reason(status: u16) -> &'static str {
+    reqwest::StatusCode::from_u16(status)
+        .ok()
+        .and_then(|s| s.canonical_reason())
+        .unwrap_or_default()
+}
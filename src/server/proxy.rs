@@ -0,0 +1,608 @@
+//! OpenAI compatible `/v1/chat/completions` and `/v1/responses` endpoints so that existing sdk
+//! scripts chat through cookie's providers, with their conversations recorded as sessions.
+
+use color_eyre::eyre::{Result, bail};
+use serde::Deserialize;
+use serde_json::json;
+use tokio::{io::AsyncWrite, sync::broadcast};
+
+use crate::{
+    chat::*,
+    llm::*,
+    models::{ClientId, OPENAI_MODELS, ServiceReq, ServiceResp, settings::ProviderKind},
+    server::{
+        Server,
+        http::{Request, Response, write_event, write_event_stream_head},
+        recv_reply,
+    },
+    service::llms::open_ai::api::Model,
+};
+
+/// Source of proxied sessions unless the client sets `x-cookie-source`.
+const DEFAULT_SOURCE: &str = "proxy";
+
+#[derive(Deserialize)]
+struct ChatCompletionsReq {
+    model: String,
+    messages: Vec<InputMessage>,
+    #[serde(default)]
+    stream: bool,
+    temperature: Option<f32>,
+    top_p: Option<f32>,
+    max_tokens: Option<u32>,
+    max_completion_tokens: Option<u32>,
+}
+
+#[derive(Deserialize)]
+struct ResponsesReq {
+    model: String,
+    input: ResponsesInput,
+    instructions: Option<String>,
+    #[serde(default)]
+    stream: bool,
+    previous_response_id: Option<String>,
+    temperature: Option<f32>,
+    top_p: Option<f32>,
+    max_output_tokens: Option<u32>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ResponsesInput {
+    Text(String),
+    Items(Vec<InputMessage>),
+}
+
+/// Chat message or responses input item, items without role such as tool calls are skipped.
+#[derive(Deserialize)]
+struct InputMessage {
+    #[serde(default)]
+    role: Option<String>,
+    #[serde(default)]
+    content: Content,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(untagged)]
+enum Content {
+    #[default]
+    Empty,
+    Text(String),
+    Parts(Vec<ContentPart>),
+}
+
+/// Text, input_text or output_text part, other parts such as images are skipped.
+#[derive(Deserialize)]
+struct ContentPart {
+    #[serde(default)]
+    text: Option<String>,
+}
+
+impl Content {
+    fn text(&self) -> String {
+        match self {
+            Content::Empty => String::new(),
+            Content::Text(text) => text.clone(),
+            Content::Parts(parts) => parts.iter().filter_map(|p| p.text.as_deref()).collect(),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Api {
+    ChatCompletions,
+    Responses,
+}
+
+/// Proxied request mapped to cookie's chat types.
+struct Conversation {
+    api: Api,
+    model: String,
+    stream: bool,
+    source: String,
+    llm_settings: LlmSettings,
+    /// Turns before the user message, empty when continuing a previous response.
+    history: Vec<(Role, String)>,
+    user_message: String,
+    /// Response continued by the user message.
+    previous_response_id: Option<String>,
+}
+
+impl Conversation {
+    /// Returns key of the conversation after `turns` to recognize its continuation, since sdk
+    /// clients resend the whole history. Keys are stored, so they are sha-256 digests of the
+    /// serialized conversation which stay the same across builds.
+    fn key(&self, turns: &[(Role, String)]) -> String {
+        let turns: Vec<_> = turns
+            .iter()
+            .map(|(role, msg)| (role.as_str_name(), msg))
+            .collect();
+        let serialized = json!([self.source, self.llm_settings.instructions, turns]).to_string();
+        let digest = ring::digest::digest(&ring::digest::SHA256, serialized.as_bytes());
+        digest.as_ref().iter().map(|b| format!("{b:02x}")).collect()
+    }
+}
+
+impl Server {
+    /// Looks up session of conversation `key`, Err with the response if the service fails.
+    async fn find_conversation(&self, key: &str) -> std::result::Result<Option<String>, Response> {
+        let Some((client_id, mut resp_rx)) =
+            self.send(ServiceReq::FindConversation(key.to_string()))
+        else {
+            return Err(Response::error(503, "service stopped"));
+        };
+        match recv_reply(&mut resp_rx, client_id).await {
+            Some(ServiceResp::Conversation { session_id, .. }) => Ok(session_id),
+            Some(ServiceResp::Error(e)) => Err(Response::error(500, &e)),
+            _ => Err(Response::error(504, "service did not respond")),
+        }
+    }
+
+    pub(super) async fn chat_completions<W: AsyncWrite + Unpin>(
+        &self,
+        req: &Request,
+        conn: &mut W,
+    ) -> Result<()> {
+        let conversation = req.json::<ChatCompletionsReq>().and_then(|body| {
+            let mut instructions = Vec::new();
+            let mut turns = Vec::new();
+            for message in &body.messages {
+                match message.role.as_deref() {
+                    Some("system" | "developer") => instructions.push(message.content.text()),
+                    Some("user") => turns.push((Role::User, message.content.text())),
+                    Some("assistant") => turns.push((Role::Assistant, message.content.text())),
+                    _ => {}
+                }
+            }
+            let llm_settings = self.proxy_llm_settings(
+                &body.model,
+                instructions.join("\n\n"),
+                body.temperature,
+                body.top_p,
+                body.max_completion_tokens.or(body.max_tokens),
+            )?;
+            let Some((Role::User, user_message)) = turns.pop() else {
+                bail!("last message must be a user message");
+            };
+            Ok(Conversation {
+                api: Api::ChatCompletions,
+                model: body.model,
+                stream: body.stream,
+                source: source(req),
+                llm_settings,
+                history: turns,
+                user_message,
+                previous_response_id: None,
+            })
+        });
+        match conversation {
+            Ok(conversation) => self.proxy(conversation, conn).await,
+            Err(e) => Response::error(400, &format!("{e:#}")).write(conn).await,
+        }
+    }
+
+    pub(super) async fn responses<W: AsyncWrite + Unpin>(
+        &self,
+        req: &Request,
+        conn: &mut W,
+    ) -> Result<()> {
+        let conversation = req.json::<ResponsesReq>().and_then(|body| {
+            let mut turns = match body.input {
+                ResponsesInput::Text(text) => vec![(Role::User, text)],
+                ResponsesInput::Items(items) => items
+                    .iter()
+                    .filter_map(|item| match item.role.as_deref() {
+                        Some("user") => Some((Role::User, item.content.text())),
+                        Some("assistant") => Some((Role::Assistant, item.content.text())),
+                        _ => None,
+                    })
+                    .collect(),
+            };
+            let llm_settings = self.proxy_llm_settings(
+                &body.model,
+                body.instructions.unwrap_or_default(),
+                body.temperature,
+                body.top_p,
+                body.max_output_tokens,
+            )?;
+            let Some((Role::User, user_message)) = turns.pop() else {
+                bail!("last input item must be a user message");
+            };
+            Ok(Conversation {
+                api: Api::Responses,
+                model: body.model,
+                stream: body.stream,
+                source: source(req),
+                llm_settings,
+                history: if body.previous_response_id.is_some() {
+                    Vec::new()
+                } else {
+                    turns
+                },
+                user_message,
+                previous_response_id: body.previous_response_id,
+            })
+        });
+        match conversation {
+            Ok(conversation) => self.proxy(conversation, conn).await,
+            Err(e) => Response::error(400, &format!("{e:#}")).write(conn).await,
+        }
+    }
+
+    /// Returns settings of `model`, taking tools from the configured defaults.
+    fn proxy_llm_settings(
+        &self,
+        model: &str,
+        instructions: String,
+        temperature: Option<f32>,
+        top_p: Option<f32>,
+        max_output_tokens: Option<u32>,
+    ) -> Result<LlmSettings> {
+        let provider = if model == ProviderKind::Mock.display_name() {
+            llm_settings::Provider::Mock(MockSettings {})
+        } else {
+            let Some(model) = OPENAI_MODELS
+                .iter()
+                .find(|m| Model::from(**m).name() == model)
+            else {
+                bail!("model {model} is not supported");
+            };
+            let mut settings = match &self.default_llm_settings.provider {
+                Some(llm_settings::Provider::OpenAi(settings)) => *settings,
+                _ => OpenAiSettings::default(),
+            };
+            settings.model = *model as i32;
+            settings.temperature = temperature;
+            settings.top_p = top_p;
+            settings.max_output_tokens = max_output_tokens;
+            llm_settings::Provider::OpenAi(settings)
+        };
+        let llm_settings = LlmSettings {
+            provider: Some(provider),
+            instructions,
+        };
+        llm_settings.validate()?;
+        Ok(llm_settings)
+    }
+
+    /// Sends user message of `conversation` to its session, importing history of new sessions, and
+    /// replies in the format of its api.
+    async fn proxy<W: AsyncWrite + Unpin>(
+        &self,
+        conversation: Conversation,
+        conn: &mut W,
+    ) -> Result<()> {
+        let known_session_id = match &conversation.previous_response_id {
+            Some(id) => match self.find_conversation(id).await {
+                Ok(Some(session_id)) => Some(session_id),
+                Ok(None) => {
+                    return Response::error(400, &format!("previous response {id} not found"))
+                        .write(conn)
+                        .await;
+                }
+                Err(resp) => return resp.write(conn).await,
+            },
+            None => match self
+                .find_conversation(&conversation.key(&conversation.history))
+                .await
+            {
+                Ok(session_id) => session_id,
+                Err(resp) => return resp.write(conn).await,
+            },
+        };
+        let session_id = match known_session_id {
+            Some(session_id) => session_id,
+            None => {
+                // import the session to tag it with its source, along with history sent before
+                let session_id = uuid::Uuid::new_v4().to_string();
+                {
+                    let mut chat_session = ChatSession::new(
+                        session_id.clone(),
+                        Some(conversation.llm_settings.clone()),
+                    );
+                    chat_session.source = conversation.source.clone();
+                    chat_session.events = conversation
+                        .history
+                        .iter()
+                        .map(|(role, msg)| {
+                            ChatEvent::new(
+                                session_id.clone(),
+                                Some(conversation.llm_settings.clone()),
+                                chat_event::Payload::Message(Message {
+                                    role: *role as i32,
+                                    msg: msg.clone(),
+                                    response_ref: None,
+                                }),
+                            )
+                        })
+                        .collect();
+                    if self
+                        .send(ServiceReq::ImportSession(Box::new(chat_session)))
+                        .is_none()
+                    {
+                        return Response::error(503, "service stopped").write(conn).await;
+                    }
+                }
+                session_id
+            }
+        };
+
+        let user_message = ChatEvent::new(
+            session_id.clone(),
+            Some(conversation.llm_settings.clone()),
+            chat_event::Payload::Message(Message {
+                role: Role::User as i32,
+                msg: conversation.user_message.clone(),
+                response_ref: None,
+            }),
+        );
+        let Some((client_id, mut resp_rx)) = self.send(ServiceReq::ChatMessage(user_message))
+        else {
+            return Response::error(503, "service stopped").write(conn).await;
+        };
+
+        let mut reply = Reply::new(conversation.api, &conversation.model);
+        if conversation.stream {
+            write_event_stream_head(conn).await?;
+            for (event, data) in reply.start() {
+                write_event(conn, event, &data.to_string()).await?;
+            }
+        }
+        let msg = loop {
+            let payload = match next_payload(&mut resp_rx, client_id, &session_id).await {
+                Ok(Some(payload)) => payload,
+                Ok(None) => return Ok(()),
+                Err(e) => {
+                    let error = json!({"error": {"message": e, "type": "server_error"}});
+                    return match conversation.stream {
+                        true => write_event(conn, Some("error"), &error.to_string()).await,
+                        false => Response::json(500, &error).write(conn).await,
+                    };
+                }
+            };
+            match payload {
+                chat_event::Payload::MessageDelta(d) if conversation.stream => {
+                    for (event, data) in reply.delta(&d.delta) {
+                        write_event(conn, event, &data.to_string()).await?;
+                    }
+                }
+                chat_event::Payload::Message(m) if m.role() == Role::Assistant => break m.msg,
+                _ => {}
+            }
+        };
+
+        // recognize continuations by the resent history or the response id
+        let mut turns = conversation.history.clone();
+        turns.push((Role::User, conversation.user_message.clone()));
+        turns.push((Role::Assistant, msg.clone()));
+        // recording failing only loses the continuation, the reply is sent regardless
+        self.send(ServiceReq::RecordConversation {
+            keys: vec![conversation.key(&turns), reply.id.clone()],
+            session_id,
+        });
+
+        if conversation.stream {
+            for (event, data) in reply.done(&msg) {
+                write_event(conn, event, &data).await?;
+            }
+            Ok(())
+        } else {
+            Response::json(200, &reply.completion(&msg))
+                .write(conn)
+                .await
+        }
+    }
+}
+
+/// Returns source of the request, set by clients with `x-cookie-source`.
+fn source(req: &Request) -> String {
+    req.header("x-cookie-source")
+        .unwrap_or(DEFAULT_SOURCE)
+        .to_string()
+}
+
+/// Receives the next payload of `session_id`, None if the service stopped, Err with the message
+/// of service errors replied to client `client_id` or of failed chats of the session.
+async fn next_payload(
+    resp_rx: &mut broadcast::Receiver<ServiceResp>,
+    client_id: ClientId,
+    session_id: &str,
+) -> std::result::Result<Option<chat_event::Payload>, String> {
+    loop {
+        match resp_rx.recv().await {
+            Ok(ServiceResp::ChatEvent(chat_event)) if chat_event.session_id == session_id => {
+                if let Some(payload) = chat_event.payload {
+                    return Ok(Some(payload));
+                }
+            }
+            Ok(ServiceResp::ToClient {
+                client_id: id,
+                resp,
+            }) if id == client_id => {
+                if let ServiceResp::Error(e) | ServiceResp::Notice(e) = *resp {
+                    return Err(e);
+                }
+            }
+            Ok(ServiceResp::ChatFailed {
+                session_id: id,
+                error,
+            }) if id == session_id => {
+                return Err(error);
+            }
+            Ok(_) => {}
+            Err(broadcast::error::RecvError::Lagged(n)) => {
+                tracing::warn!("skipped {n} service responses")
+            }
+            Err(broadcast::error::RecvError::Closed) => return Ok(None),
+        }
+    }
+}
+
+/// Renders reply in the wire format of an api, streamed as server sent events or as one body.
+struct Reply {
+    api: Api,
+    id: String,
+    model: String,
+    created: i64,
+    sequence_number: u64,
+}
+
+impl Reply {
+    fn new(api: Api, model: &str) -> Self {
+        let id = uuid::Uuid::new_v4().simple().to_string();
+        let id = match api {
+            Api::ChatCompletions => format!("chatcmpl-{id}"),
+            Api::Responses => format!("resp_{id}"),
+        };
+        Self {
+            api,
+            id,
+            model: model.to_string(),
+            created: chrono::Utc::now().timestamp(),
+            sequence_number: 0,
+        }
+    }
+
+    fn chunk(&self, delta: serde_json::Value, finish_reason: Option<&str>) -> serde_json::Value {
+        json!({
+            "id": self.id,
+            "object": "chat.completion.chunk",
+            "created": self.created,
+            "model": self.model,
+            "choices": [{"index": 0, "delta": delta, "finish_reason": finish_reason}],
+        })
+    }
+
+    fn response(&self, status: &str, msg: Option<&str>) -> serde_json::Value {
+        let output = match msg {
+            Some(msg) => json!([{
+                "type": "message",
+                "id": format!("msg_{}", self.id),
+                "status": "completed",
+                "role": "assistant",
+                "content": [{"type": "output_text", "text": msg, "annotations": []}],
+            }]),
+            None => json!([]),
+        };
+        json!({
+            "id": self.id,
+            "object": "response",
+            "created_at": self.created,
+            "status": status,
+            "model": self.model,
+            "output": output,
+        })
+    }
+
+    /// Returns named event with the next sequence number.
+    fn event(
+        &mut self,
+        kind: &'static str,
+        mut data: serde_json::Value,
+    ) -> (Option<&'static str>, serde_json::Value) {
+        data["type"] = json!(kind);
+        data["sequence_number"] = json!(self.sequence_number);
+        self.sequence_number += 1;
+        (Some(kind), data)
+    }
+
+    fn start(&mut self) -> Vec<(Option<&'static str>, serde_json::Value)> {
+        match self.api {
+            Api::ChatCompletions => {
+                vec![(
+                    None,
+                    self.chunk(json!({"role": "assistant", "content": ""}), None),
+                )]
+            }
+            Api::Responses => {
+                let response = self.response("in_progress", None);
+                vec![self.event("response.created", json!({"response": response}))]
+            }
+        }
+    }
+
+    fn delta(&mut self, delta: &str) -> Vec<(Option<&'static str>, serde_json::Value)> {
+        match self.api {
+            Api::ChatCompletions => vec![(None, self.chunk(json!({"content": delta}), None))],
+            Api::Responses => {
+                let item_id = format!("msg_{}", self.id);
+                vec![self.event(
+                    "response.output_text.delta",
+                    json!({"item_id": item_id, "output_index": 0, "content_index": 0, "delta": delta}),
+                )]
+            }
+        }
+    }
+
+    /// Returns closing events, data is a string since chat completions end with `[DONE]`.
+    fn done(&mut self, msg: &str) -> Vec<(Option<&'static str>, String)> {
+        match self.api {
+            Api::ChatCompletions => vec![
+                (None, self.chunk(json!({}), Some("stop")).to_string()),
+                (None, "[DONE]".to_string()),
+            ],
+            Api::Responses => {
+                let item_id = format!("msg_{}", self.id);
+                let response = self.response("completed", Some(msg));
+                [
+                    self.event(
+                        "response.output_text.done",
+                        json!({"item_id": item_id, "output_index": 0, "content_index": 0, "text": msg}),
+                    ),
+                    self.event("response.completed", json!({"response": response})),
+                ]
+                .into_iter()
+                .map(|(event, data)| (event, data.to_string()))
+                .collect()
+            }
+        }
+    }
+
+    fn completion(&self, msg: &str) -> serde_json::Value {
+        match self.api {
+            Api::ChatCompletions => json!({
+                "id": self.id,
+                "object": "chat.completion",
+                "created": self.created,
+                "model": self.model,
+                "choices": [{
+                    "index": 0,
+                    "message": {"role": "assistant", "content": msg},
+                    "finish_reason": "stop",
+                }],
+            }),
+            Api::Responses => self.response("completed", Some(msg)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn conversation_key_is_stable() {
+        let conversation = Conversation {
+            api: Api::ChatCompletions,
+            model: "gpt-5".to_string(),
+            stream: false,
+            source: DEFAULT_SOURCE.to_string(),
+            llm_settings: LlmSettings {
+                instructions: "Be brief.".to_string(),
+                ..Default::default()
+            },
+            history: Vec::new(),
+            user_message: "How are you?".to_string(),
+            previous_response_id: None,
+        };
+        let turns = [
+            (Role::User, "Hi".to_string()),
+            (Role::Assistant, "Hello!".to_string()),
+        ];
+        // stored keys must keep matching, fail if the key derivation changes
+        assert_eq!(
+            conversation.key(&turns),
+            "c956914a0eaf4dfeb830259fcb24ae6a6e589313315606ac96e8e112a71def53"
+        );
+    }
+}
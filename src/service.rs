@@ -1,49 +1,95 @@
 mod chat;
 mod chat_session_worker;
-mod database;
+mod context;
+pub mod database;
 pub mod llms;
+pub mod shutdown;
 mod stores;
+#[cfg(test)]
+mod tests;
 mod utils;
 
-use color_eyre::{Result, eyre::eyre};
-use futures_util::StreamExt;
-use futures_util::stream::FuturesUnordered;
-use std::{collections::HashMap, sync::Arc};
+use color_eyre::Result;
+use rusqlite::Connection;
+use std::{collections::HashMap, sync::Arc, time::Duration};
 use tokio::{
-    sync::mpsc::{UnboundedReceiver, UnboundedSender},
-    task::JoinHandle,
+    sync::{
+        mpsc::{UnboundedReceiver, UnboundedSender},
+        watch,
+    },
+    task::{self, JoinError, JoinSet},
 };
 
 use crate::{
-    models::{ServiceReq, ServiceResp},
+    models::{
+        ClientId, ServiceReq, ServiceResp,
+        configs::{Config, TrashConfig, WorkerConfig},
+    },
     service::{
         chat_session_worker::ChatSessionWorkerHandle,
+        context::ContextManager,
         database::{DBWorker, get_db_conn, spawn_db_thread},
         llms::LlmClientRouter,
         stores::{
             chat_event_store::{ChatEventStore, ChatEventStoreImpl},
             chat_session_store::{ChatSessionStore, ChatSessionStoreImpl},
+            conversation_store::{ConversationStore, ConversationStoreImpl},
+            pending_response_store::{PendingResponseStore, PendingResponseStoreImpl},
         },
     },
 };
 
+/// Upper bound of how often idle workers are looked for.
+const IDLE_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+/// How often sessions in trash beyond retention are purged.
+const PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
 pub struct ServiceBuilder {
     req_rx: UnboundedReceiver<ServiceReq>,
     resp_tx: UnboundedSender<ServiceResp>,
+    config: Config,
+    /// Overrides db connection at the default path.
+    db_conn: Option<Connection>,
+    /// Overrides llm router built from config.
+    llm_router: Option<LlmClientRouter>,
 }
 
 impl ServiceBuilder {
     pub fn new(
         req_rx: UnboundedReceiver<ServiceReq>,
         resp_tx: UnboundedSender<ServiceResp>,
+        config: Config,
     ) -> Self {
-        Self { req_rx, resp_tx }
+        Self {
+            req_rx,
+            resp_tx,
+            config,
+            db_conn: None,
+            llm_router: None,
+        }
     }
 
-    pub fn build(self) -> Option<Service> {
+    #[cfg(test)]
+    pub fn with_db_conn(mut self, db_conn: Connection) -> Self {
+        self.db_conn = Some(db_conn);
+        self
+    }
+
+    #[cfg(test)]
+    pub fn with_llm_router(mut self, llm_router: LlmClientRouter) -> Self {
+        self.llm_router = Some(llm_router);
+        self
+    }
+
+    pub async fn build(self) -> Option<Service> {
         // Make db connection and build llm router. Skip builder service and send an error to tui
-        // on failure.
-        let conn = match get_db_conn() {
+        // on db failure, providers failed to build are disabled by the router. Injected
+        // connections, e.g. in memory ones of tests, are not shared so not locked.
+        let conn = match self.db_conn {
+            Some(conn) => Ok((conn, None)),
+            None => get_db_conn().map(|(conn, lock)| (conn, Some(lock))),
+        };
+        let (conn, db_lock) = match conn {
             Ok(conn) => conn,
             Err(e) => {
                 let message = ServiceResp::Error(e.to_string());
@@ -52,28 +98,30 @@ impl ServiceBuilder {
             }
         };
 
-        let router = match LlmClientRouter::build() {
-            Ok(router) => router,
-            Err(e) => {
-                // If we failed to build llm router, send an error to tui and skip innitialization.
-                let message = ServiceResp::Error(e.to_string());
-                self.resp_tx.send(message);
-                return None;
-            }
+        let router = match self.llm_router {
+            Some(router) => router,
+            None => LlmClientRouter::build(&self.config).await,
         };
 
         // Spawn db thread and create stores.
-        let db_worker = spawn_db_thread(conn);
+        let db_worker = spawn_db_thread(conn, db_lock);
         let chat_event_store = ChatEventStoreImpl::new(db_worker.sender());
         let chat_session_store = ChatSessionStoreImpl::new(db_worker.sender());
+        let pending_response_store = PendingResponseStoreImpl::new(db_worker.sender());
+        let conversation_store = ConversationStoreImpl::new(db_worker.sender());
 
         Some(Service::new(
             self.req_rx,
             self.resp_tx,
             Arc::new(chat_event_store),
             Arc::new(chat_session_store),
+            Arc::new(pending_response_store),
+            Arc::new(conversation_store),
             db_worker,
             router,
+            ContextManager::new(self.config.context),
+            self.config.workers,
+            self.config.trash,
         ))
     }
 }
@@ -85,76 +133,232 @@ pub struct Service {
     /// DB thread.
     chat_event_store: Arc<dyn ChatEventStore>,
     chat_session_store: Arc<dyn ChatSessionStore>,
-    _db_worker: DBWorker,
+    pending_response_store: Arc<dyn PendingResponseStore>,
+    conversation_store: Arc<dyn ConversationStore>,
+    db_worker: DBWorker,
 
     llm_router: LlmClientRouter,
+    context_manager: ContextManager,
+    worker_config: WorkerConfig,
+    trash_config: TrashConfig,
     session_worker_handles: HashMap<String, ChatSessionWorkerHandle>,
+    /// Tasks of stopped workers by session, awaited before respawning the worker of the session.
+    stopping_workers: HashMap<String, task::Id>,
+    /// Tasks of workers, including stopped ones finishing what was sent to them.
+    worker_tasks: JoinSet<Result<()>>,
+    /// Tells workers to save what they streamed and stop.
+    shutdown_tx: watch::Sender<bool>,
+    /// Title generation holding stores, aborted on shutdown.
+    title_tasks: JoinSet<()>,
+    /// Client of the request being handled.
+    client_id: Option<ClientId>,
 }
 
 impl Service {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         req_rx: UnboundedReceiver<ServiceReq>,
         resp_tx: UnboundedSender<ServiceResp>,
         chat_event_store: Arc<dyn ChatEventStore>,
         chat_session_store: Arc<dyn ChatSessionStore>,
+        pending_response_store: Arc<dyn PendingResponseStore>,
+        conversation_store: Arc<dyn ConversationStore>,
         db_worker: DBWorker,
         llm_router: LlmClientRouter,
+        context_manager: ContextManager,
+        worker_config: WorkerConfig,
+        trash_config: TrashConfig,
     ) -> Self {
         Self {
             req_rx,
             resp_tx,
             chat_event_store,
             chat_session_store,
-            _db_worker: db_worker,
+            pending_response_store,
+            conversation_store,
+            db_worker,
             llm_router,
+            context_manager,
+            worker_config,
+            trash_config,
             session_worker_handles: HashMap::new(),
+            stopping_workers: HashMap::new(),
+            worker_tasks: JoinSet::new(),
+            shutdown_tx: watch::Sender::new(false),
+            title_tasks: JoinSet::new(),
+            client_id: None,
+        }
+    }
+
+    /// Serves requests until the request channel closes.
+    pub async fn run(self) -> Result<()> {
+        self.run_until(std::future::pending()).await
+    }
+
+    /// Serves requests until the request channel closes or `shutdown` resolves, then shuts down
+    /// workers and db.
+    pub async fn run_until(mut self, shutdown: impl Future<Output = ()>) -> Result<()> {
+        let res = self.serve(shutdown).await;
+        if let Err(e) = &res {
+            tracing::error!("service failed: {e:?}");
         }
+        let shutdown_res = self.shutdown().await;
+        res.and(shutdown_res)
     }
 
-    pub async fn run(mut self) -> Result<()> {
-        // initialize tui with stored sessions
-        self.send_sessions().await?;
+    async fn serve(&mut self, shutdown: impl Future<Output = ()>) -> Result<()> {
+        tokio::pin!(shutdown);
+        // initialize tui with stored sessions and available providers
+        self.send_sessions_changed()?;
+        self.resp_tx
+            .send(ServiceResp::Providers(self.llm_router.providers()))?;
+
+        let idle_timeout = Duration::from_secs(self.worker_config.idle_timeout_secs);
+        let mut idle_check =
+            tokio::time::interval(idle_timeout.clamp(Duration::from_secs(1), IDLE_CHECK_INTERVAL));
 
-        let mut chat_handles = FuturesUnordered::<JoinHandle<Result<()>>>::new();
+        // resume background responses left pending by previous runs
+        for pending_response in self.pending_response_store.get_pending_responses().await? {
+            if !self
+                .session_worker_handles
+                .contains_key(&pending_response.session_id)
+                && !self.spawn_session(pending_response.clone()).await?
+            {
+                continue;
+            }
+            self.handle_resume_response(pending_response)?;
+        }
+
+        self.purge_expired_sessions().await?;
+        let mut purge_check =
+            tokio::time::interval_at(tokio::time::Instant::now() + PURGE_INTERVAL, PURGE_INTERVAL);
 
         loop {
             tokio::select! {
                 maybe_req = self.req_rx.recv() => {
                     match maybe_req {
-                        None => break,
-                        Some(ServiceReq::ChatMessage ( user_message )) => {
-                            if !self.session_worker_handles.contains_key(&user_message.session_id) {
-                                let chat_handle = self.spawn_session(user_message.clone()).await?;
-                                chat_handles.push(chat_handle);
-                            }
-                            self.handle_user_message(user_message)?;
-                        }
-                        Some(ServiceReq::GetSession(session_id)) => {
-                           self.handle_get_session(&session_id).await?
-                        }
-                        Some(ServiceReq::DeleteSession(session_id)) => {
-                            self.handle_delete_session(&session_id).await?
+                        None => return Ok(()),
+                        Some(ServiceReq::FromClient { client_id, req }) => {
+                            self.client_id = Some(client_id);
+                            let res = self.handle_req(*req).await;
+                            self.client_id = None;
+                            res?
                         }
+                        Some(req) => self.handle_req(req).await?,
                     }
                 }
-                Some(res) = chat_handles.next(), if !chat_handles.is_empty() => {
-                    match res {
-                        Ok(Ok(())) => {},
-                        Ok(Err(e)) => {
-                            return Err(e.wrap_err("chat failed"));
-                        }
-                        Err(join_err) => {
-                            return Err(eyre!("chat panicked: {:?}", join_err))
-                        }
-                    }
+                _ = &mut shutdown => return Ok(()),
+                _ = idle_check.tick() => self.stop_idle_workers(),
+                _ = purge_check.tick() => self.purge_expired_sessions().await?,
+                Some(res) = self.worker_tasks.join_next_with_id(), if !self.worker_tasks.is_empty() => {
+                    self.handle_worker_exit(res)
                 }
             }
         }
+    }
+
+    /// Forgets worker that exited with `res`, dropping its handle if it failed.
+    fn handle_worker_exit(&mut self, res: Result<(task::Id, Result<()>), JoinError>) {
+        let task_id = match res {
+            Ok((task_id, Ok(()))) => task_id,
+            // aborted on session delete
+            Err(join_err) if join_err.is_cancelled() => join_err.id(),
+            Ok((task_id, Err(e))) => {
+                tracing::error!("chat failed: {e:?}");
+                task_id
+            }
+            Err(join_err) => {
+                tracing::error!("chat panicked: {join_err:?}");
+                join_err.id()
+            }
+        };
+        self.stopping_workers.retain(|_, id| *id != task_id);
+        // other sessions keep serving, the session respawns its worker on the next message
+        self.session_worker_handles
+            .retain(|_, handle| handle.task_id() != task_id);
+    }
 
-        // stop chat workers to drop db job senders they hold
-        for (_, handle) in self.session_worker_handles.drain() {
-            drop(handle);
+    /// Handles request of the current client.
+    async fn handle_req(&mut self, req: ServiceReq) -> Result<()> {
+        match req {
+            ServiceReq::ChatMessage(user_message) => {
+                if !self
+                    .session_worker_handles
+                    .contains_key(&user_message.session_id)
+                    && !self.spawn_session(user_message.clone()).await?
+                {
+                    return Ok(());
+                }
+                self.handle_user_message(user_message)?;
+            }
+            ServiceReq::GetSession(session_id) => self.handle_get_session(&session_id).await?,
+            ServiceReq::DeleteSessions(session_ids) => {
+                self.handle_delete_sessions(session_ids).await?
+            }
+            ServiceReq::RestoreSessions(session_ids) => {
+                self.handle_restore_sessions(session_ids).await?
+            }
+            ServiceReq::PurgeSessions(session_ids) => {
+                self.handle_purge_sessions(session_ids).await?
+            }
+            ServiceReq::CancelResponse(session_id) => {
+                self.handle_cancel_response(&session_id).await?
+            }
+            ServiceReq::ImportSession(chat_session) => {
+                self.handle_import_session(*chat_session).await?
+            }
+            ServiceReq::RenameSession { session_id, title } => {
+                self.handle_update_session(&session_id, |s| s.title = title.clone())
+                    .await?
+            }
+            ServiceReq::PinSession { session_id, pinned } => {
+                self.handle_update_session(&session_id, |s| s.pinned = pinned)
+                    .await?
+            }
+            ServiceReq::ArchiveSession {
+                session_id,
+                archived,
+            } => {
+                self.handle_update_session(&session_id, |s| s.archived = archived)
+                    .await?
+            }
+            ServiceReq::SetSessionTags { session_id, tags } => {
+                self.handle_set_session_tags(&session_id, tags).await?
+            }
+            ServiceReq::QuerySessions {
+                query,
+                cursor,
+                trash,
+                include_archived,
+            } => {
+                self.handle_query_sessions(query, cursor, trash, include_archived)
+                    .await?
+            }
+            ServiceReq::GetEvents { session_id, before } => {
+                self.handle_get_events(session_id, before).await?
+            }
+            ServiceReq::FindConversation(key) => self.handle_find_conversation(key).await?,
+            ServiceReq::RecordConversation { keys, session_id } => {
+                self.handle_record_conversation(keys, session_id).await?
+            }
+            ServiceReq::FromClient { client_id, .. } => {
+                tracing::warn!("ignoring nested request of client {client_id}")
+            }
         }
         Ok(())
     }
+
+    /// Sends reply to the client of the request being handled, or to every client if the request
+    /// is not of a client.
+    fn reply(&self, resp: ServiceResp) -> Result<()> {
+        let resp = match self.client_id {
+            Some(client_id) => ServiceResp::ToClient {
+                client_id,
+                resp: Box::new(resp),
+            },
+            None => resp,
+        };
+        self.resp_tx.send(resp)?;
+        Ok(())
+    }
 }
@@ -1,5 +1,8 @@
 use color_eyre::{Result, eyre::eyre};
-use std::sync::Arc;
+use std::{
+    sync::Arc,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
 use tokio::sync::{
     Mutex,
     mpsc::{UnboundedSender, unbounded_channel},
@@ -8,40 +11,56 @@ use tokio::sync::{
 use crate::{
     chat::*,
     llm::*,
-    models::ServiceResp,
+    models::{
+        ServiceResp,
+        constants::{EVENT_PAGE_SIZE, SESSION_PAGE_SIZE},
+        session_query::SessionQuery,
+    },
     service::{
         Service,
-        chat_session_worker::{ChatSessionWorker, ChatSessionWorkerHandle},
+        chat_session_worker::{
+            ChatSessionWorker, ChatSessionWorkerHandle, IdleSince, cancel_pending_response,
+        },
         llms::{LlmClient, LlmClientRouter, LlmReq},
-        stores::chat_session_store::ChatSessionStore,
+        stores::{Page, chat_session_store::ChatSessionStore},
     },
 };
 
 impl Service {
     /// Gets chat session from stores if exists or create one if it not exists. Spawns a chat session
-    /// worker job and returns the handle. Spawns a job to generate title for new session as well.
-    pub async fn spawn_session(
-        &mut self,
-        user_message: ChatEvent,
-    ) -> Result<tokio::task::JoinHandle<Result<()>>> {
+    /// worker job and keeps its handle. Spawns a job to generate title for new session as well.
+    /// Returns false with an error message sent to tui if the session is in trash.
+    pub async fn spawn_session(&mut self, user_message: ChatEvent) -> Result<bool> {
         let session_id = user_message.session_id.clone();
+        // the stopped worker may still be saving what it handled
+        self.join_stopping_worker(&session_id).await;
         // get session from database or create one
         let chat_session = match self
             .chat_session_store
             .get_chat_session(&session_id)
             .await?
         {
+            Some(chat_session) if chat_session.deleted_at.is_some() => {
+                self.reply(ServiceResp::Notice(format!(
+                    "session {session_id} is in trash"
+                )))?;
+                return Ok(false);
+            }
             Some(mut chat_session) => {
                 let chat_events = self
                     .chat_event_store
                     .get_chat_events_for_session(&session_id)
                     .await?;
+                // sessions imported without history are titled after their first message
+                if chat_session.title.is_empty() && chat_events.is_empty() {
+                    self.spawn_title_generation(user_message, chat_session.clone());
+                }
                 chat_session.events = chat_events;
                 chat_session
             }
             None => {
                 // create session
-                let llm_settings = user_message.llm_settings;
+                let llm_settings = user_message.llm_settings.clone();
                 let mut chat_session = ChatSession::new(session_id.clone(), llm_settings);
                 tracing::debug!("creating session {chat_session:?}");
                 chat_session = self
@@ -49,49 +68,114 @@ impl Service {
                     .create_chat_session(chat_session)
                     .await?;
 
-                // publich sessions to tui
-                self.send_sessions().await?;
-
-                // generate title async
-                let chat_session_store = self.chat_session_store.clone();
-                let llm_router = self.llm_router.clone();
-                let resp_tx = self.resp_tx.clone();
-                tokio::spawn(Self::try_update_session_title(
-                    chat_session_store,
-                    user_message,
-                    chat_session.clone(),
-                    llm_router,
-                    resp_tx,
-                ));
+                // list the new session in tui
+                self.send_sessions_changed()?;
 
+                self.spawn_title_generation(user_message, chat_session.clone());
                 chat_session
             }
         };
 
+        // make room for the worker
+        if self.session_worker_handles.len() >= self.worker_config.max_workers {
+            self.stop_least_recently_used_worker();
+        }
+
         // create channel and spawn session worker
         let (chat_tx, chat_rx) = unbounded_channel::<ChatEvent>();
+        let (cancel_tx, cancel_rx) = unbounded_channel::<()>();
         let chat_session = Arc::new(Mutex::new(chat_session));
+        let idle_since: IdleSince = Arc::new(std::sync::Mutex::new(Some(Instant::now())));
 
         let llm_router = self.llm_router.clone();
         let resp_tx = self.resp_tx.clone();
         let chat_event_store = self.chat_event_store.clone();
         let chat_session_store = self.chat_session_store.clone();
+        let pending_response_store = self.pending_response_store.clone();
 
         let worker = ChatSessionWorker::new(
             chat_rx,
+            cancel_rx,
             chat_session.clone(),
+            idle_since.clone(),
+            self.shutdown_tx.subscribe(),
             llm_router,
             resp_tx,
             chat_event_store,
             chat_session_store,
+            pending_response_store,
+            self.context_manager.clone(),
+        );
+
+        // spawn chat
+        let abort_handle = self.worker_tasks.spawn(worker.run());
+        let worker_handle = ChatSessionWorkerHandle::new(
+            chat_tx,
+            cancel_tx,
+            chat_session,
+            idle_since,
+            abort_handle,
         );
-        let worker_handle = ChatSessionWorkerHandle::new(chat_tx, chat_session);
         self.session_worker_handles
             .insert(session_id.to_string(), worker_handle);
+        Ok(true)
+    }
 
-        // spawn chat
-        let handle = tokio::spawn(worker.run());
-        Ok(handle)
+    /// Waits for the stopped worker of `session_id` to exit, if any.
+    async fn join_stopping_worker(&mut self, session_id: &str) {
+        while self.stopping_workers.contains_key(session_id) {
+            match self.worker_tasks.join_next_with_id().await {
+                Some(res) => self.handle_worker_exit(res),
+                None => {
+                    self.stopping_workers.clear();
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Drops handle of worker of `session_id`, the worker stops after handling events already sent
+    /// to it.
+    fn stop_worker(&mut self, session_id: &str) -> Option<ChatSessionWorkerHandle> {
+        let handle = self.session_worker_handles.remove(session_id)?;
+        self.stopping_workers
+            .insert(session_id.to_string(), handle.task_id());
+        Some(handle)
+    }
+
+    /// Stops workers idle for longer than the configured timeout.
+    pub fn stop_idle_workers(&mut self) {
+        let idle_timeout = Duration::from_secs(self.worker_config.idle_timeout_secs);
+        let idle: Vec<String> = self
+            .session_worker_handles
+            .iter()
+            .filter(|(_, handle)| handle.idle_for().is_some_and(|idle| idle >= idle_timeout))
+            .map(|(session_id, _)| session_id.clone())
+            .collect();
+        for session_id in idle {
+            tracing::debug!("stopping idle worker of session {session_id}");
+            self.stop_worker(&session_id);
+        }
+    }
+
+    /// Stops the worker idle for the longest, keeps all if every worker is busy.
+    fn stop_least_recently_used_worker(&mut self) {
+        let lru = self
+            .session_worker_handles
+            .iter()
+            .filter_map(|(session_id, handle)| Some((session_id, handle.idle_for()?)))
+            .max_by_key(|(_, idle)| *idle)
+            .map(|(session_id, _)| session_id.clone());
+        match lru {
+            Some(session_id) => {
+                tracing::debug!("stopping least recently used worker of session {session_id}");
+                self.stop_worker(&session_id);
+            }
+            None => tracing::warn!(
+                "all {} workers are busy, exceeding max workers",
+                self.session_worker_handles.len()
+            ),
+        }
     }
 
     /// Finds session chat sender for session of `user_message` and dispatch message. Send error
@@ -103,7 +187,7 @@ impl Service {
                 handle.send_user_message(user_message)?;
             }
             None => {
-                self.resp_tx.send(ServiceResp::Error(format!(
+                self.reply(ServiceResp::Notice(format!(
                     "session {session_id} not found"
                 )))?;
             }
@@ -112,77 +196,351 @@ impl Service {
         Ok(())
     }
 
-    /// Sends `session` of `session_id` to tui. Send error message to tui if session not found.
+    /// Dispatches chat event with `PendingResponse` payload to worker of its session to resume.
+    pub fn handle_resume_response(&mut self, pending_response: ChatEvent) -> Result<()> {
+        match self
+            .session_worker_handles
+            .get_mut(&pending_response.session_id)
+        {
+            Some(handle) => handle.resume_response(pending_response)?,
+            None => tracing::error!(
+                "session {} of pending response not found",
+                pending_response.session_id
+            ),
+        }
+        Ok(())
+    }
+
+    /// Cancels background response of `session_id` and notifies tui. The worker of the session
+    /// cancels it if running, since it may be streaming the response.
+    pub async fn handle_cancel_response(&mut self, session_id: &str) -> Result<()> {
+        if let Some(handle) = self.session_worker_handles.get(session_id) {
+            return handle.cancel_response();
+        }
+        cancel_pending_response(
+            self.pending_response_store.as_ref(),
+            &self.llm_router,
+            &self.resp_tx,
+            session_id,
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Sends `session` of `session_id` with its latest page of events to tui. Send error message
+    /// to tui if session not found.
     pub async fn handle_get_session(&mut self, session_id: &str) -> Result<()> {
         // Read from worker for active session.
         if let Some(handle) = self.session_worker_handles.get_mut(session_id) {
-            let chat_session = handle.get_chat_events().await;
-            self.resp_tx.send(ServiceResp::Session(chat_session))?;
+            let mut chat_session = handle.get_chat_events().await;
+            let page = latest_events_page(&chat_session.events, None);
+            chat_session.events = page.items;
+            chat_session.events_cursor = page.next_cursor.unwrap_or_default();
+            self.reply(ServiceResp::Session(chat_session))?;
         } else {
             // Otherwise read from db.
             match self.chat_session_store.get_chat_session(session_id).await {
                 Ok(Some(mut chat_session)) => {
-                    let chat_events = self
+                    let page = self
                         .chat_event_store
-                        .get_chat_events_for_session(session_id)
+                        .get_chat_events_page(session_id, None, EVENT_PAGE_SIZE)
                         .await?;
-                    chat_session.events = chat_events;
-                    self.resp_tx.send(ServiceResp::Session(chat_session))?;
+                    chat_session.events = page.items;
+                    chat_session.events_cursor = page.next_cursor.unwrap_or_default();
+                    self.reply(ServiceResp::Session(chat_session))?;
                 }
                 Ok(None) => {
-                    self.resp_tx.send(ServiceResp::Error(format!(
+                    self.reply(ServiceResp::Notice(format!(
                         "session {session_id} not found"
                     )))?;
                 }
                 Err(e) => {
-                    self.resp_tx.send(ServiceResp::Error(e.to_string()))?;
+                    self.reply(ServiceResp::Error(e.to_string()))?;
                 }
             };
         }
         Ok(())
     }
 
-    /// Delete `session` of `session_id` and sends updated sessions to tui.
-    pub async fn handle_delete_session(&mut self, session_id: &str) -> Result<()> {
-        // TODO: kill worker.
+    /// Moves sessions of `session_ids` to trash and notifies tui.
+    pub async fn handle_delete_sessions(&mut self, session_ids: Vec<String>) -> Result<()> {
+        // cancel streaming responses, sessions in trash are not chatted with
+        for session_id in &session_ids {
+            if let Some(handle) = self.stop_worker(session_id) {
+                handle.abort();
+            }
+        }
+        let res = self
+            .chat_session_store
+            .trash_chat_sessions(session_ids, true)
+            .await;
+        self.send_sessions_or_error(res)
+    }
+
+    /// Restores sessions of `session_ids` from trash and notifies tui.
+    pub async fn handle_restore_sessions(&mut self, session_ids: Vec<String>) -> Result<()> {
+        let res = self
+            .chat_session_store
+            .trash_chat_sessions(session_ids, false)
+            .await;
+        self.send_sessions_or_error(res)
+    }
+
+    /// Permanently deletes sessions of `session_ids` in trash and notifies tui.
+    pub async fn handle_purge_sessions(&mut self, session_ids: Vec<String>) -> Result<()> {
+        let res = self
+            .chat_session_store
+            .delete_chat_sessions(session_ids)
+            .await;
+        self.send_sessions_or_error(res)
+    }
+
+    /// Permanently deletes sessions in trash for longer than the retention period.
+    pub async fn purge_expired_sessions(&mut self) -> Result<()> {
+        let retention = self.trash_config.retention_days as i64 * 24 * 60 * 60;
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+        let deleted = self
+            .chat_session_store
+            .delete_expired_sessions(now - retention)
+            .await?;
+        if deleted > 0 {
+            tracing::info!("purged {deleted} sessions in trash");
+            // refresh trash listed in tui
+            self.send_sessions_changed()?;
+        }
+        Ok(())
+    }
+
+    fn send_sessions_or_error(&self, res: Result<()>) -> Result<()> {
+        match res {
+            Ok(()) => self.send_sessions_changed(),
+            Err(e) => {
+                self.reply(ServiceResp::Error(e.to_string()))?;
+                Ok(())
+            }
+        }
+    }
+
+    /// Applies `update` to session of `session_id`, e.g. its title, and sends the updated session
+    /// summary to tui. Send error message to tui if session not found.
+    pub async fn handle_update_session(
+        &mut self,
+        session_id: &str,
+        update: impl Fn(&mut ChatSession),
+    ) -> Result<()> {
+        // keep the active session in sync since it is sent to tui from the worker
+        if let Some(handle) = self.session_worker_handles.get(session_id) {
+            handle.update_chat_session(&update).await;
+        }
+        let Some(mut chat_session) = self.chat_session_store.get_chat_session(session_id).await?
+        else {
+            self.reply(ServiceResp::Notice(format!(
+                "session {session_id} not found"
+            )))?;
+            return Ok(());
+        };
+        update(&mut chat_session);
         match self
             .chat_session_store
-            .delete_chat_session(session_id)
+            .update_chat_session(chat_session)
             .await
         {
-            Ok(_) => self.send_sessions().await?,
-            Err(e) => {
-                self.resp_tx.send(ServiceResp::Error(e.to_string()))?;
-            }
+            Ok(chat_session) => self
+                .resp_tx
+                .send(ServiceResp::SessionSummary(chat_session))?,
+            Err(e) => self.reply(ServiceResp::Error(e.to_string()))?,
         }
         Ok(())
     }
 
-    /// Sends sessions in stores to tui.
-    pub async fn send_sessions(&mut self) -> Result<()> {
-        tracing::debug!("sending sessions");
-        match self.chat_session_store.get_chat_sessions().await {
-            Ok(chat_sessions) => {
-                self.resp_tx.send(ServiceResp::Sessions(chat_sessions))?;
+    /// Replaces tags of session of `session_id` and sends the updated session summary to tui. Tags
+    /// are trimmed of a leading `#` and deduplicated ignoring case.
+    pub async fn handle_set_session_tags(
+        &mut self,
+        session_id: &str,
+        tags: Vec<String>,
+    ) -> Result<()> {
+        let mut normalized: Vec<String> = Vec::new();
+        for tag in tags {
+            let tag = tag.trim().trim_start_matches('#');
+            if tag.is_empty() || tag.contains(char::is_whitespace) {
+                self.reply(ServiceResp::Notice(format!("invalid tag \"{tag}\"")))?;
+                return Ok(());
             }
-            Err(e) => {
-                self.resp_tx.send(ServiceResp::Error(e.to_string()))?;
+            if !normalized.iter().any(|t| t.eq_ignore_ascii_case(tag)) {
+                normalized.push(tag.to_string());
+            }
+        }
+        match self
+            .chat_session_store
+            .set_session_tags(session_id, normalized)
+            .await
+        {
+            Ok(Some(chat_session)) => self
+                .resp_tx
+                .send(ServiceResp::SessionSummary(chat_session))?,
+            Ok(None) => self.reply(ServiceResp::Notice(format!(
+                "session {session_id} not found"
+            )))?,
+            Err(e) => self.reply(ServiceResp::Error(e.to_string()))?,
+        }
+        Ok(())
+    }
+
+    /// Sends a page of sessions matching sidebar filter `query` after `cursor` to tui, from trash
+    /// if `trash`, with archived ones if `include_archived`.
+    pub async fn handle_query_sessions(
+        &mut self,
+        query: String,
+        cursor: Option<String>,
+        trash: bool,
+        include_archived: bool,
+    ) -> Result<()> {
+        let session_query = SessionQuery {
+            trash,
+            include_archived,
+            ..SessionQuery::parse(&query)
+        };
+        match self
+            .chat_session_store
+            .query_chat_sessions(session_query, cursor.clone(), SESSION_PAGE_SIZE)
+            .await
+        {
+            Ok(page) => self.reply(ServiceResp::SessionQuery {
+                query,
+                cursor,
+                trash,
+                include_archived,
+                sessions: page.items,
+                next_cursor: page.next_cursor,
+            })?,
+            // e.g. of a malformed cursor, the sidebar keeps the sessions listed so far
+            Err(e) => self.reply(ServiceResp::Notice(format!("failed to list sessions: {e}")))?,
+        }
+        Ok(())
+    }
+
+    /// Sends a page of events of session `session_id` older than event of id `before` to tui.
+    pub async fn handle_get_events(&mut self, session_id: String, before: String) -> Result<()> {
+        // Read from worker for active session.
+        let page = if let Some(handle) = self.session_worker_handles.get_mut(&session_id) {
+            let chat_session = handle.get_chat_events().await;
+            latest_events_page(&chat_session.events, Some(&before))
+        } else {
+            match self
+                .chat_event_store
+                .get_chat_events_page(&session_id, Some(before.clone()), EVENT_PAGE_SIZE)
+                .await
+            {
+                Ok(page) => page,
+                // e.g. of a malformed cursor, the loaded events are kept
+                Err(e) => {
+                    self.reply(ServiceResp::Notice(format!("failed to load events: {e}")))?;
+                    return Ok(());
+                }
             }
         };
+        self.reply(ServiceResp::Events {
+            session_id,
+            before,
+            events: page.items,
+            next_cursor: page.next_cursor,
+        })?;
         Ok(())
     }
 
-    /// Attempts to generate title with LLM and send to tui.
+    /// Persists `chat_session` with its events, generates its title and notifies tui. Replies a
+    /// notice if the session exists.
+    pub async fn handle_import_session(&mut self, mut chat_session: ChatSession) -> Result<()> {
+        if self
+            .chat_session_store
+            .get_chat_session(&chat_session.id)
+            .await?
+            .is_some()
+        {
+            self.reply(ServiceResp::Notice(format!(
+                "session {} already exists",
+                chat_session.id
+            )))?;
+            return Ok(());
+        }
+        let events = std::mem::take(&mut chat_session.events);
+        let chat_session = self
+            .chat_session_store
+            .create_chat_session(chat_session)
+            .await?;
+        for chat_event in &events {
+            self.chat_event_store
+                .create_chat_event(chat_event.clone())
+                .await?;
+        }
+        self.send_sessions_changed()?;
+
+        let first_user_message = events.into_iter().find(|e| {
+            matches!(&e.payload, Some(chat_event::Payload::Message(m)) if m.role() == Role::User)
+        });
+        if let Some(user_message) = first_user_message {
+            self.spawn_title_generation(user_message, chat_session);
+        }
+        Ok(())
+    }
+
+    /// Sends session of proxied conversation `key`.
+    pub async fn handle_find_conversation(&mut self, key: String) -> Result<()> {
+        match self.conversation_store.get_conversation_session(&key).await {
+            Ok(session_id) => self.reply(ServiceResp::Conversation { key, session_id })?,
+            Err(e) => self.reply(ServiceResp::Error(e.to_string()))?,
+        }
+        Ok(())
+    }
+
+    /// Records `keys` of a proxied conversation of session of `session_id`.
+    pub async fn handle_record_conversation(
+        &mut self,
+        keys: Vec<String>,
+        session_id: String,
+    ) -> Result<()> {
+        if let Err(e) = self
+            .conversation_store
+            .record_conversation(keys, session_id)
+            .await
+        {
+            self.reply(ServiceResp::Error(e.to_string()))?;
+        }
+        Ok(())
+    }
+
+    /// Generates title of `chat_session` from `user_message` async.
+    fn spawn_title_generation(&mut self, user_message: ChatEvent, chat_session: ChatSession) {
+        // reap finished ones
+        while self.title_tasks.try_join_next().is_some() {}
+        self.title_tasks.spawn(Self::try_update_session_title(
+            self.chat_session_store.clone(),
+            user_message,
+            chat_session,
+            self.llm_router.clone(),
+            self.resp_tx.clone(),
+        ));
+    }
+
+    /// Notifies tui that sessions were created, deleted or restored, so that it queries them again.
+    pub fn send_sessions_changed(&self) -> Result<()> {
+        self.resp_tx.send(ServiceResp::SessionsChanged)?;
+        Ok(())
+    }
+
+    /// Attempts to generate title with LLM and send to tui. Keeps the title if the session was
+    /// renamed meanwhile.
     pub async fn try_update_session_title(
         chat_session_store: Arc<dyn ChatSessionStore>,
         user_message: ChatEvent,
-        mut chat_session: ChatSession,
+        chat_session: ChatSession,
         llm_router: LlmClientRouter,
         resp_tx: UnboundedSender<ServiceResp>,
     ) {
-        chat_session.title = match Self::generate_session_title(
+        let title = match Self::generate_session_title(
             user_message,
-            chat_session.llm_settings.unwrap_or_default(),
+            chat_session.llm_settings.clone().unwrap_or_default(),
             llm_router,
         )
         .await
@@ -194,6 +552,16 @@ impl Service {
             }
         };
 
+        // reread the session to keep changes made while generating, e.g. pinning it
+        let mut chat_session = match chat_session_store.get_chat_session(&chat_session.id).await {
+            Ok(Some(chat_session)) if chat_session.title.is_empty() => chat_session,
+            Ok(_) => return,
+            Err(e) => {
+                tracing::error!("failed to get session to update title: {e}");
+                return;
+            }
+        };
+        chat_session.title = title;
         match chat_session_store.update_chat_session(chat_session).await {
             Ok(chat_session) => {
                 if let Err(e) = resp_tx.send(ServiceResp::SessionSummary(chat_session)) {
@@ -238,3 +606,16 @@ impl Service {
         maybe_title.ok_or(eyre!("Llm response has no title"))
     }
 }
+
+/// Returns the latest page of `events` before event of id `before`, like the chat event store.
+fn latest_events_page(events: &[ChatEvent], before: Option<&str>) -> Page<ChatEvent> {
+    let end = match before {
+        Some(before) => events.iter().position(|e| e.id == before).unwrap_or(0),
+        None => events.len(),
+    };
+    let start = end.saturating_sub(EVENT_PAGE_SIZE);
+    Page {
+        items: events[start..end].to_vec(),
+        next_cursor: (start > 0).then(|| events[start].id.clone()),
+    }
+}
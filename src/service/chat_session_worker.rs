@@ -1,170 +1,548 @@
-use color_eyre::Result;
-use std::sync::Arc;
-use tokio::sync::{
-    Mutex,
-    mpsc::{UnboundedReceiver, UnboundedSender},
+use color_eyre::{Result, eyre::eyre};
+use futures_util::stream::BoxStream;
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::{
+    sync::{
+        Mutex,
+        mpsc::{UnboundedReceiver, UnboundedSender},
+        watch,
+    },
+    task::AbortHandle,
 };
 use tokio_stream::StreamExt as _;
 
 use crate::{
     chat::*,
-    models::ServiceResp,
+    models::{ServiceResp, configs::ContextStrategy},
     service::{
+        context::ContextManager,
         llms::{LlmClient, LlmClientRouter, LlmReq},
-        stores::{chat_event_store::ChatEventStore, chat_session_store::ChatSessionStore},
+        stores::{
+            chat_event_store::ChatEventStore, chat_session_store::ChatSessionStore,
+            pending_response_store::PendingResponseStore,
+        },
     },
 };
 
+/// When the worker went idle, None while it has events to handle. Shared by worker and its handle.
+pub type IdleSince = Arc<std::sync::Mutex<Option<Instant>>>;
+
 /// ChatSessionWorkerHandle allows server to interact with each ChatSessionWorker. When
-/// ChatSessionWorkerHandle is dropped, the corresponding woker is stopped.
+/// ChatSessionWorkerHandle is dropped, the corresponding woker stops after handling events already
+/// sent to it.
 pub struct ChatSessionWorkerHandle {
     chat_tx: UnboundedSender<ChatEvent>,
+    cancel_tx: UnboundedSender<()>,
     chat_session: Arc<Mutex<ChatSession>>,
+    idle_since: IdleSince,
+    abort_handle: AbortHandle,
 }
 
 impl ChatSessionWorkerHandle {
-    pub fn new(chat_tx: UnboundedSender<ChatEvent>, chat_session: Arc<Mutex<ChatSession>>) -> Self {
+    pub fn new(
+        chat_tx: UnboundedSender<ChatEvent>,
+        cancel_tx: UnboundedSender<()>,
+        chat_session: Arc<Mutex<ChatSession>>,
+        idle_since: IdleSince,
+        abort_handle: AbortHandle,
+    ) -> Self {
         Self {
             chat_tx,
+            cancel_tx,
             chat_session,
+            idle_since,
+            abort_handle,
         }
     }
 
     /// Sends user message to worker.
     pub fn send_user_message(&self, user_message: ChatEvent) -> Result<()> {
+        // sent holding the lock so that the worker sees it before going idle
+        let mut idle_since = self.idle_since.lock().unwrap();
+        *idle_since = None;
         self.chat_tx.send(user_message)?;
         Ok(())
     }
 
+    /// Sends chat event with `PendingResponse` payload to worker to resume the background response.
+    pub fn resume_response(&self, pending_response: ChatEvent) -> Result<()> {
+        let mut idle_since = self.idle_since.lock().unwrap();
+        *idle_since = None;
+        self.chat_tx.send(pending_response)?;
+        Ok(())
+    }
+
+    /// Asks worker to cancel the background response of its session, so that it stops tracking
+    /// progress of the response before it is dropped from store.
+    pub fn cancel_response(&self) -> Result<()> {
+        self.cancel_tx.send(())?;
+        Ok(())
+    }
+
+    /// Returns how long the worker has been idle, None while it is busy.
+    pub fn idle_for(&self) -> Option<Duration> {
+        self.idle_since.lock().unwrap().map(|since| since.elapsed())
+    }
+
+    /// Stops worker immediately, cancelling the response it is streaming.
+    pub fn abort(&self) {
+        self.abort_handle.abort();
+    }
+
+    pub fn task_id(&self) -> tokio::task::Id {
+        self.abort_handle.id()
+    }
+
+    /// Applies `update` to the session held by worker.
+    pub async fn update_chat_session(&self, update: impl FnOnce(&mut ChatSession)) {
+        update(&mut *self.chat_session.lock().await);
+    }
+
     pub async fn get_chat_events(&mut self) -> ChatSession {
         let chat_session = self.chat_session.lock().await;
         chat_session.clone()
     }
 }
 
+/// Cancels pending background response of `session_id` at its provider, drops it from store and
+/// notifies tui. Returns whether there was a response to cancel.
+pub async fn cancel_pending_response(
+    pending_response_store: &dyn PendingResponseStore,
+    llm_router: &LlmClientRouter,
+    resp_tx: &UnboundedSender<ServiceResp>,
+    session_id: &str,
+) -> Result<bool> {
+    let Some(chat_event) = pending_response_store
+        .get_pending_responses()
+        .await?
+        .into_iter()
+        .find(|e| e.session_id == session_id)
+    else {
+        tracing::warn!("session {session_id} has no pending response");
+        return Ok(false);
+    };
+    let Some(chat_event::Payload::PendingResponse(mut pending_response)) = chat_event.payload
+    else {
+        return Err(eyre!("pending response has no payload"));
+    };
+
+    let settings = chat_event.llm_settings.clone().unwrap_or_default();
+    if let Err(e) = llm_router
+        .cancel(settings, &pending_response.response_id)
+        .await
+    {
+        tracing::error!(
+            "failed to cancel response {}: {e}",
+            pending_response.response_id
+        );
+        return Ok(false);
+    }
+    pending_response_store
+        .delete_pending_response(&pending_response.response_id)
+        .await?;
+
+    pending_response.status = "cancelled".to_string();
+    resp_tx.send(ServiceResp::ChatEvent(ChatEvent::new(
+        session_id.to_string(),
+        chat_event.llm_settings,
+        chat_event::Payload::PendingResponse(pending_response),
+    )))?;
+    Ok(true)
+}
+
+/// Resolves once shutdown is requested, or never if the service is gone without requesting it.
+async fn shutdown_requested(shutdown_rx: &mut watch::Receiver<bool>) {
+    if shutdown_rx.wait_for(|shutdown| *shutdown).await.is_err() {
+        std::future::pending::<()>().await;
+    }
+}
+
 pub struct ChatSessionWorker {
     chat_rx: UnboundedReceiver<ChatEvent>,
+    cancel_rx: UnboundedReceiver<()>,
     chat_session: Arc<Mutex<ChatSession>>,
+    idle_since: IdleSince,
+    /// Set once the service shuts down.
+    shutdown_rx: watch::Receiver<bool>,
     llm_router: LlmClientRouter,
     resp_tx: UnboundedSender<ServiceResp>,
     chat_event_store: Arc<dyn ChatEventStore>,
     chat_session_store: Arc<dyn ChatSessionStore>,
+    pending_response_store: Arc<dyn PendingResponseStore>,
+    context_manager: ContextManager,
 }
 
 impl ChatSessionWorker {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         chat_rx: UnboundedReceiver<ChatEvent>,
+        cancel_rx: UnboundedReceiver<()>,
         chat_session: Arc<Mutex<ChatSession>>,
+        idle_since: IdleSince,
+        shutdown_rx: watch::Receiver<bool>,
         llm_router: LlmClientRouter,
         resp_tx: UnboundedSender<ServiceResp>,
         chat_event_store: Arc<dyn ChatEventStore>,
         chat_session_store: Arc<dyn ChatSessionStore>,
+        pending_response_store: Arc<dyn PendingResponseStore>,
+        context_manager: ContextManager,
     ) -> Self {
         Self {
             chat_rx,
+            cancel_rx,
             chat_session,
+            idle_since,
+            shutdown_rx,
             llm_router,
             resp_tx,
             chat_event_store,
             chat_session_store,
+            pending_response_store,
+            context_manager,
         }
     }
 
     /// Polls user messages from `chat_rx`, for each user message, constructs `LlmReq` and request
-    /// llm response with streaming. Send response to tui and persist to db.
+    /// llm response with streaming. Resumes background responses sent as `PendingResponse` chat
+    /// events and cancels them on request. Send response to tui and persist to db. Stops once its
+    /// handle is dropped or on shutdown, saving the message streamed so far.
     pub async fn run(mut self) -> Result<()> {
-        // TODO: close worker after inactivity
-        while let Some(mut user_message) = self.chat_rx.recv().await {
-            // ----------------------------------------------------------------
-            // Persist user message and send it back to tui.
-            // ----------------------------------------------------------------
-            user_message = self
-                .chat_event_store
-                .create_chat_event(user_message)
-                .await?;
-            self.resp_tx
-                .send(ServiceResp::ChatEvent(user_message.clone()))?;
-
-            // ----------------------------------------------------------------
-            // Update chat session and prepare llm request.
-            // ----------------------------------------------------------------
-            let llm_req = {
-                let mut chat_session = self.chat_session.lock().await;
+        let mut shutdown_rx = self.shutdown_rx.clone();
+        loop {
+            let chat_event = tokio::select! {
+                _ = shutdown_requested(&mut shutdown_rx) => break,
+                Some(()) = self.cancel_rx.recv() => {
+                    self.cancel_response().await?;
+                    continue;
+                }
+                chat_event = self.chat_rx.recv() => match chat_event {
+                    Some(chat_event) => chat_event,
+                    None => break,
+                },
+            };
+            // failed chat is reported to the session, which keeps serving
+            if let Err(e) = self.handle_event(chat_event).await {
+                let session_id = self.chat_session.lock().await.id.clone();
+                tracing::error!("chat of session {session_id} failed: {e:?}");
+                self.resp_tx.send(ServiceResp::ChatFailed {
+                    session_id,
+                    error: format!("{e:#}"),
+                })?;
+            }
+            // checked holding the lock, which the handle sends under
+            let mut idle_since = self.idle_since.lock().unwrap();
+            if self.chat_rx.is_empty() {
+                *idle_since = Some(Instant::now());
+            }
+        }
+        Ok(())
+    }
 
-                // update settings if changed.
-                if chat_session.llm_settings != user_message.llm_settings {
-                    chat_session.llm_settings = user_message.llm_settings;
-                    self.chat_session_store
-                        .update_chat_session(chat_session.clone())
-                        .await?;
+    /// Streams reply to a user message or resumes a background response.
+    async fn handle_event(&mut self, chat_event: ChatEvent) -> Result<()> {
+        let stream = match &chat_event.payload {
+            Some(chat_event::Payload::PendingResponse(pending_response)) => {
+                let settings = chat_event.llm_settings.clone().unwrap_or_default();
+                match self
+                    .llm_router
+                    .resume(settings, pending_response.clone())
+                    .await
+                {
+                    Ok(stream) => {
+                        self.chat_session
+                            .lock()
+                            .await
+                            .events
+                            .push(chat_event.clone());
+                        self.resp_tx.send(ServiceResp::ChatEvent(chat_event))?;
+                        stream
+                    }
+                    Err(e) => {
+                        // drop responses that can not be resumed, e.g. expired ones
+                        tracing::error!(
+                            "failed to resume response {}: {e}",
+                            pending_response.response_id
+                        );
+                        self.pending_response_store
+                            .delete_pending_response(&pending_response.response_id)
+                            .await?;
+                        return Ok(());
+                    }
                 }
-                // append user message.
-                chat_session.events.push(user_message);
-
-                // load history events
-                let events = chat_session
-                    .events
-                    .iter()
-                    .filter_map(|e| e.payload.clone())
-                    .collect();
-
-                LlmReq {
-                    events,
-                    instructions: None,
-                    settings: chat_session.llm_settings.unwrap_or_default(),
+            }
+            _ => {
+                let llm_req = self.handle_user_message(chat_event).await?;
+                self.llm_router.stream(llm_req).await?
+            }
+        };
+        self.handle_stream(stream).await
+    }
+
+    /// Persists user message and sends it back to tui, updates chat session and prepares llm
+    /// request.
+    async fn handle_user_message(&self, mut user_message: ChatEvent) -> Result<LlmReq> {
+        user_message = self
+            .chat_event_store
+            .create_chat_event(user_message)
+            .await?;
+        self.resp_tx
+            .send(ServiceResp::ChatEvent(user_message.clone()))?;
+
+        {
+            let mut chat_session = self.chat_session.lock().await;
+
+            // update settings if changed, on the stored session to keep its other fields, e.g. the
+            // title generated meanwhile.
+            if chat_session.llm_settings != user_message.llm_settings {
+                chat_session.llm_settings = user_message.llm_settings.clone();
+                if let Some(mut stored) = self
+                    .chat_session_store
+                    .get_chat_session(&chat_session.id)
+                    .await?
+                {
+                    stored.llm_settings = user_message.llm_settings.clone();
+                    self.chat_session_store.update_chat_session(stored).await?;
+                }
+            }
+            // append user message.
+            chat_session.events.push(user_message);
+        }
+        self.build_llm_req().await
+    }
+
+    /// Handles llm response stream, sends events to tui and persists non delta events.
+    async fn handle_stream(
+        &mut self,
+        mut stream: BoxStream<'static, chat_event::Payload>,
+    ) -> Result<()> {
+        let mut shutdown_rx = self.shutdown_rx.clone();
+        loop {
+            let payload = tokio::select! {
+                _ = shutdown_requested(&mut shutdown_rx) => {
+                    return self.save_partial_message().await;
+                }
+                // stop streaming the response here, between events, so that its progress is not
+                // stored again once it is cancelled
+                Some(()) = self.cancel_rx.recv() => {
+                    if self.cancel_response().await? {
+                        return Ok(());
+                    }
+                    continue;
                 }
+                payload = stream.next() => match payload {
+                    Some(payload) => payload,
+                    None => break,
+                },
             };
+            let chat_event = {
+                let mut chat_session = self.chat_session.lock().await;
+
+                let mut chat_event = ChatEvent::new(
+                    chat_session.id.clone(),
+                    chat_session.llm_settings.clone(),
+                    payload.clone(),
+                );
 
-            // ----------------------------------------------------------------
-            // Stream request and handle response.
-            // ----------------------------------------------------------------
-            let mut stream = self.llm_router.stream(llm_req).await?;
-            while let Some(payload) = stream.next().await {
-                let chat_event = {
-                    let mut chat_session = self.chat_session.lock().await;
-
-                    let mut chat_event = ChatEvent::new(
-                        chat_session.id.clone(),
-                        chat_session.llm_settings,
-                        payload.clone(),
-                    );
-
-                    // persist non delta event and update timestamp
-                    match payload {
-                        chat_event::Payload::Message(_) => {
-                            // if the last message is message delta, pop it
-                            chat_session.events.pop_if(|event| {
-                                matches!(event.payload, Some(chat_event::Payload::MessageDelta(_)))
-                            });
-                            chat_event =
-                                self.chat_event_store.create_chat_event(chat_event).await?;
+                // persist non delta event and update timestamp
+                match payload {
+                    chat_event::Payload::Message(_) => {
+                        // if the last message is message delta, pop it
+                        chat_session.events.pop_if(|event| {
+                            matches!(event.payload, Some(chat_event::Payload::MessageDelta(_)))
+                        });
+                        chat_event = self.chat_event_store.create_chat_event(chat_event).await?;
+                        chat_session.events.push(chat_event.clone());
+                    }
+                    chat_event::Payload::MessageDelta(message_delta) => {
+                        // if the last message is message delta, just append delta to it,
+                        // otherwise append the delta
+                        if let Some(event) = chat_session.events.last_mut()
+                            && let Some(chat_event::Payload::MessageDelta(
+                                ref mut last_delta_message,
+                            )) = event.payload
+                        {
+                            last_delta_message.delta.push_str(&message_delta.delta);
+                        } else {
                             chat_session.events.push(chat_event.clone());
                         }
-                        chat_event::Payload::MessageDelta(message_delta) => {
-                            // if the last message is message delta, just append delta to it,
-                            // otherwise append the delta
-                            if let Some(event) = chat_session.events.last_mut()
-                                && let Some(chat_event::Payload::MessageDelta(
-                                    ref mut last_delta_message,
-                                )) = event.payload
-                            {
-                                last_delta_message.delta.push_str(&message_delta.delta);
-                            } else {
-                                chat_session.events.push(chat_event.clone());
-                            }
-                        }
-                        chat_event::Payload::ToolEvent(_) => {
-                            chat_event =
-                                self.chat_event_store.create_chat_event(chat_event).await?;
+                    }
+                    chat_event::Payload::ToolEvent(_) | chat_event::Payload::ContextTrim(_) => {
+                        chat_event = self.chat_event_store.create_chat_event(chat_event).await?;
+                        chat_session.events.push(chat_event.clone());
+                    }
+                    // keep the latest progress of background response until it is done
+                    chat_event::Payload::PendingResponse(pending_response) => {
+                        chat_session.events.retain(|event| {
+                            !matches!(event.payload, Some(chat_event::Payload::PendingResponse(_)))
+                        });
+                        if pending_response.is_done() {
+                            self.pending_response_store
+                                .delete_pending_response(&pending_response.response_id)
+                                .await?;
+                        } else {
+                            self.pending_response_store
+                                .upsert_pending_response(chat_event.clone())
+                                .await?;
                             chat_session.events.push(chat_event.clone());
                         }
                     }
-                    chat_event
-                };
-                // send to tui
-                self.resp_tx.send(ServiceResp::ChatEvent(chat_event))?;
-            }
+                }
+                chat_event
+            };
+            // send to tui
+            self.resp_tx.send(ServiceResp::ChatEvent(chat_event))?;
         }
+
+        // response stays pending in store to resume on restart if the stream ended before done
+        self.chat_session.lock().await.events.retain(|event| {
+            !matches!(event.payload, Some(chat_event::Payload::PendingResponse(_)))
+        });
+        Ok(())
+    }
+
+    /// Cancels pending background response of the session and drops its progress and the message
+    /// streamed so far. Returns whether there was a response to cancel.
+    async fn cancel_response(&self) -> Result<bool> {
+        let session_id = self.chat_session.lock().await.id.clone();
+        let cancelled = cancel_pending_response(
+            self.pending_response_store.as_ref(),
+            &self.llm_router,
+            &self.resp_tx,
+            &session_id,
+        )
+        .await?;
+        if cancelled {
+            self.chat_session.lock().await.events.retain(|event| {
+                !matches!(
+                    event.payload,
+                    Some(
+                        chat_event::Payload::PendingResponse(_)
+                            | chat_event::Payload::MessageDelta(_)
+                    )
+                )
+            });
+        }
+        Ok(cancelled)
+    }
+
+    /// Persists message streamed so far as the assistant message. Background responses stay
+    /// pending in store to resume on restart, where they complete with the whole message, so their
+    /// partial message is dropped.
+    async fn save_partial_message(&self) -> Result<()> {
+        let mut chat_session = self.chat_session.lock().await;
+        let Some(ChatEvent {
+            payload: Some(chat_event::Payload::MessageDelta(message_delta)),
+            ..
+        }) = chat_session
+            .events
+            .pop_if(|event| matches!(event.payload, Some(chat_event::Payload::MessageDelta(_))))
+        else {
+            return Ok(());
+        };
+        let background = chat_session
+            .events
+            .iter()
+            .any(|event| matches!(event.payload, Some(chat_event::Payload::PendingResponse(_))));
+        if background {
+            return Ok(());
+        }
+        let chat_event = ChatEvent::new(
+            chat_session.id.clone(),
+            chat_session.llm_settings.clone(),
+            chat_event::Payload::Message(Message {
+                role: Role::Assistant as i32,
+                msg: message_delta.delta,
+                response_ref: None,
+            }),
+        );
+        let chat_event = self.chat_event_store.create_chat_event(chat_event).await?;
+        chat_session.events.push(chat_event.clone());
+        // frontends may be gone on shutdown
+        let _ = self.resp_tx.send(ServiceResp::ChatEvent(chat_event));
         Ok(())
     }
+
+    /// Fits session history into the model's context window and builds llm request. With
+    /// `Summarize` strategy, trimmed history is summarized and stored as a summary event. Notifies
+    /// tui if any history is left out.
+    async fn build_llm_req(&self) -> Result<LlmReq> {
+        let (session_id, settings, mut events) = {
+            let chat_session = self.chat_session.lock().await;
+            (
+                chat_session.id.clone(),
+                chat_session.llm_settings.clone(),
+                chat_session.events.clone(),
+            )
+        };
+        let llm_settings = settings.clone().unwrap_or_default();
+        let mut fitted = self.context_manager.fit(&events, &llm_settings);
+
+        if self.context_manager.strategy() == ContextStrategy::Summarize
+            && let Some(last_event) = fitted.trimmed.last()
+        {
+            let summary_req = ContextManager::summary_request(
+                fitted.summary.as_deref(),
+                &fitted.trimmed,
+                llm_settings.clone(),
+            );
+            match self.summarize(summary_req).await {
+                Ok(summary) => {
+                    let payload = chat_event::Payload::ContextTrim(ContextTrim {
+                        trimmed_events: (fitted.summarized + fitted.trimmed.len()) as u32,
+                        summary,
+                        last_event_id: last_event.id.clone(),
+                    });
+                    let chat_event = self
+                        .chat_event_store
+                        .create_chat_event(ChatEvent::new(
+                            session_id.clone(),
+                            settings.clone(),
+                            payload,
+                        ))
+                        .await?;
+                    self.chat_session
+                        .lock()
+                        .await
+                        .events
+                        .push(chat_event.clone());
+                    events.push(chat_event);
+                    fitted = self.context_manager.fit(&events, &llm_settings);
+                }
+                // fall back to dropping trimmed history
+                Err(e) => tracing::error!("failed to summarize history: {e}"),
+            }
+        }
+
+        if fitted.is_trimmed() {
+            let payload = chat_event::Payload::ContextTrim(ContextTrim {
+                trimmed_events: (fitted.summarized + fitted.trimmed.len()) as u32,
+                summary: fitted.summary.clone().unwrap_or_default(),
+                last_event_id: String::new(),
+            });
+            self.resp_tx.send(ServiceResp::ChatEvent(ChatEvent::new(
+                session_id, settings, payload,
+            )))?;
+        }
+
+        Ok(LlmReq {
+            instructions: fitted.instructions(&llm_settings.instructions),
+            events: fitted.events,
+            settings: llm_settings,
+        })
+    }
+
+    /// Requests llm to summarize history.
+    async fn summarize(&self, summary_req: LlmReq) -> Result<String> {
+        let resp = self.llm_router.request(summary_req).await?;
+        resp.output
+            .into_iter()
+            .find_map(|payload| match payload {
+                chat_event::Payload::Message(m) => Some(m.msg),
+                _ => None,
+            })
+            .ok_or(eyre!("Llm response has no summary"))
+    }
 }
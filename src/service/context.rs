@@ -0,0 +1,381 @@
+use crate::{
+    chat::*,
+    llm::*,
+    models::configs::{ContextConfig, ContextStrategy},
+    service::llms::LlmReq,
+};
+
+/// Rough per message overhead for role and formatting tokens.
+const MESSAGE_OVERHEAD_TOKENS: usize = 4;
+
+/// Estimates token count of `text` with the rule of thumb of ~4 characters per token.
+pub fn estimate_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(4)
+}
+
+/// Estimates token count of one payload as sent to llm.
+fn estimate_payload_tokens(payload: &chat_event::Payload) -> usize {
+    match payload {
+        chat_event::Payload::Message(message) => {
+            estimate_tokens(&message.msg) + MESSAGE_OVERHEAD_TOKENS
+        }
+        chat_event::Payload::ToolEvent(tool_event) => match &tool_event.event {
+            Some(tool_event::Event::WebSearchCall(wsc)) => {
+                estimate_tokens(&wsc.action_json) + MESSAGE_OVERHEAD_TOKENS
+            }
+            None => 0,
+        },
+        chat_event::Payload::MessageDelta(_)
+        | chat_event::Payload::ContextTrim(_)
+        | chat_event::Payload::PendingResponse(_) => 0,
+    }
+}
+
+/// History to send to llm after applying context strategy.
+#[derive(Debug, Default)]
+pub struct FittedContext {
+    /// History payloads to send.
+    pub events: Vec<chat_event::Payload>,
+    /// Summary of history before `events`.
+    pub summary: Option<String>,
+    /// Number of history events covered by `summary`.
+    pub summarized: usize,
+    /// History events left out of the request, in order.
+    pub trimmed: Vec<ChatEvent>,
+}
+
+impl FittedContext {
+    /// Returns whether any history is left out of the request.
+    pub fn is_trimmed(&self) -> bool {
+        self.summary.is_some() || !self.trimmed.is_empty()
+    }
+
+    /// Returns session `instructions` followed by the summary of earlier history.
+    pub fn instructions(&self, instructions: &str) -> Option<String> {
+        let summary = self
+            .summary
+            .as_ref()
+            .map(|summary| format!("Summary of the earlier conversation:\n{summary}"));
+        match (instructions.is_empty(), summary) {
+            (true, summary) => summary,
+            (false, None) => Some(instructions.to_string()),
+            (false, Some(summary)) => Some(format!("{instructions}\n\n{summary}")),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct ContextManager {
+    config: ContextConfig,
+}
+
+impl ContextManager {
+    pub fn new(config: ContextConfig) -> Self {
+        Self { config }
+    }
+
+    pub fn strategy(&self) -> ContextStrategy {
+        self.config.strategy
+    }
+
+    /// Returns token budget for history given `settings`.
+    fn budget(&self, settings: &LlmSettings) -> usize {
+        self.config
+            .max_context_tokens
+            .unwrap_or_else(|| settings.context_window())
+            .saturating_sub(self.config.reserve_tokens)
+    }
+
+    /// Fits `events` of a session into the context window of `settings`. History before the
+    /// latest stored summary is replaced with the summary. The last turn is always kept.
+    pub fn fit(&self, events: &[ChatEvent], settings: &LlmSettings) -> FittedContext {
+        // ----------------------------------------------------------------
+        // Replace history covered by the latest summary.
+        // ----------------------------------------------------------------
+        let mut summary = None;
+        let mut summarized = 0;
+        let mut history = events;
+        if let Some(context_trim) = events.iter().rev().find_map(|e| match &e.payload {
+            Some(chat_event::Payload::ContextTrim(t)) if !t.summary.is_empty() => Some(t),
+            _ => None,
+        }) && let Some(idx) = events
+            .iter()
+            .position(|e| e.id == context_trim.last_event_id)
+        {
+            summary = Some(context_trim.summary.clone());
+            summarized = context_trim.trimmed_events as usize;
+            history = &events[idx + 1..];
+        }
+
+        // ----------------------------------------------------------------
+        // Group history into turns, each turn starts with a user message.
+        // ----------------------------------------------------------------
+        let mut turns: Vec<Vec<&ChatEvent>> = Vec::new();
+        for event in history {
+            let Some(payload) = &event.payload else {
+                continue;
+            };
+            match payload {
+                chat_event::Payload::MessageDelta(_)
+                | chat_event::Payload::ContextTrim(_)
+                | chat_event::Payload::PendingResponse(_) => continue,
+                chat_event::Payload::Message(message) if message.role() == Role::User => {
+                    turns.push(vec![event]);
+                }
+                _ => match turns.last_mut() {
+                    Some(turn) => turn.push(event),
+                    None => turns.push(vec![event]),
+                },
+            }
+        }
+
+        let turn_tokens = |turn: &Vec<&ChatEvent>| -> usize {
+            turn.iter()
+                .filter_map(|e| e.payload.as_ref())
+                .map(estimate_payload_tokens)
+                .sum()
+        };
+        let mut tokens: Vec<usize> = turns.iter().map(turn_tokens).collect();
+        let budget = self
+            .budget(settings)
+            .saturating_sub(summary.as_deref().map_or(0, estimate_tokens));
+
+        // ----------------------------------------------------------------
+        // Drop turns until history fits.
+        // ----------------------------------------------------------------
+        let mut trimmed: Vec<ChatEvent> = Vec::new();
+        if tokens.iter().sum::<usize>() > budget {
+            let len = turns.len();
+            // range of turns to drop first, oldest first
+            let (start, end) = match self.config.strategy {
+                ContextStrategy::DropOldest => (0, len.saturating_sub(1)),
+                ContextStrategy::KeepEnds => (
+                    self.config.keep_first.min(len),
+                    len.saturating_sub(self.config.keep_last.max(1)),
+                ),
+                ContextStrategy::Summarize => (0, len.saturating_sub(self.config.keep_last.max(1))),
+            };
+            let mut dropped = vec![false; len];
+            let mut drop_turn = |idx: usize, tokens: &mut Vec<usize>| {
+                dropped[idx] = true;
+                tokens[idx] = 0;
+            };
+            for idx in start..end.max(start) {
+                // summarize drops every turn in range at once so that summaries are not
+                // regenerated on every turn
+                if self.config.strategy != ContextStrategy::Summarize
+                    && tokens.iter().sum::<usize>() <= budget
+                {
+                    break;
+                }
+                drop_turn(idx, &mut tokens);
+            }
+            // fall back to dropping oldest turns except for the last one
+            for idx in 0..len.saturating_sub(1) {
+                if tokens.iter().sum::<usize>() <= budget {
+                    break;
+                }
+                drop_turn(idx, &mut tokens);
+            }
+
+            let mut kept = Vec::new();
+            for (turn, dropped) in turns.into_iter().zip(dropped) {
+                if dropped {
+                    trimmed.extend(turn.into_iter().cloned());
+                } else {
+                    kept.push(turn);
+                }
+            }
+            turns = kept;
+        }
+
+        FittedContext {
+            events: turns
+                .into_iter()
+                .flatten()
+                .filter_map(|e| e.payload.clone())
+                .collect(),
+            summary,
+            summarized,
+            trimmed,
+        }
+    }
+
+    /// Builds request to summarize `trimmed` history on top of `previous_summary`.
+    pub fn summary_request(
+        previous_summary: Option<&str>,
+        trimmed: &[ChatEvent],
+        settings: LlmSettings,
+    ) -> LlmReq {
+        let mut prompt = "You are an AI assistant that summarizes a conversation so that it can \
+        be continued without the full history. Keep facts, decisions, code, names and open \
+        questions that later messages may refer to. Only reply with the summary."
+            .to_string();
+        if let Some(previous_summary) = previous_summary {
+            prompt.push_str("\n\nSummary of the conversation before these messages:\n");
+            prompt.push_str(previous_summary);
+        }
+
+        LlmReq {
+            events: trimmed.iter().filter_map(|e| e.payload.clone()).collect(),
+            instructions: Some(prompt),
+            settings,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        chat::*,
+        llm::*,
+        models::configs::{ContextConfig, ContextStrategy},
+        service::context::ContextManager,
+    };
+
+    fn message(role: Role, msg: &str) -> ChatEvent {
+        ChatEvent::new(
+            "session".to_string(),
+            None,
+            chat_event::Payload::Message(Message {
+                role: role as i32,
+                msg: msg.to_string(),
+                response_ref: None,
+            }),
+        )
+    }
+
+    /// Returns `turns` user and assistant message pairs of 10 tokens each.
+    fn history(turns: usize) -> Vec<ChatEvent> {
+        (0..turns)
+            .flat_map(|i| {
+                [
+                    message(Role::User, &format!("{i:0>24}")),
+                    message(Role::Assistant, &format!("{i:0>24}")),
+                ]
+            })
+            .collect()
+    }
+
+    fn msgs(payloads: &[chat_event::Payload]) -> Vec<String> {
+        payloads
+            .iter()
+            .filter_map(|p| match p {
+                chat_event::Payload::Message(m) => Some(m.msg.trim_start_matches('0').to_string()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn fit() {
+        struct Case {
+            description: &'static str,
+            strategy: ContextStrategy,
+            turns: usize,
+            max_context_tokens: usize,
+            summary_through: Option<usize>,
+            expected_msgs: Vec<&'static str>,
+            expected_trimmed: usize,
+        }
+        let cases = vec![
+            Case {
+                description: "history fits",
+                strategy: ContextStrategy::DropOldest,
+                turns: 3,
+                max_context_tokens: 60,
+                summary_through: None,
+                expected_msgs: vec!["", "", "1", "1", "2", "2"],
+                expected_trimmed: 0,
+            },
+            Case {
+                description: "drop oldest turns",
+                strategy: ContextStrategy::DropOldest,
+                turns: 4,
+                max_context_tokens: 40,
+                summary_through: None,
+                expected_msgs: vec!["2", "2", "3", "3"],
+                expected_trimmed: 4,
+            },
+            Case {
+                description: "keep the last turn even if it does not fit",
+                strategy: ContextStrategy::DropOldest,
+                turns: 2,
+                max_context_tokens: 10,
+                summary_through: None,
+                expected_msgs: vec!["1", "1"],
+                expected_trimmed: 2,
+            },
+            Case {
+                description: "keep ends drops turns in between",
+                strategy: ContextStrategy::KeepEnds,
+                turns: 5,
+                max_context_tokens: 60,
+                summary_through: None,
+                expected_msgs: vec!["", "", "3", "3", "4", "4"],
+                expected_trimmed: 4,
+            },
+            Case {
+                description: "summarize trims all turns before the last ones at once",
+                strategy: ContextStrategy::Summarize,
+                turns: 5,
+                max_context_tokens: 80,
+                summary_through: None,
+                expected_msgs: vec!["3", "3", "4", "4"],
+                expected_trimmed: 6,
+            },
+            Case {
+                description: "history before summary is replaced",
+                strategy: ContextStrategy::Summarize,
+                turns: 5,
+                max_context_tokens: 80,
+                summary_through: Some(3),
+                expected_msgs: vec!["2", "2", "3", "3", "4", "4"],
+                expected_trimmed: 0,
+            },
+        ];
+
+        for case in cases {
+            let manager = ContextManager::new(ContextConfig {
+                strategy: case.strategy,
+                keep_first: 1,
+                keep_last: 2,
+                reserve_tokens: 0,
+                max_context_tokens: Some(case.max_context_tokens),
+            });
+            let mut events = history(case.turns);
+            if let Some(n) = case.summary_through {
+                let last_event_id = events[n].id.clone();
+                events.push(ChatEvent::new(
+                    "session".to_string(),
+                    None,
+                    chat_event::Payload::ContextTrim(ContextTrim {
+                        trimmed_events: n as u32 + 1,
+                        summary: "summary".to_string(),
+                        last_event_id,
+                    }),
+                ));
+            }
+
+            let fitted = manager.fit(&events, &LlmSettings::default());
+            assert_eq!(
+                msgs(&fitted.events),
+                case.expected_msgs,
+                "{} events",
+                case.description
+            );
+            assert_eq!(
+                fitted.trimmed.len(),
+                case.expected_trimmed,
+                "{} trimmed",
+                case.description
+            );
+            assert_eq!(
+                fitted.summary.is_some(),
+                case.summary_through.is_some(),
+                "{} summary",
+                case.description
+            );
+        }
+    }
+}
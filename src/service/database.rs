@@ -1,8 +1,15 @@
 use color_eyre::Result;
-use color_eyre::eyre::eyre;
-use rusqlite::Connection;
+use color_eyre::eyre::{Context as _, eyre};
+use rusqlite::{Connection, Transaction};
+use std::fs::{File, TryLockError};
 use std::sync::mpsc::{self, Sender};
-use std::{path::PathBuf, thread::JoinHandle};
+use std::{
+    path::{Path, PathBuf},
+    thread::JoinHandle,
+    time::Duration,
+};
+
+use crate::service::stores::chat_session_store::ChatSessionStoreImpl;
 
 pub type Job = Box<dyn FnOnce(&mut Connection) + Send + 'static>;
 
@@ -11,6 +18,8 @@ pub struct DBWorker {
     handle: Option<JoinHandle<()>>,
     /// DB job sender.
     job_tx: Option<Sender<Job>>,
+    /// Lock of the database file, released after db thread finished.
+    _lock: Option<File>,
 }
 
 impl Drop for DBWorker {
@@ -30,9 +39,24 @@ impl DBWorker {
             .expect("DBWorker sender is missing")
             .clone()
     }
+
+    /// Waits up to `timeout` for queued jobs to finish without blocking the runtime. The thread
+    /// only exits once every sender, e.g. those held by stores, is dropped.
+    pub async fn close(mut self, timeout: Duration) -> Result<()> {
+        self.job_tx.take();
+        let Some(handle) = self.handle.take() else {
+            return Ok(());
+        };
+        match tokio::time::timeout(timeout, tokio::task::spawn_blocking(move || handle.join()))
+            .await
+        {
+            Ok(joined) => joined?.map_err(|_| eyre!("DB thread panicked")),
+            Err(_) => Err(eyre!("DB jobs did not finish in {timeout:?}")),
+        }
+    }
 }
 
-pub fn spawn_db_thread(mut conn: Connection) -> DBWorker {
+pub fn spawn_db_thread(mut conn: Connection, lock: Option<File>) -> DBWorker {
     let (job_tx, job_rx) = mpsc::channel::<Job>();
 
     let db_thread_handle = std::thread::spawn(move || {
@@ -44,24 +68,113 @@ pub fn spawn_db_thread(mut conn: Connection) -> DBWorker {
     DBWorker {
         handle: Some(db_thread_handle),
         job_tx: Some(job_tx),
+        _lock: lock,
     }
 }
 
 // embed schema
 const SCHEMA_SQL: &str = include_str!("./database/schema.sql");
-pub fn get_db_conn() -> Result<Connection> {
+/// Changes to the schema applied in order, the number applied is kept in `user_version`.
+const MIGRATIONS: &[fn(&Transaction) -> Result<()>] = &[
+    migrate_session_tags,
+    migrate_session_summary_columns,
+    migrate_session_trash,
+    migrate_proxy_conversations,
+];
+
+/// Opens the database once its lock is taken, see `lock_db`.
+pub fn get_db_conn() -> Result<(Connection, File)> {
     let db_path = get_db_path()?;
     if let Some(dir) = db_path.parent() {
         std::fs::create_dir_all(dir)?;
     }
+    let lock = lock_db(&db_path)?;
     // Open by default disables per-connection mutex.
     let conn = Connection::open(db_path)?;
+    Ok((init_db_conn(conn)?, lock))
+}
+
+/// Fails if another service owns the database, without taking it.
+pub fn check_db_lock() -> Result<()> {
+    let db_path = get_db_path()?;
+    if let Some(dir) = db_path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    lock_db(&db_path).map(drop)
+}
 
+/// Takes exclusive lock of a file next to the database at `db_path`, held until the returned
+/// file is dropped. Services sharing a database would both resume its pending responses and purge
+/// its trash, with separate workers per session.
+fn lock_db(db_path: &Path) -> Result<File> {
+    let mut lock_path = db_path.as_os_str().to_owned();
+    lock_path.push(".lock");
+    let lock = File::options()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&lock_path)
+        .wrap_err_with(|| format!("failed to open {}", Path::new(&lock_path).display()))?;
+    match lock.try_lock() {
+        Ok(()) => Ok(lock),
+        Err(TryLockError::WouldBlock) => Err(eyre!(
+            "database {} is used by another cookie service, stop `cookie serve`, the daemon or \
+             the tui running it in process first",
+            db_path.display()
+        )),
+        Err(TryLockError::Error(e)) => Err(e).wrap_err("failed to lock database"),
+    }
+}
+
+/// Enables foreign keys, creates schema on `conn` and migrates it to the latest version.
+pub fn init_db_conn(mut conn: Connection) -> Result<Connection> {
     conn.pragma_update(None, "foreign_keys", "ON")?;
     conn.execute_batch(SCHEMA_SQL)?;
+    migrate(&mut conn)?;
     Ok(conn)
 }
 
+/// Applies migrations not yet applied, each in its own transaction.
+fn migrate(conn: &mut Connection) -> Result<()> {
+    let version: i64 = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    for (i, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        let version = i as i64 + 1;
+        let tx = conn.transaction()?;
+        migration(&tx).wrap_err_with(|| format!("failed to migrate db to version {version}"))?;
+        tx.pragma_update(None, "user_version", version)?;
+        tx.commit()?;
+    }
+    Ok(())
+}
+
+/// Adds tags and columns queried by the session filter.
+fn migrate_session_tags(tx: &Transaction) -> Result<()> {
+    tx.execute_batch(include_str!("./database/migrations/001_session_tags.sql"))?;
+    Ok(())
+}
+
+/// Adds columns of session summaries and fills all session columns.
+fn migrate_session_summary_columns(tx: &Transaction) -> Result<()> {
+    tx.execute_batch(include_str!(
+        "./database/migrations/002_session_summary_columns.sql"
+    ))?;
+    ChatSessionStoreImpl::backfill_columns(tx)
+}
+
+/// Adds when sessions were moved to trash.
+fn migrate_session_trash(tx: &Transaction) -> Result<()> {
+    tx.execute_batch(include_str!("./database/migrations/003_session_trash.sql"))?;
+    Ok(())
+}
+
+/// Adds sessions of proxied conversations.
+fn migrate_proxy_conversations(tx: &Transaction) -> Result<()> {
+    tx.execute_batch(include_str!(
+        "./database/migrations/004_proxy_conversations.sql"
+    ))?;
+    Ok(())
+}
+
 /// Returns the DB path (using $XDG_DATA_HOME if exists or the platform’s standard local data
 /// directory).
 fn get_db_path() -> Result<PathBuf> {
@@ -73,3 +186,61 @@ fn get_db_path() -> Result<PathBuf> {
         .or_else(|_| dirs::data_local_dir().ok_or_else(|| eyre!("failed to get local data dir")))?;
     Ok(config_dir.join(COOKIE_DB_FILE))
 }
+
+#[cfg(test)]
+mod tests {
+    use prost::Message as _;
+    use rusqlite::Connection;
+
+    use crate::{
+        chat::ChatSession,
+        service::database::{MIGRATIONS, SCHEMA_SQL, init_db_conn, lock_db},
+    };
+
+    #[test]
+    fn lock_db_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("sqlite.db");
+        let lock = lock_db(&db_path).unwrap();
+        let err = lock_db(&db_path).unwrap_err();
+        assert!(err.to_string().contains("used by another cookie service"));
+        drop(lock);
+        lock_db(&db_path).unwrap();
+    }
+
+    #[test]
+    fn migrate_db_of_earlier_version() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(SCHEMA_SQL).unwrap();
+        let chat_session = ChatSession {
+            id: "session".to_string(),
+            title: "Old chat".to_string(),
+            pinned: true,
+            ..Default::default()
+        };
+        conn.execute(
+            "INSERT INTO chat_sessions (id, data, updated_at) VALUES (?1, ?2, 0)",
+            (&chat_session.id, chat_session.encode_to_vec()),
+        )
+        .unwrap();
+
+        let conn = init_db_conn(conn).unwrap();
+        let version: usize = conn
+            .pragma_query_value(None, "user_version", |row| row.get(0))
+            .unwrap();
+        assert_eq!(version, MIGRATIONS.len());
+        let columns: (String, bool) = conn
+            .query_row("SELECT title, pinned FROM chat_sessions", [], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .unwrap();
+        assert_eq!(
+            columns,
+            ("Old chat".to_string(), true),
+            "columns are backfilled"
+        );
+
+        // migrations are applied once
+        init_db_conn(conn).unwrap();
+    }
+}
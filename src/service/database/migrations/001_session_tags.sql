@@ -0,0 +1,17 @@
+-- columns of ChatSession proto message queried by the session filter
+ALTER TABLE chat_sessions ADD COLUMN title TEXT NOT NULL DEFAULT '';
+ALTER TABLE chat_sessions ADD COLUMN model TEXT NOT NULL DEFAULT '';
+
+CREATE TABLE tags (
+    id           INTEGER primary key,
+    name         TEXT NOT NULL UNIQUE COLLATE NOCASE
+);
+
+CREATE TABLE session_tags (
+	-- uuid
+    session_id   TEXT NOT NULL REFERENCES chat_sessions(id) ON DELETE CASCADE,
+    tag_id       INTEGER NOT NULL REFERENCES tags(id) ON DELETE CASCADE,
+    PRIMARY KEY (session_id, tag_id)
+);
+
+CREATE INDEX session_tags_tag_id ON session_tags(tag_id);
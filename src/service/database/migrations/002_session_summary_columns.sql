@@ -0,0 +1,8 @@
+-- columns of ChatSession proto message listed in the sidebar without decoding data
+ALTER TABLE chat_sessions ADD COLUMN pinned INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE chat_sessions ADD COLUMN archived INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE chat_sessions ADD COLUMN source TEXT NOT NULL DEFAULT '';
+
+-- sidebar order and cursor
+CREATE INDEX chat_sessions_order ON chat_sessions(pinned DESC, updated_at DESC, id DESC);
+CREATE INDEX chat_events_session_id ON chat_events(session_id);
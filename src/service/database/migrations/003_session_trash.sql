@@ -0,0 +1,4 @@
+-- unix seconds (UTC) the session was moved to trash, NULL if it is not in trash
+ALTER TABLE chat_sessions ADD COLUMN deleted_at INTEGER;
+
+CREATE INDEX chat_sessions_deleted_at ON chat_sessions(deleted_at);
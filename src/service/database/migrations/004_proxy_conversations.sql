@@ -0,0 +1,10 @@
+CREATE TABLE proxy_conversations (
+    -- hash of the turns of a proxied conversation or the id of its last response
+    key          TEXT primary key,
+    -- uuid
+    session_id   TEXT NOT NULL REFERENCES chat_sessions(id) ON DELETE CASCADE,
+    -- unix seconds (UTC)
+    created_at   INTEGER NOT NULL DEFAULT (strftime('%s', 'now'))
+);
+
+CREATE INDEX proxy_conversations_session_id ON proxy_conversations(session_id);
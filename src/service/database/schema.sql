@@ -18,3 +18,14 @@ CREATE TABLE IF NOT EXISTS chat_events (
     data         BLOB NOT NULL,
     created_at   INTEGER NOT NULL DEFAULT (strftime('%s', 'now'))
 );
+
+CREATE TABLE IF NOT EXISTS pending_responses (
+    -- provider response id
+    id           TEXT primary key,
+    -- uuid
+    session_id   TEXT NOT NULL REFERENCES chat_sessions(id) ON DELETE CASCADE,
+    -- full ChatEvent proto message with PendingResponse payload
+    data         BLOB NOT NULL,
+    -- unix seconds (UTC)
+    updated_at   INTEGER NOT NULL DEFAULT (strftime('%s', 'now'))
+);
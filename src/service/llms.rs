@@ -1,16 +1,33 @@
+pub mod mock;
 pub mod open_ai;
 
 use async_trait::async_trait;
-use color_eyre::eyre::{Context, Result, eyre};
+use color_eyre::eyre::{Result, eyre};
 use futures_util::stream::BoxStream;
-use std::sync::Arc;
+use std::{collections::HashMap, sync::Arc};
 
-use crate::{chat::*, llm::*, service::llms::open_ai::OpenAIClientImpl};
+use crate::{
+    chat::*,
+    llm::*,
+    models::{
+        configs::Config,
+        settings::{PROVIDERS, ProviderKind, ProviderStatus},
+    },
+    service::llms::{mock::MockLlmClient, open_ai::OpenAIClientImpl},
+};
 
 #[async_trait]
-pub trait LlmClient {
+pub trait LlmClient: Send + Sync {
     async fn request(&self, llm_req: LlmReq) -> Result<LlmResp>;
     async fn stream(&self, llm_req: LlmReq) -> Result<BoxStream<'static, chat_event::Payload>>;
+    /// Resumes stream of background response after its last handled event.
+    async fn resume(
+        &self,
+        settings: LlmSettings,
+        pending_response: PendingResponse,
+    ) -> Result<BoxStream<'static, chat_event::Payload>>;
+    /// Cancels background response `response_id`.
+    async fn cancel(&self, settings: LlmSettings, response_id: &str) -> Result<()>;
 }
 
 #[derive(Debug, Clone)]
@@ -24,19 +41,78 @@ pub struct LlmResp {
     pub output: Vec<chat_event::Payload>,
 }
 
-#[derive(Clone)]
+/// Routes llm requests to provider clients registered by `LlmSettings.provider` variant.
+#[derive(Clone, Default)]
 pub struct LlmClientRouter {
-    open_ai: Arc<OpenAIClientImpl>,
+    clients: HashMap<ProviderKind, Arc<dyn LlmClient>>,
+    /// Reasons of providers failed to build.
+    unavailable: HashMap<ProviderKind, String>,
 }
 
 impl LlmClientRouter {
-    pub fn build() -> Result<Self> {
+    /// Builds a client for each provider from `config`. Providers failed to build, e.g. missing
+    /// credentials, are disabled and reported in `providers`.
+    pub async fn build(config: &Config) -> Self {
         let client = reqwest::Client::new();
-        let open_ai_key = std::env::var("OPENAI_API_KEY")
-            .wrap_err("set the OPENAI_API_KEY environment variable")?;
+        let mut router = Self::default();
+        for provider in PROVIDERS {
+            let built: Result<Arc<dyn LlmClient>> = match provider {
+                ProviderKind::OpenAi => config
+                    .open_ai
+                    .api_key
+                    .resolve("OPENAI_API_KEY")
+                    .await
+                    .map(|key| Arc::new(OpenAIClientImpl::new(client.clone(), key)) as _),
+                ProviderKind::Mock => match &config.mock {
+                    Some(mock) => MockLlmClient::load(&mock.fixture).map(|c| Arc::new(c) as _),
+                    // development only provider is left out unless configured
+                    None => continue,
+                },
+            };
+            match built {
+                Ok(llm_client) => router.register(*provider, llm_client),
+                Err(e) => {
+                    tracing::warn!("{} is unavailable: {e:#}", provider.display_name());
+                    router.unavailable.insert(*provider, format!("{e:#}"));
+                }
+            }
+        }
+        router
+    }
+
+    pub fn register(&mut self, provider: ProviderKind, llm_client: Arc<dyn LlmClient>) {
+        self.unavailable.remove(&provider);
+        self.clients.insert(provider, llm_client);
+    }
+
+    /// Returns status of configured providers.
+    pub fn providers(&self) -> Vec<ProviderStatus> {
+        PROVIDERS
+            .iter()
+            .filter_map(|provider| {
+                let error = match self.unavailable.get(provider) {
+                    Some(error) => Some(error.clone()),
+                    None if self.clients.contains_key(provider) => None,
+                    None => return None,
+                };
+                Some(ProviderStatus {
+                    provider: *provider,
+                    error,
+                })
+            })
+            .collect()
+    }
 
-        Ok(Self {
-            open_ai: Arc::new(OpenAIClientImpl::new(client, open_ai_key)),
+    fn client(&self, settings: &LlmSettings) -> Result<&Arc<dyn LlmClient>> {
+        let provider = settings
+            .provider_kind()
+            .ok_or_else(|| eyre!("Llm settings does not specify provider"))?;
+        self.clients.get(&provider).ok_or_else(|| {
+            let reason = self
+                .unavailable
+                .get(&provider)
+                .map_or("not configured", String::as_str);
+            eyre!("{} is unavailable: {reason}", provider.display_name())
         })
     }
 }
@@ -44,20 +120,24 @@ impl LlmClientRouter {
 #[async_trait]
 impl LlmClient for LlmClientRouter {
     async fn request(&self, llm_req: LlmReq) -> Result<LlmResp> {
-        match llm_req.settings.provider {
-            Some(llm_settings::Provider::OpenAi { .. }) => {
-                return self.open_ai.request(llm_req).await;
-            }
-            _ => Err(eyre!("Llm settings does not specify provider")),
-        }
+        self.client(&llm_req.settings)?.request(llm_req).await
     }
 
     async fn stream(&self, llm_req: LlmReq) -> Result<BoxStream<'static, chat_event::Payload>> {
-        match llm_req.settings.provider {
-            Some(llm_settings::Provider::OpenAi { .. }) => {
-                return self.open_ai.stream(llm_req).await;
-            }
-            _ => Err(eyre!("Llm settings does not specify provider")),
-        }
+        self.client(&llm_req.settings)?.stream(llm_req).await
+    }
+
+    async fn resume(
+        &self,
+        settings: LlmSettings,
+        pending_response: PendingResponse,
+    ) -> Result<BoxStream<'static, chat_event::Payload>> {
+        self.client(&settings)?
+            .resume(settings, pending_response)
+            .await
+    }
+
+    async fn cancel(&self, settings: LlmSettings, response_id: &str) -> Result<()> {
+        self.client(&settings)?.cancel(settings, response_id).await
     }
 }
@@ -0,0 +1,300 @@
+use async_trait::async_trait;
+use color_eyre::eyre::{Context, Result, eyre};
+use futures_util::stream::{self, BoxStream, StreamExt};
+use serde::Deserialize;
+use std::{path::Path, time::Duration};
+
+use crate::{
+    chat::*,
+    llm::LlmSettings,
+    service::llms::{LlmClient, LlmReq, LlmResp},
+};
+
+/// Scripted responses played by `MockLlmClient`, loaded from a json fixture such as
+/// `{"responses": [{"when": "weather", "events": [{"delay_ms": 50}, {"delta": "Sunny"}]}]}`.
+#[derive(Deserialize, Clone, Debug, Default)]
+pub struct MockScript {
+    pub responses: Vec<MockResponse>,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct MockResponse {
+    /// Plays this response only if instructions or the last user message contain the text.
+    /// Responses without it match any request.
+    #[serde(default)]
+    pub when: Option<String>,
+    pub events: Vec<MockEvent>,
+    /// Runs as background response of this id, reporting its progress so that it can be resumed
+    /// or cancelled.
+    #[serde(default)]
+    pub background: Option<String>,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum MockEvent {
+    /// Streams a chunk of assistant message.
+    Delta(String),
+    /// Emits a completed web search call.
+    WebSearch { id: String, query: String },
+    /// Waits before the next event.
+    DelayMs(u64),
+    /// Holds the stream until it is dropped, like a stalled provider. Resumed background
+    /// responses pass it, as if they went on at the provider meanwhile.
+    Hold,
+    /// Fails the request if nothing is streamed yet, otherwise ends the stream like a dropped
+    /// connection.
+    Error(String),
+}
+
+/// Llm client playing scripted responses, for offline development and tests.
+#[derive(Clone)]
+pub struct MockLlmClient {
+    script: MockScript,
+}
+
+impl MockLlmClient {
+    pub fn new(script: MockScript) -> Self {
+        Self { script }
+    }
+
+    /// Loads script from json fixture at `path`.
+    pub fn load(path: &Path) -> Result<Self> {
+        let script = std::fs::read_to_string(path)
+            .wrap_err_with(|| format!("failed to read mock fixture {}", path.display()))?;
+        let script = serde_json::from_str(&script)
+            .wrap_err_with(|| format!("failed to parse mock fixture {}", path.display()))?;
+        Ok(Self::new(script))
+    }
+
+    /// Returns the first response matching `llm_req`.
+    fn response(&self, llm_req: &LlmReq) -> Result<&MockResponse> {
+        let instructions = llm_req.instructions.as_deref().unwrap_or_default();
+        let user_message = llm_req
+            .events
+            .iter()
+            .rev()
+            .find_map(|payload| match payload {
+                chat_event::Payload::Message(m) if m.role() == Role::User => Some(m.msg.as_str()),
+                _ => None,
+            })
+            .unwrap_or_default();
+        self.script
+            .responses
+            .iter()
+            .find(|resp| match &resp.when {
+                Some(when) => instructions.contains(when) || user_message.contains(when),
+                None => true,
+            })
+            .ok_or_else(|| eyre!("no mock response matches {user_message:?}"))
+    }
+
+    fn background_response(&self, response_id: &str) -> Result<&MockResponse> {
+        self.script
+            .responses
+            .iter()
+            .find(|resp| resp.background.as_deref() == Some(response_id))
+            .ok_or_else(|| eyre!("mock response {response_id} not found"))
+    }
+}
+
+fn web_search_call(id: String, query: String) -> chat_event::Payload {
+    chat_event::Payload::ToolEvent(ToolEvent {
+        event: Some(tool_event::Event::WebSearchCall(
+            tool_event::WebSearchCall {
+                id,
+                status: "completed".to_string(),
+                action_json: serde_json::json!({"type": "search", "query": query}).to_string(),
+            },
+        )),
+    })
+}
+
+fn assistant_message(msg: String) -> chat_event::Payload {
+    chat_event::Payload::Message(Message {
+        role: Role::Assistant as i32,
+        msg,
+        response_ref: None,
+    })
+}
+
+fn pending_response(response_id: &str, status: &str, sequence_number: u64) -> chat_event::Payload {
+    chat_event::Payload::PendingResponse(PendingResponse {
+        response_id: response_id.to_string(),
+        status: status.to_string(),
+        sequence_number,
+    })
+}
+
+/// Plays `response` after its first `skip` events and emits the full message at the end.
+/// Background responses report progress at the start and once done, so they are resumed from the
+/// start like open ai responses without persisted events.
+fn play(
+    response: &MockResponse,
+    skip: usize,
+    resumed: bool,
+) -> BoxStream<'static, chat_event::Payload> {
+    let msg: String = response
+        .events
+        .iter()
+        .filter_map(|e| match e {
+            MockEvent::Delta(delta) => Some(delta.as_str()),
+            _ => None,
+        })
+        .collect();
+    let background = response.background.as_deref();
+    let start = background.map(|id| pending_response(id, "in_progress", 0));
+    let mut ending = vec![assistant_message(msg)];
+    ending.extend(background.map(|id| pending_response(id, "completed", 0)));
+    let events = response
+        .events
+        .clone()
+        .into_iter()
+        .skip(skip)
+        .filter(move |e| !(resumed && matches!(e, MockEvent::Hold)));
+    let stream = stream::unfold(
+        (events, ending.into_iter()),
+        |(mut events, mut ending)| async move {
+            loop {
+                let payload = match events.next() {
+                    Some(MockEvent::Delta(delta)) => {
+                        chat_event::Payload::MessageDelta(MessageDelta { delta })
+                    }
+                    Some(MockEvent::WebSearch { id, query }) => web_search_call(id, query),
+                    Some(MockEvent::DelayMs(ms)) => {
+                        tokio::time::sleep(Duration::from_millis(ms)).await;
+                        continue;
+                    }
+                    Some(MockEvent::Hold) => std::future::pending().await,
+                    // dropped connection, background response stays pending
+                    Some(MockEvent::Error(e)) => {
+                        tracing::error!("stream error: {e}");
+                        return None;
+                    }
+                    None => ending.next()?,
+                };
+                return Some((payload, (events, ending)));
+            }
+        },
+    );
+    stream::iter(start).chain(stream).boxed()
+}
+
+#[async_trait]
+impl LlmClient for MockLlmClient {
+    async fn request(&self, llm_req: LlmReq) -> Result<LlmResp> {
+        let mut output = Vec::new();
+        let mut msg = String::new();
+        for event in self.response(&llm_req)?.events.clone() {
+            match event {
+                MockEvent::Delta(delta) => msg.push_str(&delta),
+                MockEvent::WebSearch { id, query } => output.push(web_search_call(id, query)),
+                MockEvent::DelayMs(ms) => tokio::time::sleep(Duration::from_millis(ms)).await,
+                MockEvent::Hold => std::future::pending().await,
+                MockEvent::Error(e) => return Err(eyre!(e)),
+            }
+        }
+        output.push(assistant_message(msg));
+        Ok(LlmResp { output })
+    }
+
+    async fn stream(&self, llm_req: LlmReq) -> Result<BoxStream<'static, chat_event::Payload>> {
+        let response = self.response(&llm_req)?;
+        let mut events = response.events.iter().peekable();
+        // fail the request on errors before any output
+        let mut skip = 0;
+        while let Some(event) =
+            events.next_if(|e| matches!(e, MockEvent::DelayMs(_) | MockEvent::Hold))
+        {
+            match event {
+                MockEvent::DelayMs(ms) => tokio::time::sleep(Duration::from_millis(*ms)).await,
+                _ => std::future::pending().await,
+            }
+            skip += 1;
+        }
+        if let Some(MockEvent::Error(e)) = events.peek() {
+            return Err(eyre!(e.clone()));
+        }
+        Ok(play(response, skip, false))
+    }
+
+    async fn resume(
+        &self,
+        _settings: LlmSettings,
+        pending_response: PendingResponse,
+    ) -> Result<BoxStream<'static, chat_event::Payload>> {
+        let response = self.background_response(&pending_response.response_id)?;
+        Ok(play(
+            response,
+            pending_response.sequence_number as usize,
+            true,
+        ))
+    }
+
+    async fn cancel(&self, _settings: LlmSettings, response_id: &str) -> Result<()> {
+        self.background_response(response_id)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::StreamExt;
+
+    use crate::{
+        chat::*,
+        llm::*,
+        service::llms::{
+            LlmClient, LlmReq,
+            mock::{MockLlmClient, MockScript},
+        },
+    };
+
+    fn llm_req(msg: &str) -> LlmReq {
+        LlmReq {
+            events: vec![chat_event::Payload::Message(Message {
+                role: Role::User as i32,
+                msg: msg.to_string(),
+                response_ref: None,
+            })],
+            settings: LlmSettings::default(),
+            instructions: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn stream() {
+        let script: MockScript = serde_json::from_str(
+            r#"{"responses": [
+                {"when": "fail", "events": [{"delay_ms": 1}, {"error": "boom"}]},
+                {"when": "drop", "events": [{"delta": "Hi"}, {"error": "dropped"}]},
+                {"events": [{"web_search": {"id": "ws", "query": "q"}}, {"delta": "Hi"}, {"delta": "!"}]}
+            ]}"#,
+        )
+        .unwrap();
+        let client = MockLlmClient::new(script);
+
+        assert!(client.stream(llm_req("fail")).await.is_err());
+
+        let payloads: Vec<_> = client
+            .stream(llm_req("drop"))
+            .await
+            .unwrap()
+            .collect()
+            .await;
+        assert_eq!(payloads.len(), 1);
+
+        let payloads: Vec<_> = client
+            .stream(llm_req("hello"))
+            .await
+            .unwrap()
+            .collect()
+            .await;
+        assert!(matches!(payloads[0], chat_event::Payload::ToolEvent(_)));
+        assert!(matches!(
+            payloads.last(),
+            Some(chat_event::Payload::Message(m)) if m.msg == "Hi!"
+        ));
+        assert_eq!(payloads.len(), 4);
+    }
+}
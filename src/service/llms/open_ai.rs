@@ -1,4 +1,6 @@
 pub mod api;
+#[cfg(test)]
+mod cassette;
 
 use std::io::Write;
 
@@ -11,24 +13,50 @@ use futures_util::{
 
 use crate::{
     chat::{self, *},
+    llm::LlmSettings,
     service::{
         llms::{LlmClient, LlmReq, LlmResp, open_ai::api::ResponsesStream},
-        utils,
+        utils::{self, HttpError},
     },
 };
-use api::{ContentItem, OutputItem, Responses, ResponsesReq};
+use api::{ContentItem, Model, OutputItem, Responses, ResponsesReq, open_ai_settings};
 
+/// Returns whether `e` rejects `previous_response_id` because the response expired or was never
+/// stored, other errors such as rate limits are not fixed by resending history.
+fn is_previous_response_not_found(e: &color_eyre::Report) -> bool {
+    e.downcast_ref::<HttpError>().is_some_and(|e| {
+        matches!(e.status.as_u16(), 400 | 404)
+            && e.code().as_deref() == Some("previous_response_not_found")
+    })
+}
+
+#[derive(Clone)]
 pub struct OpenAIClientImpl {
     client: reqwest::Client,
     api_key: String,
+    /// Base url requests are sent to, e.g. a local server replaying recorded responses in tests.
+    host: String,
 }
 
 #[async_trait]
 impl LlmClient for OpenAIClientImpl {
     async fn request(&self, llm_req: LlmReq) -> Result<LlmResp> {
+        let chained = ResponsesReq::build_chained(&llm_req)?;
         let req = ResponsesReq::build(llm_req)?;
         // tracing::debug!(model=?req.model, input=?req.input);
-        let resp = self.responses(req).await?;
+        // fall back to replaying history if the stored response is gone
+        let resp = match chained {
+            Some(chained) => match self.responses(&chained).await {
+                Ok(resp) => resp,
+                Err(e) if is_previous_response_not_found(&e) => {
+                    tracing::warn!("failed to continue stored response: {e:?}");
+                    self.responses(&req).await?
+                }
+                Err(e) => return Err(e),
+            },
+            None => self.responses(&req).await?,
+        };
+        let response_ref = req.response_ref(&resp.id);
 
         let mut chat_events: Vec<chat_event::Payload> = Vec::new();
         for output in &resp.output {
@@ -49,6 +77,7 @@ impl LlmClient for OpenAIClientImpl {
                     chat_events.push(chat_event::Payload::Message(Message {
                         role: chat::Role::from(role) as i32,
                         msg,
+                        response_ref: response_ref.clone(),
                     }));
                 }
                 OutputItem::WebSearchCall { action, id, status } => {
@@ -74,10 +103,111 @@ impl LlmClient for OpenAIClientImpl {
     }
 
     async fn stream(&self, llm_req: LlmReq) -> Result<BoxStream<'static, chat_event::Payload>> {
+        let chained = ResponsesReq::build_chained(&llm_req)?.map(ResponsesReq::with_streaming);
         let req = ResponsesReq::build(llm_req)?.with_streaming();
         tracing::debug!(model=?req.model, input=?req.input);
-        let stream = self.stream_responses(req).await?;
-        let event_stream = stream
+        // fall back to replaying history if the stored response is gone
+        let stream = match chained {
+            Some(chained) => match self.stream_responses(&chained).await {
+                Ok(stream) => stream,
+                Err(e) if is_previous_response_not_found(&e) => {
+                    tracing::warn!("failed to continue stored response: {e:?}");
+                    self.stream_responses(&req).await?
+                }
+                Err(e) => return Err(e),
+            },
+            None => self.stream_responses(&req).await?,
+        };
+
+        let state = StreamState::new(req.model.clone(), req.store, req.background);
+        let stream = if req.background {
+            self.resumable(stream, None, None)
+        } else {
+            stream
+        };
+        Ok(state.into_payloads(stream))
+    }
+
+    async fn resume(
+        &self,
+        settings: LlmSettings,
+        pending_response: PendingResponse,
+    ) -> Result<BoxStream<'static, chat_event::Payload>> {
+        let model: Model = open_ai_settings(&settings)?.model().into();
+        // replay from the start if nothing is handled yet
+        let starting_after =
+            (pending_response.sequence_number > 0).then_some(pending_response.sequence_number);
+        let response_id = pending_response.response_id.clone();
+        let stream = self
+            .get_stream_responses(&response_id, starting_after)
+            .await?;
+
+        let mut state = StreamState::new(model, true, true);
+        state.set_pending_response(pending_response);
+        Ok(state.into_payloads(self.resumable(stream, Some(response_id), starting_after)))
+    }
+
+    async fn cancel(&self, _settings: LlmSettings, response_id: &str) -> Result<()> {
+        utils::post::<serde_json::Value, Responses>(
+            &self.client,
+            format!("{}/v1/responses/{response_id}/cancel", self.host),
+            self.api_key.clone(),
+            &serde_json::json!({}),
+        )
+        .await?;
+        Ok(())
+    }
+}
+
+/// Converts response stream events into chat event payloads. Tracks the stored response to
+/// reference from assistant messages and progress of background responses.
+struct StreamState {
+    model: Model,
+    store: bool,
+    background: bool,
+    response_ref: Option<ResponseRef>,
+    pending_response: Option<PendingResponse>,
+}
+
+impl StreamState {
+    fn new(model: Model, store: bool, background: bool) -> Self {
+        Self {
+            model,
+            store,
+            background,
+            response_ref: None,
+            pending_response: None,
+        }
+    }
+
+    /// Sets background response to continue tracking, e.g. on resuming it.
+    fn set_pending_response(&mut self, pending_response: PendingResponse) {
+        self.response_ref = Some(ResponseRef {
+            id: pending_response.response_id.clone(),
+            model: self.model.name(),
+        });
+        self.pending_response = Some(pending_response);
+    }
+
+    fn update_response(&mut self, response: &Responses) {
+        if self.store && !response.id.is_empty() {
+            self.response_ref = Some(ResponseRef {
+                id: response.id.clone(),
+                model: self.model.name(),
+            });
+        }
+        if self.background {
+            let pending_response = self.pending_response.get_or_insert_default();
+            pending_response.response_id = response.id.clone();
+            pending_response.status = response.status.clone();
+        }
+    }
+
+    fn into_payloads(
+        mut self,
+        stream: BoxStream<'static, Result<ResponsesStream>>,
+    ) -> BoxStream<'static, chat_event::Payload> {
+        stream
             .filter_map(|res| async move {
                 match res {
                     Ok(resp) => Some(resp),
@@ -87,86 +217,433 @@ impl LlmClient for OpenAIClientImpl {
                     }
                 }
             })
-            .flat_map(|resp| {
-                let payloads = match resp {
-                    ResponsesStream::OutputTextDelta(d) => {
-                        vec![chat_event::Payload::MessageDelta(MessageDelta {
-                            delta: d.delta,
-                        })]
-                    }
-                    ResponsesStream::OutputItemDone(d) => match d.item {
-                        OutputItem::WebSearchCall { action, .. } => {
-                            tracing::debug!("web search call {action}");
-                            Vec::new()
+            .flat_map(move |resp| stream::iter(self.payloads(resp)))
+            .boxed()
+    }
+
+    /// Returns payloads of `resp`. For background responses, progress is reported after each
+    /// event that is persisted so that it is resumed after the last persisted event.
+    fn payloads(&mut self, resp: ResponsesStream) -> Vec<chat_event::Payload> {
+        let sequence_number = resp.sequence_number();
+        let (mut payloads, report_progress) = match resp {
+            ResponsesStream::Created(u)
+            | ResponsesStream::Queued(u)
+            | ResponsesStream::InProgress(u) => {
+                self.update_response(&u.response);
+                (Vec::new(), true)
+            }
+            ResponsesStream::OutputTextDelta(d) => (
+                vec![chat_event::Payload::MessageDelta(MessageDelta {
+                    delta: d.delta,
+                })],
+                false,
+            ),
+            ResponsesStream::OutputItemDone(d) => {
+                if let OutputItem::WebSearchCall { action, .. } = d.item {
+                    tracing::debug!("web search call {action}");
+                }
+                (Vec::new(), false)
+            }
+            ResponsesStream::OutputTextDone(d) => (
+                vec![chat_event::Payload::Message(Message {
+                    role: Role::Assistant as i32,
+                    msg: d.text,
+                    response_ref: self.response_ref.clone(),
+                })],
+                true,
+            ),
+            // handle web search call here since streaming does not contain action payload
+            ResponsesStream::Completed(u) => {
+                self.update_response(&u.response);
+                let payloads = u
+                    .response
+                    .output
+                    .into_iter()
+                    .filter_map(|output| {
+                        if let OutputItem::WebSearchCall { action, id, status } = output {
+                            tracing::info!("web search call action {action}");
+                            Some(chat_event::Payload::ToolEvent(ToolEvent {
+                                event: Some(tool_event::Event::WebSearchCall(
+                                    tool_event::WebSearchCall {
+                                        id,
+                                        status,
+                                        action_json: action.to_string(),
+                                    },
+                                )),
+                            }))
+                        } else {
+                            None
                         }
-                        _ => Vec::new(),
-                    },
-                    ResponsesStream::OutputTextDone(d) => {
-                        vec![chat_event::Payload::Message(Message {
-                            role: Role::Assistant as i32,
-                            msg: d.text,
-                        })]
-                    }
-                    // handle web search call here since streaming does not contain action payload
-                    ResponsesStream::Completed { response } => response
-                        .output
-                        .into_iter()
-                        .filter_map(|output| {
-                            if let OutputItem::WebSearchCall { action, id, status } = output {
-                                tracing::info!("web search call action {action}");
-                                Some(chat_event::Payload::ToolEvent(ToolEvent {
-                                    event: Some(tool_event::Event::WebSearchCall(
-                                        tool_event::WebSearchCall {
-                                            id,
-                                            status,
-                                            action_json: action.to_string(),
-                                        },
-                                    )),
-                                }))
-                            } else {
-                                None
-                            }
-                        })
-                        .collect(),
-                    _ => Vec::new(),
-                };
-                stream::iter(payloads)
-            })
-            .boxed();
+                    })
+                    .collect();
+                (payloads, true)
+            }
+            ResponsesStream::Failed(u)
+            | ResponsesStream::Cancelled(u)
+            | ResponsesStream::Incomplete(u) => {
+                self.update_response(&u.response);
+                (Vec::new(), true)
+            }
+            ResponsesStream::Unimplement => (Vec::new(), false),
+        };
 
-        Ok(event_stream)
+        if report_progress && let Some(pending_response) = &mut self.pending_response {
+            if let Some(sequence_number) = sequence_number {
+                pending_response.sequence_number = sequence_number;
+            }
+            payloads.push(chat_event::Payload::PendingResponse(
+                pending_response.clone(),
+            ));
+        }
+        payloads
     }
 }
 
+/// Background response stream that resumes after the last received event on dropped connection.
+struct ResumableStream {
+    client: OpenAIClientImpl,
+    stream: BoxStream<'static, Result<ResponsesStream>>,
+    response_id: Option<String>,
+    sequence_number: Option<u64>,
+    attempts: u32,
+    done: bool,
+}
+
 impl OpenAIClientImpl {
     const OPENAI_HOST: &str = "https://api.openai.com";
+    /// Max attempts in a row to resume a dropped background response stream.
+    const MAX_RESUME_ATTEMPTS: u32 = 3;
 
     pub fn new(client: reqwest::Client, api_key: String) -> Self {
-        Self { client, api_key }
+        Self {
+            client,
+            api_key,
+            host: Self::OPENAI_HOST.to_string(),
+        }
+    }
+
+    #[cfg(test)]
+    pub fn with_host(mut self, host: String) -> Self {
+        self.host = host;
+        self
     }
 
-    async fn responses(&self, req: ResponsesReq) -> Result<Responses> {
+    async fn responses(&self, req: &ResponsesReq) -> Result<Responses> {
         let resp = utils::post::<ResponsesReq, Responses>(
             &self.client,
-            format!("{}/v1/responses", Self::OPENAI_HOST),
+            format!("{}/v1/responses", self.host),
             self.api_key.clone(),
-            &req,
+            req,
         )
         .await?;
         Ok(resp)
     }
 
+    /// Opens response stream, fails if the first event is an error, e.g. on non success status.
     async fn stream_responses(
         &self,
-        req: ResponsesReq,
+        req: &ResponsesReq,
     ) -> Result<BoxStream<'static, Result<ResponsesStream>>> {
-        let stream = utils::post_stream::<ResponsesReq, ResponsesStream>(
+        let mut stream = utils::post_stream::<ResponsesReq, ResponsesStream>(
             &self.client,
-            format!("{}/v1/responses", Self::OPENAI_HOST),
+            format!("{}/v1/responses", self.host),
             self.api_key.clone(),
-            &req,
+            req,
         )
         .await?;
-        Ok(stream)
+        match stream.next().await {
+            Some(Err(e)) => Err(e),
+            Some(Ok(first)) => Ok(stream::once(async { Ok(first) }).chain(stream).boxed()),
+            None => Ok(stream::empty().boxed()),
+        }
+    }
+
+    /// Streams events of stored response `response_id` after `starting_after`.
+    async fn get_stream_responses(
+        &self,
+        response_id: &str,
+        starting_after: Option<u64>,
+    ) -> Result<BoxStream<'static, Result<ResponsesStream>>> {
+        let mut url = format!("{}/v1/responses/{response_id}?stream=true", self.host);
+        if let Some(starting_after) = starting_after {
+            url.push_str(&format!("&starting_after={starting_after}"));
+        }
+        utils::get_stream::<ResponsesStream>(&self.client, url, self.api_key.clone()).await
+    }
+
+    /// Wraps background response `stream` to resume it after the last received event if the
+    /// connection drops before the response is done.
+    fn resumable(
+        &self,
+        stream: BoxStream<'static, Result<ResponsesStream>>,
+        response_id: Option<String>,
+        sequence_number: Option<u64>,
+    ) -> BoxStream<'static, Result<ResponsesStream>> {
+        let state = ResumableStream {
+            client: self.clone(),
+            stream,
+            response_id,
+            sequence_number,
+            attempts: 0,
+            done: false,
+        };
+        stream::unfold(state, |mut state| async move {
+            if state.done {
+                return None;
+            }
+            loop {
+                let end = match state.stream.next().await {
+                    Some(Ok(event)) => {
+                        state.attempts = 0;
+                        if let Some(sequence_number) = event.sequence_number() {
+                            state.sequence_number = Some(sequence_number);
+                        }
+                        if let ResponsesStream::Created(u) = &event {
+                            state.response_id = Some(u.response.id.clone());
+                        }
+                        state.done = event.is_terminal();
+                        return Some((Ok(event), state));
+                    }
+                    end => end,
+                };
+
+                let Some(response_id) = state.response_id.clone() else {
+                    state.done = true;
+                    return end.map(|err| (err, state));
+                };
+                if state.attempts >= Self::MAX_RESUME_ATTEMPTS {
+                    state.done = true;
+                    return end.map(|err| (err, state));
+                }
+                state.attempts += 1;
+                tracing::warn!(
+                    "response {response_id} stream dropped, resuming after {:?}",
+                    state.sequence_number
+                );
+                tokio::time::sleep(std::time::Duration::from_secs(1 << state.attempts)).await;
+                match state
+                    .client
+                    .get_stream_responses(&response_id, state.sequence_number)
+                    .await
+                {
+                    Ok(stream) => state.stream = stream,
+                    Err(e) => tracing::warn!("failed to resume response {response_id}: {e:?}"),
+                }
+            }
+        })
+        .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::StreamExt;
+
+    use crate::{
+        chat::*,
+        llm::*,
+        service::{
+            llms::{
+                LlmClient, LlmReq,
+                open_ai::{
+                    StreamState,
+                    api::{Model, ResponsesStream},
+                    cassette::CassetteServer,
+                    is_previous_response_not_found,
+                },
+            },
+            utils::HttpError,
+        },
+    };
+
+    fn message(role: Role, msg: &str, response_ref: Option<ResponseRef>) -> chat_event::Payload {
+        chat_event::Payload::Message(Message {
+            role: role as i32,
+            msg: msg.to_string(),
+            response_ref,
+        })
+    }
+
+    fn web_search_call(id: &str, query: &str) -> chat_event::Payload {
+        chat_event::Payload::ToolEvent(ToolEvent {
+            event: Some(tool_event::Event::WebSearchCall(
+                tool_event::WebSearchCall {
+                    id: id.to_string(),
+                    status: "completed".to_string(),
+                    action_json: serde_json::json!({"type": "search", "query": query}).to_string(),
+                },
+            )),
+        })
+    }
+
+    fn llm_req(store: bool, events: Vec<chat_event::Payload>) -> LlmReq {
+        LlmReq {
+            events,
+            settings: LlmSettings {
+                provider: Some(llm_settings::Provider::OpenAi(OpenAiSettings {
+                    model: OpenAiModel::Gpt4o as i32,
+                    web_search: true,
+                    store,
+                    ..Default::default()
+                })),
+                ..Default::default()
+            },
+            instructions: None,
+        }
+    }
+
+    fn response_ref(id: &str) -> Option<ResponseRef> {
+        Some(ResponseRef {
+            id: id.to_string(),
+            model: "gpt-4o".to_string(),
+        })
+    }
+
+    #[tokio::test]
+    async fn request_falls_back_to_history() {
+        let server = CassetteServer::start("responses_fallback").await.unwrap();
+        let events = vec![
+            message(Role::User, "What's new in Rust?", None),
+            message(
+                Role::Assistant,
+                "Rust 1.88 is out.",
+                response_ref("resp_68a1f0c2"),
+            ),
+            message(Role::User, "And in 1.89?", None),
+        ];
+        let resp = server
+            .client()
+            .request(llm_req(true, events))
+            .await
+            .unwrap();
+        server.finish().await.unwrap();
+
+        let expected = vec![
+            web_search_call("ws_68a1f3da", "Rust 1.89 release"),
+            message(
+                Role::Assistant,
+                "Rust 1.89 stabilized explicitly inferred const arguments. ([blog.rust-lang.org](https://blog.rust-lang.org/2025/08/07/Rust-1.89.0/))",
+                response_ref("resp_68a1f3d9"),
+            ),
+        ];
+        assert_eq!(resp.output, expected);
+    }
+
+    #[test]
+    fn falls_back_only_if_previous_response_not_found() {
+        let error = |status: u16, code: &str| {
+            color_eyre::Report::new(HttpError {
+                status: reqwest::StatusCode::from_u16(status).unwrap(),
+                body: serde_json::json!({"error": {"code": code}}).to_string(),
+            })
+        };
+        assert!(is_previous_response_not_found(&error(
+            400,
+            "previous_response_not_found"
+        )));
+        assert!(is_previous_response_not_found(&error(
+            404,
+            "previous_response_not_found"
+        )));
+        assert!(!is_previous_response_not_found(&error(
+            429,
+            "rate_limit_exceeded"
+        )));
+        assert!(!is_previous_response_not_found(&error(500, "server_error")));
+        assert!(!is_previous_response_not_found(&color_eyre::eyre::eyre!(
+            "failed to send request"
+        )));
+    }
+
+    #[tokio::test]
+    async fn stream() {
+        let server = CassetteServer::start("responses_stream").await.unwrap();
+        let events = vec![message(
+            Role::User,
+            "What is the latest Rust release?",
+            None,
+        )];
+        let payloads: Vec<_> = server
+            .client()
+            .stream(llm_req(false, events))
+            .await
+            .unwrap()
+            .collect()
+            .await;
+        server.finish().await.unwrap();
+
+        let expected = vec![
+            chat_event::Payload::MessageDelta(MessageDelta {
+                delta: "Rust 1.89 ".to_string(),
+            }),
+            chat_event::Payload::MessageDelta(MessageDelta {
+                delta: "is the latest release.".to_string(),
+            }),
+            message(Role::Assistant, "Rust 1.89 is the latest release.", None),
+            web_search_call("ws_68a1f5e1", "latest Rust release"),
+        ];
+        assert_eq!(payloads, expected);
+    }
+
+    #[test]
+    fn background_stream_payloads() {
+        let events = [
+            r#"{"type":"response.created","sequence_number":0,"response":{"id":"resp_1","status":"queued","output":[]}}"#,
+            r#"{"type":"response.in_progress","sequence_number":1,"response":{"id":"resp_1","status":"in_progress","output":[]}}"#,
+            r#"{"type":"response.output_text.delta","sequence_number":2,"output_index":0,"delta":"hi"}"#,
+            r#"{"type":"response.output_text.done","sequence_number":3,"output_index":0,"text":"hi"}"#,
+            r#"{"type":"response.completed","sequence_number":4,"response":{"id":"resp_1","status":"completed","output":[]}}"#,
+        ];
+
+        let mut state = StreamState::new(Model::O3, true, true);
+        let payloads: Vec<chat_event::Payload> = events
+            .iter()
+            .map(|e| serde_json::from_str::<ResponsesStream>(e).unwrap())
+            .flat_map(|resp| state.payloads(resp))
+            .collect();
+
+        let pending = |status: &str, sequence_number: u64| {
+            chat_event::Payload::PendingResponse(PendingResponse {
+                response_id: "resp_1".to_string(),
+                status: status.to_string(),
+                sequence_number,
+            })
+        };
+        let expected = vec![
+            pending("queued", 0),
+            pending("in_progress", 1),
+            chat_event::Payload::MessageDelta(MessageDelta {
+                delta: "hi".to_string(),
+            }),
+            chat_event::Payload::Message(Message {
+                role: Role::Assistant as i32,
+                msg: "hi".to_string(),
+                response_ref: Some(ResponseRef {
+                    id: "resp_1".to_string(),
+                    model: "o3-deep-research".to_string(),
+                }),
+            }),
+            pending("in_progress", 3),
+            pending("completed", 4),
+        ];
+        assert_eq!(payloads, expected);
+    }
+
+    #[test]
+    fn terminal_events() {
+        let update = |kind: &str, status: &str| {
+            serde_json::from_value::<ResponsesStream>(serde_json::json!({
+                "type": kind,
+                "sequence_number": 0,
+                "response": {"id": "resp_1", "status": status, "output": []},
+            }))
+            .unwrap()
+        };
+        assert!(update("response.completed", "completed").is_terminal());
+        assert!(update("response.cancelled", "cancelled").is_terminal());
+        assert!(update("response.incomplete", "incomplete").is_terminal());
+        // resuming a response cancelled meanwhile replays its updates
+        assert!(update("response.in_progress", "cancelled").is_terminal());
+        assert!(!update("response.in_progress", "in_progress").is_terminal());
     }
 }
@@ -16,6 +16,22 @@ pub struct ResponsesReq {
     pub input: Vec<InputItem>,
     pub stream: bool,
     pub tools: Vec<Tool>,
+    /// Whether to store the response so that follow up requests can continue from it.
+    pub store: bool,
+    /// Stored response to continue from, `input` then only contains new input after it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub previous_response_id: Option<String>,
+    /// Whether to run the response in background so that it survives dropped connections.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub background: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_output_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub truncation: Option<Truncation>,
 }
 
 impl ResponsesReq {
@@ -25,19 +41,19 @@ impl ResponsesReq {
     }
 
     pub fn build(llm_req: LlmReq) -> Result<Self> {
-        let (model, web_search) = match llm_req.settings.provider {
-            Some(llm_settings::Provider::OpenAi(open_ai_settings)) => {
-                (open_ai_settings.model(), open_ai_settings.web_search)
-            }
-            _ => return Err(eyre!("Client and settings do not match")),
-        };
+        let settings = open_ai_settings(&llm_req.settings)?;
 
         let mut tools = vec![];
-        if web_search {
+        if settings.web_search {
             tools.push(Tool::WebSearch);
         }
+        // drop parameters the model does not support, e.g. after switching to a reasoning model
+        let supports_sampling = settings.model().supports_sampling();
+        let model: Model = settings.model().into();
+        // background responses are always stored
+        let background = model.runs_in_background();
         Ok(ResponsesReq {
-            model: model.into(),
+            model,
             instructions: llm_req.instructions,
             input: llm_req
                 .events
@@ -46,10 +62,77 @@ impl ResponsesReq {
                 .collect(),
             stream: false,
             tools,
+            store: settings.store || background,
+            previous_response_id: None,
+            background,
+            temperature: settings.temperature.filter(|_| supports_sampling),
+            top_p: settings.top_p.filter(|_| supports_sampling),
+            max_output_tokens: settings.max_output_tokens,
+            truncation: settings.truncation().into(),
+        })
+    }
+
+    /// Builds request that continues from the stored response of the last assistant message and
+    /// only sends user input after it. Returns None if the chain is broken, e.g. responses are not
+    /// stored, the model changed or history after the response is not new user input.
+    pub fn build_chained(llm_req: &LlmReq) -> Result<Option<Self>> {
+        let settings = open_ai_settings(&llm_req.settings)?;
+        if !settings.store {
+            return Ok(None);
+        }
+
+        let Some(idx) = llm_req.events.iter().rposition(|payload| {
+            matches!(payload, chat_event::Payload::Message(m) if m.role() == chat::Role::Assistant)
+        }) else {
+            return Ok(None);
+        };
+        let chat_event::Payload::Message(Message {
+            response_ref: Some(response_ref),
+            ..
+        }) = &llm_req.events[idx]
+        else {
+            return Ok(None);
+        };
+        let model: Model = settings.model().into();
+        if response_ref.id.is_empty() || response_ref.model != model.name() {
+            return Ok(None);
+        }
+
+        // tool events of the stored response are followed by new user input
+        let new_events: Vec<chat_event::Payload> = llm_req.events[idx + 1..]
+            .iter()
+            .skip_while(|payload| matches!(payload, chat_event::Payload::ToolEvent(_)))
+            .cloned()
+            .collect();
+        let is_user_input = |payload: &chat_event::Payload| matches!(payload, chat_event::Payload::Message(m) if m.role() == chat::Role::User);
+        if new_events.is_empty() || !new_events.iter().all(is_user_input) {
+            return Ok(None);
+        }
+
+        let mut req = Self::build(LlmReq {
+            events: new_events,
+            ..llm_req.clone()
+        })?;
+        req.previous_response_id = Some(response_ref.id.clone());
+        Ok(Some(req))
+    }
+
+    /// Returns reference to response `id` produced by this request if it is stored.
+    pub fn response_ref(&self, id: &str) -> Option<ResponseRef> {
+        (self.store && !id.is_empty()).then(|| ResponseRef {
+            id: id.to_string(),
+            model: self.model.name(),
         })
     }
 }
 
+pub fn open_ai_settings(settings: &LlmSettings) -> Result<OpenAiSettings> {
+    match settings.provider {
+        Some(llm_settings::Provider::OpenAi(open_ai_settings)) => Ok(open_ai_settings),
+        _ => Err(eyre!("Client and settings do not match")),
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "lowercase")]
 pub enum Role {
@@ -62,7 +145,7 @@ impl From<chat::Role> for Role {
         match value {
             chat::Role::Unspecified => Role::User,
             chat::Role::User => Role::User,
-            chat::Role::Assistant => Role::User,
+            chat::Role::Assistant => Role::Assistant,
         }
     }
 }
@@ -71,7 +154,7 @@ impl From<&Role> for chat::Role {
     fn from(value: &Role) -> Self {
         match value {
             Role::User => chat::Role::User,
-            Role::Assistant => chat::Role::User,
+            Role::Assistant => chat::Role::Assistant,
         }
     }
 }
@@ -106,7 +189,9 @@ impl From<&chat_event::Payload> for Option<InputItem> {
                 role: message.role().into(),
                 content: message.msg.clone(),
             }),
-            chat_event::Payload::MessageDelta(_) => None,
+            chat_event::Payload::MessageDelta(_)
+            | chat_event::Payload::ContextTrim(_)
+            | chat_event::Payload::PendingResponse(_) => None,
             chat_event::Payload::ToolEvent(tool_event) => match &tool_event.event {
                 Some(tool_event::Event::WebSearchCall(wsc)) => Some(InputItem::WebSearchCall {
                     id: wsc.id.clone(),
@@ -147,6 +232,21 @@ pub enum Model {
     O3Mini,
 }
 
+impl Model {
+    /// Returns model name as sent to api.
+    pub fn name(&self) -> String {
+        serde_json::to_value(self)
+            .ok()
+            .and_then(|v| v.as_str().map(str::to_string))
+            .unwrap_or_default()
+    }
+
+    /// Returns whether responses of the model run in background, e.g. long deep research runs.
+    pub fn runs_in_background(&self) -> bool {
+        matches!(self, Model::O3)
+    }
+}
+
 impl From<OpenAiModel> for Model {
     fn from(value: OpenAiModel) -> Self {
         match value {
@@ -172,8 +272,39 @@ impl From<&Model> for OpenAiModel {
     }
 }
 
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Truncation {
+    Auto,
+    Disabled,
+}
+
+impl From<OpenAiTruncation> for Option<Truncation> {
+    fn from(value: OpenAiTruncation) -> Self {
+        match value {
+            OpenAiTruncation::Unspecified => None,
+            OpenAiTruncation::Auto => Some(Truncation::Auto),
+            OpenAiTruncation::Disabled => Some(Truncation::Disabled),
+        }
+    }
+}
+
+impl From<Truncation> for OpenAiTruncation {
+    fn from(value: Truncation) -> Self {
+        match value {
+            Truncation::Auto => OpenAiTruncation::Auto,
+            Truncation::Disabled => OpenAiTruncation::Disabled,
+        }
+    }
+}
+
 #[derive(Deserialize, Debug)]
 pub struct Responses {
+    #[serde(default)]
+    pub id: String,
+    /// One of queued, in_progress, completed, failed, cancelled or incomplete.
+    #[serde(default)]
+    pub status: String,
     pub output: Vec<OutputItem>,
 }
 
@@ -227,15 +358,19 @@ pub enum Annotation {
 #[serde(tag = "type")]
 pub enum ResponsesStream {
     #[serde(rename = "response.created")]
-    Created { response: Responses },
+    Created(ResponseUpdate),
+    #[serde(rename = "response.queued")]
+    Queued(ResponseUpdate),
     #[serde(rename = "response.in_progress")]
-    InProgress { response: Responses },
+    InProgress(ResponseUpdate),
     #[serde(rename = "response.completed")]
-    Completed { response: Responses },
+    Completed(ResponseUpdate),
     #[serde(rename = "response.failed")]
-    Failed { response: Responses },
+    Failed(ResponseUpdate),
+    #[serde(rename = "response.cancelled")]
+    Cancelled(ResponseUpdate),
     #[serde(rename = "response.incomplete")]
-    Incomplete { response: Responses },
+    Incomplete(ResponseUpdate),
     #[serde(rename = "response.output_item.done")]
     OutputItemDone(OutputItemDone),
     #[serde(rename = "response.output_text.delta")]
@@ -246,6 +381,50 @@ pub enum ResponsesStream {
     Unimplement,
 }
 
+impl ResponsesStream {
+    /// Returns sequence number of the event to resume stream after.
+    pub fn sequence_number(&self) -> Option<u64> {
+        match self {
+            ResponsesStream::Created(u)
+            | ResponsesStream::Queued(u)
+            | ResponsesStream::InProgress(u)
+            | ResponsesStream::Completed(u)
+            | ResponsesStream::Failed(u)
+            | ResponsesStream::Cancelled(u)
+            | ResponsesStream::Incomplete(u) => Some(u.sequence_number),
+            ResponsesStream::OutputItemDone(d) => Some(d.common.sequence_number),
+            ResponsesStream::OutputTextDelta(d) => Some(d.common.sequence_number),
+            ResponsesStream::OutputTextDone(d) => Some(d.common.sequence_number),
+            ResponsesStream::Unimplement => None,
+        }
+    }
+
+    /// Returns whether the event ends the response, including updates of a response already
+    /// done, e.g. one cancelled before its stream is resumed.
+    pub fn is_terminal(&self) -> bool {
+        match self {
+            ResponsesStream::Completed(_)
+            | ResponsesStream::Failed(_)
+            | ResponsesStream::Cancelled(_)
+            | ResponsesStream::Incomplete(_) => true,
+            ResponsesStream::Created(u)
+            | ResponsesStream::Queued(u)
+            | ResponsesStream::InProgress(u) => matches!(
+                u.response.status.as_str(),
+                "completed" | "failed" | "cancelled" | "incomplete"
+            ),
+            _ => false,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ResponseUpdate {
+    #[serde(default)]
+    pub sequence_number: u64,
+    pub response: Responses,
+}
+
 #[derive(Deserialize, Debug)]
 pub struct StreamCommon {
     pub sequence_number: u64,
@@ -278,3 +457,162 @@ pub struct OutputTextDone {
     pub common: StreamCommon,
     pub text: String,
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        chat::*,
+        llm::*,
+        service::llms::{
+            LlmReq,
+            open_ai::api::{self, ResponsesReq},
+        },
+    };
+
+    fn message(role: Role, msg: &str, response_ref: Option<(&str, &str)>) -> chat_event::Payload {
+        chat_event::Payload::Message(Message {
+            role: role as i32,
+            msg: msg.to_string(),
+            response_ref: response_ref.map(|(id, model)| ResponseRef {
+                id: id.to_string(),
+                model: model.to_string(),
+            }),
+        })
+    }
+
+    fn web_search_call() -> chat_event::Payload {
+        chat_event::Payload::ToolEvent(ToolEvent {
+            event: Some(tool_event::Event::WebSearchCall(
+                tool_event::WebSearchCall::default(),
+            )),
+        })
+    }
+
+    fn settings(store: bool) -> LlmSettings {
+        LlmSettings {
+            provider: Some(llm_settings::Provider::OpenAi(OpenAiSettings {
+                model: OpenAiModel::Gpt4o as i32,
+                web_search: false,
+                store,
+                ..Default::default()
+            })),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn map_roles() {
+        let llm_req = LlmReq {
+            events: vec![
+                message(Role::User, "q1", None),
+                message(Role::Assistant, "a1", None),
+                message(Role::User, "q2", None),
+            ],
+            settings: settings(false),
+            instructions: None,
+        };
+        let req = serde_json::to_value(ResponsesReq::build(llm_req).unwrap()).unwrap();
+        let roles: Vec<&str> = req["input"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|item| item["role"].as_str().unwrap())
+            .collect();
+        assert_eq!(roles, vec!["user", "assistant", "user"]);
+
+        assert_eq!(Role::from(&api::Role::Assistant), Role::Assistant);
+        assert_eq!(Role::from(&api::Role::User), Role::User);
+    }
+
+    #[test]
+    fn build_chained() {
+        struct Case {
+            description: &'static str,
+            store: bool,
+            events: Vec<chat_event::Payload>,
+            expected_previous_response_id: Option<&'static str>,
+            expected_input: usize,
+        }
+        let cases = vec![
+            Case {
+                description: "continue from stored response",
+                store: true,
+                events: vec![
+                    message(Role::User, "q1", None),
+                    message(Role::Assistant, "a1", Some(("resp_1", "gpt-4o"))),
+                    web_search_call(),
+                    message(Role::User, "q2", None),
+                ],
+                expected_previous_response_id: Some("resp_1"),
+                expected_input: 1,
+            },
+            Case {
+                description: "responses are not stored",
+                store: false,
+                events: vec![
+                    message(Role::User, "q1", None),
+                    message(Role::Assistant, "a1", Some(("resp_1", "gpt-4o"))),
+                    message(Role::User, "q2", None),
+                ],
+                expected_previous_response_id: None,
+                expected_input: 0,
+            },
+            Case {
+                description: "model changed",
+                store: true,
+                events: vec![
+                    message(Role::User, "q1", None),
+                    message(Role::Assistant, "a1", Some(("resp_1", "o4-mini"))),
+                    message(Role::User, "q2", None),
+                ],
+                expected_previous_response_id: None,
+                expected_input: 0,
+            },
+            Case {
+                description: "last assistant message is not stored",
+                store: true,
+                events: vec![
+                    message(Role::User, "q1", None),
+                    message(Role::Assistant, "a1", Some(("resp_1", "gpt-4o"))),
+                    message(Role::User, "q2", None),
+                    message(Role::Assistant, "a2", None),
+                    message(Role::User, "q3", None),
+                ],
+                expected_previous_response_id: None,
+                expected_input: 0,
+            },
+            Case {
+                description: "no new user input",
+                store: true,
+                events: vec![
+                    message(Role::User, "q1", None),
+                    message(Role::Assistant, "a1", Some(("resp_1", "gpt-4o"))),
+                ],
+                expected_previous_response_id: None,
+                expected_input: 0,
+            },
+        ];
+
+        for case in cases {
+            let llm_req = LlmReq {
+                events: case.events,
+                settings: settings(case.store),
+                instructions: None,
+            };
+
+            let req = ResponsesReq::build_chained(&llm_req).unwrap();
+            assert_eq!(
+                req.as_ref().and_then(|r| r.previous_response_id.as_deref()),
+                case.expected_previous_response_id,
+                "{}",
+                case.description
+            );
+            assert_eq!(
+                req.map_or(0, |r| r.input.len()),
+                case.expected_input,
+                "{}",
+                case.description
+            );
+        }
+    }
+}
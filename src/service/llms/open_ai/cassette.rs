@@ -0,0 +1,270 @@
+//! Cassettes of recorded open ai request and response pairs, replayed from a local server that
+//! the client points at so that response parsing is tested without network access.
+//!
+//! Set `COOKIE_RECORD_CASSETTES=1` and `OPENAI_API_KEY` to record cassettes again from the api.
+//! Recorded requests and responses are scrubbed of api keys and organization or project ids
+//! before they are written. The checked in cassettes are written by hand after the api format
+//! until they are recorded with a key.
+
+use color_eyre::eyre::{Context as _, Result, bail};
+use regex::Regex;
+use reqwest::header::{AUTHORIZATION, CONTENT_TYPE};
+use serde::{Deserialize, Serialize};
+use std::{path::PathBuf, sync::Arc};
+use tokio::{
+    io::BufReader,
+    net::{TcpListener, TcpStream},
+    sync::Mutex,
+    task::JoinHandle,
+};
+
+use crate::{server::http, service::llms::open_ai::OpenAIClientImpl};
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct Cassette {
+    pub interactions: Vec<Interaction>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Interaction {
+    pub request: RecordedRequest,
+    pub response: RecordedResponse,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct RecordedRequest {
+    pub method: String,
+    /// Path with query, e.g. `/v1/responses/resp_1?stream=true`.
+    pub path: String,
+    /// Json body, null if the request has none.
+    #[serde(default)]
+    pub body: serde_json::Value,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RecordedResponse {
+    pub status: u16,
+    pub content_type: String,
+    /// Raw body, event stream text for streamed responses.
+    pub body: String,
+}
+
+/// Replaces secrets in recorded text.
+const REDACTED: &str = "REDACTED";
+
+enum Mode {
+    Replay,
+    Record { client: reqwest::Client },
+}
+
+/// Returns `text` with the api key of `authorization` and anything shaped like an api key,
+/// organization or project id redacted.
+fn scrub(text: &str, authorization: Option<&str>) -> String {
+    let mut text = text.to_string();
+    if let Some(key) = authorization
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .filter(|key| !key.is_empty())
+    {
+        text = text.replace(key, REDACTED);
+    }
+    let secrets = Regex::new(r"\b(sk-[A-Za-z0-9_-]{8,}|org-[A-Za-z0-9]{8,}|proj_[A-Za-z0-9]{8,})")
+        .expect("valid regex");
+    secrets.replace_all(&text, REDACTED).into_owned()
+}
+
+struct Recorder {
+    mode: Mode,
+    cassette: Cassette,
+    /// Index of the next interaction to replay.
+    next: usize,
+}
+
+impl Recorder {
+    async fn respond(
+        &mut self,
+        request: RecordedRequest,
+        authorization: Option<String>,
+    ) -> Result<RecordedResponse> {
+        match &self.mode {
+            Mode::Replay => {
+                let Some(interaction) = self.cassette.interactions.get(self.next) else {
+                    bail!("unexpected request {} {}", request.method, request.path);
+                };
+                if interaction.request != request {
+                    bail!(
+                        "request does not match cassette\nexpected: {}\nactual: {}",
+                        serde_json::to_string(&interaction.request)?,
+                        serde_json::to_string(&request)?
+                    );
+                }
+                self.next += 1;
+                Ok(interaction.response.clone())
+            }
+            Mode::Record { client } => {
+                let url = format!("{}{}", OpenAIClientImpl::OPENAI_HOST, request.path);
+                let mut req = client.request(request.method.parse()?, url);
+                if let Some(authorization) = &authorization {
+                    req = req.header(AUTHORIZATION, authorization);
+                }
+                if !request.body.is_null() {
+                    req = req.json(&request.body);
+                }
+                let resp = req.send().await.wrap_err("failed to send request")?;
+                let status = resp.status().as_u16();
+                let content_type = resp
+                    .headers()
+                    .get(CONTENT_TYPE)
+                    .and_then(|v| v.to_str().ok())
+                    .unwrap_or_default()
+                    .to_string();
+                let body = resp.text().await.wrap_err("failed to read response body")?;
+                let response = RecordedResponse {
+                    status,
+                    content_type,
+                    body,
+                };
+                // keep secrets out of cassettes checked in
+                let authorization = authorization.as_deref();
+                let recorded_body = scrub(&request.body.to_string(), authorization);
+                self.cassette.interactions.push(Interaction {
+                    request: RecordedRequest {
+                        body: serde_json::from_str(&recorded_body)?,
+                        ..request
+                    },
+                    response: RecordedResponse {
+                        body: scrub(&response.body, authorization),
+                        ..response.clone()
+                    },
+                });
+                Ok(response)
+            }
+        }
+    }
+}
+
+/// Local http server replaying cassette `name`, or recording it from the api in record mode.
+pub struct CassetteServer {
+    host: String,
+    path: PathBuf,
+    recorder: Arc<Mutex<Recorder>>,
+    handle: JoinHandle<()>,
+}
+
+impl CassetteServer {
+    pub async fn start(name: &str) -> Result<Self> {
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("src/service/llms/open_ai/cassettes")
+            .join(format!("{name}.json"));
+        let recorder = if std::env::var_os("COOKIE_RECORD_CASSETTES").is_some() {
+            Recorder {
+                mode: Mode::Record {
+                    client: reqwest::Client::new(),
+                },
+                cassette: Cassette::default(),
+                next: 0,
+            }
+        } else {
+            let cassette = std::fs::read_to_string(&path)
+                .wrap_err_with(|| format!("failed to read cassette {}", path.display()))?;
+            Recorder {
+                mode: Mode::Replay,
+                cassette: serde_json::from_str(&cassette)
+                    .wrap_err_with(|| format!("failed to parse cassette {}", path.display()))?,
+                next: 0,
+            }
+        };
+        let recorder = Arc::new(Mutex::new(recorder));
+
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let host = format!("http://{}", listener.local_addr()?);
+        let handle = tokio::spawn({
+            let recorder = recorder.clone();
+            async move {
+                while let Ok((conn, _)) = listener.accept().await {
+                    let recorder = recorder.clone();
+                    tokio::spawn(async move {
+                        if let Err(e) = serve(conn, recorder).await {
+                            tracing::error!("cassette server failed: {e:?}");
+                        }
+                    });
+                }
+            }
+        });
+        Ok(Self {
+            host,
+            path,
+            recorder,
+            handle,
+        })
+    }
+
+    /// Returns client sending requests to this server.
+    pub fn client(&self) -> OpenAIClientImpl {
+        let api_key = std::env::var("OPENAI_API_KEY").unwrap_or_else(|_| "test".to_string());
+        OpenAIClientImpl::new(reqwest::Client::new(), api_key).with_host(self.host.clone())
+    }
+
+    /// Stops server, then writes the recorded cassette or checks all interactions are replayed.
+    pub async fn finish(self) -> Result<()> {
+        self.handle.abort();
+        let recorder = self.recorder.lock().await;
+        match recorder.mode {
+            Mode::Replay => {
+                let remaining = recorder.cassette.interactions.len() - recorder.next;
+                if remaining > 0 {
+                    bail!("{remaining} interactions are not replayed");
+                }
+            }
+            Mode::Record { .. } => {
+                let cassette = serde_json::to_string_pretty(&recorder.cassette)?;
+                std::fs::write(&self.path, cassette + "\n").wrap_err_with(|| {
+                    format!("failed to write cassette {}", self.path.display())
+                })?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Serves a single request on `conn`, responding with an error status if it is not replayable.
+async fn serve(conn: TcpStream, recorder: Arc<Mutex<Recorder>>) -> Result<()> {
+    let mut conn = BufReader::new(conn);
+    let req = http::read_request(&mut conn).await?;
+    let request = RecordedRequest {
+        method: req.method.clone(),
+        path: req.target.clone(),
+        body: if req.body.is_empty() {
+            serde_json::Value::Null
+        } else {
+            req.json()?
+        },
+    };
+    let authorization = req.header("authorization").map(str::to_string);
+    let response = match recorder.lock().await.respond(request, authorization).await {
+        Ok(response) => http::Response {
+            status: response.status,
+            content_type: response.content_type,
+            body: response.body.into_bytes(),
+        },
+        Err(e) => http::Response::error(500, &format!("{e:#}")),
+    };
+    response.write(conn.get_mut()).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::scrub;
+
+    #[test]
+    fn scrub_secrets() {
+        let body = r#"{"error": {"message": "Incorrect API key provided: sk-proj-abcdef123456. You can find your API key at https://platform.openai.com/account/api-keys."}}"#;
+        assert_eq!(
+            scrub(body, Some("Bearer sk-proj-abcdef123456")),
+            r#"{"error": {"message": "Incorrect API key provided: REDACTED. You can find your API key at https://platform.openai.com/account/api-keys."}}"#
+        );
+        assert_eq!(
+            scrub("org-AbCdEf123456 proj_AbCdEf123456 resp_68a1f0c2", None),
+            "REDACTED REDACTED resp_68a1f0c2"
+        );
+    }
+}
@@ -0,0 +1,92 @@
+use color_eyre::{
+    Result,
+    eyre::{Context as _, bail},
+};
+use std::{collections::HashMap, time::Duration};
+use tokio::signal::unix::{SignalKind, signal as unix_signal};
+
+use crate::service::{Service, database::DBWorker};
+
+/// Resolves on SIGINT, SIGTERM or SIGHUP, e.g. when the terminal closes.
+pub async fn signal() {
+    let (Ok(mut terminate), Ok(mut hangup)) = (
+        unix_signal(SignalKind::terminate()),
+        unix_signal(SignalKind::hangup()),
+    ) else {
+        tracing::error!("failed to listen for SIGTERM and SIGHUP");
+        let _ = tokio::signal::ctrl_c().await;
+        return;
+    };
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {}
+        _ = terminate.recv() => {}
+        _ = hangup.recv() => {}
+    }
+}
+
+impl Service {
+    /// Tells workers to save responses they are streaming and waits for them until the shutdown
+    /// timeout, aborting the rest, then waits for db jobs to finish. Fails listing sessions that
+    /// could not be saved.
+    pub(super) async fn shutdown(mut self) -> Result<()> {
+        let timeout = Duration::from_secs(self.worker_config.shutdown_timeout_secs);
+        self.shutdown_tx.send_replace(true);
+        // titles are generated again for sessions without one
+        self.title_tasks.abort_all();
+
+        // workers stopped before shutdown are idle and have nothing to save
+        let session_ids: HashMap<_, _> = self
+            .session_worker_handles
+            .drain()
+            .map(|(session_id, handle)| (handle.task_id(), session_id))
+            .collect();
+        let session_id = |id| {
+            session_ids
+                .get(&id)
+                .cloned()
+                .unwrap_or_else(|| "of a stopped worker".to_string())
+        };
+
+        let mut unsaved = Vec::new();
+        let mut timed_out = false;
+        let deadline = tokio::time::sleep(timeout);
+        tokio::pin!(deadline);
+        loop {
+            tokio::select! {
+                res = self.worker_tasks.join_next_with_id() => match res {
+                    None => break,
+                    Some(Ok((_, Ok(())))) => {}
+                    Some(Ok((id, Err(e)))) => {
+                        let session_id = session_id(id);
+                        tracing::error!("failed to save session {session_id}: {e:?}");
+                        unsaved.push(session_id);
+                    }
+                    // aborted on session delete before shutdown
+                    Some(Err(join_err)) if join_err.is_cancelled() && !timed_out => {}
+                    Some(Err(join_err)) => {
+                        let session_id = session_id(join_err.id());
+                        tracing::error!("failed to save session {session_id}: {join_err:?}");
+                        unsaved.push(session_id);
+                    }
+                },
+                _ = &mut deadline, if !timed_out => {
+                    timed_out = true;
+                    self.worker_tasks.abort_all();
+                }
+            }
+        }
+        while self.title_tasks.join_next().await.is_some() {}
+
+        // stores are dropped with the service so that the db thread exits once its jobs are done
+        let db_res = self.into_db_worker().close(timeout).await;
+        if !unsaved.is_empty() {
+            unsaved.sort();
+            bail!("failed to save sessions {} on shutdown", unsaved.join(", "));
+        }
+        db_res.wrap_err("failed to flush db on shutdown")
+    }
+
+    fn into_db_worker(self) -> DBWorker {
+        self.db_worker
+    }
+}
@@ -1,2 +1,11 @@
 pub mod chat_event_store;
 pub mod chat_session_store;
+pub mod conversation_store;
+pub mod pending_response_store;
+
+/// Items of one page and the cursor to request the next page, None on the last page.
+#[derive(Debug)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub next_cursor: Option<String>,
+}
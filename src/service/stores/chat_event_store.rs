@@ -5,11 +5,22 @@ use rusqlite::Connection;
 use std::sync::mpsc::Sender;
 use tokio::sync::oneshot;
 
-use crate::{chat::ChatEvent, service::database::Job};
+use crate::{
+    chat::ChatEvent,
+    service::{database::Job, stores::Page},
+};
 
 #[async_trait]
 pub trait ChatEventStore: Send + Sync {
     async fn get_chat_events_for_session(&self, session_id: &str) -> Result<Vec<ChatEvent>>;
+    /// Returns a page of the latest events of session before event of id `before`, oldest first.
+    /// The next cursor is the id of the oldest event returned.
+    async fn get_chat_events_page(
+        &self,
+        session_id: &str,
+        before: Option<String>,
+        limit: usize,
+    ) -> Result<Page<ChatEvent>>;
     /// Persists chat event to database and update session store for updated time.
     async fn create_chat_event(&self, chat_event: ChatEvent) -> Result<ChatEvent>;
 }
@@ -43,6 +54,27 @@ impl ChatEventStore for ChatEventStoreImpl {
         resp_rx.await?
     }
 
+    async fn get_chat_events_page(
+        &self,
+        session_id: &str,
+        before: Option<String>,
+        limit: usize,
+    ) -> Result<Page<ChatEvent>> {
+        let (resp_tx, resp_rx) = oneshot::channel();
+
+        let session_id = session_id.to_string();
+
+        let job = Box::new(move |conn: &mut Connection| {
+            let result = Self::get_chat_events_page_internal(conn, session_id, before, limit);
+            let _ = resp_tx.send(result);
+        });
+
+        self.job_tx
+            .send(job)
+            .map_err(|e| eyre!("failed to send job to DB thread: {}", e))?;
+        resp_rx.await?
+    }
+
     async fn create_chat_event(&self, chat_event: ChatEvent) -> Result<ChatEvent> {
         let (resp_tx, resp_rx) = oneshot::channel();
 
@@ -76,6 +108,37 @@ impl ChatEventStoreImpl {
         rows.collect::<Result<Vec<_>, _>>().map_err(Into::into)
     }
 
+    fn get_chat_events_page_internal(
+        conn: &mut Connection,
+        session_id: String,
+        before: Option<String>,
+        limit: usize,
+    ) -> Result<Page<ChatEvent>> {
+        // events are ordered by insertion since created_at is in seconds, one more is fetched to
+        // tell whether there is a next page
+        let mut stmt = conn.prepare(
+            r#"
+            SELECT id, session_id, data, created_at
+             FROM chat_events
+             WHERE session_id = ?1
+               AND (?2 IS NULL OR rowid < (SELECT rowid FROM chat_events WHERE id = ?2))
+             ORDER BY rowid DESC
+             LIMIT ?3
+             "#,
+        )?;
+
+        let rows = stmt.query_map((session_id, before, limit + 1), ChatEvent::from_row)?;
+        let mut items = rows.collect::<Result<Vec<_>, _>>()?;
+        let next_cursor = if items.len() > limit {
+            items.truncate(limit);
+            items.last().map(|e| e.id.clone())
+        } else {
+            None
+        };
+        items.reverse();
+        Ok(Page { items, next_cursor })
+    }
+
     fn create_chat_event_internal(
         conn: &mut Connection,
         chat_event: ChatEvent,
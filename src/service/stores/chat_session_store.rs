@@ -1,20 +1,63 @@
-use crate::{chat::ChatSession, service::database::Job};
+use crate::{
+    chat::ChatSession,
+    models::session_query::SessionQuery,
+    service::{database::Job, stores::Page},
+};
 use async_trait::async_trait;
 use color_eyre::{Result, eyre::eyre};
 use prost::Message;
-use rusqlite::{Connection, OptionalExtension as _};
+use rusqlite::{Connection, OptionalExtension as _, ToSql};
 use std::sync::mpsc::Sender;
 use tokio::sync::oneshot;
 
 #[async_trait]
 pub trait ChatSessionStore: Send + Sync {
-    async fn get_chat_sessions(&self) -> Result<Vec<ChatSession>>;
     async fn get_chat_session(&self, session_id: &str) -> Result<Option<ChatSession>>;
     async fn create_chat_session(&self, chat_session: ChatSession) -> Result<ChatSession>;
     async fn update_chat_session(&self, chat_session: ChatSession) -> Result<ChatSession>;
-    async fn delete_chat_session(&self, session_id: &str) -> Result<()>;
+    /// Moves sessions to trash, or restores them from trash if not `trashed`.
+    async fn trash_chat_sessions(&self, session_ids: Vec<String>, trashed: bool) -> Result<()>;
+    /// Permanently deletes sessions in trash with their events.
+    async fn delete_chat_sessions(&self, session_ids: Vec<String>) -> Result<()>;
+    /// Permanently deletes sessions moved to trash before `deleted_before` unix seconds and returns
+    /// how many were deleted.
+    async fn delete_expired_sessions(&self, deleted_before: i64) -> Result<usize>;
+    /// Returns a page of summaries of sessions matching `query` after `cursor`, pinned first then
+    /// most recently updated. Summaries are read from columns without events and settings.
+    async fn query_chat_sessions(
+        &self,
+        query: SessionQuery,
+        cursor: Option<String>,
+        limit: usize,
+    ) -> Result<Page<ChatSession>>;
+    /// Replaces tags of session and returns the updated session, None if it does not exist.
+    async fn set_session_tags(
+        &self,
+        session_id: &str,
+        tags: Vec<String>,
+    ) -> Result<Option<ChatSession>>;
 }
 
+/// Selects sessions with their tags joined by the unit separator.
+const SELECT_SESSIONS: &str = r#"
+    SELECT data, created_at, updated_at, deleted_at, (
+        SELECT group_concat(tags.name, char(31))
+        FROM session_tags JOIN tags ON tags.id = session_tags.tag_id
+        WHERE session_tags.session_id = chat_sessions.id
+    ) AS tags
+    FROM chat_sessions
+"#;
+
+/// Selects session summaries with their tags joined by the unit separator.
+const SELECT_SUMMARIES: &str = r#"
+    SELECT id, title, pinned, archived, source, created_at, updated_at, deleted_at, (
+        SELECT group_concat(tags.name, char(31))
+        FROM session_tags JOIN tags ON tags.id = session_tags.tag_id
+        WHERE session_tags.session_id = chat_sessions.id
+    ) AS tags
+    FROM chat_sessions
+"#;
+
 pub struct ChatSessionStoreImpl {
     job_tx: Sender<Job>,
 }
@@ -27,11 +70,13 @@ impl ChatSessionStoreImpl {
 
 #[async_trait]
 impl ChatSessionStore for ChatSessionStoreImpl {
-    async fn get_chat_sessions(&self) -> Result<Vec<ChatSession>> {
+    async fn get_chat_session(&self, session_id: &str) -> Result<Option<ChatSession>> {
         let (resp_tx, resp_rx) = oneshot::channel();
 
+        let session_id = session_id.to_string();
+
         let job = Box::new(move |conn: &mut Connection| {
-            let result = Self::get_chat_sessions_internal(conn);
+            let result = Self::get_chat_session_internal(conn, session_id);
             let _ = resp_tx.send(result);
         });
 
@@ -41,13 +86,25 @@ impl ChatSessionStore for ChatSessionStoreImpl {
         resp_rx.await?
     }
 
-    async fn get_chat_session(&self, session_id: &str) -> Result<Option<ChatSession>> {
+    async fn create_chat_session(&self, chat_session: ChatSession) -> Result<ChatSession> {
         let (resp_tx, resp_rx) = oneshot::channel();
 
-        let session_id = session_id.to_string();
+        let job = Box::new(move |conn: &mut Connection| {
+            let result = Self::create_chat_session_internal(conn, chat_session);
+            let _ = resp_tx.send(result);
+        });
+
+        self.job_tx
+            .send(job)
+            .map_err(|e| eyre!("failed to send job to DB thread: {}", e))?;
+        resp_rx.await?
+    }
+
+    async fn update_chat_session(&self, chat_session: ChatSession) -> Result<ChatSession> {
+        let (resp_tx, resp_rx) = oneshot::channel();
 
         let job = Box::new(move |conn: &mut Connection| {
-            let result = Self::get_chat_session_internal(conn, session_id);
+            let result = Self::update_chat_session_internal(conn, chat_session);
             let _ = resp_tx.send(result);
         });
 
@@ -57,11 +114,11 @@ impl ChatSessionStore for ChatSessionStoreImpl {
         resp_rx.await?
     }
 
-    async fn create_chat_session(&self, chat_session: ChatSession) -> Result<ChatSession> {
+    async fn trash_chat_sessions(&self, session_ids: Vec<String>, trashed: bool) -> Result<()> {
         let (resp_tx, resp_rx) = oneshot::channel();
 
         let job = Box::new(move |conn: &mut Connection| {
-            let result = Self::create_chat_session_internal(conn, chat_session);
+            let result = Self::trash_chat_sessions_internal(conn, session_ids, trashed);
             let _ = resp_tx.send(result);
         });
 
@@ -71,11 +128,25 @@ impl ChatSessionStore for ChatSessionStoreImpl {
         resp_rx.await?
     }
 
-    async fn update_chat_session(&self, chat_session: ChatSession) -> Result<ChatSession> {
+    async fn delete_chat_sessions(&self, session_ids: Vec<String>) -> Result<()> {
         let (resp_tx, resp_rx) = oneshot::channel();
 
         let job = Box::new(move |conn: &mut Connection| {
-            let result = Self::update_chat_session_internal(conn, chat_session);
+            let result = Self::delete_chat_sessions_internal(conn, session_ids);
+            let _ = resp_tx.send(result);
+        });
+
+        self.job_tx
+            .send(job)
+            .map_err(|e| eyre!("failed to send job to DB thread: {}", e))?;
+        resp_rx.await?
+    }
+
+    async fn delete_expired_sessions(&self, deleted_before: i64) -> Result<usize> {
+        let (resp_tx, resp_rx) = oneshot::channel();
+
+        let job = Box::new(move |conn: &mut Connection| {
+            let result = Self::delete_expired_sessions_internal(conn, deleted_before);
             let _ = resp_tx.send(result);
         });
 
@@ -85,12 +156,35 @@ impl ChatSessionStore for ChatSessionStoreImpl {
         resp_rx.await?
     }
 
-    async fn delete_chat_session(&self, session_id: &str) -> Result<()> {
+    async fn query_chat_sessions(
+        &self,
+        query: SessionQuery,
+        cursor: Option<String>,
+        limit: usize,
+    ) -> Result<Page<ChatSession>> {
+        let (resp_tx, resp_rx) = oneshot::channel();
+
+        let job = Box::new(move |conn: &mut Connection| {
+            let result = Self::query_chat_sessions_internal(conn, query, cursor, limit);
+            let _ = resp_tx.send(result);
+        });
+
+        self.job_tx
+            .send(job)
+            .map_err(|e| eyre!("failed to send job to DB thread: {}", e))?;
+        resp_rx.await?
+    }
+
+    async fn set_session_tags(
+        &self,
+        session_id: &str,
+        tags: Vec<String>,
+    ) -> Result<Option<ChatSession>> {
         let (resp_tx, resp_rx) = oneshot::channel();
 
         let session_id = session_id.to_string();
         let job = Box::new(move |conn: &mut Connection| {
-            let result = Self::delete_chat_session_internal(conn, session_id);
+            let result = Self::set_session_tags_internal(conn, session_id, tags);
             let _ = resp_tx.send(result);
         });
 
@@ -102,29 +196,11 @@ impl ChatSessionStore for ChatSessionStoreImpl {
 }
 
 impl ChatSessionStoreImpl {
-    fn get_chat_sessions_internal(conn: &mut Connection) -> Result<Vec<ChatSession>> {
-        let mut stmt = conn.prepare(
-            r#"
-            SELECT data, created_at, updated_at
-            FROM chat_sessions
-            ORDER BY created_at ASC
-            "#,
-        )?;
-        let rows = stmt.query_map([], ChatSession::from_row)?;
-        rows.collect::<Result<Vec<_>, _>>().map_err(Into::into)
-    }
-
     fn get_chat_session_internal(
         conn: &mut Connection,
         session_id: String,
     ) -> Result<Option<ChatSession>> {
-        let mut stmt = conn.prepare(
-            r#"
-            SELECT data, created_at, updated_at
-            FROM chat_sessions
-            WHERE id = ?
-            "#,
-        )?;
+        let mut stmt = conn.prepare(&format!("{SELECT_SESSIONS} WHERE id = ?"))?;
         let chat_session = stmt
             .query_row([session_id], ChatSession::from_row)
             .optional()?;
@@ -135,64 +211,320 @@ impl ChatSessionStoreImpl {
         conn: &mut Connection,
         chat_session: ChatSession,
     ) -> Result<ChatSession> {
-        let mut buf = Vec::new();
-        chat_session.encode(&mut buf)?;
-        let mut stmt = conn.prepare(
+        let (buf, model) = Self::encode(&chat_session)?;
+        conn.execute(
             r#"
-            INSERT INTO chat_sessions (id, data, updated_at)
-            VALUES (?1, ?2, strftime('%s', 'now'))
-            RETURNING id, data, created_at, updated_at
+            INSERT INTO chat_sessions
+                (id, data, title, model, pinned, archived, source, updated_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, strftime('%s', 'now'))
             "#,
+            (
+                &chat_session.id,
+                &buf,
+                &chat_session.title,
+                model,
+                chat_session.pinned,
+                chat_session.archived,
+                &chat_session.source,
+            ),
         )?;
-        let session = stmt.query_row((&chat_session.id, &buf), ChatSession::from_row)?;
-        Ok(session)
+        Self::get_chat_session_internal(conn, chat_session.id)?
+            .ok_or_else(|| eyre!("created session not found"))
     }
 
     fn update_chat_session_internal(
         conn: &mut Connection,
         chat_session: ChatSession,
     ) -> Result<ChatSession> {
-        let mut buf = Vec::new();
-        chat_session.encode(&mut buf)?;
-        let mut stmt = conn.prepare(
+        let (buf, model) = Self::encode(&chat_session)?;
+        let updated = conn.execute(
             r#"
             UPDATE chat_sessions
-            SET data = ?1, updated_at = strftime('%s', 'now')
-            WHERE id = ?2
-            RETURNING id, data, created_at, updated_at
+            SET data = ?1, title = ?2, model = ?3, pinned = ?4, archived = ?5, source = ?6,
+                updated_at = strftime('%s', 'now')
+            WHERE id = ?7
             "#,
+            (
+                &buf,
+                &chat_session.title,
+                model,
+                chat_session.pinned,
+                chat_session.archived,
+                &chat_session.source,
+                &chat_session.id,
+            ),
         )?;
-        let session = stmt.query_row((&buf, &chat_session.id), ChatSession::from_row)?;
-        Ok(session)
+        if updated == 0 {
+            return Err(eyre!("session {} not found", chat_session.id));
+        }
+        Self::get_chat_session_internal(conn, chat_session.id)?
+            .ok_or_else(|| eyre!("updated session not found"))
     }
 
-    fn delete_chat_session_internal(conn: &mut Connection, session_id: String) -> Result<()> {
-        let mut stmt = conn.prepare(
-            r#"
-            DELETE FROM chat_sessions
-            WHERE id = ?1
-            "#,
+    fn trash_chat_sessions_internal(
+        conn: &mut Connection,
+        session_ids: Vec<String>,
+        trashed: bool,
+    ) -> Result<()> {
+        let tx = conn.transaction()?;
+        for session_id in &session_ids {
+            tx.execute(
+                r#"
+                UPDATE chat_sessions
+                SET deleted_at = CASE WHEN ?1 THEN strftime('%s', 'now') END
+                WHERE id = ?2
+                "#,
+                (trashed, session_id),
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn delete_chat_sessions_internal(
+        conn: &mut Connection,
+        session_ids: Vec<String>,
+    ) -> Result<()> {
+        let tx = conn.transaction()?;
+        for session_id in &session_ids {
+            tx.execute(
+                r#"
+                DELETE FROM chat_sessions
+                WHERE id = ?1 AND deleted_at IS NOT NULL
+                "#,
+                (session_id,),
+            )?;
+        }
+        Self::delete_unused_tags(&tx)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn delete_expired_sessions_internal(
+        conn: &mut Connection,
+        deleted_before: i64,
+    ) -> Result<usize> {
+        let tx = conn.transaction()?;
+        let deleted = tx.execute(
+            "DELETE FROM chat_sessions WHERE deleted_at < ?1",
+            (deleted_before,),
+        )?;
+        Self::delete_unused_tags(&tx)?;
+        tx.commit()?;
+        Ok(deleted)
+    }
+
+    fn query_chat_sessions_internal(
+        conn: &mut Connection,
+        query: SessionQuery,
+        cursor: Option<String>,
+        limit: usize,
+    ) -> Result<Page<ChatSession>> {
+        let title_pattern = query.title_pattern();
+        let deleted = if query.trash { "NOT NULL" } else { "NULL" };
+        let mut sql = format!(
+            "{SELECT_SUMMARIES} WHERE deleted_at IS {deleted} AND title LIKE ? ESCAPE '\\'"
+        );
+        if !query.include_archived {
+            sql.push_str(" AND archived = 0");
+        }
+        let mut params: Vec<&dyn ToSql> = vec![&title_pattern];
+        let cursor = cursor.as_deref().map(SessionCursor::parse).transpose()?;
+        if let Some(cursor) = &cursor {
+            sql.push_str(" AND (pinned, updated_at, id) < (?, ?, ?)");
+            params.extend([&cursor.pinned as &dyn ToSql, &cursor.updated_at, &cursor.id]);
+        }
+        for tag in &query.tags {
+            sql.push_str(
+                r#"
+                AND EXISTS (
+                    SELECT 1 FROM session_tags JOIN tags ON tags.id = session_tags.tag_id
+                    WHERE session_tags.session_id = chat_sessions.id AND tags.name = ?
+                )"#,
+            );
+            params.push(tag);
+        }
+        for model in &query.models {
+            sql.push_str(" AND model = ? COLLATE NOCASE");
+            params.push(model);
+        }
+        // one more to tell whether there is a next page
+        let limit_plus_one = limit + 1;
+        sql.push_str(" ORDER BY pinned DESC, updated_at DESC, id DESC LIMIT ?");
+        params.push(&limit_plus_one);
+
+        let mut stmt = conn.prepare(&sql)?;
+        let rows = stmt.query_map(params.as_slice(), ChatSession::from_summary_row)?;
+        let mut items = rows.collect::<Result<Vec<_>, _>>()?;
+        let next_cursor = if items.len() > limit {
+            items.truncate(limit);
+            items.last().map(|s| SessionCursor::of(s).to_string())
+        } else {
+            None
+        };
+        Ok(Page { items, next_cursor })
+    }
+
+    fn set_session_tags_internal(
+        conn: &mut Connection,
+        session_id: String,
+        tags: Vec<String>,
+    ) -> Result<Option<ChatSession>> {
+        let tx = conn.transaction()?;
+        tx.execute(
+            "DELETE FROM session_tags WHERE session_id = ?1",
+            (&session_id,),
+        )?;
+        for tag in &tags {
+            tx.execute("INSERT OR IGNORE INTO tags (name) VALUES (?1)", (tag,))?;
+            // ignore the session not existing, it is reported by returning None
+            tx.execute(
+                r#"
+                INSERT OR IGNORE INTO session_tags (session_id, tag_id)
+                SELECT chat_sessions.id, tags.id FROM chat_sessions, tags
+                WHERE chat_sessions.id = ?1 AND tags.name = ?2
+                "#,
+                (&session_id, tag),
+            )?;
+        }
+        Self::delete_unused_tags(&tx)?;
+        tx.commit()?;
+        Self::get_chat_session_internal(conn, session_id)
+    }
+
+    fn delete_unused_tags(conn: &Connection) -> Result<()> {
+        conn.execute(
+            "DELETE FROM tags WHERE id NOT IN (SELECT tag_id FROM session_tags)",
+            (),
         )?;
-        stmt.execute((session_id,))?;
         Ok(())
     }
+
+    /// Encodes `chat_session` without its tags, which are stored in their own table, and returns
+    /// it with its model name.
+    fn encode(chat_session: &ChatSession) -> Result<(Vec<u8>, &'static str)> {
+        let mut buf = Vec::new();
+        ChatSession {
+            tags: Vec::new(),
+            events_cursor: String::new(),
+            deleted_at: None,
+            ..chat_session.clone()
+        }
+        .encode(&mut buf)?;
+        let model = chat_session
+            .llm_settings
+            .as_ref()
+            .map_or("", |s| s.model_name());
+        Ok((buf, model))
+    }
+
+    /// Fills columns of sessions stored before they were added.
+    pub fn backfill_columns(conn: &Connection) -> Result<()> {
+        let mut stmt = conn.prepare("SELECT id, data FROM chat_sessions")?;
+        let rows = stmt.query_map([], |row| {
+            Ok((row.get::<_, String>("id")?, row.get::<_, Vec<u8>>("data")?))
+        })?;
+        for row in rows {
+            let (id, data) = row?;
+            let chat_session = ChatSession::decode(&*data)?;
+            let (_, model) = Self::encode(&chat_session)?;
+            conn.execute(
+                r#"
+                UPDATE chat_sessions
+                SET title = ?1, model = ?2, pinned = ?3, archived = ?4, source = ?5
+                WHERE id = ?6
+                "#,
+                (
+                    &chat_session.title,
+                    model,
+                    chat_session.pinned,
+                    chat_session.archived,
+                    &chat_session.source,
+                    &id,
+                ),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Position in the session order after the session it is taken from, formatted as
+/// `{pinned}:{updated_at}:{id}`.
+struct SessionCursor {
+    pinned: bool,
+    updated_at: i64,
+    id: String,
+}
+
+impl SessionCursor {
+    fn of(chat_session: &ChatSession) -> Self {
+        Self {
+            pinned: chat_session.pinned,
+            updated_at: chat_session.updated_at.unwrap_or_default().seconds,
+            id: chat_session.id.clone(),
+        }
+    }
+
+    fn parse(cursor: &str) -> Result<Self> {
+        let mut parts = cursor.splitn(3, ':');
+        match (parts.next(), parts.next(), parts.next()) {
+            (Some(pinned), Some(updated_at), Some(id)) => Ok(Self {
+                pinned: pinned.parse()?,
+                updated_at: updated_at.parse()?,
+                id: id.to_string(),
+            }),
+            _ => Err(eyre!("invalid session cursor {cursor}")),
+        }
+    }
+}
+
+impl std::fmt::Display for SessionCursor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}:{}", self.pinned, self.updated_at, self.id)
+    }
 }
 
 impl ChatSession {
     fn from_row(row: &rusqlite::Row) -> rusqlite::Result<ChatSession> {
         let data: Vec<u8> = row.get("data")?;
-        let created_at: i64 = row.get("created_at")?;
-        let updated_at: i64 = row.get("updated_at")?;
         let mut chat_session =
             ChatSession::decode(&*data).map_err(|_| rusqlite::Error::ExecuteReturnedResults)?;
-        chat_session.created_at = Some(prost_types::Timestamp {
+        chat_session.read_row_columns(row)?;
+        Ok(chat_session)
+    }
+
+    fn from_summary_row(row: &rusqlite::Row) -> rusqlite::Result<ChatSession> {
+        let mut chat_session = ChatSession {
+            id: row.get("id")?,
+            title: row.get("title")?,
+            pinned: row.get("pinned")?,
+            archived: row.get("archived")?,
+            source: row.get("source")?,
+            ..Default::default()
+        };
+        chat_session.read_row_columns(row)?;
+        Ok(chat_session)
+    }
+
+    /// Reads timestamps and tags, which are kept in columns and tables only.
+    fn read_row_columns(&mut self, row: &rusqlite::Row) -> rusqlite::Result<()> {
+        let created_at: i64 = row.get("created_at")?;
+        let updated_at: i64 = row.get("updated_at")?;
+        let deleted_at: Option<i64> = row.get("deleted_at")?;
+        self.deleted_at = deleted_at.map(|seconds| prost_types::Timestamp { seconds, nanos: 0 });
+        self.created_at = Some(prost_types::Timestamp {
             seconds: created_at,
             nanos: 0,
         });
-        chat_session.updated_at = Some(prost_types::Timestamp {
+        self.updated_at = Some(prost_types::Timestamp {
             seconds: updated_at,
             nanos: 0,
         });
-        Ok(chat_session)
+        let tags: Option<String> = row.get("tags")?;
+        self.tags = tags
+            .map(|tags| tags.split('\u{1f}').map(String::from).collect())
+            .unwrap_or_default();
+        self.tags.sort();
+        Ok(())
     }
 }
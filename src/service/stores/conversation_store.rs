@@ -0,0 +1,140 @@
+use async_trait::async_trait;
+use color_eyre::{Result, eyre::eyre};
+use rusqlite::{Connection, OptionalExtension as _};
+use std::sync::mpsc::Sender;
+use tokio::sync::oneshot;
+
+use crate::service::database::Job;
+
+/// Upper bound of recorded conversation keys, the oldest are dropped beyond it.
+const MAX_CONVERSATION_KEYS: usize = 10_000;
+
+/// Persists sessions of conversations proxied through the server by their keys, so that
+/// continuations are recognized across restarts.
+#[async_trait]
+pub trait ConversationStore: Send + Sync {
+    /// Returns id of session of conversation `key`, None if not recorded.
+    async fn get_conversation_session(&self, key: &str) -> Result<Option<String>>;
+    /// Records `keys` of a conversation of session of `session_id`.
+    async fn record_conversation(&self, keys: Vec<String>, session_id: String) -> Result<()>;
+}
+
+pub struct ConversationStoreImpl {
+    /// Db job sender.
+    job_tx: Sender<Job>,
+}
+
+impl ConversationStoreImpl {
+    pub fn new(job_tx: Sender<Job>) -> Self {
+        Self { job_tx }
+    }
+}
+
+#[async_trait]
+impl ConversationStore for ConversationStoreImpl {
+    async fn get_conversation_session(&self, key: &str) -> Result<Option<String>> {
+        let (resp_tx, resp_rx) = oneshot::channel();
+
+        let key = key.to_string();
+        let job = Box::new(move |conn: &mut Connection| {
+            let result = Self::get_conversation_session_internal(conn, key);
+            let _ = resp_tx.send(result);
+        });
+
+        self.job_tx
+            .send(job)
+            .map_err(|e| eyre!("failed to send job to DB thread: {}", e))?;
+        resp_rx.await?
+    }
+
+    async fn record_conversation(&self, keys: Vec<String>, session_id: String) -> Result<()> {
+        let (resp_tx, resp_rx) = oneshot::channel();
+
+        let job = Box::new(move |conn: &mut Connection| {
+            let result = Self::record_conversation_internal(conn, keys, session_id);
+            let _ = resp_tx.send(result);
+        });
+
+        self.job_tx
+            .send(job)
+            .map_err(|e| eyre!("failed to send job to DB thread: {}", e))?;
+        resp_rx.await?
+    }
+}
+
+impl ConversationStoreImpl {
+    fn get_conversation_session_internal(
+        conn: &mut Connection,
+        key: String,
+    ) -> Result<Option<String>> {
+        conn.query_row(
+            "SELECT session_id FROM proxy_conversations WHERE key = ?1",
+            (key,),
+            |row| row.get(0),
+        )
+        .optional()
+        .map_err(Into::into)
+    }
+
+    fn record_conversation_internal(
+        conn: &mut Connection,
+        keys: Vec<String>,
+        session_id: String,
+    ) -> Result<()> {
+        let tx = conn.transaction()?;
+        for key in keys {
+            tx.execute(
+                r#"
+                INSERT OR REPLACE INTO proxy_conversations (key, session_id)
+                VALUES (?1, ?2)
+                "#,
+                (key, &session_id),
+            )?;
+        }
+        // replaced keys are reinserted, so the oldest have the lowest rowids
+        tx.execute(
+            r#"
+            DELETE FROM proxy_conversations
+            WHERE rowid NOT IN (
+                SELECT rowid FROM proxy_conversations ORDER BY rowid DESC LIMIT ?1
+            )
+            "#,
+            (MAX_CONVERSATION_KEYS,),
+        )?;
+        tx.commit()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rusqlite::Connection;
+
+    use crate::service::{
+        database::init_db_conn,
+        stores::conversation_store::{ConversationStoreImpl, MAX_CONVERSATION_KEYS},
+    };
+
+    #[test]
+    fn record_conversation() {
+        let mut conn = init_db_conn(Connection::open_in_memory().unwrap()).unwrap();
+        conn.execute(
+            "INSERT INTO chat_sessions (id, data, updated_at) VALUES ('session', x'', 0)",
+            (),
+        )
+        .unwrap();
+
+        let keys = (0..=MAX_CONVERSATION_KEYS).map(|i| i.to_string()).collect();
+        ConversationStoreImpl::record_conversation_internal(&mut conn, keys, "session".to_string())
+            .unwrap();
+        let session_id = |conn: &mut Connection, key: &str| {
+            ConversationStoreImpl::get_conversation_session_internal(conn, key.to_string()).unwrap()
+        };
+        assert_eq!(session_id(&mut conn, "0"), None, "oldest key is dropped");
+        assert_eq!(session_id(&mut conn, "1").as_deref(), Some("session"));
+
+        // keys go with their session
+        conn.execute("DELETE FROM chat_sessions", ()).unwrap();
+        assert_eq!(session_id(&mut conn, "1"), None);
+    }
+}
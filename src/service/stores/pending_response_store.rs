@@ -0,0 +1,130 @@
+use async_trait::async_trait;
+use color_eyre::{Result, eyre::eyre};
+use prost::Message;
+use rusqlite::Connection;
+use std::sync::mpsc::Sender;
+use tokio::sync::oneshot;
+
+use crate::{
+    chat::{ChatEvent, chat_event},
+    service::database::Job,
+};
+
+/// Persists background llm responses so that they are resumed across restarts.
+#[async_trait]
+pub trait PendingResponseStore: Send + Sync {
+    /// Returns chat events with `PendingResponse` payload of all sessions not in trash.
+    async fn get_pending_responses(&self) -> Result<Vec<ChatEvent>>;
+    /// Creates or updates pending response of chat event with `PendingResponse` payload.
+    async fn upsert_pending_response(&self, chat_event: ChatEvent) -> Result<()>;
+    async fn delete_pending_response(&self, response_id: &str) -> Result<()>;
+}
+
+pub struct PendingResponseStoreImpl {
+    /// Db job sender.
+    job_tx: Sender<Job>,
+}
+
+impl PendingResponseStoreImpl {
+    pub fn new(job_tx: Sender<Job>) -> Self {
+        Self { job_tx }
+    }
+}
+
+#[async_trait]
+impl PendingResponseStore for PendingResponseStoreImpl {
+    async fn get_pending_responses(&self) -> Result<Vec<ChatEvent>> {
+        let (resp_tx, resp_rx) = oneshot::channel();
+
+        let job = Box::new(move |conn: &mut Connection| {
+            let result = Self::get_pending_responses_internal(conn);
+            let _ = resp_tx.send(result);
+        });
+
+        self.job_tx
+            .send(job)
+            .map_err(|e| eyre!("failed to send job to DB thread: {}", e))?;
+        resp_rx.await?
+    }
+
+    async fn upsert_pending_response(&self, chat_event: ChatEvent) -> Result<()> {
+        let (resp_tx, resp_rx) = oneshot::channel();
+
+        let job = Box::new(move |conn: &mut Connection| {
+            let result = Self::upsert_pending_response_internal(conn, chat_event);
+            let _ = resp_tx.send(result);
+        });
+
+        self.job_tx
+            .send(job)
+            .map_err(|e| eyre!("failed to send job to DB thread: {}", e))?;
+        resp_rx.await?
+    }
+
+    async fn delete_pending_response(&self, response_id: &str) -> Result<()> {
+        let (resp_tx, resp_rx) = oneshot::channel();
+
+        let response_id = response_id.to_string();
+        let job = Box::new(move |conn: &mut Connection| {
+            let result = Self::delete_pending_response_internal(conn, response_id);
+            let _ = resp_tx.send(result);
+        });
+
+        self.job_tx
+            .send(job)
+            .map_err(|e| eyre!("failed to send job to DB thread: {}", e))?;
+        resp_rx.await?
+    }
+}
+
+impl PendingResponseStoreImpl {
+    fn get_pending_responses_internal(conn: &mut Connection) -> Result<Vec<ChatEvent>> {
+        let mut stmt = conn.prepare(
+            r#"
+            SELECT data
+            FROM pending_responses
+            WHERE session_id NOT IN (SELECT id FROM chat_sessions WHERE deleted_at IS NOT NULL)
+            ORDER BY updated_at ASC
+            "#,
+        )?;
+        let rows = stmt.query_map([], |row| {
+            let data: Vec<u8> = row.get("data")?;
+            ChatEvent::decode(&*data).map_err(|_| rusqlite::Error::ExecuteReturnedResults)
+        })?;
+        rows.collect::<Result<Vec<_>, _>>().map_err(Into::into)
+    }
+
+    fn upsert_pending_response_internal(
+        conn: &mut Connection,
+        chat_event: ChatEvent,
+    ) -> Result<()> {
+        let Some(chat_event::Payload::PendingResponse(pending_response)) = &chat_event.payload
+        else {
+            return Err(eyre!("chat event is not pending response"));
+        };
+        let mut buf = Vec::new();
+        chat_event.encode(&mut buf)?;
+
+        conn.execute(
+            r#"
+            INSERT INTO pending_responses (id, session_id, data)
+            VALUES (?1, ?2, ?3)
+            ON CONFLICT(id) DO UPDATE
+            SET data = excluded.data, updated_at = strftime('%s', 'now')
+            "#,
+            (&pending_response.response_id, &chat_event.session_id, &buf),
+        )?;
+        Ok(())
+    }
+
+    fn delete_pending_response_internal(conn: &mut Connection, response_id: String) -> Result<()> {
+        conn.execute(
+            r#"
+            DELETE FROM pending_responses
+            WHERE id = ?1
+            "#,
+            (response_id,),
+        )?;
+        Ok(())
+    }
+}
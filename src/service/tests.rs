@@ -0,0 +1,1033 @@
+use color_eyre::Result;
+use prost::Message as _;
+use rusqlite::Connection;
+use std::{sync::Arc, time::Duration};
+use tokio::{
+    sync::mpsc::{UnboundedReceiver, UnboundedSender, unbounded_channel},
+    task::JoinHandle,
+};
+
+use crate::{
+    chat::*,
+    llm::*,
+    models::{
+        ServiceReq, ServiceResp,
+        configs::{Config, WorkerConfig},
+        constants::{EVENT_PAGE_SIZE, SESSION_PAGE_SIZE},
+        settings::ProviderKind,
+    },
+    service::{
+        Service, ServiceBuilder,
+        database::init_db_conn,
+        llms::{
+            LlmClientRouter,
+            mock::{MockLlmClient, MockScript},
+        },
+    },
+};
+
+const SCRIPT: &str = r#"{"responses": [
+    {"when": "concise title", "events": [{"delta": "Mock title"}]},
+    {"when": "fail", "events": [{"error": "rate limited"}]},
+    {"when": "slow", "events": ["hold", {"delta": "Finally"}]},
+    {"when": "partial", "events": [{"delta": "Part"}, "hold", {"delta": "ial"}]},
+    {"when": "research", "background": "resp_research", "events": [
+        {"delta": "Deep"},
+        "hold",
+        {"delta": " research"}
+    ]},
+    {"when": "search", "events": [
+        {"web_search": {"id": "ws_1", "query": "rust"}},
+        {"delay_ms": 5},
+        {"delta": "Rust is "},
+        {"delta": "a language."}
+    ]},
+    {"events": [{"delta": "Hello!"}]}
+]}"#;
+
+/// Service running on in-memory db with mock provider, driven over its channels.
+struct TestService {
+    req_tx: UnboundedSender<ServiceReq>,
+    resp_rx: UnboundedReceiver<ServiceResp>,
+    /// Received responses not yet matched, e.g. title generated while streaming.
+    unmatched: Vec<ServiceResp>,
+    handle: JoinHandle<Result<()>>,
+}
+
+/// Builds service on `db_conn` with mock provider.
+async fn build_service(
+    config: Config,
+    db_conn: Connection,
+) -> (
+    Service,
+    UnboundedSender<ServiceReq>,
+    UnboundedReceiver<ServiceResp>,
+) {
+    let (req_tx, req_rx) = unbounded_channel::<ServiceReq>();
+    let (resp_tx, resp_rx) = unbounded_channel::<ServiceResp>();
+
+    let script: MockScript = serde_json::from_str(SCRIPT).unwrap();
+    let mut llm_router = LlmClientRouter::default();
+    llm_router.register(ProviderKind::Mock, Arc::new(MockLlmClient::new(script)));
+
+    let service = ServiceBuilder::new(req_rx, resp_tx, config)
+        .with_db_conn(db_conn)
+        .with_llm_router(llm_router)
+        .build()
+        .await
+        .unwrap();
+    (service, req_tx, resp_rx)
+}
+
+fn memory_db() -> Connection {
+    init_db_conn(Connection::open_in_memory().unwrap()).unwrap()
+}
+
+fn user_message(session_id: &str, msg: &str) -> ChatEvent {
+    let settings = LlmSettings {
+        provider: Some(llm_settings::Provider::Mock(MockSettings {})),
+        ..Default::default()
+    };
+    ChatEvent::new(
+        session_id.to_string(),
+        Some(settings),
+        chat_event::Payload::Message(Message {
+            role: Role::User as i32,
+            msg: msg.to_string(),
+            response_ref: None,
+        }),
+    )
+}
+
+impl TestService {
+    async fn start() -> Self {
+        Self::start_with(Config::default(), memory_db()).await
+    }
+
+    async fn start_with(config: Config, db_conn: Connection) -> Self {
+        let (service, req_tx, resp_rx) = build_service(config, db_conn).await;
+        Self {
+            req_tx,
+            resp_rx,
+            unmatched: Vec::new(),
+            handle: tokio::spawn(service.run()),
+        }
+    }
+
+    fn send_message(&self, session_id: &str, msg: &str) {
+        self.req_tx
+            .send(ServiceReq::ChatMessage(user_message(session_id, msg)))
+            .unwrap();
+    }
+
+    /// Receives the first response matching `f`, starting from unmatched ones.
+    async fn recv(&mut self, f: impl Fn(&ServiceResp) -> bool) -> ServiceResp {
+        if let Some(idx) = self.unmatched.iter().position(&f) {
+            return self.unmatched.remove(idx);
+        }
+        loop {
+            let resp = self.resp_rx.recv().await.expect("service stopped");
+            if f(&resp) {
+                return resp;
+            }
+            self.unmatched.push(resp);
+        }
+    }
+
+    /// Receives payloads of chat events until assistant message.
+    async fn recv_reply(&mut self) -> Vec<chat_event::Payload> {
+        let mut payloads = Vec::new();
+        loop {
+            let resp = self
+                .recv(|resp| matches!(resp, ServiceResp::ChatEvent(_)))
+                .await;
+            let ServiceResp::ChatEvent(ChatEvent {
+                payload: Some(payload),
+                ..
+            }) = resp
+            else {
+                continue;
+            };
+            let done = matches!(
+                &payload,
+                chat_event::Payload::Message(m) if m.role() == Role::Assistant
+            );
+            payloads.push(payload);
+            if done {
+                return payloads;
+            }
+        }
+    }
+
+    async fn get_session(&mut self, session_id: &str) -> ChatSession {
+        self.req_tx
+            .send(ServiceReq::GetSession(session_id.to_string()))
+            .unwrap();
+        match self
+            .recv(|resp| matches!(resp, ServiceResp::Session(_)))
+            .await
+        {
+            ServiceResp::Session(session) => session,
+            _ => unreachable!(),
+        }
+    }
+
+    /// Stops service by closing its request channel.
+    async fn stop(self) -> Result<()> {
+        drop(self.req_tx);
+        self.handle.await?
+    }
+}
+
+fn kind(payload: &chat_event::Payload) -> &'static str {
+    match payload {
+        chat_event::Payload::Message(m) if m.role() == Role::User => "user",
+        chat_event::Payload::Message(_) => "assistant",
+        chat_event::Payload::MessageDelta(_) => "delta",
+        chat_event::Payload::ToolEvent(_) => "tool",
+        chat_event::Payload::ContextTrim(_) => "trim",
+        chat_event::Payload::PendingResponse(_) => "pending",
+    }
+}
+
+fn kinds(payloads: &[chat_event::Payload]) -> Vec<&'static str> {
+    payloads.iter().map(kind).collect()
+}
+
+#[tokio::test]
+async fn chat() {
+    let mut service = TestService::start().await;
+    let ServiceResp::Providers(providers) = service
+        .recv(|resp| matches!(resp, ServiceResp::Providers(_)))
+        .await
+    else {
+        unreachable!()
+    };
+    assert_eq!(providers.len(), 1);
+    assert_eq!(providers[0].provider, ProviderKind::Mock);
+
+    service.send_message("session", "search rust");
+    let reply = service.recv_reply().await;
+    assert_eq!(
+        kinds(&reply),
+        vec!["user", "tool", "delta", "delta", "assistant"]
+    );
+    let ServiceResp::SessionSummary(session) = service
+        .recv(|resp| matches!(resp, ServiceResp::SessionSummary(_)))
+        .await
+    else {
+        unreachable!()
+    };
+    assert_eq!(session.title, "Mock title");
+
+    service.send_message("session", "hi");
+    let reply = service.recv_reply().await;
+    assert_eq!(kinds(&reply), vec!["user", "delta", "assistant"]);
+
+    // deltas are dropped once the message completes
+    let session = service.get_session("session").await;
+    let payloads: Vec<_> = session
+        .events
+        .into_iter()
+        .filter_map(|e| e.payload)
+        .collect();
+    assert_eq!(
+        kinds(&payloads),
+        vec!["user", "tool", "assistant", "user", "assistant"]
+    );
+    let messages: Vec<_> = payloads
+        .iter()
+        .filter_map(|p| match p {
+            chat_event::Payload::Message(m) => Some(m.msg.as_str()),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(
+        messages,
+        vec!["search rust", "Rust is a language.", "hi", "Hello!"]
+    );
+
+    // drop sessions sent before deletion
+    service.unmatched.clear();
+    service
+        .req_tx
+        .send(ServiceReq::DeleteSessions(vec!["session".to_string()]))
+        .unwrap();
+    service
+        .recv(|resp| matches!(resp, ServiceResp::SessionsChanged))
+        .await;
+    assert_eq!(service.query_ids(false).await, Vec::<String>::new());
+
+    service.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn rename_pin_and_archive_session() {
+    let mut service = TestService::start().await;
+    service.send_message("session", "hi");
+    service.recv_reply().await;
+    service
+        .recv(|resp| matches!(resp, ServiceResp::SessionSummary(_)))
+        .await;
+
+    let reqs = [
+        ServiceReq::RenameSession {
+            session_id: "session".to_string(),
+            title: "Renamed".to_string(),
+        },
+        ServiceReq::PinSession {
+            session_id: "session".to_string(),
+            pinned: true,
+        },
+        ServiceReq::ArchiveSession {
+            session_id: "session".to_string(),
+            archived: true,
+        },
+    ];
+    for req in reqs {
+        service.req_tx.send(req).unwrap();
+        service
+            .recv(|resp| matches!(resp, ServiceResp::SessionSummary(_)))
+            .await;
+    }
+
+    // the active session is updated with the stored one
+    let session = service.get_session("session").await;
+    assert_eq!(
+        (session.title.as_str(), session.pinned, session.archived),
+        ("Renamed", true, true)
+    );
+
+    service
+        .req_tx
+        .send(ServiceReq::RenameSession {
+            session_id: "missing".to_string(),
+            title: "Renamed".to_string(),
+        })
+        .unwrap();
+    let resp = service
+        .recv(|resp| matches!(resp, ServiceResp::Notice(_)))
+        .await;
+    assert_eq!(
+        resp,
+        ServiceResp::Notice("session missing not found".to_string())
+    );
+
+    service.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn reply_to_client() {
+    let mut service = TestService::start().await;
+    service.send_message("session", "hi");
+    service.recv_reply().await;
+
+    // replies go to the requesting client only, updates to every client
+    for (client_id, session_id) in [(1, "session"), (2, "missing")] {
+        service
+            .req_tx
+            .send(ServiceReq::FromClient {
+                client_id,
+                req: Box::new(ServiceReq::GetSession(session_id.to_string())),
+            })
+            .unwrap();
+    }
+    let resp = service
+        .recv(|resp| matches!(resp, ServiceResp::ToClient { client_id: 1, .. }))
+        .await;
+    assert!(matches!(
+        resp,
+        ServiceResp::ToClient { resp, .. } if matches!(*resp, ServiceResp::Session(_))
+    ));
+    let resp = service
+        .recv(|resp| matches!(resp, ServiceResp::ToClient { client_id: 2, .. }))
+        .await;
+    assert_eq!(
+        resp,
+        ServiceResp::ToClient {
+            client_id: 2,
+            resp: Box::new(ServiceResp::Notice("session missing not found".to_string())),
+        }
+    );
+
+    service
+        .req_tx
+        .send(ServiceReq::FromClient {
+            client_id: 1,
+            req: Box::new(ServiceReq::PinSession {
+                session_id: "session".to_string(),
+                pinned: true,
+            }),
+        })
+        .unwrap();
+    service
+        .recv(|resp| matches!(resp, ServiceResp::SessionSummary(s) if s.pinned))
+        .await;
+
+    service.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn tag_and_query_sessions() {
+    let mut service = TestService::start().await;
+    for session_id in ["a", "b"] {
+        service.send_message(session_id, "hi");
+        service.recv_reply().await;
+        service
+            .recv(|resp| matches!(resp, ServiceResp::SessionSummary(s) if s.id == session_id))
+            .await;
+    }
+    service
+        .req_tx
+        .send(ServiceReq::RenameSession {
+            session_id: "b".to_string(),
+            title: "Rust async".to_string(),
+        })
+        .unwrap();
+    service
+        .req_tx
+        .send(ServiceReq::SetSessionTags {
+            session_id: "a".to_string(),
+            tags: vec!["#work".to_string(), "Work".to_string(), "rust".to_string()],
+        })
+        .unwrap();
+    let ServiceResp::SessionSummary(session) = service
+        .recv(|resp| matches!(resp, ServiceResp::SessionSummary(s) if s.id == "a"))
+        .await
+    else {
+        unreachable!()
+    };
+    assert_eq!(session.tags, vec!["rust", "work"]);
+
+    // invalid tags are reported and leave tags as they are
+    service
+        .req_tx
+        .send(ServiceReq::SetSessionTags {
+            session_id: "a".to_string(),
+            tags: vec!["two words".to_string()],
+        })
+        .unwrap();
+    let resp = service
+        .recv(|resp| matches!(resp, ServiceResp::Notice(_)))
+        .await;
+    assert_eq!(
+        resp,
+        ServiceResp::Notice("invalid tag \"two words\"".to_string())
+    );
+
+    let cases = [
+        ("tag:WORK", vec!["a"]),
+        ("tag:work tag:rust", vec!["a"]),
+        ("tag:rust rsy", vec![]),
+        ("rsy", vec!["b"]),
+        ("model:scripted", vec!["a", "b"]),
+        ("model:o3", vec![]),
+    ];
+    for (query, expected) in cases {
+        service
+            .req_tx
+            .send(ServiceReq::QuerySessions {
+                query: query.to_string(),
+                cursor: None,
+                trash: false,
+                include_archived: false,
+            })
+            .unwrap();
+        let ServiceResp::SessionQuery { sessions, .. } = service
+            .recv(|resp| matches!(resp, ServiceResp::SessionQuery { query: q, .. } if q == query))
+            .await
+        else {
+            unreachable!()
+        };
+        // sessions updated within the same second are in any order
+        let mut ids: Vec<_> = sessions.iter().map(|s| s.id.as_str()).collect();
+        ids.sort();
+        assert_eq!(ids, expected, "{query}");
+    }
+
+    service.stop().await.unwrap();
+}
+
+/// Stores session `session_id` updated at `updated_at` with `event_count` user messages.
+fn seed_session(
+    conn: &Connection,
+    session_id: &str,
+    updated_at: i64,
+    pinned: bool,
+    event_count: usize,
+) {
+    let session = ChatSession {
+        id: session_id.to_string(),
+        title: session_id.to_string(),
+        pinned,
+        ..Default::default()
+    };
+    conn.execute(
+        "INSERT INTO chat_sessions (id, data, title, pinned, updated_at) VALUES (?1, ?2, ?1, ?3, ?4)",
+        (session_id, session.encode_to_vec(), pinned, updated_at),
+    )
+    .unwrap();
+    for i in 0..event_count {
+        let event = user_message(session_id, &i.to_string());
+        conn.execute(
+            "INSERT INTO chat_events (id, session_id, data) VALUES (?1, ?2, ?3)",
+            (&event.id, session_id, event.encode_to_vec()),
+        )
+        .unwrap();
+    }
+}
+
+fn msgs(events: &[ChatEvent]) -> Vec<String> {
+    events
+        .iter()
+        .filter_map(|e| match &e.payload {
+            Some(chat_event::Payload::Message(m)) => Some(m.msg.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn page_sessions() {
+    let conn = memory_db();
+    for i in 0..SESSION_PAGE_SIZE + 10 {
+        seed_session(&conn, &format!("s{i:02}"), 1_000 + i as i64, false, 0);
+    }
+    seed_session(&conn, "pinned", 0, true, 0);
+    // archived sessions are left out of pages rather than filling them
+    for i in 0..SESSION_PAGE_SIZE {
+        seed_session(
+            &conn,
+            &format!("archived{i:02}"),
+            2_000 + i as i64,
+            false,
+            0,
+        );
+    }
+    conn.execute(
+        "UPDATE chat_sessions SET archived = 1 WHERE id LIKE 'archived%'",
+        (),
+    )
+    .unwrap();
+    let mut service = TestService::start_with(Config::default(), conn).await;
+
+    let mut ids = Vec::new();
+    let mut cursor = None;
+    let mut page_sizes = Vec::new();
+    loop {
+        service
+            .req_tx
+            .send(ServiceReq::QuerySessions {
+                query: String::new(),
+                cursor: cursor.clone(),
+                trash: false,
+                include_archived: false,
+            })
+            .unwrap();
+        let ServiceResp::SessionQuery {
+            sessions,
+            next_cursor,
+            ..
+        } = service
+            .recv(
+                |resp| matches!(resp, ServiceResp::SessionQuery { cursor: c, .. } if *c == cursor),
+            )
+            .await
+        else {
+            unreachable!()
+        };
+        page_sizes.push(sessions.len());
+        ids.extend(sessions.into_iter().map(|s| s.id));
+        cursor = next_cursor;
+        if cursor.is_none() {
+            break;
+        }
+    }
+    assert_eq!(page_sizes, vec![SESSION_PAGE_SIZE, 11]);
+    // pinned first, then most recently updated
+    let mut expected = vec!["pinned".to_string()];
+    expected.extend(
+        (0..SESSION_PAGE_SIZE + 10)
+            .rev()
+            .map(|i| format!("s{i:02}")),
+    );
+    assert_eq!(ids, expected);
+
+    service
+        .req_tx
+        .send(ServiceReq::QuerySessions {
+            query: String::new(),
+            cursor: None,
+            trash: false,
+            include_archived: true,
+        })
+        .unwrap();
+    let ServiceResp::SessionQuery { sessions, .. } = service
+        .recv(|resp| {
+            matches!(
+                resp,
+                ServiceResp::SessionQuery {
+                    include_archived: true,
+                    ..
+                }
+            )
+        })
+        .await
+    else {
+        unreachable!()
+    };
+    assert_eq!(sessions[0].id, "pinned");
+    assert!(sessions[1..].iter().all(|s| s.archived));
+
+    service.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn page_events() {
+    let conn = memory_db();
+    seed_session(&conn, "session", 0, false, 2 * EVENT_PAGE_SIZE + 10);
+    let mut service = TestService::start_with(Config::default(), conn).await;
+
+    // session comes with its latest events
+    let session = service.get_session("session").await;
+    let expected: Vec<_> = (EVENT_PAGE_SIZE + 10..2 * EVENT_PAGE_SIZE + 10)
+        .map(|i| i.to_string())
+        .collect();
+    assert_eq!(msgs(&session.events), expected);
+
+    let mut before = session.events_cursor;
+    let mut pages = Vec::new();
+    while !before.is_empty() {
+        service
+            .req_tx
+            .send(ServiceReq::GetEvents {
+                session_id: "session".to_string(),
+                before: before.clone(),
+            })
+            .unwrap();
+        let ServiceResp::Events {
+            events,
+            next_cursor,
+            ..
+        } = service
+            .recv(|resp| matches!(resp, ServiceResp::Events { before: b, .. } if *b == before))
+            .await
+        else {
+            unreachable!()
+        };
+        pages.push(msgs(&events));
+        before = next_cursor.unwrap_or_default();
+    }
+    let expected: Vec<Vec<String>> = vec![
+        (10..EVENT_PAGE_SIZE + 10).map(|i| i.to_string()).collect(),
+        (0..10).map(|i| i.to_string()).collect(),
+    ];
+    assert_eq!(pages, expected);
+
+    service.stop().await.unwrap();
+}
+
+impl TestService {
+    /// Returns ids of the first page of sessions, in trash if `trash`, sorted.
+    async fn query_ids(&mut self, trash: bool) -> Vec<String> {
+        self.req_tx
+            .send(ServiceReq::QuerySessions {
+                query: String::new(),
+                cursor: None,
+                trash,
+                include_archived: false,
+            })
+            .unwrap();
+        let ServiceResp::SessionQuery { sessions, .. } = self
+            .recv(|resp| matches!(resp, ServiceResp::SessionQuery { trash: t, .. } if *t == trash))
+            .await
+        else {
+            unreachable!()
+        };
+        let mut ids: Vec<_> = sessions.into_iter().map(|s| s.id).collect();
+        ids.sort();
+        ids
+    }
+}
+
+#[tokio::test]
+async fn import_existing_session() {
+    let conn = memory_db();
+    seed_session(&conn, "a", 0, false, 1);
+    let mut service = TestService::start_with(Config::default(), conn).await;
+
+    service
+        .req_tx
+        .send(ServiceReq::ImportSession(Box::new(ChatSession {
+            id: "a".to_string(),
+            events: vec![user_message("a", "imported")],
+            ..Default::default()
+        })))
+        .unwrap();
+    let resp = service
+        .recv(|resp| matches!(resp, ServiceResp::Notice(_)))
+        .await;
+    assert_eq!(
+        resp,
+        ServiceResp::Notice("session a already exists".to_string())
+    );
+    // the stored session is left as it is
+    assert_eq!(msgs(&service.get_session("a").await.events), vec!["0"]);
+
+    service.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn trash_restore_and_purge_sessions() {
+    let conn = memory_db();
+    for session_id in ["a", "b", "expired"] {
+        seed_session(&conn, session_id, 0, false, 1);
+    }
+    // moved to trash beyond retention
+    conn.execute(
+        "UPDATE chat_sessions SET deleted_at = 1 WHERE id = 'expired'",
+        (),
+    )
+    .unwrap();
+    let mut service = TestService::start_with(Config::default(), conn).await;
+    assert_eq!(service.query_ids(true).await, Vec::<String>::new());
+
+    service
+        .req_tx
+        .send(ServiceReq::DeleteSessions(vec![
+            "a".to_string(),
+            "b".to_string(),
+        ]))
+        .unwrap();
+    service
+        .recv(|resp| matches!(resp, ServiceResp::SessionsChanged))
+        .await;
+    assert_eq!(service.query_ids(false).await, Vec::<String>::new());
+    assert_eq!(service.query_ids(true).await, vec!["a", "b"]);
+    // sessions in trash can still be viewed
+    assert_eq!(service.get_session("a").await.events.len(), 1);
+
+    service
+        .req_tx
+        .send(ServiceReq::RestoreSessions(vec!["a".to_string()]))
+        .unwrap();
+    service
+        .recv(|resp| matches!(resp, ServiceResp::SessionsChanged))
+        .await;
+    assert_eq!(service.query_ids(false).await, vec!["a"]);
+
+    // sessions in trash are not chatted with
+    service.send_message("b", "hi");
+    let resp = service
+        .recv(|resp| matches!(resp, ServiceResp::Notice(_)))
+        .await;
+    assert_eq!(
+        resp,
+        ServiceResp::Notice("session b is in trash".to_string())
+    );
+
+    // sessions not in trash are not purged
+    service
+        .req_tx
+        .send(ServiceReq::PurgeSessions(vec![
+            "a".to_string(),
+            "b".to_string(),
+        ]))
+        .unwrap();
+    service
+        .recv(|resp| matches!(resp, ServiceResp::SessionsChanged))
+        .await;
+    assert_eq!(service.query_ids(true).await, Vec::<String>::new());
+    assert_eq!(service.query_ids(false).await, vec!["a"]);
+
+    service.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn llm_error_keeps_service() {
+    let mut service = TestService::start().await;
+    service.send_message("session", "fail");
+    let ServiceResp::ChatFailed { session_id, error } = service
+        .recv(|resp| matches!(resp, ServiceResp::ChatFailed { .. }))
+        .await
+    else {
+        unreachable!()
+    };
+    assert_eq!(
+        (session_id.as_str(), error.as_str()),
+        ("session", "rate limited")
+    );
+    // drop echo of the failed message
+    service.unmatched.clear();
+
+    // the failed session and other sessions keep serving
+    service.send_message("other", "hi");
+    let reply = service.recv_reply().await;
+    assert_eq!(kinds(&reply), vec!["user", "delta", "assistant"]);
+    service.send_message("session", "hi");
+    let reply = service.recv_reply().await;
+    assert_eq!(kinds(&reply), vec!["user", "delta", "assistant"]);
+
+    service.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn delete_session_cancels_stream() {
+    let mut service = TestService::start().await;
+    service.send_message("session", "slow");
+    // streaming starts once the user message is echoed
+    service
+        .recv(|resp| matches!(resp, ServiceResp::ChatEvent(_)))
+        .await;
+    service
+        .recv(|resp| matches!(resp, ServiceResp::SessionSummary(_)))
+        .await;
+
+    service
+        .req_tx
+        .send(ServiceReq::DeleteSessions(vec!["session".to_string()]))
+        .unwrap();
+    service
+        .recv(|resp| matches!(resp, ServiceResp::SessionsChanged))
+        .await;
+    assert_eq!(service.query_ids(false).await, Vec::<String>::new());
+
+    // service keeps serving other sessions
+    service.send_message("other", "hi");
+    let reply = service.recv_reply().await;
+    assert_eq!(kinds(&reply), vec!["user", "delta", "assistant"]);
+    service
+        .recv(|resp| matches!(resp, ServiceResp::SessionSummary(_)))
+        .await;
+
+    // stops without waiting for the cancelled stream
+    tokio::time::timeout(Duration::from_secs(1), service.stop())
+        .await
+        .expect("cancelled stream is still running")
+        .unwrap();
+}
+
+#[tokio::test]
+async fn idle_worker_reloads_session() {
+    let config = Config {
+        workers: WorkerConfig {
+            idle_timeout_secs: 0,
+            ..Default::default()
+        },
+        ..Config::default()
+    };
+    let mut service = TestService::start_with(config, memory_db()).await;
+    service.send_message("session", "hi");
+    service.recv_reply().await;
+    service
+        .recv(|resp| matches!(resp, ServiceResp::SessionSummary(_)))
+        .await;
+
+    // idle workers are looked for every second
+    tokio::time::pause();
+    tokio::time::advance(Duration::from_millis(1100)).await;
+    tokio::time::resume();
+    // let the service stop the worker before the next message
+    tokio::task::yield_now().await;
+    service.send_message("session", "hi again");
+    service.recv_reply().await;
+    let session = service.get_session("session").await;
+    assert_eq!(session.events.len(), 4);
+
+    service.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn stop_idle_and_least_recently_used_workers() {
+    let config = Config {
+        workers: WorkerConfig {
+            idle_timeout_secs: 0,
+            max_workers: 2,
+            ..Default::default()
+        },
+        ..Config::default()
+    };
+    let (mut service, _req_tx, _resp_rx) = build_service(config, memory_db()).await;
+    let workers = |service: &Service| {
+        let mut session_ids: Vec<_> = service.session_worker_handles.keys().cloned().collect();
+        session_ids.sort();
+        session_ids
+    };
+
+    for session_id in ["a", "b", "c"] {
+        service
+            .spawn_session(user_message(session_id, "hi"))
+            .await
+            .unwrap();
+    }
+    assert_eq!(workers(&service), ["b", "c"]);
+
+    // busy workers are kept
+    service
+        .handle_user_message(user_message("b", "slow"))
+        .unwrap();
+    service
+        .spawn_session(user_message("d", "hi"))
+        .await
+        .unwrap();
+    assert_eq!(workers(&service), ["b", "d"]);
+    service.stop_idle_workers();
+    assert_eq!(workers(&service), ["b"]);
+
+    // respawning waits for the stopped worker of the session
+    service
+        .spawn_session(user_message("d", "hi"))
+        .await
+        .unwrap();
+    assert!(!service.stopping_workers.contains_key("d"));
+    assert_eq!(workers(&service), ["b", "d"]);
+
+    service.session_worker_handles["b"].abort();
+    service.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn shutdown_saves_partial_message() {
+    let db = tempfile::NamedTempFile::new().unwrap();
+    let db_conn = || init_db_conn(Connection::open(db.path()).unwrap()).unwrap();
+    let (service, req_tx, mut resp_rx) = build_service(Config::default(), db_conn()).await;
+    let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+    let handle = tokio::spawn(service.run_until(async {
+        let _ = shutdown_rx.await;
+    }));
+
+    req_tx
+        .send(ServiceReq::ChatMessage(user_message("session", "partial")))
+        .unwrap();
+    while !matches!(
+        resp_rx.recv().await,
+        Some(ServiceResp::ChatEvent(ChatEvent {
+            payload: Some(chat_event::Payload::MessageDelta(_)),
+            ..
+        }))
+    ) {}
+    shutdown_tx.send(()).unwrap();
+    tokio::time::timeout(Duration::from_secs(1), handle)
+        .await
+        .expect("shutdown waited for the stream")
+        .unwrap()
+        .unwrap();
+
+    let mut service = TestService::start_with(Config::default(), db_conn()).await;
+    let session = service.get_session("session").await;
+    let messages: Vec<_> = session
+        .events
+        .into_iter()
+        .filter_map(|e| match e.payload {
+            Some(chat_event::Payload::Message(m)) => Some(m.msg),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(messages, vec!["partial", "Part"]);
+    service.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn shutdown_resumes_background_response() {
+    let db = tempfile::NamedTempFile::new().unwrap();
+    let db_conn = || init_db_conn(Connection::open(db.path()).unwrap()).unwrap();
+    let (service, req_tx, mut resp_rx) = build_service(Config::default(), db_conn()).await;
+    let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+    let handle = tokio::spawn(service.run_until(async {
+        let _ = shutdown_rx.await;
+    }));
+
+    req_tx
+        .send(ServiceReq::ChatMessage(user_message("session", "research")))
+        .unwrap();
+    while !matches!(
+        resp_rx.recv().await,
+        Some(ServiceResp::ChatEvent(ChatEvent {
+            payload: Some(chat_event::Payload::MessageDelta(_)),
+            ..
+        }))
+    ) {}
+    shutdown_tx.send(()).unwrap();
+    handle.await.unwrap().unwrap();
+
+    // the response is resumed without the partial message saved alongside
+    let mut service = TestService::start_with(Config::default(), db_conn()).await;
+    service.recv(|resp| is_pending(resp, "completed")).await;
+    let session = service.get_session("session").await;
+    assert_eq!(msgs(&session.events), vec!["research", "Deep research"]);
+    service.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn shutdown_reports_unsaved_sessions() {
+    let config = Config {
+        workers: WorkerConfig {
+            shutdown_timeout_secs: 1,
+            ..Default::default()
+        },
+        ..Config::default()
+    };
+    let mut service = TestService::start_with(config, memory_db()).await;
+    // slow response is still being requested when shutting down
+    service.send_message("session", "slow");
+    service
+        .recv(|resp| matches!(resp, ServiceResp::ChatEvent(_)))
+        .await;
+
+    let err = service.stop().await.unwrap_err();
+    assert_eq!(
+        format!("{err:#}"),
+        "failed to save sessions session on shutdown"
+    );
+}
+
+/// Returns whether `resp` is progress of a background response with `status`.
+fn is_pending(resp: &ServiceResp, status: &str) -> bool {
+    matches!(
+        resp,
+        ServiceResp::ChatEvent(ChatEvent {
+            payload: Some(chat_event::Payload::PendingResponse(p)),
+            ..
+        }) if p.status == status
+    )
+}
+
+#[tokio::test]
+async fn cancel_background_response() {
+    let db = tempfile::NamedTempFile::new().unwrap();
+    let db_conn = || init_db_conn(Connection::open(db.path()).unwrap()).unwrap();
+    let mut service = TestService::start_with(Config::default(), db_conn()).await;
+    service.send_message("session", "research");
+    service.recv(|resp| is_pending(resp, "in_progress")).await;
+    service
+        .recv(|resp| {
+            matches!(
+                resp,
+                ServiceResp::ChatEvent(ChatEvent {
+                    payload: Some(chat_event::Payload::MessageDelta(_)),
+                    ..
+                })
+            )
+        })
+        .await;
+
+    service
+        .req_tx
+        .send(ServiceReq::CancelResponse("session".to_string()))
+        .unwrap();
+    service.recv(|resp| is_pending(resp, "cancelled")).await;
+    // the worker stops streaming the cancelled response
+    let session = service.get_session("session").await;
+    assert_eq!(msgs(&session.events), vec!["research"]);
+    tokio::time::timeout(Duration::from_secs(1), service.stop())
+        .await
+        .expect("cancelled response is still streaming")
+        .unwrap();
+
+    let pending: i64 = db_conn()
+        .query_row("SELECT COUNT(*) FROM pending_responses", [], |row| {
+            row.get(0)
+        })
+        .unwrap();
+    assert_eq!(pending, 0);
+}
@@ -2,7 +2,7 @@ use std::pin::Pin;
 
 use color_eyre::{
     Result,
-    eyre::{Context as _, bail},
+    eyre::{Context as _, eyre},
 };
 use futures_util::{Stream, StreamExt, stream::BoxStream};
 use reqwest::header::AUTHORIZATION;
@@ -26,12 +26,38 @@ pub async fn post<U: Serialize, T: DeserializeOwned>(
     handle_resp(resp).await
 }
 
+/// Non success response of an api, kept typed so that callers can react to specific errors.
+#[derive(Debug)]
+pub struct HttpError {
+    pub status: reqwest::StatusCode,
+    pub body: String,
+}
+
+impl std::fmt::Display for HttpError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "request failed: HTTP {} with body:\n{}",
+            self.status, self.body
+        )
+    }
+}
+
+impl std::error::Error for HttpError {}
+
+impl HttpError {
+    /// Returns `error.code` of an open ai style error body.
+    pub fn code(&self) -> Option<String> {
+        let body: serde_json::Value = serde_json::from_str(&self.body).ok()?;
+        body["error"]["code"].as_str().map(str::to_string)
+    }
+}
+
 async fn handle_resp<T: DeserializeOwned>(resp: reqwest::Response) -> Result<T> {
     let status = resp.status();
     let body = resp.text().await.wrap_err("failed to read response body")?;
     if !status.is_success() {
-        // TODO: return error
-        bail!("request failed: HTTP {status} with body:\n{body}");
+        return Err(HttpError { status, body }.into());
     }
 
     let result: T = serde_json::from_str(&body)
@@ -59,6 +85,22 @@ where
     Ok(stream::<T>(event_source))
 }
 
+pub async fn get_stream<T>(
+    client: &reqwest::Client,
+    url: String,
+    api_key: String,
+) -> Result<Pin<Box<dyn Stream<Item = Result<T>> + Send>>>
+where
+    T: DeserializeOwned + Send + 'static,
+{
+    let request_builder = client
+        .get(url)
+        .header(AUTHORIZATION, format!("Bearer {}", api_key));
+
+    let event_source = EventSource::new(request_builder).wrap_err("failed to create SSE client")?;
+    Ok(stream::<T>(event_source))
+}
+
 pub(crate) fn stream<T>(mut event_source: EventSource) -> BoxStream<'static, Result<T>>
 where
     T: DeserializeOwned + Send + 'static,
@@ -68,7 +110,18 @@ where
     tokio::spawn(async move {
         while let Some(event) = event_source.next().await {
             match event {
-                Err(_) => break, // stream ends
+                Err(reqwest_eventsource::Error::StreamEnded) => break,
+                Err(e) => {
+                    let err = match e {
+                        reqwest_eventsource::Error::InvalidStatusCode(status, resp) => {
+                            let body = resp.text().await.unwrap_or_default();
+                            eyre!(HttpError { status, body })
+                        }
+                        e => eyre!(e).wrap_err("SSE stream failed"),
+                    };
+                    let _ = tx.send(Err(err));
+                    break;
+                }
                 Ok(Event::Message(msg)) => {
                     // tracing::debug!(msg.data);
                     let parsed = serde_json::from_str::<T>(msg.data.as_str())